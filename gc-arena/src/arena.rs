@@ -172,6 +172,14 @@ macro_rules! make_arena {
                 self.context.total_allocated()
             }
 
+            /// Returns the total number of objects across all `FinalizerQueue`s that are ready to be
+            /// finalized.
+            #[allow(unused)]
+            #[inline]
+            pub fn pending_finalization(&self) -> usize {
+                self.context.pending_finalization()
+            }
+
             /// When the garbage collector is not sleeping, all allocated objects cause the arena to
             /// accumulate "allocation debt".  This debt is then be used to time incremental garbage
             /// collection based on the tuning parameters set in `ArenaParameters`.  The allocation
@@ -5,7 +5,9 @@ use std::{f64, mem, usize};
 
 use crate::arena::ArenaParameters;
 use crate::collect::Collect;
+use crate::finalize::FinalizerList;
 use crate::types::{GcBox, GcColor, GcFlags, Invariant};
+use crate::weak::CollectWeak;
 
 /// Handle value given by arena callbacks during construction and mutation.  Allows allocating new
 /// `Gc` pointers and internally mutating values held by `Gc` pointers.
@@ -23,6 +25,32 @@ impl<'gc, 'context> MutationContext<'gc, 'context> {
     pub(crate) unsafe fn write_barrier<T: 'gc + Collect>(self, ptr: NonNull<GcBox<T>>) {
         self.context.write_barrier(ptr)
     }
+
+    pub(crate) unsafe fn add_finalizer_queue<T: 'gc + FinalizerList>(self, ptr: NonNull<GcBox<T>>) {
+        self.context.add_finalizer_queue(ptr)
+    }
+
+    pub(crate) fn finalizer_taken(self) {
+        self.context.finalizer_taken()
+    }
+
+    pub(crate) unsafe fn finalizer_registered<T: 'gc + Collect>(self, ptr: &T) {
+        self.context.finalizer_registered(ptr)
+    }
+
+    pub(crate) unsafe fn set_external_size<T: 'gc + Collect>(
+        self,
+        ptr: NonNull<GcBox<T>>,
+        size: usize,
+    ) {
+        self.context.set_external_size(ptr, size)
+    }
+
+    /// Returns the total memory currently allocated in this arena, including the external memory
+    /// of every object.
+    pub fn total_allocated(self) -> usize {
+        self.context.total_allocated()
+    }
 }
 
 /// Handle value given by arena callbacks during garbage collection, which must be passed through
@@ -36,6 +64,23 @@ impl<'context> CollectionContext<'context> {
     pub(crate) unsafe fn trace<T: Collect>(self, ptr: NonNull<GcBox<T>>) {
         self.context.trace(ptr)
     }
+
+    /// Registers a value holding weak pointers, so that its `CollectWeak` methods will be called at
+    /// the end of this collection's mark phase.  Registering the same value more than once in a
+    /// single collection has no further effect.
+    ///
+    /// This must be called from inside the `Collect::trace` implementation of the value held by a
+    /// `Gc` or `GcCell` pointer, and panics if it is not (for example, while tracing the arena
+    /// root).
+    ///
+    /// # Safety
+    ///
+    /// `weak` must be the *entire* value held by the `Gc` or `GcCell` pointer that is currently
+    /// being traced (not, for example, one variant of an enum or a value behind a `Box`), so that
+    /// it remains valid for the rest of the collection no matter how the object is mutated.
+    pub unsafe fn register_weak<'gc, W: CollectWeak + 'gc>(self, weak: &W) {
+        self.context.register_weak(weak)
+    }
 }
 
 // Main gc context type, public because it must be accessible from the `make_arena!` macro.
@@ -55,8 +100,23 @@ pub struct Context {
 
     gray: RefCell<Vec<NonNull<GcBox<Collect>>>>,
     gray_again: RefCell<Vec<NonNull<GcBox<Collect>>>>,
+
+    // Every `FinalizerQueue` that has not yet been found to be unreachable.
+    finalizer_queues: RefCell<Vec<NonNull<GcBox<dyn FinalizerList>>>>,
+    // Whether the finalizer queues have been checked for unreachable objects this cycle.
+    finalizers_checked: Cell<bool>,
+    // The total number of objects that are ready to be finalized across all finalizer queues.
+    pending_finalization: Cell<usize>,
+
+    // The object whose value is currently being traced out of the gray queue, if any.
+    tracing: Cell<Option<NonNull<GcBox<dyn Collect>>>>,
+    // Every weak value registered during this collection.
+    weak: RefCell<Vec<WeakEntry>>,
 }
 
+// A value registered with `CollectionContext::register_weak`, along with the object that holds it
+type WeakEntry = (NonNull<GcBox<dyn Collect>>, NonNull<dyn CollectWeak>);
+
 impl Drop for Context {
     fn drop(&mut self) {
         struct DropAll(Option<NonNull<GcBox<Collect>>>);
@@ -94,6 +154,11 @@ impl Context {
             sweep_prev: Cell::new(None),
             gray: RefCell::new(Vec::new()),
             gray_again: RefCell::new(Vec::new()),
+            finalizer_queues: RefCell::new(Vec::new()),
+            finalizers_checked: Cell::new(false),
+            pending_finalization: Cell::new(0),
+            tracing: Cell::new(None),
+            weak: RefCell::new(Vec::new()),
         }
     }
 
@@ -118,6 +183,11 @@ impl Context {
         self.total_allocated.get()
     }
 
+    #[inline]
+    pub fn pending_finalization(&self) -> usize {
+        self.pending_finalization.get()
+    }
+
     // If the garbage collector is currently in the sleep phase, transition to the wake phase.
     pub fn wake(&self) {
         if self.phase.get() == Phase::Sleep {
@@ -143,6 +213,7 @@ impl Context {
                     // In the Wake phase, we trace the root object and add its children to the gray
                     // queue, and transition to the propagate phase.
                     root.trace(cc);
+                    self.finalizers_checked.set(false);
 
                     let root_size = mem::size_of::<R>() as f64;
                     work_done += root_size;
@@ -158,7 +229,7 @@ impl Context {
                     // double count them.  Processing "gray again" objects later also gives them
                     // more time to be mutated again without triggering another write barrier.
                     let next_gray = if let Some(ptr) = self.gray.borrow_mut().pop() {
-                        let gray_size = mem::size_of_val(ptr.as_ref()) as f64;
+                        let gray_size = ptr.as_ref().total_size() as f64;
                         work_done += gray_size;
                         self.allocation_debt
                             .set((self.allocation_debt.get() - gray_size).max(0.0));
@@ -173,11 +244,27 @@ impl Context {
                         // If we have an object in the gray queue, take one, trace it, and turn it
                         // black.
                         let gc_box = ptr.as_ref();
+                        self.tracing.set(Some(ptr));
                         (*gc_box.value.get()).trace(cc);
+                        self.tracing.set(None);
                         gc_box.flags.set_color(GcColor::Black);
+                    } else if self.trace_weak(cc) {
+                        // Objects that are only conditionally reachable through weak values have
+                        // been marked, and we must continue propagating until this no longer
+                        // happens.
+                    } else if !self.finalizers_checked.get() {
+                        // Once every reachable object has been marked, any object registered for
+                        // finalization that is still white would be freed by the upcoming sweep.
+                        // Instead, we resurrect these objects into their finalizer queues and
+                        // continue propagating, so that everything they reference is kept alive
+                        // as well.
+                        self.finalizers_checked.set(true);
+                        self.resurrect_finalizable(cc);
                     } else {
-                        // If we have no objects left in the normal gray queue, we enter the sweep
-                        // phase.
+                        // If we have no objects left in the normal gray queue, every object that is
+                        // still white is dead.  All weak pointers to these objects are cleared, and
+                        // we enter the sweep phase.
+                        self.clear_weak(cc);
                         self.phase.set(Phase::Sweep);
                         self.sweep.set(self.all.get());
                     }
@@ -185,7 +272,7 @@ impl Context {
                 Phase::Sweep => {
                     if let Some(sweep_ptr) = self.sweep.get() {
                         let sweep = sweep_ptr.as_ref();
-                        let sweep_size = mem::size_of_val(sweep);
+                        let sweep_size = sweep.total_size();
 
                         let next_ptr = sweep.next.get();
                         self.sweep.set(next_ptr);
@@ -249,7 +336,27 @@ impl Context {
     }
 
     unsafe fn allocate<T: Collect>(&self, t: T) -> NonNull<GcBox<T>> {
-        let alloc_size = mem::size_of::<GcBox<T>>();
+        self.add_allocated(mem::size_of::<GcBox<T>>());
+
+        let gc_box = GcBox {
+            flags: GcFlags::new(),
+            next: Cell::new(self.all.get()),
+            external_size: Cell::new(0),
+            value: UnsafeCell::new(t),
+        };
+        gc_box.flags.set_needs_trace(T::needs_trace());
+        let ptr = NonNull::new_unchecked(Box::into_raw(Box::new(gc_box)));
+        self.all.set(Some(static_gc_box(ptr)));
+        if self.phase.get() == Phase::Sweep && self.sweep_prev.get().is_none() {
+            self.sweep_prev.set(self.all.get());
+        }
+
+        ptr
+    }
+
+    // Counts newly allocated memory, waking the collector and adding to the allocation debt just
+    // like allocating a new object of the same size would.
+    fn add_allocated(&self, alloc_size: usize) {
         self.total_allocated
             .set(self.total_allocated.get() + alloc_size);
         if self.phase.get() == Phase::Sleep && self.total_allocated.get() > self.wakeup_total.get()
@@ -264,20 +371,106 @@ impl Context {
                     + alloc_size as f64 / self.parameters.timing_factor,
             );
         }
+    }
 
-        let gc_box = GcBox {
-            flags: GcFlags::new(),
-            next: Cell::new(self.all.get()),
-            value: UnsafeCell::new(t),
-        };
-        gc_box.flags.set_needs_trace(T::needs_trace());
-        let ptr = NonNull::new_unchecked(Box::into_raw(Box::new(gc_box)));
-        self.all.set(Some(static_gc_box(ptr)));
-        if self.phase.get() == Phase::Sweep && self.sweep_prev.get().is_none() {
-            self.sweep_prev.set(self.all.get());
+    unsafe fn set_external_size<T: Collect>(&self, ptr: NonNull<GcBox<T>>, size: usize) {
+        let gc_box = ptr.as_ref();
+        let old_size = gc_box.external_size.replace(size);
+        if size > old_size {
+            self.add_allocated(size - old_size);
+        } else {
+            self.total_allocated
+                .set(self.total_allocated.get() - (old_size - size));
         }
+    }
 
-        ptr
+    unsafe fn add_finalizer_queue<'gc, T: 'gc + FinalizerList>(&self, ptr: NonNull<GcBox<T>>) {
+        // Like objects registered late (see `finalizer_registered`), a queue created after the
+        // queues have been checked this cycle would be freed by the upcoming sweep while still in
+        // `finalizer_queues`, so we mark it and it is checked during the next cycle.
+        if self.phase.get() == Phase::Propagate && self.finalizers_checked.get() {
+            self.trace(ptr);
+        }
+        let ptr: NonNull<GcBox<dyn FinalizerList + 'gc>> = ptr;
+        self.finalizer_queues.borrow_mut().push(mem::transmute::<
+            NonNull<GcBox<dyn FinalizerList + 'gc>>,
+            NonNull<GcBox<dyn FinalizerList>>,
+        >(ptr));
+    }
+
+    fn finalizer_taken(&self) {
+        self.pending_finalization
+            .set(self.pending_finalization.get() - 1);
+    }
+
+    unsafe fn finalizer_registered<T: Collect>(&self, ptr: &T) {
+        // Registered objects are only checked once per cycle, at the end of the propagate phase.
+        // An object registered after that point would otherwise stay white and be freed during the
+        // upcoming sweep while still registered, so we mark it instead and it is checked during the
+        // next cycle.  Objects registered during the sweep phase need no special handling, they are
+        // either already black or were allocated before the sweep pointer.
+        if self.phase.get() == Phase::Propagate && self.finalizers_checked.get() {
+            ptr.trace(CollectionContext { context: self });
+        }
+    }
+
+    // Must only be called at the end of the propagate phase, once the gray queues are empty.
+    unsafe fn resurrect_finalizable(&self, cc: CollectionContext) {
+        let mut pending = self.pending_finalization.get();
+        self.finalizer_queues.borrow_mut().retain(|&ptr| {
+            let gc_box = ptr.as_ref();
+            if gc_box.flags.color() == GcColor::White {
+                // The queue itself is unreachable and will be freed during this sweep, along with
+                // every object that was waiting in it.
+                pending -= (*gc_box.value.get()).ready_len();
+                false
+            } else {
+                pending += (*gc_box.value.get()).resurrect_unreachable(cc);
+                true
+            }
+        });
+        self.pending_finalization.set(pending);
+    }
+
+    unsafe fn register_weak<'gc>(&self, weak: &(dyn CollectWeak + 'gc)) {
+        let owner = self
+            .tracing
+            .get()
+            .expect("weak values may only be registered while tracing a Gc pointer");
+        let owner_box = owner.as_ref();
+        debug_assert!({
+            let start = owner_box as *const GcBox<dyn Collect> as *const u8 as usize;
+            let end = start + mem::size_of_val(owner_box);
+            let weak_addr = weak as *const dyn CollectWeak as *const u8 as usize;
+            weak_addr >= start && weak_addr < end
+        });
+
+        if !owner_box.flags.weak_registered() {
+            owner_box.flags.set_weak_registered(true);
+            self.weak.borrow_mut().push((
+                owner,
+                mem::transmute::<NonNull<dyn CollectWeak + 'gc>, NonNull<dyn CollectWeak>>(
+                    NonNull::from(weak),
+                ),
+            ));
+        }
+    }
+
+    // Calls `CollectWeak::trace_weak` on every registered weak value, and returns whether any new
+    // object was marked as a result.
+    unsafe fn trace_weak(&self, cc: CollectionContext) -> bool {
+        for &(_, weak) in self.weak.borrow().iter() {
+            weak.as_ref().trace_weak(cc);
+        }
+        !self.gray.borrow().is_empty()
+    }
+
+    // Must only be called at the very end of the propagate phase, once every live object is black.
+    unsafe fn clear_weak(&self, cc: CollectionContext) {
+        for (owner, mut weak) in self.weak.borrow_mut().drain(..) {
+            owner.as_ref().flags.set_weak_registered(false);
+            weak.as_mut().clear_dead(cc);
+        }
     }
 
     unsafe fn write_barrier<T: Collect>(&self, ptr: NonNull<GcBox<T>>) {
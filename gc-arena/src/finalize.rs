@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+
+use crate::collect::Collect;
+use crate::context::{CollectionContext, MutationContext};
+use crate::gc::Gc;
+use crate::types::GcPointerSealed;
+
+/// Garbage collected pointer types which may be registered for finalization with a
+/// `FinalizerQueue`.  Implemented for `Gc` and `GcCell`, and cannot be implemented outside of this
+/// crate.
+pub trait GcPointer<'gc>: 'gc + Copy + Collect + GcPointerSealed {}
+
+/// A queue of objects which have been registered for finalization.
+///
+/// A `FinalizerQueue` does not keep the objects registered with it alive.  When the garbage
+/// collector determines that a registered object is otherwise unreachable, rather than freeing it
+/// during the sweep phase, it "resurrects" the object (along with everything reachable from it)
+/// and moves it into this queue's list of objects which are ready to be finalized.  Objects that
+/// are ready to be finalized are kept alive by the queue until they are taken out of it with
+/// `FinalizerQueue::take_ready`.
+///
+/// The garbage collector never runs any finalization code itself, it is up to the user of the
+/// arena to drain the queue at a time of their choosing, and to decide what to do if finalizing an
+/// object fails.  Once an object is taken from the queue it is no longer registered, and will be
+/// freed normally once it is unreachable again, unless it is registered again.  Finalization is
+/// not performed when the arena itself is dropped.
+///
+/// Objects become ready in the reverse order that they were registered in.  If a
+/// `FinalizerQueue` itself becomes unreachable, the objects registered with it will no longer be
+/// finalized.
+pub struct FinalizerQueue<'gc, T: GcPointer<'gc>>(Gc<'gc, FinalizerQueueState<T>>);
+
+impl<'gc, T: GcPointer<'gc>> Copy for FinalizerQueue<'gc, T> {}
+
+impl<'gc, T: GcPointer<'gc>> Clone for FinalizerQueue<'gc, T> {
+    fn clone(&self) -> FinalizerQueue<'gc, T> {
+        *self
+    }
+}
+
+impl<'gc, T: GcPointer<'gc>> Debug for FinalizerQueue<'gc, T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("FinalizerQueue")
+            .field("registered", &self.0.registered.borrow().len())
+            .field("ready", &self.0.ready.borrow().len())
+            .finish()
+    }
+}
+
+unsafe impl<'gc, T: GcPointer<'gc>> Collect for FinalizerQueue<'gc, T> {
+    fn trace(&self, cc: CollectionContext) {
+        self.0.trace(cc)
+    }
+}
+
+impl<'gc, T: GcPointer<'gc>> FinalizerQueue<'gc, T> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> FinalizerQueue<'gc, T> {
+        let queue = Gc::allocate(
+            mc,
+            FinalizerQueueState {
+                registered: RefCell::new(Vec::new()),
+                ready: RefCell::new(VecDeque::new()),
+            },
+        );
+        unsafe {
+            mc.add_finalizer_queue(queue.ptr);
+        }
+        FinalizerQueue(queue)
+    }
+
+    /// Registers the given object for finalization.  An object which is registered multiple times
+    /// will become ready for finalization once per registration.
+    pub fn register(self, mc: MutationContext<'gc, '_>, ptr: T) {
+        self.0.registered.borrow_mut().push(ptr);
+        unsafe {
+            mc.finalizer_registered(&ptr);
+        }
+    }
+
+    /// Removes and returns the next object that is ready to be finalized, if there is one.
+    pub fn take_ready(self, mc: MutationContext<'gc, '_>) -> Option<T> {
+        let ptr = self.0.ready.borrow_mut().pop_front();
+        if ptr.is_some() {
+            mc.finalizer_taken();
+        }
+        ptr
+    }
+
+    /// The number of objects that are ready to be finalized.
+    pub fn ready_len(self) -> usize {
+        self.0.ready.borrow().len()
+    }
+
+    /// The number of objects that are registered but not yet ready to be finalized.
+    pub fn registered_len(self) -> usize {
+        self.0.registered.borrow().len()
+    }
+}
+
+// The type erased interface the collector uses to process every live `FinalizerQueue` before
+// sweeping.
+pub(crate) trait FinalizerList: Collect {
+    // Moves every registered object that is still white into the ready list and traces it,
+    // returning the number of objects that were resurrected.
+    //
+    // Safety: must only be called at the end of the propagate phase, when every reachable object
+    // has been marked black and no white object has been freed yet.
+    unsafe fn resurrect_unreachable(&self, cc: CollectionContext) -> usize;
+
+    fn ready_len(&self) -> usize;
+}
+
+struct FinalizerQueueState<T> {
+    // Registered objects are weak, they are intentionally not traced.
+    registered: RefCell<Vec<T>>,
+    ready: RefCell<VecDeque<T>>,
+}
+
+unsafe impl<T: Collect> Collect for FinalizerQueueState<T> {
+    fn trace(&self, cc: CollectionContext) {
+        for ptr in self.ready.borrow().iter() {
+            ptr.trace(cc);
+        }
+    }
+}
+
+impl<'gc, T: GcPointer<'gc>> FinalizerList for FinalizerQueueState<T> {
+    unsafe fn resurrect_unreachable(&self, cc: CollectionContext) -> usize {
+        let mut registered = self.registered.borrow_mut();
+        let mut ready = self.ready.borrow_mut();
+        let ready_start = ready.len();
+
+        registered.retain(|ptr| {
+            if ptr.is_white() {
+                ptr.trace(cc);
+                ready.push_back(*ptr);
+                false
+            } else {
+                true
+            }
+        });
+
+        // Newly ready objects should be finalized in the reverse order of their registration.
+        let resurrected = ready.len() - ready_start;
+        for i in 0..resurrected / 2 {
+            ready.swap(ready_start + i, ready_start + resurrected - 1 - i);
+        }
+        resurrected
+    }
+
+    fn ready_len(&self) -> usize {
+        self.ready.borrow().len()
+    }
+}
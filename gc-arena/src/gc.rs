@@ -5,7 +5,8 @@ use std::ptr::NonNull;
 
 use crate::collect::Collect;
 use crate::context::{CollectionContext, MutationContext};
-use crate::types::{GcBox, Invariant};
+use crate::finalize::GcPointer;
+use crate::types::{GcBox, GcColor, GcPointerSealed, Invariant};
 
 /// A garbage collected pointer to a type T.  Implements Copy, and is implemented as a plain machine
 /// pointer.  You can only allocate `Gc` pointers through an `Allocator` inside an arena type, and
@@ -66,6 +67,16 @@ impl<'gc, T: 'gc + Collect> Gc<'gc, T> {
         }
     }
 
+    /// Records that the pointed to object owns `size` bytes of memory outside of its own allocation,
+    /// such as the contents of a `Vec`, replacing any previously recorded size.  This memory is
+    /// counted in the arena's total allocation until the object is freed, and affects collector
+    /// pacing just like allocating new objects.
+    pub fn set_external_size(mc: MutationContext<'gc, '_>, gc: Self, size: usize) {
+        unsafe {
+            mc.set_external_size(gc.ptr, size);
+        }
+    }
+
     pub fn ptr_eq(this: Gc<'gc, T>, other: Gc<'gc, T>) -> bool {
         Gc::as_ptr(this) == Gc::as_ptr(other)
     }
@@ -73,4 +84,19 @@ impl<'gc, T: 'gc + Collect> Gc<'gc, T> {
     pub fn as_ptr(gc: Gc<'gc, T>) -> *const T {
         unsafe { gc.ptr.as_ref().value.get() }
     }
+
+    /// Returns whether the pointed to object has been reached so far during the current
+    /// collection.  Within `CollectWeak::clear_dead`, an object that is not marked is dead and is
+    /// about to be freed.
+    pub fn is_marked(gc: Gc<'gc, T>, _cc: CollectionContext) -> bool {
+        unsafe { !gc.is_white() }
+    }
 }
+
+impl<'gc, T: 'gc + Collect> GcPointerSealed for Gc<'gc, T> {
+    unsafe fn is_white(&self) -> bool {
+        self.ptr.as_ref().flags.color() == GcColor::White
+    }
+}
+
+impl<'gc, T: 'gc + Collect> GcPointer<'gc> for Gc<'gc, T> {}
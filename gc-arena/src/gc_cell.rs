@@ -3,7 +3,9 @@ use std::fmt::{self, Debug};
 
 use crate::collect::Collect;
 use crate::context::{CollectionContext, MutationContext};
+use crate::finalize::GcPointer;
 use crate::gc::Gc;
+use crate::types::GcPointerSealed;
 
 /// A garbage collected pointer to a type T that may be safely mutated.  When a type that may hold
 /// `Gc` pointers is mutated, it may adopt new `Gc` pointers, and in order for this to be safe this
@@ -44,6 +46,11 @@ impl<'gc, T: 'gc + Collect> GcCell<'gc, T> {
         ))
     }
 
+    /// Records external memory owned by the pointed to object, see `Gc::set_external_size`.
+    pub fn set_external_size(self, mc: MutationContext<'gc, '_>, size: usize) {
+        Gc::set_external_size(mc, self.0, size)
+    }
+
     pub fn ptr_eq(this: GcCell<'gc, T>, other: GcCell<'gc, T>) -> bool {
         this.as_ptr() == other.as_ptr()
     }
@@ -52,6 +59,12 @@ impl<'gc, T: 'gc + Collect> GcCell<'gc, T> {
         self.0.cell.as_ptr()
     }
 
+    /// Returns whether the pointed to object has been reached so far during the current
+    /// collection, see `Gc::is_marked`.
+    pub fn is_marked(self, cc: CollectionContext) -> bool {
+        Gc::is_marked(self.0, cc)
+    }
+
     pub fn read<'a>(&'a self) -> Ref<'a, T> {
         self.0.cell.borrow()
     }
@@ -76,6 +89,14 @@ impl<'gc, T: 'gc + Collect> GcCell<'gc, T> {
     }
 }
 
+impl<'gc, T: 'gc + Collect> GcPointerSealed for GcCell<'gc, T> {
+    unsafe fn is_white(&self) -> bool {
+        self.0.is_white()
+    }
+}
+
+impl<'gc, T: 'gc + Collect> GcPointer<'gc> for GcCell<'gc, T> {}
+
 struct GcRefCell<T: Collect> {
     cell: RefCell<T>,
 }
@@ -5,14 +5,18 @@ mod arena;
 mod collect;
 mod collect_impl;
 mod context;
+mod finalize;
 mod gc;
 mod gc_cell;
 mod static_collect;
 mod types;
+mod weak;
 
 pub use self::arena::*;
 pub use self::collect::*;
 pub use self::context::*;
+pub use self::finalize::*;
 pub use self::gc::*;
 pub use self::gc_cell::*;
 pub use self::static_collect::*;
+pub use self::weak::*;
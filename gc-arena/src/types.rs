@@ -14,9 +14,19 @@ pub(crate) enum GcColor {
 pub(crate) struct GcBox<T: Collect + ?Sized> {
     pub(crate) flags: GcFlags,
     pub(crate) next: Cell<Option<NonNull<GcBox<Collect>>>>,
+    // The number of bytes of memory owned by the value outside of this box, which are counted as
+    // part of the size of this box.
+    pub(crate) external_size: Cell<usize>,
     pub(crate) value: UnsafeCell<T>,
 }
 
+impl<T: Collect + ?Sized> GcBox<T> {
+    // The size of this box, including any external memory owned by its value
+    pub(crate) fn total_size(&self) -> usize {
+        std::mem::size_of_val(self) + self.external_size.get()
+    }
+}
+
 pub(crate) struct GcFlags(Cell<u8>);
 
 impl GcFlags {
@@ -52,6 +62,25 @@ impl GcFlags {
         self.0
             .set((self.0.get() & !0x4) | if needs_trace { 0x4 } else { 0x0 });
     }
+
+    pub(crate) fn weak_registered(&self) -> bool {
+        self.0.get() & 0x8 != 0x0
+    }
+
+    pub(crate) fn set_weak_registered(&self, weak_registered: bool) {
+        self.0
+            .set((self.0.get() & !0x8) | if weak_registered { 0x8 } else { 0x0 });
+    }
+}
+
+// Implemented by the garbage collected pointer types.  This trait is not nameable outside of this
+// crate, which seals `GcPointer` and hides this method from users.
+pub trait GcPointerSealed {
+    // Returns whether the pointed to object is white, meaning that it has not been reached during
+    // the current collection cycle.
+    //
+    // Safety: the pointed to object must not have been freed.
+    unsafe fn is_white(&self) -> bool;
 }
 
 // Phantom type that holds a lifetime and ensures that it is invariant.
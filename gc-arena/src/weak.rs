@@ -0,0 +1,28 @@
+use crate::context::CollectionContext;
+
+/// A trait for types that hold `Gc` pointers which should not, by themselves, keep the objects they
+/// point to alive, such as caches keyed by objects or weak tables.
+///
+/// A type implementing `CollectWeak` simply skips tracing its weakly held pointers inside
+/// `Collect::trace`, and instead registers itself with `CollectionContext::register_weak`.  Once
+/// every strongly reachable object has been marked, the collector calls `trace_weak` so that
+/// conditionally reachable pointers (like the values of an ephemeron table whose keys are alive)
+/// may be traced, and then `clear_dead` so that every pointer to an object which is about to be
+/// freed may be removed.
+///
+/// # Safety
+///
+/// After `clear_dead` returns, *no* pointer to an object that was found to be dead may remain,
+/// otherwise it will be dangling once the sweep phase frees that object.
+pub unsafe trait CollectWeak {
+    /// Called each time marking runs out of objects to trace, until a call to `trace_weak` on every
+    /// registered value no longer causes any new object to be marked.  Any pointer may be traced
+    /// here, and use `Gc::is_marked` or `GcCell::is_marked` to find out which of the weakly held
+    /// objects have been reached so far.
+    #[inline]
+    fn trace_weak(&self, _cc: CollectionContext) {}
+
+    /// Called at the end of marking, right before the sweep phase.  *Must* remove every held
+    /// pointer for which `Gc::is_marked` or `GcCell::is_marked` returns false.
+    fn clear_dead(&mut self, cc: CollectionContext);
+}
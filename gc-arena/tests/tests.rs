@@ -3,7 +3,10 @@ use std::rc::Rc;
 
 use rand::distributions::Distribution;
 
-use gc_arena::{make_arena, unsafe_empty_collect, ArenaParameters, Collect, Gc, GcCell};
+use gc_arena::{
+    make_arena, unsafe_empty_collect, ArenaParameters, Collect, CollectWeak, CollectionContext,
+    FinalizerQueue, Gc, GcCell,
+};
 
 #[test]
 fn simple_allocation() {
@@ -130,6 +133,344 @@ fn all_garbage_collected() {
     assert_eq!(Rc::strong_count(&r.0), 1);
 }
 
+#[test]
+fn external_size() {
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc>(GcCell<'gc, Option<Gc<'gc, Vec<u8>>>>);
+    make_arena!(TestArena, TestRoot);
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| {
+        TestRoot(GcCell::allocate(mc, None))
+    });
+    let base = arena.total_allocated();
+
+    arena.mutate(|mc, root| {
+        let v = Gc::allocate(mc, vec![0; 1000]);
+        Gc::set_external_size(mc, v, 1000);
+        *root.0.write(mc) = Some(v);
+        assert!(mc.total_allocated() >= base + 1000);
+    });
+    arena.collect_all();
+    arena.collect_all();
+    assert!(arena.total_allocated() >= base + 1000);
+
+    arena.mutate(|mc, root| {
+        let v = root.0.read().unwrap();
+        Gc::set_external_size(mc, v, 10);
+        assert!(mc.total_allocated() < base + 1000);
+        *root.0.write(mc) = None;
+    });
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(arena.total_allocated(), base);
+}
+
+#[test]
+fn finalization() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct Object<'gc> {
+        id: i32,
+        child: Gc<'gc, RefCounter>,
+    }
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        objects: GcCell<'gc, Vec<GcCell<'gc, Object<'gc>>>>,
+        finalizers: FinalizerQueue<'gc, GcCell<'gc, Object<'gc>>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        objects: GcCell::allocate(mc, Vec::new()),
+        finalizers: FinalizerQueue::new(mc),
+    });
+
+    arena.mutate(|mc, root| {
+        let mut objects = root.objects.write(mc);
+        for id in 0..10 {
+            let object = GcCell::allocate(
+                mc,
+                Object {
+                    id,
+                    child: Gc::allocate(mc, r.clone()),
+                },
+            );
+            root.finalizers.register(mc, object);
+            objects.push(object);
+        }
+    });
+
+    arena.collect_all();
+    assert_eq!(arena.pending_finalization(), 0);
+    assert_eq!(Rc::strong_count(&r.0), 11);
+
+    arena.mutate(|mc, root| {
+        root.objects.write(mc).retain(|o| o.read().id % 2 == 0);
+    });
+
+    // Unreachable objects are resurrected along with everything they point to.
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(arena.pending_finalization(), 5);
+    assert_eq!(Rc::strong_count(&r.0), 11);
+
+    arena.mutate(|mc, root| {
+        assert_eq!(root.finalizers.registered_len(), 5);
+        let mut finalized = Vec::new();
+        while let Some(object) = root.finalizers.take_ready(mc) {
+            finalized.push(object.read().id);
+        }
+        assert_eq!(finalized, vec![9, 7, 5, 3, 1]);
+    });
+    assert_eq!(arena.pending_finalization(), 0);
+
+    // Once taken from the queue, objects are freed normally.
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 6);
+
+    arena.mutate(|mc, root| {
+        root.objects.write(mc).clear();
+    });
+    arena.collect_all();
+    assert_eq!(arena.pending_finalization(), 5);
+    drop(arena);
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[test]
+fn unreachable_finalizer_queue() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc>(GcCell<'gc, Option<FinalizerQueue<'gc, Gc<'gc, RefCounter>>>>);
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| {
+        TestRoot(GcCell::allocate(mc, Some(FinalizerQueue::new(mc))))
+    });
+
+    arena.mutate(|mc, root| {
+        let queue = root.0.read().unwrap();
+        for _ in 0..10 {
+            queue.register(mc, Gc::allocate(mc, r.clone()));
+        }
+    });
+    arena.collect_all();
+    assert_eq!(arena.pending_finalization(), 10);
+    assert_eq!(Rc::strong_count(&r.0), 11);
+
+    arena.mutate(|mc, root| {
+        *root.0.write(mc) = None;
+    });
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(arena.pending_finalization(), 0);
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[test]
+fn register_during_collection() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        finalizers: FinalizerQueue<'gc, Gc<'gc, Vec<Gc<'gc, RefCounter>>>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        finalizers: FinalizerQueue::new(mc),
+    });
+
+    // Register unreachable objects while the collector makes incremental progress, so that objects
+    // are registered in every phase, including while the objects resurrected for finalization are
+    // still being propagated.  Every one of them must become ready exactly once, and none of them
+    // may be freed while still registered.
+    let mut finalized = 0;
+    for _ in 0..1_000 {
+        arena.mutate(|mc, root| {
+            let children = (0..32).map(|_| Gc::allocate(mc, r.clone())).collect();
+            root.finalizers.register(mc, Gc::allocate(mc, children));
+            while let Some(object) = root.finalizers.take_ready(mc) {
+                assert_eq!(object.len(), 32);
+                finalized += 1;
+            }
+        });
+        arena.collect_debt();
+    }
+
+    arena.collect_all();
+    arena.mutate(|mc, root| {
+        while let Some(object) = root.finalizers.take_ready(mc) {
+            assert_eq!(object.len(), 32);
+            finalized += 1;
+        }
+    });
+    assert_eq!(finalized, 1_000);
+
+    arena.collect_all();
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[test]
+fn queue_created_during_collection() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    type Queue<'gc> = FinalizerQueue<'gc, Gc<'gc, Vec<Gc<'gc, RefCounter>>>>;
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        finalizers: Queue<'gc>,
+        queues: GcCell<'gc, Vec<Queue<'gc>>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        finalizers: FinalizerQueue::new(mc),
+        queues: GcCell::allocate(mc, Vec::new()),
+    });
+
+    // Create queues while the collector makes incremental progress, so that some of them are
+    // created after the finalizer queues have been checked in the current cycle.  Unreachable
+    // queues must not be freed while the collector still refers to them, and every queue must be
+    // counted exactly once.
+    for i in 0..1_000 {
+        arena.mutate(|mc, root| {
+            let children: Vec<_> = (0..32).map(|_| Gc::allocate(mc, r.clone())).collect();
+            root.finalizers
+                .register(mc, Gc::allocate(mc, children.clone()));
+            let queue = FinalizerQueue::new(mc);
+            queue.register(mc, Gc::allocate(mc, children));
+            if i % 2 == 0 {
+                root.queues.write(mc).push(queue);
+            }
+            while root.finalizers.take_ready(mc).is_some() {}
+        });
+        arena.collect_debt();
+    }
+
+    // Every object registered with a reachable queue is now ready, and dropping those queues must
+    // discard them from the total exactly once.
+    arena.collect_all();
+    arena.collect_all();
+    arena.mutate(|mc, root| while root.finalizers.take_ready(mc).is_some() {});
+    assert_eq!(arena.pending_finalization(), 500);
+    arena.mutate(|mc, root| root.queues.write(mc).clear());
+    arena.collect_all();
+    arena.collect_all();
+    assert_eq!(arena.pending_finalization(), 0);
+    assert_eq!(Rc::strong_count(&r.0), 1);
+}
+
+#[test]
+fn ephemerons() {
+    #[derive(Clone)]
+    struct RefCounter(Rc<()>);
+    unsafe_empty_collect!(RefCounter);
+
+    // A map whose values are only kept alive while their keys are.
+    struct EphemeronMap<'gc>(Vec<(Gc<'gc, RefCounter>, Gc<'gc, RefCounter>)>);
+
+    unsafe impl<'gc> Collect for EphemeronMap<'gc> {
+        fn trace(&self, cc: CollectionContext) {
+            unsafe {
+                cc.register_weak(self);
+            }
+            self.trace_weak(cc);
+        }
+    }
+
+    unsafe impl<'gc> CollectWeak for EphemeronMap<'gc> {
+        fn trace_weak(&self, cc: CollectionContext) {
+            for (key, value) in &self.0 {
+                if Gc::is_marked(*key, cc) {
+                    value.trace(cc);
+                }
+            }
+        }
+
+        fn clear_dead(&mut self, cc: CollectionContext) {
+            self.0
+                .retain(|(key, value)| Gc::is_marked(*key, cc) && Gc::is_marked(*value, cc));
+        }
+    }
+
+    #[derive(Collect)]
+    #[collect(empty_drop)]
+    struct TestRoot<'gc> {
+        keys: GcCell<'gc, Vec<Gc<'gc, RefCounter>>>,
+        map: GcCell<'gc, EphemeronMap<'gc>>,
+    }
+    make_arena!(TestArena, TestRoot);
+
+    let r = RefCounter(Rc::new(()));
+
+    let mut arena = TestArena::new(ArenaParameters::default(), |mc| TestRoot {
+        keys: GcCell::allocate(mc, Vec::new()),
+        map: GcCell::allocate(mc, EphemeronMap(Vec::new())),
+    });
+
+    arena.mutate(|mc, root| {
+        let mut keys = root.keys.write(mc);
+        let mut map = root.map.write(mc);
+        for _ in 0..5 {
+            let key = Gc::allocate(mc, r.clone());
+            keys.push(key);
+            map.0.push((key, Gc::allocate(mc, r.clone())));
+        }
+
+        // Entries may form chains, where the value of one entry is the key of another.
+        let mut key = keys[0];
+        for _ in 0..5 {
+            let value = Gc::allocate(mc, r.clone());
+            map.0.push((key, value));
+            key = value;
+        }
+    });
+
+    arena.collect_all();
+    assert_eq!(arena.mutate(|_, root| root.map.read().0.len()), 10);
+    assert_eq!(Rc::strong_count(&r.0), 16);
+
+    arena.mutate(|mc, root| {
+        root.keys.write(mc).truncate(3);
+    });
+    arena.collect_all();
+    assert_eq!(arena.mutate(|_, root| root.map.read().0.len()), 8);
+    assert_eq!(Rc::strong_count(&r.0), 12);
+
+    arena.mutate(|mc, root| {
+        root.keys.write(mc).remove(0);
+    });
+    arena.collect_all();
+    assert_eq!(arena.mutate(|_, root| root.map.read().0.len()), 2);
+    assert_eq!(Rc::strong_count(&r.0), 5);
+}
+
 #[test]
 fn derive_collect() {
     #[allow(unused)]
@@ -143,6 +143,14 @@ macro_rules! make_sequencable_arena {
                     self.0.total_allocated()
                 }
 
+                /// Returns the total number of objects across all `FinalizerQueue`s that are ready
+                /// to be finalized.
+                #[allow(unused)]
+                #[inline]
+                $innervis fn pending_finalization(&self) -> usize {
+                    self.0.pending_finalization()
+                }
+
                 /// Returns the current "allocation debt", measured in bytes.  Allocation debt rises
                 /// as allocation takes place based on the `ArenaParameters` set for this arena.
                 #[allow(unused)]
@@ -209,6 +217,12 @@ macro_rules! make_sequencable_arena {
                     self.0.total_allocated()
                 }
 
+                #[allow(unused)]
+                #[inline]
+                $innervis fn pending_finalization(&self) -> usize {
+                    self.0.pending_finalization()
+                }
+
                 #[allow(unused)]
                 #[inline]
                 $innervis fn allocation_debt(&self) -> f64 {
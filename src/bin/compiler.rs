@@ -5,18 +5,18 @@ use std::env;
 use std::error::Error as StdError;
 use std::fs::File;
 
-use luster::{compile, io, Lua, StaticError};
+use luster::{compile_named, io, Lua, StaticError};
 
 fn main() -> Result<(), Box<StdError>> {
     let mut args = env::args();
     args.next();
-    let file = io::buffered_read(File::open(
-        args.next().ok_or_else(|| "no file argument given")?,
-    )?)?;
+    let file_name = args.next().ok_or_else(|| "no file argument given")?;
+    let file = io::buffered_read(File::open(&file_name)?)?;
 
     let mut lua = Lua::new();
     lua.mutate(|mc, root| -> Result<(), StaticError> {
-        let function = compile(mc, root.interned_strings, file).map_err(|e| e.to_static())?;
+        let function = compile_named(mc, root.interned_strings, file_name.as_bytes(), file)
+            .map_err(|e| e.to_static())?;
         println!("output: {:#?}", function);
         Ok(())
     })?;
@@ -1,5 +1,6 @@
 use std::error::Error as StdError;
 use std::fs::File;
+use std::process;
 use std::vec::Vec;
 
 use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg};
@@ -7,7 +8,8 @@ use rustyline::Editor;
 
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
 use luster::{
-    compile, io, Closure, Error, Function, Lua, ParserError, StaticError, ThreadSequence,
+    compile_named, io, Closure, Error, Function, Lua, ParserError, ParserErrorKind, StaticError,
+    ThreadSequence,
 };
 
 fn run_repl(lua: &mut Lua) {
@@ -27,15 +29,18 @@ fn run_repl(lua: &mut Lua) {
 
             match lua.sequence(move |root| {
                 sequence::from_fn_with(root, move |mc, root| {
-                    let result = compile(mc, root.interned_strings, line_clone.as_bytes());
+                    let result =
+                        compile_named(mc, root.interned_strings, b"stdin", line_clone.as_bytes());
                     let result = match result {
                         Ok(res) => Ok(res),
-                        err @ Err(Error::ParserError(ParserError::EndOfStream { expected: _ })) => {
-                            err
-                        }
-                        Err(_) => compile(
+                        err @ Err(Error::ParserError(ParserError {
+                            kind: ParserErrorKind::EndOfStream { .. },
+                            ..
+                        })) => err,
+                        Err(_) => compile_named(
                             mc,
                             root.interned_strings,
+                            b"stdin",
                             (String::new() + "return " + &line_clone).as_bytes(),
                         ),
                     };
@@ -62,7 +67,10 @@ fn run_repl(lua: &mut Lua) {
                 })
                 .boxed()
             }) {
-                err @ Err(StaticError::ParserError(ParserError::EndOfStream { expected: _ })) => {
+                err @ Err(StaticError::ParserError(ParserError {
+                    kind: ParserErrorKind::EndOfStream { .. },
+                    ..
+                })) => {
                     match line.chars().last() {
                         Some(c) => {
                             if c == '\n' {
@@ -112,13 +120,14 @@ fn main() -> Result<(), Box<StdError>> {
         return Ok(());
     }
 
-    let file = io::buffered_read(File::open(matches.value_of("file").unwrap())?)?;
+    let file_name = matches.value_of("file").unwrap().to_owned();
+    let file = io::buffered_read(File::open(&file_name)?)?;
 
-    lua.sequence(|root| {
-        sequence::from_fn_with(root, |mc, root| {
+    let result = lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
             Ok(Closure::new(
                 mc,
-                compile(mc, root.interned_strings, file)?,
+                compile_named(mc, root.interned_strings, file_name.as_bytes(), file)?,
                 Some(root.globals),
             )?)
         })
@@ -133,7 +142,11 @@ fn main() -> Result<(), Box<StdError>> {
         .map_ok(|_| ())
         .map_err(|e| e.to_static())
         .boxed()
-    })?;
+    });
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 
     if matches.is_present("repl") {
         run_repl(&mut lua);
@@ -1,20 +1,71 @@
 use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
 
 use gc_arena::{Collect, Gc, MutationContext, StaticCollect};
 use gc_sequence::{Sequence, SequenceExt};
 
 use crate::{Error, Function, Value};
 
+/// A buffer of values which holds the arguments to a callback, and which the callback then fills
+/// with its results and returns.
+///
+/// A `Thread` keeps a pool of buffers that it hands out to callbacks and takes back once they have
+/// returned, so as long as callbacks return the buffer they were given, calling a callback does not
+/// allocate.
+// Safe, does not implement drop
+#[derive(Debug, Default, PartialEq, Collect)]
+#[collect(unsafe_drop)]
+pub struct ValueBuffer<'gc>(Vec<Value<'gc>>);
+
+impl<'gc> ValueBuffer<'gc> {
+    /// Creates a new, empty buffer.  Callbacks should prefer returning the buffer they were given,
+    /// since a new buffer must allocate.
+    pub fn new() -> ValueBuffer<'gc> {
+        ValueBuffer(Vec::new())
+    }
+
+    /// Replaces the contents of this buffer with the given values.
+    pub fn with_values(mut self, values: &[Value<'gc>]) -> ValueBuffer<'gc> {
+        self.0.clear();
+        self.0.extend_from_slice(values);
+        self
+    }
+
+    pub fn into_vec(self) -> Vec<Value<'gc>> {
+        self.0
+    }
+}
+
+impl<'gc> From<Vec<Value<'gc>>> for ValueBuffer<'gc> {
+    fn from(values: Vec<Value<'gc>>) -> ValueBuffer<'gc> {
+        ValueBuffer(values)
+    }
+}
+
+impl<'gc> Deref for ValueBuffer<'gc> {
+    type Target = Vec<Value<'gc>>;
+
+    fn deref(&self) -> &Vec<Value<'gc>> {
+        &self.0
+    }
+}
+
+impl<'gc> DerefMut for ValueBuffer<'gc> {
+    fn deref_mut(&mut self) -> &mut Vec<Value<'gc>> {
+        &mut self.0
+    }
+}
+
 // Safe, does not implement drop
 #[derive(Collect)]
 #[collect(unsafe_drop)]
 pub enum CallbackResult<'gc> {
-    Return(Vec<Value<'gc>>),
-    Yield(Vec<Value<'gc>>),
+    Return(ValueBuffer<'gc>),
+    Yield(ValueBuffer<'gc>),
     TailCall {
         function: Function<'gc>,
-        args: Vec<Value<'gc>>,
+        args: ValueBuffer<'gc>,
         continuation: Continuation<'gc>,
     },
 }
@@ -25,7 +76,7 @@ pub enum CallbackReturn<'gc> {
 }
 
 pub trait ContinuationFn<'gc>: Collect {
-    fn call(self: Box<Self>, res: Result<Vec<Value<'gc>>, Error<'gc>>) -> CallbackReturn<'gc>;
+    fn call(self: Box<Self>, res: Result<ValueBuffer<'gc>, Error<'gc>>) -> CallbackReturn<'gc>;
 }
 
 // Safe, does not implement drop
@@ -36,7 +87,7 @@ pub struct Continuation<'gc>(Box<dyn ContinuationFn<'gc> + 'gc>);
 impl<'gc> Continuation<'gc> {
     pub fn new<F>(cont: F) -> Continuation<'gc>
     where
-        F: 'static + FnOnce(Result<Vec<Value<'gc>>, Error<'gc>>) -> CallbackReturn<'gc>,
+        F: 'static + FnOnce(Result<ValueBuffer<'gc>, Error<'gc>>) -> CallbackReturn<'gc>,
     {
         #[derive(Collect)]
         #[collect(require_static)]
@@ -44,11 +95,11 @@ impl<'gc> Continuation<'gc> {
 
         impl<'gc, F> ContinuationFn<'gc> for StaticContinuationFn<F>
         where
-            F: 'static + FnOnce(Result<Vec<Value<'gc>>, Error<'gc>>) -> CallbackReturn<'gc>,
+            F: 'static + FnOnce(Result<ValueBuffer<'gc>, Error<'gc>>) -> CallbackReturn<'gc>,
         {
             fn call(
                 self: Box<Self>,
-                res: Result<Vec<Value<'gc>>, Error<'gc>>,
+                res: Result<ValueBuffer<'gc>, Error<'gc>>,
             ) -> CallbackReturn<'gc> {
                 self.0(res)
             }
@@ -60,7 +111,7 @@ impl<'gc> Continuation<'gc> {
     pub fn new_with<C, F>(context: C, continuation: F) -> Continuation<'gc>
     where
         C: 'gc + Collect,
-        F: 'static + FnOnce(C, Result<Vec<Value<'gc>>, Error<'gc>>) -> CallbackReturn<'gc>,
+        F: 'static + FnOnce(C, Result<ValueBuffer<'gc>, Error<'gc>>) -> CallbackReturn<'gc>,
     {
         // Safe, does not implement drop
         #[derive(Collect)]
@@ -70,11 +121,11 @@ impl<'gc> Continuation<'gc> {
         impl<'gc, C, F> ContinuationFn<'gc> for ContextContinuationFn<C, F>
         where
             C: 'gc + Collect,
-            F: 'static + FnOnce(C, Result<Vec<Value<'gc>>, Error<'gc>>) -> CallbackReturn<'gc>,
+            F: 'static + FnOnce(C, Result<ValueBuffer<'gc>, Error<'gc>>) -> CallbackReturn<'gc>,
         {
             fn call(
                 self: Box<Self>,
-                res: Result<Vec<Value<'gc>>, Error<'gc>>,
+                res: Result<ValueBuffer<'gc>, Error<'gc>>,
             ) -> CallbackReturn<'gc> {
                 (self.1).0(self.0, res)
             }
@@ -89,7 +140,7 @@ impl<'gc> Continuation<'gc> {
     pub fn new_immediate<F>(cont: F) -> Continuation<'gc>
     where
         F: 'static
-            + FnOnce(Result<Vec<Value<'gc>>, Error<'gc>>) -> Result<CallbackResult<'gc>, Error<'gc>>,
+            + FnOnce(Result<ValueBuffer<'gc>, Error<'gc>>) -> Result<CallbackResult<'gc>, Error<'gc>>,
     {
         Continuation::new(move |res| CallbackReturn::Immediate(cont(res)))
     }
@@ -100,7 +151,7 @@ impl<'gc> Continuation<'gc> {
         F: 'static
             + FnOnce(
                 C,
-                Result<Vec<Value<'gc>>, Error<'gc>>,
+                Result<ValueBuffer<'gc>, Error<'gc>>,
             ) -> Result<CallbackResult<'gc>, Error<'gc>>,
     {
         Continuation::new_with(context, move |context, res| {
@@ -111,7 +162,7 @@ impl<'gc> Continuation<'gc> {
     pub fn new_sequence<S, F>(cont: F) -> Continuation<'gc>
     where
         S: 'gc + Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>>,
-        F: 'static + FnOnce(Result<Vec<Value<'gc>>, Error<'gc>>) -> Result<S, Error<'gc>>,
+        F: 'static + FnOnce(Result<ValueBuffer<'gc>, Error<'gc>>) -> Result<S, Error<'gc>>,
     {
         Continuation::new(move |res| match cont(res) {
             Ok(seq) => CallbackReturn::Sequence(seq.boxed()),
@@ -123,7 +174,7 @@ impl<'gc> Continuation<'gc> {
     where
         C: 'gc + Collect,
         S: 'gc + Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>>,
-        F: 'static + FnOnce(C, Result<Vec<Value<'gc>>, Error<'gc>>) -> Result<S, Error<'gc>>,
+        F: 'static + FnOnce(C, Result<ValueBuffer<'gc>, Error<'gc>>) -> Result<S, Error<'gc>>,
     {
         Continuation::new_with(context, move |context, res| {
             match continuation(context, res) {
@@ -133,13 +184,13 @@ impl<'gc> Continuation<'gc> {
         })
     }
 
-    pub fn call(self, res: Result<Vec<Value<'gc>>, Error<'gc>>) -> CallbackReturn<'gc> {
+    pub fn call(self, res: Result<ValueBuffer<'gc>, Error<'gc>>) -> CallbackReturn<'gc> {
         self.0.call(res)
     }
 }
 
 pub trait CallbackFn<'gc>: Collect {
-    fn call(&self, res: Vec<Value<'gc>>) -> CallbackReturn<'gc>;
+    fn call(&self, res: ValueBuffer<'gc>) -> CallbackReturn<'gc>;
 }
 
 #[derive(Clone, Copy, Collect)]
@@ -149,7 +200,7 @@ pub struct Callback<'gc>(pub Gc<'gc, Box<dyn CallbackFn<'gc> + 'gc>>);
 impl<'gc> Callback<'gc> {
     pub fn new<F>(mc: MutationContext<'gc, '_>, f: F) -> Callback<'gc>
     where
-        F: 'static + Fn(Vec<Value<'gc>>) -> CallbackReturn<'gc>,
+        F: 'static + Fn(ValueBuffer<'gc>) -> CallbackReturn<'gc>,
     {
         #[derive(Collect)]
         #[collect(require_static)]
@@ -157,9 +208,9 @@ impl<'gc> Callback<'gc> {
 
         impl<'gc, F> CallbackFn<'gc> for StaticCallbackFn<F>
         where
-            F: 'static + Fn(Vec<Value<'gc>>) -> CallbackReturn<'gc>,
+            F: 'static + Fn(ValueBuffer<'gc>) -> CallbackReturn<'gc>,
         {
-            fn call(&self, res: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+            fn call(&self, res: ValueBuffer<'gc>) -> CallbackReturn<'gc> {
                 self.0(res)
             }
         }
@@ -170,7 +221,7 @@ impl<'gc> Callback<'gc> {
     pub fn new_with<C, F>(mc: MutationContext<'gc, '_>, c: C, f: F) -> Callback<'gc>
     where
         C: 'gc + Collect,
-        F: 'static + Fn(&C, Vec<Value<'gc>>) -> CallbackReturn<'gc>,
+        F: 'static + Fn(&C, ValueBuffer<'gc>) -> CallbackReturn<'gc>,
     {
         #[derive(Collect)]
         #[collect(empty_drop)]
@@ -179,9 +230,9 @@ impl<'gc> Callback<'gc> {
         impl<'gc, C, F> CallbackFn<'gc> for ContextCallbackFn<C, F>
         where
             C: 'gc + Collect,
-            F: 'static + Fn(&C, Vec<Value<'gc>>) -> CallbackReturn<'gc>,
+            F: 'static + Fn(&C, ValueBuffer<'gc>) -> CallbackReturn<'gc>,
         {
-            fn call(&self, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+            fn call(&self, args: ValueBuffer<'gc>) -> CallbackReturn<'gc> {
                 (self.1).0(&self.0, args)
             }
         }
@@ -194,7 +245,7 @@ impl<'gc> Callback<'gc> {
 
     pub fn new_immediate<F>(mc: MutationContext<'gc, '_>, f: F) -> Callback<'gc>
     where
-        F: 'static + Fn(Vec<Value<'gc>>) -> Result<CallbackResult<'gc>, Error<'gc>>,
+        F: 'static + Fn(ValueBuffer<'gc>) -> Result<CallbackResult<'gc>, Error<'gc>>,
     {
         Callback::new(mc, move |res| CallbackReturn::Immediate(f(res)))
     }
@@ -202,7 +253,7 @@ impl<'gc> Callback<'gc> {
     pub fn new_immediate_with<C, F>(mc: MutationContext<'gc, '_>, c: C, f: F) -> Callback<'gc>
     where
         C: 'gc + Collect,
-        F: 'static + Fn(&C, Vec<Value<'gc>>) -> Result<CallbackResult<'gc>, Error<'gc>>,
+        F: 'static + Fn(&C, ValueBuffer<'gc>) -> Result<CallbackResult<'gc>, Error<'gc>>,
     {
         Callback::new_with(mc, c, move |c, res| CallbackReturn::Immediate(f(c, res)))
     }
@@ -210,7 +261,7 @@ impl<'gc> Callback<'gc> {
     pub fn new_sequence<S, F>(mc: MutationContext<'gc, '_>, f: F) -> Callback<'gc>
     where
         S: 'gc + Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>>,
-        F: 'static + Fn(Vec<Value<'gc>>) -> Result<S, Error<'gc>>,
+        F: 'static + Fn(ValueBuffer<'gc>) -> Result<S, Error<'gc>>,
     {
         Callback::new(mc, move |res| match f(res) {
             Ok(seq) => CallbackReturn::Sequence(seq.boxed()),
@@ -222,7 +273,7 @@ impl<'gc> Callback<'gc> {
     where
         C: 'gc + Collect,
         S: 'gc + Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>>,
-        F: 'static + Fn(&C, Vec<Value<'gc>>) -> Result<S, Error<'gc>>,
+        F: 'static + Fn(&C, ValueBuffer<'gc>) -> Result<S, Error<'gc>>,
     {
         Callback::new_with(mc, c, move |c, res| match f(c, res) {
             Ok(seq) => CallbackReturn::Sequence(seq.boxed()),
@@ -230,7 +281,7 @@ impl<'gc> Callback<'gc> {
         })
     }
 
-    pub fn call(&self, args: Vec<Value<'gc>>) -> CallbackReturn<'gc> {
+    pub fn call(&self, args: ValueBuffer<'gc>) -> CallbackReturn<'gc> {
         self.0.call(args)
     }
 }
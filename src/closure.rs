@@ -4,7 +4,8 @@ use std::hash::{Hash, Hasher};
 
 use gc_arena::{Collect, Gc, GcCell, MutationContext};
 
-use crate::{Constant, OpCode, RegisterIndex, Table, Thread, UpValueIndex, Value};
+use crate::parser::LineNumber;
+use crate::{Constant, OpCode, RegisterIndex, String, Table, Thread, UpValueIndex, Value};
 
 #[derive(Debug, Collect, Clone, Copy, PartialEq, Eq)]
 #[collect(require_static)]
@@ -17,13 +18,59 @@ pub enum UpValueDescriptor {
 #[derive(Debug, Collect)]
 #[collect(empty_drop)]
 pub struct FunctionProto<'gc> {
+    pub chunk_name: String<'gc>,
     pub fixed_params: u8,
     pub has_varargs: bool,
     pub stack_size: u16,
     pub constants: Vec<Constant<'gc>>,
     pub opcodes: Vec<OpCode>,
+    /// The source line of every opcode, as pairs of the index of the first opcode on a line and
+    /// the line itself, ordered by opcode index.
+    pub opcode_lines: Vec<(usize, LineNumber)>,
     pub upvalues: Vec<UpValueDescriptor>,
+    /// The name of each upvalue, in the same order as `upvalues`.
+    pub upvalue_names: Vec<String<'gc>>,
+    /// Every local variable declared in the function, ordered by the opcode index where it comes
+    /// into scope.
+    pub local_variables: Vec<LocalVariable<'gc>>,
     pub prototypes: Vec<Gc<'gc, FunctionProto<'gc>>>,
+    /// Whether this is the function of a whole chunk rather than of a function definition.
+    pub is_main_chunk: bool,
+}
+
+/// A local variable which is held in `register` while the opcodes in `start_pc..end_pc` run.
+#[derive(Debug, Collect, Copy, Clone)]
+#[collect(require_copy)]
+pub struct LocalVariable<'gc> {
+    pub name: String<'gc>,
+    pub register: RegisterIndex,
+    pub start_pc: usize,
+    pub end_pc: usize,
+}
+
+impl<'gc> FunctionProto<'gc> {
+    /// Returns the source line of the opcode at the given index.
+    pub fn opcode_line(&self, pc: usize) -> Option<LineNumber> {
+        match self
+            .opcode_lines
+            .binary_search_by_key(&pc, |&(start, _)| start)
+        {
+            Ok(i) => Some(self.opcode_lines[i].1),
+            Err(0) => None,
+            Err(i) => Some(self.opcode_lines[i - 1].1),
+        }
+    }
+
+    /// Returns the name of the local variable held in the given register at the given opcode index,
+    /// if there is one.
+    pub fn local_name(&self, register: RegisterIndex, pc: usize) -> Option<String<'gc>> {
+        self.local_variables
+            .iter()
+            .rev()
+            .skip_while(|local| local.start_pc > pc)
+            .find(|local| local.register == register && pc < local.end_pc)
+            .map(|local| local.name)
+    }
 }
 
 #[derive(Debug, Collect, Copy, Clone)]
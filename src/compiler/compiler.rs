@@ -9,14 +9,14 @@ use gc_arena::{Collect, Gc, MutationContext};
 use crate::parser::{
     AssignmentStatement, AssignmentTarget, BinaryOperator, Block, CallSuffix, Chunk,
     ConstructorField, Expression, FieldSuffix, ForStatement, FunctionCallStatement,
-    FunctionDefinition, FunctionStatement, HeadExpression, IfStatement, LocalFunctionStatement,
-    LocalStatement, PrimaryExpression, RecordKey, RepeatStatement, ReturnStatement,
-    SimpleExpression, Statement, SuffixPart, SuffixedExpression, TableConstructor, UnaryOperator,
-    WhileStatement,
+    FunctionDefinition, FunctionStatement, HeadExpression, IfStatement, LineNumber, LocalAttribute,
+    LocalFunctionStatement, LocalStatement, PrimaryExpression, RecordKey, RepeatStatement,
+    ReturnStatement, SimpleExpression, Statement, SuffixPart, SuffixedExpression, TableConstructor,
+    UnaryOperator, WhileStatement,
 };
 use crate::{
-    Constant, ConstantIndex16, ConstantIndex8, FunctionProto, OpCode, Opt254, PrototypeIndex,
-    RegisterIndex, String, UpValueDescriptor, UpValueIndex, VarCount,
+    Constant, ConstantIndex16, ConstantIndex8, FunctionProto, LocalVariable, OpCode, Opt254,
+    PrototypeIndex, RegisterIndex, String, UpValueDescriptor, UpValueIndex, VarCount,
 };
 
 use super::operators::{
@@ -24,6 +24,7 @@ use super::operators::{
     simple_binop_const_fold, simple_binop_opcode, unop_const_fold, unop_opcode, BinOpCategory,
     ComparisonBinOp, RegisterOrConstant, ShortCircuitBinOp, SimpleBinOp,
 };
+use super::optimize::optimize_jumps;
 use super::register_allocator::RegisterAllocator;
 
 #[derive(Debug, Collect)]
@@ -39,13 +40,14 @@ pub enum CompilerError {
     GotoInvalid,
     JumpLocal,
     JumpOverflow,
+    AssignToConst(std::string::String),
 }
 
 impl StdError for CompilerError {}
 
 impl fmt::Display for CompilerError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
+        match self {
             CompilerError::Registers => write!(fmt, "insufficient available registers"),
             CompilerError::UpValues => write!(fmt, "too many upvalues"),
             CompilerError::FixedParameters => write!(fmt, "too many fixed parameters"),
@@ -56,25 +58,33 @@ impl fmt::Display for CompilerError {
             CompilerError::GotoInvalid => write!(fmt, "goto target label not found"),
             CompilerError::JumpLocal => write!(fmt, "jump into scope of new local variable"),
             CompilerError::JumpOverflow => write!(fmt, "jump offset overflow"),
+            CompilerError::AssignToConst(name) => {
+                write!(fmt, "attempt to assign to const variable '{}'", name)
+            }
         }
     }
 }
 
 pub fn compile_chunk<'gc>(
     mc: MutationContext<'gc, '_>,
+    chunk_name: String<'gc>,
     chunk: &Chunk<String<'gc>>,
 ) -> Result<FunctionProto<'gc>, CompilerError> {
     let mut compiler = Compiler {
         mutation_context: mc,
-        current_function: CompilerFunction::start(&[], true)?,
+        chunk_name,
+        current_function: CompilerFunction::start(&[], true, LineNumber(1))?,
         upper_functions: Vec::new(),
     };
     compiler.block(&chunk.block)?;
-    compiler.current_function.finish(mc)
+    let mut proto = compiler.current_function.finish(mc, chunk_name)?;
+    proto.is_main_chunk = true;
+    Ok(proto)
 }
 
 struct Compiler<'gc, 'a> {
     mutation_context: MutationContext<'gc, 'a>,
+    chunk_name: String<'gc>,
     current_function: CompilerFunction<'gc>,
     upper_functions: Vec<CompilerFunction<'gc>>,
 }
@@ -91,7 +101,7 @@ struct CompilerFunction<'gc> {
 
     has_varargs: bool,
     fixed_params: u8,
-    locals: Vec<(String<'gc>, RegisterIndex)>,
+    locals: Vec<(String<'gc>, RegisterIndex, Option<LocalAttribute>)>,
 
     blocks: Vec<BlockDescriptor>,
     unique_jump_id: u64,
@@ -99,6 +109,10 @@ struct CompilerFunction<'gc> {
     pending_jumps: Vec<PendingJump<'gc>>,
 
     opcodes: Vec<OpCode>,
+    // The source line of every opcode, stored as the index of the first opcode of each run of
+    // opcodes on the same line.
+    opcode_lines: Vec<(usize, LineNumber)>,
+    local_variables: Vec<LocalVariable<'gc>>,
 }
 
 #[derive(Debug)]
@@ -134,11 +148,13 @@ enum ExprDescriptor<'gc> {
     FunctionCall {
         func: Box<ExprDescriptor<'gc>>,
         args: Vec<ExprDescriptor<'gc>>,
+        line: LineNumber,
     },
     MethodCall {
         table: Box<ExprDescriptor<'gc>>,
         method: Box<ExprDescriptor<'gc>>,
         args: Vec<ExprDescriptor<'gc>>,
+        line: LineNumber,
     },
     Concat(VecDeque<ExprDescriptor<'gc>>),
 }
@@ -177,6 +193,8 @@ struct BlockDescriptor {
     bottom_jump_target: usize,
     // True if any lower function has an upvalue reference to variables in this block
     owns_upvalues: bool,
+    // True if this block declares any to-be-closed variables
+    owns_to_be_closed: bool,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -216,16 +234,17 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             stack_bottom: self.current_function.register_allocator.stack_top(),
             bottom_jump_target: self.current_function.jump_targets.len(),
             owns_upvalues: false,
+            owns_to_be_closed: false,
         });
     }
 
     fn exit_block(&mut self) -> Result<(), CompilerError> {
         let last_block = self.current_function.blocks.pop().unwrap();
 
-        while let Some((_, last)) = self.current_function.locals.last() {
+        while let Some((_, last, _)) = self.current_function.locals.last() {
             if last.0 as u16 >= last_block.stack_bottom {
                 self.current_function.register_allocator.free(*last);
-                self.current_function.locals.pop();
+                self.current_function.pop_local();
             } else {
                 break;
             }
@@ -234,7 +253,8 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             .jump_targets
             .drain(last_block.bottom_jump_target..);
 
-        if last_block.owns_upvalues && !self.current_function.blocks.is_empty() {
+        let needs_close = last_block.owns_upvalues || last_block.owns_to_be_closed;
+        if needs_close && !self.current_function.blocks.is_empty() {
             self.current_function.opcodes.push(OpCode::Jump {
                 offset: 0,
                 close_upvalues: cast(last_block.stack_bottom)
@@ -244,7 +264,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         }
 
         // Bring all the pending jumps outward one level, and mark them to close upvalues if this
-        // block owned any.
+        // block owned any upvalues or to-be-closed variables.
         if !self.current_function.blocks.is_empty() {
             for pending_jump in self.current_function.pending_jumps.iter_mut().rev() {
                 if pending_jump.block_index < self.current_function.blocks.len() {
@@ -255,7 +275,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     pending_jump.stack_top >= self.current_function.register_allocator.stack_top()
                 );
                 pending_jump.stack_top = self.current_function.register_allocator.stack_top();
-                pending_jump.close_upvalues |= last_block.owns_upvalues;
+                pending_jump.close_upvalues |= needs_close;
             }
         }
 
@@ -268,15 +288,15 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
     // to the end of the block over local variable scope.  This is logically equivalent to an extra
     // `do end` around the inside of the block not including the trailing labels.
     fn block_statements(&mut self, block: &Block<String<'gc>>) -> Result<(), CompilerError> {
-        if let Some(return_statement) = &block.return_statement {
-            for statement in &block.statements {
-                self.statement(statement)?;
+        if let Some((return_line, return_statement)) = &block.return_statement {
+            for (line, statement) in &block.statements {
+                self.statement(*line, statement)?;
             }
-            self.return_statement(return_statement)?;
+            self.return_statement(*return_line, return_statement)?;
         } else {
             let mut last = block.statements.len();
             for i in (0..block.statements.len()).rev() {
-                match &block.statements[i] {
+                match &block.statements[i].1 {
                     Statement::Label(_) => {}
                     _ => break,
                 }
@@ -285,19 +305,24 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             let trailing_labels = &block.statements[last..block.statements.len()];
 
             self.enter_block();
-            for i in 0..block.statements.len() - trailing_labels.len() {
-                self.statement(&block.statements[i])?;
+            for (line, statement) in &block.statements[0..last] {
+                self.statement(*line, statement)?;
             }
             self.exit_block()?;
 
-            for label_statement in trailing_labels {
-                self.statement(&label_statement)?;
+            for (line, label_statement) in trailing_labels {
+                self.statement(*line, label_statement)?;
             }
         }
         Ok(())
     }
 
-    fn statement(&mut self, statement: &Statement<String<'gc>>) -> Result<(), CompilerError> {
+    fn statement(
+        &mut self,
+        line: LineNumber,
+        statement: &Statement<String<'gc>>,
+    ) -> Result<(), CompilerError> {
+        self.current_function.set_line(line);
         match statement {
             Statement::If(if_statement) => self.if_statement(if_statement),
             Statement::While(while_statement) => self.while_statement(while_statement),
@@ -321,8 +346,10 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
 
     fn return_statement(
         &mut self,
+        line: LineNumber,
         return_statement: &ReturnStatement<String<'gc>>,
     ) -> Result<(), CompilerError> {
+        self.current_function.set_line(line);
         let mut returns = return_statement
             .returns
             .iter()
@@ -330,10 +357,17 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             .collect::<Result<Vec<_>, CompilerError>>()?;
 
         // A return of a single function call is a tail call, and this is the only thing
-        // in Lua that is considered a tail call
-        if returns.len() == 1 {
+        // in Lua that is considered a tail call.  Variables must be closed after the call returns,
+        // so there are no tail calls in the scope of a to-be-closed variable.
+        let in_to_be_closed_scope = self
+            .current_function
+            .blocks
+            .iter()
+            .any(|block| block.owns_to_be_closed);
+        if returns.len() == 1 && !in_to_be_closed_scope {
             match returns.pop().unwrap() {
-                ExprDescriptor::FunctionCall { func, args } => {
+                ExprDescriptor::FunctionCall { func, args, line } => {
+                    self.current_function.set_line(line);
                     let func = self.expr_discharge(*func, ExprDestination::PushNew)?;
                     let args = self.push_arguments(args)?;
                     self.current_function
@@ -400,6 +434,9 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         &mut self,
         for_statement: &ForStatement<String<'gc>>,
     ) -> Result<(), CompilerError> {
+        // The opcodes that run each iteration of the loop are marked with the line of the `for`
+        // rather than the last line of the body.
+        let line = self.current_function.current_line();
         match for_statement {
             ForStatement::Numeric {
                 name,
@@ -434,10 +471,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .register_allocator
                     .push(1)
                     .ok_or(CompilerError::Registers)?;
-                self.current_function.locals.push((*name, loop_var));
+                self.current_function.add_local(*name, loop_var, None);
 
                 self.block_statements(body)?;
                 self.exit_block()?;
+                self.current_function.set_line(line);
 
                 let for_loop_index = self.current_function.opcodes.len();
                 self.current_function.opcodes.push(OpCode::NumericForLoop {
@@ -510,9 +548,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     .push(name_count)
                     .ok_or(CompilerError::Registers)?;
                 for i in 0..name_count {
-                    self.current_function
-                        .locals
-                        .push((names[i as usize], RegisterIndex(names_reg.0 + i)));
+                    self.current_function.add_local(
+                        names[i as usize],
+                        RegisterIndex(names_reg.0 + i),
+                        None,
+                    );
                 }
 
                 self.jump(loop_label)?;
@@ -520,6 +560,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 let start_inst = self.current_function.opcodes.len();
                 self.block_statements(body)?;
                 self.exit_block()?;
+                self.current_function.set_line(line);
 
                 self.jump_target(loop_label)?;
                 self.current_function.opcodes.push(OpCode::GenericForCall {
@@ -580,11 +621,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
 
         // `repeat` statements do not follow the trailing label rule, because the variables inside
         // the block are in scope for the `until` condition at the end.
-        for statement in &repeat_statement.body.statements {
-            self.statement(statement)?;
+        for (line, statement) in &repeat_statement.body.statements {
+            self.statement(*line, statement)?;
         }
-        if let Some(return_statement) = &repeat_statement.body.return_statement {
-            self.return_statement(return_statement)?;
+        if let Some((line, return_statement)) = &repeat_statement.body.return_statement {
+            self.return_statement(*line, return_statement)?;
         }
 
         let condition = self.expression(&repeat_statement.until)?;
@@ -671,9 +712,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 .opcodes
                 .push(OpCode::LoadNil { dest, count });
             for i in 0..name_len {
-                self.current_function
-                    .locals
-                    .push((local_statement.names[i], RegisterIndex(dest.0 + i as u8)));
+                self.current_function.add_local(
+                    local_statement.names[i],
+                    RegisterIndex(dest.0 + i as u8),
+                    local_statement.attributes[i],
+                );
             }
         } else {
             for i in 0..val_len {
@@ -688,20 +731,39 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     let dest = self.expr_push_count(expr, names_left)?;
 
                     for j in 0..names_left {
-                        self.current_function.locals.push((
-                            local_statement.names[val_len - 1 + j as usize],
+                        let name_index = val_len - 1 + j as usize;
+                        self.current_function.add_local(
+                            local_statement.names[name_index],
                             RegisterIndex(dest.0 + j),
-                        ));
+                            local_statement.attributes[name_index],
+                        );
                     }
                 } else {
                     let reg = self.expr_discharge(expr, ExprDestination::PushNew)?;
-                    self.current_function
-                        .locals
-                        .push((local_statement.names[i], reg));
+                    self.current_function.add_local(
+                        local_statement.names[i],
+                        reg,
+                        local_statement.attributes[i],
+                    );
                 }
             }
         }
 
+        let first_local = self.current_function.locals.len() - name_len;
+        for i in first_local..self.current_function.locals.len() {
+            let (_, value, attribute) = self.current_function.locals[i];
+            if attribute == Some(LocalAttribute::Close) {
+                self.current_function
+                    .opcodes
+                    .push(OpCode::ToBeClosed { value });
+                self.current_function
+                    .blocks
+                    .last_mut()
+                    .unwrap()
+                    .owns_to_be_closed = true;
+            }
+        }
+
         Ok(())
     }
 
@@ -710,13 +772,14 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         function_call: &FunctionCallStatement<String<'gc>>,
     ) -> Result<(), CompilerError> {
         let head_expr = self.suffixed_expression(&function_call.head)?;
+        let line = self.current_function.current_line();
         match &function_call.call {
             CallSuffix::Function(args) => {
                 let arg_exprs = args
                     .iter()
                     .map(|arg| self.expression(arg))
                     .collect::<Result<_, CompilerError>>()?;
-                self.call_function(head_expr, arg_exprs, VarCount::constant(0))?;
+                self.call_function(head_expr, arg_exprs, VarCount::constant(0), line)?;
             }
             CallSuffix::Method(method, args) => {
                 let arg_exprs = args
@@ -728,6 +791,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                     ExprDescriptor::Constant(Constant::String(*method)),
                     arg_exprs,
                     VarCount::constant(0),
+                    line,
                 )?;
             }
         }
@@ -746,6 +810,12 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             };
 
             match target {
+                AssignmentTarget::Name(name) if self.is_const_variable(*name) => {
+                    return Err(CompilerError::AssignToConst(
+                        std::string::String::from_utf8_lossy(name.as_bytes()).into_owned(),
+                    ));
+                }
+
                 AssignmentTarget::Name(name) => match self.find_variable(*name)? {
                     VariableDescriptor::Local(dest) => {
                         self.expr_discharge(expr, ExprDestination::Register(dest))?;
@@ -801,8 +871,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             .opcodes
             .push(OpCode::Closure { proto, dest });
         self.current_function
-            .locals
-            .push((local_function.name, dest));
+            .add_local(local_function.name, dest, None);
 
         Ok(())
     }
@@ -811,11 +880,17 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         &mut self,
         expression: &Expression<String<'gc>>,
     ) -> Result<ExprDescriptor<'gc>, CompilerError> {
+        // Opcodes generated directly by this expression (such as calls) are marked with its line,
+        // anything generated later when the result is discharged is marked with the line of the
+        // enclosing expression or statement.
+        let outer_line = self.current_function.current_line();
+        self.current_function.set_line(expression.line);
         let mut expr = self.head_expression(&expression.head)?;
         for (binop, right) in &expression.tail {
             let right = self.expression(&right)?;
             expr = self.binary_operator_expression(expr, *binop, right)?;
         }
+        self.current_function.set_line(outer_line);
         Ok(expr)
     }
 
@@ -916,6 +991,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                         expr = ExprDescriptor::FunctionCall {
                             func: Box::new(expr),
                             args,
+                            line: self.current_function.current_line(),
                         };
                     }
                     CallSuffix::Method(method, args) => {
@@ -927,6 +1003,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                             table: Box::new(expr),
                             method: Box::new(ExprDescriptor::Constant(Constant::String(*method))),
                             args,
+                            line: self.current_function.current_line(),
                         };
                     }
                 },
@@ -1036,9 +1113,10 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         has_varargs: bool,
         body: &Block<String<'gc>>,
     ) -> Result<PrototypeIndex, CompilerError> {
+        let line = self.current_function.current_line();
         let old_current = mem::replace(
             &mut self.current_function,
-            CompilerFunction::start(parameters, has_varargs)?,
+            CompilerFunction::start(parameters, has_varargs, line)?,
         );
         self.upper_functions.push(old_current);
         self.block(body)?;
@@ -1046,7 +1124,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             &mut self.current_function,
             self.upper_functions.pop().unwrap(),
         )
-        .finish(self.mutation_context)?;
+        .finish(self.mutation_context, self.chunk_name)?;
         self.current_function.prototypes.push(proto);
         Ok(PrototypeIndex(
             cast(self.current_function.prototypes.len() - 1).ok_or(CompilerError::Functions)?,
@@ -1073,7 +1151,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
 
         for i in (0..=current_function).rev() {
             for j in (0..get_function(self, i).locals.len()).rev() {
-                let (local_name, register) = get_function(self, i).locals[j];
+                let (local_name, register, _) = get_function(self, i).locals[j];
                 if name == local_name {
                     if i == current_function {
                         return Ok(VariableDescriptor::Local(register));
@@ -1142,6 +1220,17 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         Ok(VariableDescriptor::Global(name))
     }
 
+    // Returns true if the given name refers to a `<const>` or `<close>` local variable, either in
+    // the current function or as an upvalue from an upper function.
+    fn is_const_variable(&self, name: String<'gc>) -> bool {
+        iter::once(&self.current_function)
+            .chain(self.upper_functions.iter().rev())
+            .flat_map(|function| function.locals.iter().rev())
+            .find(|(local_name, _, _)| *local_name == name)
+            .map(|(_, _, attribute)| attribute.is_some())
+            .unwrap_or(false)
+    }
+
     // Get a reference to the variable _ENV in scope, or if that is not in scope, the implicit chunk
     // _ENV.
     fn get_environment(&mut self) -> Result<ExprDescriptor<'gc>, CompilerError> {
@@ -1166,12 +1255,14 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         for jump_target in self.current_function.jump_targets.iter().rev() {
             if jump_target.label == target {
                 // We need to close upvalues only if any of the blocks we're jumping over own
-                // upvalues
+                // upvalues or to-be-closed variables
                 assert!(jump_target.stack_top <= current_stack_top);
                 assert!(jump_target.block_index <= current_block_index);
                 let needs_close_upvalues = jump_target.stack_top < current_stack_top
-                    && (jump_target.block_index..=current_block_index)
-                        .any(|i| self.current_function.blocks[i].owns_upvalues);
+                    && (jump_target.block_index..=current_block_index).any(|i| {
+                        let block = &self.current_function.blocks[i];
+                        block.owns_upvalues || block.owns_to_be_closed
+                    });
 
                 self.current_function.opcodes.push(OpCode::Jump {
                     offset: jump_offset(jmp_inst, jump_target.instruction)
@@ -1372,13 +1463,18 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
     // Performs a function call.  At the end of the function call, the return values will be left at
     // the top of the stack.  The returns are potentially variable, so none of the returns are
     // marked as allocated.  Returns the register at which the returns (if any) are placed, which
-    // will always be the current register allocator top.
+    // will always be the current register allocator top.  All of the opcodes for the call are
+    // marked with the given source line.
     fn call_function(
         &mut self,
         func: ExprDescriptor<'gc>,
         args: Vec<ExprDescriptor<'gc>>,
         returns: VarCount,
+        line: LineNumber,
     ) -> Result<RegisterIndex, CompilerError> {
+        let outer_line = self.current_function.current_line();
+        self.current_function.set_line(line);
+
         let func = self.expr_discharge(func, ExprDestination::PushNew)?;
         let args = self.push_arguments(args)?;
 
@@ -1390,6 +1486,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
 
         // OpCode::Call places returns at the previous location of the function
         self.current_function.register_allocator.free(func);
+        self.current_function.set_line(outer_line);
         Ok(func)
     }
 
@@ -1401,7 +1498,11 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         method: ExprDescriptor<'gc>,
         args: Vec<ExprDescriptor<'gc>>,
         returns: VarCount,
+        line: LineNumber,
     ) -> Result<RegisterIndex, CompilerError> {
+        let outer_line = self.current_function.current_line();
+        self.current_function.set_line(line);
+
         let (table, table_is_temp) = self.expr_any_register(table)?;
         let (method, method_to_free) = self.expr_any_register_or_constant(method)?;
 
@@ -1440,6 +1541,7 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
         self.current_function
             .register_allocator
             .pop_to(base.0 as u16);
+        self.current_function.set_line(outer_line);
 
         Ok(base)
     }
@@ -1461,8 +1563,8 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
             }
 
             let arg_count = match last_arg {
-                ExprDescriptor::FunctionCall { func, args } => {
-                    self.call_function(*func, args, VarCount::variable())?;
+                ExprDescriptor::FunctionCall { func, args, line } => {
+                    self.call_function(*func, args, VarCount::variable(), line)?;
                     VarCount::variable()
                 }
                 ExprDescriptor::VarArgs => {
@@ -1775,8 +1877,8 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 dest
             }
 
-            ExprDescriptor::FunctionCall { func, args } => {
-                let source = self.call_function(*func, args, VarCount::constant(1))?;
+            ExprDescriptor::FunctionCall { func, args, line } => {
+                let source = self.call_function(*func, args, VarCount::constant(1), line)?;
                 match dest {
                     ExprDestination::Register(dest) => {
                         assert_ne!(dest, source);
@@ -1802,8 +1904,10 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
                 table,
                 method,
                 args,
+                line,
             } => {
-                let source = self.call_method(*table, *method, args, VarCount::constant(1))?;
+                let source =
+                    self.call_method(*table, *method, args, VarCount::constant(1), line)?;
                 match dest {
                     ExprDestination::Register(dest) => {
                         assert_ne!(dest, source);
@@ -1872,11 +1976,12 @@ impl<'gc, 'a> Compiler<'gc, 'a> {
     ) -> Result<RegisterIndex, CompilerError> {
         assert!(count != 0);
         Ok(match expr {
-            ExprDescriptor::FunctionCall { func, args } => {
+            ExprDescriptor::FunctionCall { func, args, line } => {
                 let dest = self.call_function(
                     *func,
                     args,
                     VarCount::try_constant(count).ok_or(CompilerError::Registers)?,
+                    line,
                 )?;
                 self.current_function
                     .register_allocator
@@ -2000,8 +2105,10 @@ impl<'gc> CompilerFunction<'gc> {
     fn start(
         parameters: &[String<'gc>],
         has_varargs: bool,
+        line: LineNumber,
     ) -> Result<CompilerFunction<'gc>, CompilerError> {
         let mut function = CompilerFunction::default();
+        function.opcode_lines.push((0, line));
         let fixed_params: u8 = cast(parameters.len()).ok_or(CompilerError::FixedParameters)?;
         if fixed_params != 0 {
             function.register_allocator.push(fixed_params).unwrap();
@@ -2009,20 +2116,67 @@ impl<'gc> CompilerFunction<'gc> {
         function.has_varargs = has_varargs;
         function.fixed_params = fixed_params;
         for i in 0..fixed_params {
-            function
-                .locals
-                .push((parameters[i as usize], RegisterIndex(i)));
+            function.add_local(parameters[i as usize], RegisterIndex(i), None);
         }
         Ok(function)
     }
 
-    fn finish(mut self, mc: MutationContext<'gc, '_>) -> Result<FunctionProto<'gc>, CompilerError> {
+    // Declares a new local variable held in the given register, in scope from the next opcode.
+    fn add_local(
+        &mut self,
+        name: String<'gc>,
+        register: RegisterIndex,
+        attribute: Option<LocalAttribute>,
+    ) {
+        self.locals.push((name, register, attribute));
+        self.local_variables.push(LocalVariable {
+            name,
+            register,
+            start_pc: self.opcodes.len(),
+            end_pc: self.opcodes.len(),
+        });
+    }
+
+    // Removes the most recently declared local variable from scope, returning its register.
+    fn pop_local(&mut self) -> Option<RegisterIndex> {
+        let (_, register, _) = self.locals.pop()?;
+        let end_pc = self.opcodes.len();
+        if let Some(local) = self
+            .local_variables
+            .iter_mut()
+            .rev()
+            .find(|local| local.register == register)
+        {
+            local.end_pc = end_pc;
+        }
+        Some(register)
+    }
+
+    // Marks all opcodes pushed from now on as coming from the given source line.
+    fn set_line(&mut self, line: LineNumber) {
+        let next_opcode = self.opcodes.len();
+        match self.opcode_lines.last_mut() {
+            Some((_, last_line)) if *last_line == line => {}
+            Some((start, last_line)) if *start == next_opcode => *last_line = line,
+            _ => self.opcode_lines.push((next_opcode, line)),
+        }
+    }
+
+    fn current_line(&self) -> LineNumber {
+        self.opcode_lines.last().unwrap().1
+    }
+
+    fn finish(
+        mut self,
+        mc: MutationContext<'gc, '_>,
+        chunk_name: String<'gc>,
+    ) -> Result<FunctionProto<'gc>, CompilerError> {
         self.opcodes.push(OpCode::Return {
             start: RegisterIndex(0),
             count: VarCount::constant(0),
         });
         assert!(self.locals.len() == self.fixed_params as usize);
-        for (_, r) in self.locals.drain(..) {
+        while let Some(r) = self.pop_local() {
             self.register_allocator.free(r);
         }
         assert_eq!(
@@ -2035,23 +2189,53 @@ impl<'gc> CompilerFunction<'gc> {
             return Err(CompilerError::GotoInvalid);
         }
 
+        let new_indexes = optimize_jumps(&mut self.opcodes);
+
+        // Line runs and local variable scopes which have become empty are removed.
+        let mut opcode_lines: Vec<(usize, LineNumber)> = Vec::new();
+        for &(start, line) in &self.opcode_lines {
+            let start = new_indexes[start];
+            if opcode_lines.last().map(|&(last_start, _)| last_start) == Some(start) {
+                opcode_lines.pop();
+            }
+            if opcode_lines.last().map(|&(_, last_line)| last_line) != Some(line) {
+                opcode_lines.push((start, line));
+            }
+        }
+
+        let local_variables = self
+            .local_variables
+            .iter()
+            .map(|local| LocalVariable {
+                start_pc: new_indexes[local.start_pc],
+                end_pc: new_indexes[local.end_pc],
+                ..*local
+            })
+            .filter(|local| local.start_pc < local.end_pc)
+            .collect();
+
         Ok(FunctionProto {
+            chunk_name,
             fixed_params: self.fixed_params,
             has_varargs: self.has_varargs,
             stack_size: self.register_allocator.stack_size(),
             constants: self.constants,
             opcodes: self.opcodes,
+            opcode_lines,
             upvalues: self.upvalues.iter().map(|(_, d)| *d).collect(),
+            upvalue_names: self.upvalues.iter().map(|(n, _)| *n).collect(),
+            local_variables,
             prototypes: self
                 .prototypes
                 .into_iter()
                 .map(|f| Gc::allocate(mc, f))
                 .collect(),
+            is_main_chunk: false,
         })
     }
 }
 
-fn jump_offset(source: usize, target: usize) -> Option<i16> {
+pub(super) fn jump_offset(source: usize, target: usize) -> Option<i16> {
     if target > source {
         cast(target - (source + 1))
     } else {
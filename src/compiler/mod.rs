@@ -1,4 +1,5 @@
 use std::io::Read;
+use std::string::String as StdString;
 
 use gc_arena::MutationContext;
 
@@ -6,6 +7,7 @@ use crate::{parse_chunk, Error, FunctionProto, InternedStringSet};
 
 mod compiler;
 mod operators;
+mod optimize;
 mod register_allocator;
 
 pub use self::compiler::{compile_chunk, CompilerError};
@@ -15,8 +17,22 @@ pub fn compile<'gc, R: Read>(
     interned_strings: InternedStringSet<'gc>,
     source: R,
 ) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    compile_named(mc, interned_strings, b"?", source)
+}
+
+/// Compiles the given source, recording `chunk_name` in every function prototype and parser error so
+/// that errors can refer to their location as `chunk_name:line`.
+pub fn compile_named<'gc, R: Read>(
+    mc: MutationContext<'gc, '_>,
+    interned_strings: InternedStringSet<'gc>,
+    chunk_name: &[u8],
+    source: R,
+) -> Result<FunctionProto<'gc>, Error<'gc>> {
+    let chunk = parse_chunk(source, |s| interned_strings.new_string(mc, s))
+        .map_err(|err| err.with_chunk_name(StdString::from_utf8_lossy(chunk_name).into_owned()))?;
     Ok(compile_chunk(
         mc,
-        &parse_chunk(source, |s| interned_strings.new_string(mc, s))?,
+        interned_strings.new_string(mc, chunk_name),
+        &chunk,
     )?)
 }
@@ -0,0 +1,255 @@
+use crate::{OpCode, Opt254};
+
+use super::compiler::jump_offset;
+
+/// Performs jump chaining and jump threading on the opcodes of a finished function, then removes any
+/// jumps that have become dead.
+///
+/// Jumps whose target is another jump are redirected to the final target, combining any upvalue /
+/// to-be-closed variable closing along the way.  A jump which is only reached when a `Test` or
+/// `TestSet` has failed knows the boolean value of the tested register, so it is also threaded
+/// through any `Test` of the same register at its target.
+///
+/// Returns the new index of every original opcode, where removed opcodes are given the new index of
+/// the next remaining opcode.  The returned indexes have one extra entry for the end of the opcodes.
+pub fn optimize_jumps(opcodes: &mut Vec<OpCode>) -> Vec<usize> {
+    thread_jumps(opcodes);
+    let mut new_indexes = (0..=opcodes.len()).collect::<Vec<_>>();
+    while let Some(removed) = remove_dead_jumps(opcodes) {
+        for index in &mut new_indexes {
+            *index = removed[*index];
+        }
+    }
+    new_indexes
+}
+
+fn thread_jumps(opcodes: &mut [OpCode]) {
+    let entry_points = entry_points(opcodes);
+
+    for i in 0..opcodes.len() {
+        let (start_target, start_close) = match opcodes[i] {
+            OpCode::Jump {
+                offset,
+                close_upvalues,
+            } => (add_offset(i, offset), close_upvalues),
+            op => match jump_target(i, op) {
+                Some(target) => (target, Opt254::none()),
+                None => continue,
+            },
+        };
+        let is_jump = matches!(opcodes[i], OpCode::Jump { .. });
+
+        // If this jump can only be reached by failing the preceding test, then we know the boolean
+        // value of the tested register when the jump is taken.
+        let mut known = if is_jump && start_close.is_none() && i > 0 && !entry_points[i] {
+            match opcodes[i - 1] {
+                OpCode::Test { value, is_true } => Some((value, !is_true)),
+                OpCode::TestSet { dest, is_true, .. } => Some((dest, !is_true)),
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        let mut target = start_target;
+        let mut close = start_close;
+        // Bounded so that jump cycles (like in `while true do end`) cannot loop forever
+        for _ in 0..opcodes.len() {
+            if target == i || target >= opcodes.len() {
+                break;
+            }
+
+            match opcodes[target] {
+                OpCode::Jump {
+                    offset,
+                    close_upvalues,
+                } => {
+                    if close_upvalues.is_some() {
+                        if !is_jump {
+                            break;
+                        }
+                        close = combine_close(close, close_upvalues);
+                        known = None;
+                    }
+                    target = add_offset(target, offset);
+                }
+                OpCode::Test { value, is_true } => match known {
+                    Some((known_reg, known_value)) if known_reg == value => {
+                        target = if known_value == is_true {
+                            target + 2
+                        } else {
+                            target + 1
+                        };
+                    }
+                    _ => break,
+                },
+                OpCode::TestSet { value, is_true, .. } => match known {
+                    // If the `TestSet` does not skip, its assignment must still take place.
+                    Some((known_reg, known_value))
+                        if known_reg == value && known_value == is_true =>
+                    {
+                        target += 2;
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+
+        if target != start_target || close.to_u8() != start_close.to_u8() {
+            if let Some(offset) = jump_offset(i, target) {
+                set_jump(&mut opcodes[i], offset, close);
+            }
+        }
+    }
+}
+
+// Removes jumps which either jump to the next instruction without closing anything or can never be
+// reached.  If any jumps were removed, returns the new index of every instruction in the same form as
+// `optimize_jumps`.
+fn remove_dead_jumps(opcodes: &mut Vec<OpCode>) -> Option<Vec<usize>> {
+    let entry_points = entry_points(opcodes);
+
+    let is_dead = |i: usize| -> bool {
+        let (offset, close_upvalues) = match opcodes[i] {
+            OpCode::Jump {
+                offset,
+                close_upvalues,
+            } => (offset, close_upvalues),
+            _ => return false,
+        };
+
+        // The instruction after a skipping instruction must stay where it is.
+        if close_upvalues.is_some() || (i > 0 && skips_next(opcodes[i - 1])) {
+            return false;
+        }
+
+        offset == 0 || (i > 0 && !entry_points[i] && is_unconditional(opcodes[i - 1]))
+    };
+    let dead = (0..opcodes.len()).map(is_dead).collect::<Vec<_>>();
+
+    if !dead.iter().any(|&d| d) {
+        return None;
+    }
+
+    // The new index of each instruction, or for removed instructions, the new index of the next
+    // remaining instruction.
+    let mut new_indexes = Vec::with_capacity(opcodes.len() + 1);
+    let mut kept = 0;
+    for &d in &dead {
+        new_indexes.push(kept);
+        if !d {
+            kept += 1;
+        }
+    }
+    new_indexes.push(kept);
+
+    let mut new_opcodes = Vec::with_capacity(kept);
+    for (i, &op) in opcodes.iter().enumerate() {
+        if dead[i] {
+            continue;
+        }
+
+        let mut op = op;
+        if let Some(target) = jump_target(i, op) {
+            let offset = jump_offset(new_indexes[i], new_indexes[target])
+                .expect("removing instructions cannot overflow a jump offset");
+            set_offset(&mut op, offset);
+        }
+        new_opcodes.push(op);
+    }
+
+    *opcodes = new_opcodes;
+    Some(new_indexes)
+}
+
+// Returns, for every instruction, whether it may be reached other than by falling through from the
+// previous instruction.
+fn entry_points(opcodes: &[OpCode]) -> Vec<bool> {
+    let mut entry_points = vec![false; opcodes.len()];
+    for (i, &op) in opcodes.iter().enumerate() {
+        if let Some(target) = jump_target(i, op) {
+            if target < opcodes.len() {
+                entry_points[target] = true;
+            }
+        }
+        if skips_next(op) && i + 2 < opcodes.len() {
+            entry_points[i + 2] = true;
+        }
+    }
+    entry_points
+}
+
+// Returns the instruction that the given instruction at index `i` jumps to, if it is a jump
+// instruction.
+fn jump_target(i: usize, op: OpCode) -> Option<usize> {
+    match op {
+        OpCode::Jump { offset, .. } => Some(add_offset(i, offset)),
+        OpCode::NumericForPrep { jump, .. }
+        | OpCode::NumericForLoop { jump, .. }
+        | OpCode::GenericForLoop { jump, .. } => Some(add_offset(i, jump)),
+        _ => None,
+    }
+}
+
+fn set_offset(op: &mut OpCode, new_offset: i16) {
+    match op {
+        OpCode::Jump { offset, .. } => *offset = new_offset,
+        OpCode::NumericForPrep { jump, .. }
+        | OpCode::NumericForLoop { jump, .. }
+        | OpCode::GenericForLoop { jump, .. } => *jump = new_offset,
+        _ => panic!("opcode is not a jump instruction"),
+    }
+}
+
+fn set_jump(op: &mut OpCode, new_offset: i16, new_close: Opt254) {
+    set_offset(op, new_offset);
+    if let OpCode::Jump { close_upvalues, .. } = op {
+        *close_upvalues = new_close;
+    }
+}
+
+// Returns true if the given instruction may skip the instruction following it.
+fn skips_next(op: OpCode) -> bool {
+    match op {
+        OpCode::LoadBool { skip_next, .. } => skip_next,
+        OpCode::Test { .. }
+        | OpCode::TestSet { .. }
+        | OpCode::EqRR { .. }
+        | OpCode::EqRC { .. }
+        | OpCode::EqCR { .. }
+        | OpCode::EqCC { .. }
+        | OpCode::LessRR { .. }
+        | OpCode::LessRC { .. }
+        | OpCode::LessCR { .. }
+        | OpCode::LessCC { .. }
+        | OpCode::LessEqRR { .. }
+        | OpCode::LessEqRC { .. }
+        | OpCode::LessEqCR { .. }
+        | OpCode::LessEqCC { .. } => true,
+        _ => false,
+    }
+}
+
+// Returns true if execution never falls through from the given instruction to the next one.
+fn is_unconditional(op: OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::Jump { .. } | OpCode::Return { .. } | OpCode::TailCall { .. }
+    )
+}
+
+// Closing upvalues and to-be-closed variables above one register and then above another is the same
+// as closing them above the lower of the two.
+fn combine_close(a: Opt254, b: Opt254) -> Opt254 {
+    match (a.to_u8(), b.to_u8()) {
+        (Some(a), Some(b)) => Opt254::some(a.min(b)),
+        (Some(a), None) => Opt254::some(a),
+        (None, Some(b)) => Opt254::some(b),
+        (None, None) => Opt254::none(),
+    }
+}
+
+fn add_offset(i: usize, offset: i16) -> usize {
+    (i as isize + 1 + offset as isize) as usize
+}
@@ -6,7 +6,7 @@ use gc_arena::{Collect, MutationContext, StaticCollect};
 
 use crate::{
     BadThreadMode, BinaryOperatorError, ClosureError, CompilerError, InternedStringSet,
-    InvalidTableKey, ParserError, StringError, ThreadError, Value,
+    InvalidTableKey, LineNumber, MetaMethod, ParserError, StringError, ThreadError, Value,
 };
 
 #[derive(Debug, Clone, Copy, Collect)]
@@ -43,6 +43,123 @@ impl<'gc> fmt::Display for RuntimeError<'gc> {
     }
 }
 
+/// Where a value came from, as determined from the instructions that loaded it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VariableName {
+    Global(String),
+    Local(String),
+    UpValue(String),
+    Field(String),
+    Method(String),
+    Constant(String),
+}
+
+impl fmt::Display for VariableName {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VariableName::Global(name) => write!(fmt, "global '{}'", name),
+            VariableName::Local(name) => write!(fmt, "local '{}'", name),
+            VariableName::UpValue(name) => write!(fmt, "upvalue '{}'", name),
+            VariableName::Field(name) => write!(fmt, "field '{}'", name),
+            VariableName::Method(name) => write!(fmt, "method '{}'", name),
+            VariableName::Constant(name) => write!(fmt, "constant '{}'", name),
+        }
+    }
+}
+
+/// How the function running in a traceback frame was called, as determined from the instruction
+/// that called it, or `MainChunk` for a chunk that was not called by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FunctionName {
+    Function(String),
+    Method(String),
+    MetaMethod(MetaMethod),
+    MainChunk,
+}
+
+impl fmt::Display for FunctionName {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FunctionName::Function(name) => write!(fmt, "function '{}'", name),
+            FunctionName::Method(name) => write!(fmt, "method '{}'", name),
+            FunctionName::MetaMethod(method) => {
+                write!(
+                    fmt,
+                    "metamethod '{}'",
+                    method.name().trim_start_matches("__")
+                )
+            }
+            FunctionName::MainChunk => write!(fmt, "main chunk"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Lua,
+    Callback,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracebackFrame {
+    pub kind: FrameKind,
+    pub function: Option<FunctionName>,
+    /// The chunk name of a Lua function.
+    pub chunk: Option<String>,
+    /// The line that a Lua function was executing.
+    pub line: Option<LineNumber>,
+    /// Whether the function was entered by a tail call, in which case the frames of the functions
+    /// that led to it are gone.
+    pub tail_call: bool,
+}
+
+impl fmt::Display for TracebackFrame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.kind, &self.chunk, &self.line) {
+            (FrameKind::Callback, _, _) => write!(fmt, "[callback]:")?,
+            (FrameKind::Lua, Some(chunk), Some(line)) => write!(fmt, "{}:{}:", chunk, line)?,
+            (FrameKind::Lua, Some(chunk), None) => write!(fmt, "{}:", chunk)?,
+            (FrameKind::Lua, None, _) => write!(fmt, "?:")?,
+        }
+        match &self.function {
+            Some(function) => write!(fmt, " in {}", function),
+            None => write!(fmt, " in ?"),
+        }
+    }
+}
+
+/// The active frames of a thread at the point an error was raised, most recent first.
+///
+/// Very deep tracebacks only keep the frames at either end of the stack, `skipped` is the number of
+/// frames left out between the first `Traceback::HEAD_FRAMES` frames and the rest.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub struct Traceback {
+    pub frames: Vec<TracebackFrame>,
+    pub skipped: usize,
+}
+
+impl Traceback {
+    pub const HEAD_FRAMES: usize = 10;
+    pub const TAIL_FRAMES: usize = 11;
+}
+
+impl fmt::Display for Traceback {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "stack traceback:")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i == Traceback::HEAD_FRAMES && self.skipped != 0 {
+                write!(fmt, "\n\t...\t(skipping {} levels)", self.skipped)?;
+            }
+            write!(fmt, "\n\t{}", frame)?;
+            if frame.tail_call {
+                write!(fmt, "\n\t(...tail calls...)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
 // Safe, does not implement drop
 #[derive(Debug, Collect)]
 #[collect(unsafe_drop)]
@@ -58,6 +175,12 @@ pub enum Error<'gc> {
     TypeError(TypeError),
     BinaryOperatorError(BinaryOperatorError),
     RuntimeError(RuntimeError<'gc>),
+    /// An error raised inside a thread, along with the thread's traceback at the point it was
+    /// raised.
+    Traceback {
+        error: Box<Error<'gc>>,
+        traceback: Traceback,
+    },
 }
 
 impl<'gc> StdError for Error<'gc> {}
@@ -76,6 +199,7 @@ impl<'gc> fmt::Display for Error<'gc> {
             Error::TypeError(error) => write!(fmt, "type error: {}", error),
             Error::BinaryOperatorError(error) => write!(fmt, "operator error: {}", error),
             Error::RuntimeError(error) => write!(fmt, "runtime error: {}", error),
+            Error::Traceback { error, traceback } => write!(fmt, "{}\n{}", error, traceback),
         }
     }
 }
@@ -147,6 +271,30 @@ impl<'gc> From<RuntimeError<'gc>> for Error<'gc> {
 }
 
 impl<'gc> Error<'gc> {
+    /// Returns the traceback attached to this error, if it was raised inside a thread.
+    pub fn traceback(&self) -> Option<&Traceback> {
+        match self {
+            Error::Traceback { traceback, .. } => Some(traceback),
+            _ => None,
+        }
+    }
+
+    /// Returns this error without any attached traceback.
+    pub fn inner(&self) -> &Error<'gc> {
+        match self {
+            Error::Traceback { error, .. } => error,
+            error => error,
+        }
+    }
+
+    /// Removes any traceback attached to this error.
+    pub fn without_traceback(self) -> Error<'gc> {
+        match self {
+            Error::Traceback { error, .. } => *error,
+            error => error,
+        }
+    }
+
     pub fn to_static(self) -> StaticError {
         match self {
             Error::IoError(error) => StaticError::IoError(error.0),
@@ -164,6 +312,10 @@ impl<'gc> Error<'gc> {
                 error.0.display(&mut buf).unwrap();
                 StaticError::RuntimeError(StdString::from_utf8_lossy(&buf).to_owned().to_string())
             }
+            Error::Traceback { error, traceback } => StaticError::Traceback {
+                error: Box::new(error.to_static()),
+                traceback,
+            },
         }
     }
 
@@ -172,7 +324,7 @@ impl<'gc> Error<'gc> {
         mc: MutationContext<'gc, '_>,
         interned_strings: InternedStringSet<'gc>,
     ) -> Value<'gc> {
-        match self {
+        match self.without_traceback() {
             Error::RuntimeError(error) => error.0,
             other => {
                 let s = other.to_string();
@@ -196,6 +348,10 @@ pub enum StaticError {
     TypeError(TypeError),
     BinaryOperatorError(BinaryOperatorError),
     RuntimeError(String),
+    Traceback {
+        error: Box<StaticError>,
+        traceback: Traceback,
+    },
 }
 
 impl StdError for StaticError {}
@@ -214,6 +370,25 @@ impl fmt::Display for StaticError {
             StaticError::TypeError(error) => write!(fmt, "type error: {}", error),
             StaticError::BinaryOperatorError(error) => write!(fmt, "operator error: {}", error),
             StaticError::RuntimeError(error) => write!(fmt, "runtime error: {}", error),
+            StaticError::Traceback { error, traceback } => write!(fmt, "{}\n{}", error, traceback),
+        }
+    }
+}
+
+impl StaticError {
+    /// Returns the traceback attached to this error, if it was raised inside a thread.
+    pub fn traceback(&self) -> Option<&Traceback> {
+        match self {
+            StaticError::Traceback { traceback, .. } => Some(traceback),
+            _ => None,
+        }
+    }
+
+    /// Returns this error without any attached traceback.
+    pub fn inner(&self) -> &StaticError {
+        match self {
+            StaticError::Traceback { error, .. } => error,
+            error => error,
         }
     }
 }
@@ -0,0 +1,87 @@
+use gc_arena::{Collect, FinalizerQueue, GcCell, MutationContext};
+
+use crate::{Function, MetaMethod, Table, TableState, UserData, UserDataState, Value};
+
+/// Tracks objects with a `__gc` metamethod, so that their finalizers may be run once they become
+/// unreachable.
+///
+/// As in PUC-Rio Lua, an object is only registered for finalization if its metatable has a `__gc`
+/// field at the time the metatable is set with `setmetatable`.  Setting a metatable with
+/// `Table::set_metatable` or `UserData::set_metatable` does not register the object, use
+/// `Finalizers::register_table` or `Finalizers::register_user_data` to do so.
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct Finalizers<'gc> {
+    tables: FinalizerQueue<'gc, GcCell<'gc, TableState<'gc>>>,
+    user_data: FinalizerQueue<'gc, GcCell<'gc, UserDataState<'gc>>>,
+}
+
+impl<'gc> Finalizers<'gc> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> Finalizers<'gc> {
+        Finalizers {
+            tables: FinalizerQueue::new(mc),
+            user_data: FinalizerQueue::new(mc),
+        }
+    }
+
+    /// Registers the given table for finalization if its metatable has a `__gc` field and it is not
+    /// already registered.
+    pub fn register_table(&self, mc: MutationContext<'gc, '_>, table: Table<'gc>) {
+        if has_gc(table.metatable()) {
+            let mut state = table.0.write(mc);
+            if !state.finalizer_registered {
+                state.finalizer_registered = true;
+                self.tables.register(mc, table.0);
+            }
+        }
+    }
+
+    /// Registers the given userdata for finalization if its metatable has a `__gc` field and it is
+    /// not already registered.
+    pub fn register_user_data(&self, mc: MutationContext<'gc, '_>, user_data: UserData<'gc>) {
+        if has_gc(user_data.metatable()) {
+            let mut state = user_data.0.write(mc);
+            if !state.finalizer_registered {
+                state.finalizer_registered = true;
+                self.user_data.register(mc, user_data.0);
+            }
+        }
+    }
+
+    /// Takes the next unreachable object that has a `__gc` metamethod, returning the metamethod
+    /// and the object it should be called with.  Objects whose metatable no longer has a `__gc`
+    /// function are skipped.
+    pub fn take_ready(&self, mc: MutationContext<'gc, '_>) -> Option<(Function<'gc>, Value<'gc>)> {
+        while let Some(ptr) = self.tables.take_ready(mc) {
+            let table = Table(ptr);
+            table.0.write(mc).finalizer_registered = false;
+            if let Some(function) = get_gc(table.metatable()) {
+                return Some((function, Value::Table(table)));
+            }
+        }
+
+        while let Some(ptr) = self.user_data.take_ready(mc) {
+            let user_data = UserData(ptr);
+            user_data.0.write(mc).finalizer_registered = false;
+            if let Some(function) = get_gc(user_data.metatable()) {
+                return Some((function, Value::UserData(user_data)));
+            }
+        }
+
+        None
+    }
+}
+
+fn has_gc<'gc>(metatable: Option<Table<'gc>>) -> bool {
+    match metatable {
+        Some(mt) => mt.get(MetaMethod::Gc) != Value::Nil,
+        None => false,
+    }
+}
+
+fn get_gc<'gc>(metatable: Option<Table<'gc>>) -> Option<Function<'gc>> {
+    match metatable?.get(MetaMethod::Gc) {
+        Value::Function(function) => Some(function),
+        _ => None,
+    }
+}
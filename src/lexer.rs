@@ -1,8 +1,11 @@
+use std::error::Error as StdError;
 use std::io::{self, Read};
 use std::{char, fmt, i32, i64, str};
 
 use gc_arena::Collect;
 
+use crate::LineNumber;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token<S> {
     Break,
@@ -68,9 +71,42 @@ pub enum Token<S> {
     String(S),
 }
 
+/// A position in the source of a chunk.
+#[derive(Debug, Collect, Copy, Clone, PartialEq, Eq)]
+#[collect(require_static)]
+pub struct Span {
+    pub line: LineNumber,
+    /// The 1-indexed byte column within the line.
+    pub column: u64,
+    /// The byte offset from the start of the source.
+    pub offset: u64,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Collect)]
+#[collect(require_static)]
+pub struct LexerError {
+    pub kind: LexerErrorKind,
+    /// Where in the source the error was found.
+    pub span: Span,
+}
+
+impl StdError for LexerError {}
+
+impl fmt::Display for LexerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
 #[derive(Debug, Collect)]
 #[collect(require_static)]
-pub enum LexerError {
+pub enum LexerErrorKind {
     UnfinishedShortString(u8),
     UnexpectedCharacter(u8),
     HexDigitExpected,
@@ -85,33 +121,35 @@ pub enum LexerError {
     IOError(io::Error),
 }
 
-impl fmt::Display for LexerError {
+impl fmt::Display for LexerErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fn print_char(c: u8) -> char {
             char::from_u32(c as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
         }
 
         match self {
-            LexerError::UnfinishedShortString(c) => write!(
+            LexerErrorKind::UnfinishedShortString(c) => write!(
                 f,
                 "short string not finished, expected matching {}",
                 print_char(*c)
             ),
-            LexerError::UnexpectedCharacter(c) => {
+            LexerErrorKind::UnexpectedCharacter(c) => {
                 write!(f, "unexpected character: '{}'", print_char(*c))
             }
-            LexerError::HexDigitExpected => write!(f, "hexadecimal digit expected"),
-            LexerError::EscapeUnicodeStart => write!(f, "missing '{{' in \\u{{xxxx}} escape"),
-            LexerError::EscapeUnicodeEnd => write!(f, "missing '}}' in \\u{{xxxx}} escape"),
-            LexerError::EscapeUnicodeInvalid => {
+            LexerErrorKind::HexDigitExpected => write!(f, "hexadecimal digit expected"),
+            LexerErrorKind::EscapeUnicodeStart => write!(f, "missing '{{' in \\u{{xxxx}} escape"),
+            LexerErrorKind::EscapeUnicodeEnd => write!(f, "missing '}}' in \\u{{xxxx}} escape"),
+            LexerErrorKind::EscapeUnicodeInvalid => {
                 write!(f, "invalid unicode value in \\u{{xxxx}} escape")
             }
-            LexerError::EscapeDecimalTooLarge => write!(f, "\\ddd escape out of 0-255 range"),
-            LexerError::InvalidEscape => write!(f, "invalid escape sequence"),
-            LexerError::InvalidLongStringDelimiter => write!(f, "invalid long string delimiter"),
-            LexerError::UnfinishedLongString => write!(f, "unfinished long string"),
-            LexerError::BadNumber => write!(f, "malformed number"),
-            LexerError::IOError(err) => write!(f, "IO Error: {}", err),
+            LexerErrorKind::EscapeDecimalTooLarge => write!(f, "\\ddd escape out of 0-255 range"),
+            LexerErrorKind::InvalidEscape => write!(f, "invalid escape sequence"),
+            LexerErrorKind::InvalidLongStringDelimiter => {
+                write!(f, "invalid long string delimiter")
+            }
+            LexerErrorKind::UnfinishedLongString => write!(f, "unfinished long string"),
+            LexerErrorKind::BadNumber => write!(f, "malformed number"),
+            LexerErrorKind::IOError(err) => write!(f, "IO Error: {}", err),
         }
     }
 }
@@ -122,6 +160,9 @@ pub struct Lexer<R, CS> {
     peek_buffer: Vec<u8>,
     string_buffer: Vec<u8>,
     line_number: u64,
+    // The number of bytes consumed so far, and the offset of the start of the current line.
+    offset: u64,
+    line_offset: u64,
 }
 
 impl<R, S, CS> Lexer<R, CS>
@@ -136,6 +177,8 @@ where
             peek_buffer: Vec::new(),
             string_buffer: Vec::new(),
             line_number: 0,
+            offset: 0,
+            line_offset: 0,
         }
     }
 
@@ -144,6 +187,15 @@ where
         self.line_number
     }
 
+    /// The position of the next unread character in the source.
+    pub fn position(&self) -> Span {
+        Span {
+            line: LineNumber(self.line_number + 1),
+            column: self.offset - self.line_offset + 1,
+            offset: self.offset,
+        }
+    }
+
     pub fn skip_whitespace(&mut self) -> Result<(), LexerError> {
         let mut do_skip_whitespace = || {
             while let Some(c) = self.peek(0)? {
@@ -190,10 +242,7 @@ where
 
         match do_skip_whitespace() {
             Ok(()) => Ok(()),
-            Err(err) => {
-                self.reset();
-                Err(err)
-            }
+            Err(kind) => Err(self.error(kind)),
         }
     }
 
@@ -345,7 +394,7 @@ where
                                 Token::Name(self.take_string())
                             }
                         } else {
-                            return Err(LexerError::UnexpectedCharacter(c));
+                            return Err(LexerErrorKind::UnexpectedCharacter(c));
                         }
                     }
                 }))
@@ -356,13 +405,21 @@ where
 
         match do_read_token() {
             Ok(Some(token)) => Ok(Some(token)),
-            res => {
+            Ok(None) => {
                 self.reset();
-                res
+                Ok(None)
             }
+            Err(kind) => Err(self.error(kind)),
         }
     }
 
+    // Errors are located at the character where they were found, and end the stream.
+    fn error(&mut self, kind: LexerErrorKind) -> LexerError {
+        let span = self.position();
+        self.reset();
+        LexerError { kind, span }
+    }
+
     // End of stream encountered, clear any input handles and temp buffers
     fn reset(&mut self) {
         self.source = None;
@@ -372,7 +429,7 @@ where
 
     // Read any of "\n", "\r", "\n\r", or "\r\n" as a single newline, and increment the current line
     // number.  If `append_buffer` is true, then appends the read newline to the string buffer.
-    fn read_line_end(&mut self, append_string: bool) -> Result<(), LexerError> {
+    fn read_line_end(&mut self, append_string: bool) -> Result<(), LexerErrorKind> {
         let newline = self.peek(0).unwrap().unwrap();
         assert!(is_newline(newline));
         self.advance(1);
@@ -390,12 +447,13 @@ where
         }
 
         self.line_number += 1;
+        self.line_offset = self.offset;
         Ok(())
     }
 
     // Read a string on a single line delimited by ' or " that allows for \ escaping of certain
     // characters.  Always reads the contained string into the string buffer.
-    fn read_short_string(&mut self) -> Result<(), LexerError> {
+    fn read_short_string(&mut self) -> Result<(), LexerErrorKind> {
         let start_quote = self.peek(0).unwrap().unwrap();
         assert!(start_quote == b'\'' || start_quote == b'"');
         self.advance(1);
@@ -406,18 +464,18 @@ where
             let c = if let Some(c) = self.peek(0)? {
                 c
             } else {
-                return Err(LexerError::UnfinishedShortString(start_quote));
+                return Err(LexerErrorKind::UnfinishedShortString(start_quote));
             };
 
             if is_newline(c) {
-                return Err(LexerError::UnfinishedShortString(start_quote));
+                return Err(LexerErrorKind::UnfinishedShortString(start_quote));
             }
 
             self.advance(1);
             if c == b'\\' {
                 match self
                     .peek(0)?
-                    .ok_or_else(|| LexerError::UnfinishedShortString(start_quote))?
+                    .ok_or_else(|| LexerErrorKind::UnfinishedShortString(start_quote))?
                 {
                     b'a' => {
                         self.advance(1);
@@ -478,18 +536,18 @@ where
                         let first = self
                             .peek(0)?
                             .and_then(from_hex_digit)
-                            .ok_or(LexerError::HexDigitExpected)?;
+                            .ok_or(LexerErrorKind::HexDigitExpected)?;
                         let second = self
                             .peek(1)?
                             .and_then(from_hex_digit)
-                            .ok_or(LexerError::HexDigitExpected)?;
+                            .ok_or(LexerErrorKind::HexDigitExpected)?;
                         self.string_buffer.push(first << 4 | second);
                         self.advance(2);
                     }
 
                     b'u' => {
                         if self.peek(1)? != Some(b'{') {
-                            return Err(LexerError::EscapeUnicodeStart);
+                            return Err(LexerErrorKind::EscapeUnicodeStart);
                         }
                         self.advance(2);
 
@@ -503,14 +561,14 @@ where
                                     u = (u << 4) | h as u32;
                                     self.advance(1);
                                 } else {
-                                    return Err(LexerError::EscapeUnicodeEnd);
+                                    return Err(LexerErrorKind::EscapeUnicodeEnd);
                                 }
                             } else {
-                                return Err(LexerError::EscapeUnicodeEnd);
+                                return Err(LexerErrorKind::EscapeUnicodeEnd);
                             }
                         }
 
-                        let c = char::from_u32(u).ok_or(LexerError::EscapeUnicodeInvalid)?;
+                        let c = char::from_u32(u).ok_or(LexerErrorKind::EscapeUnicodeInvalid)?;
                         let mut buf = [0; 4];
                         for &b in c.encode_utf8(&mut buf).as_bytes() {
                             self.string_buffer.push(b);
@@ -542,12 +600,12 @@ where
                                 }
                             }
                             if u > 255 {
-                                return Err(LexerError::EscapeDecimalTooLarge);
+                                return Err(LexerErrorKind::EscapeDecimalTooLarge);
                             }
 
                             self.string_buffer.push(u as u8);
                         } else {
-                            return Err(LexerError::InvalidEscape);
+                            return Err(LexerErrorKind::InvalidEscape);
                         }
                     }
                 }
@@ -563,7 +621,7 @@ where
 
     // Read a [=*[...]=*] sequence with matching numbers of '='.  If `into_string` is true, writes
     // the contained string into the string buffer.
-    fn read_long_string(&mut self, into_string: bool) -> Result<(), LexerError> {
+    fn read_long_string(&mut self, into_string: bool) -> Result<(), LexerErrorKind> {
         assert_eq!(self.peek(0).unwrap().unwrap(), b'[');
         self.advance(1);
 
@@ -578,7 +636,7 @@ where
         }
 
         if self.peek(0)? != Some(b'[') {
-            return Err(LexerError::InvalidLongStringDelimiter);
+            return Err(LexerErrorKind::InvalidLongStringDelimiter);
         }
         self.advance(1);
 
@@ -586,7 +644,7 @@ where
             let c = if let Some(c) = self.peek(0)? {
                 c
             } else {
-                return Err(LexerError::UnfinishedLongString);
+                return Err(LexerErrorKind::UnfinishedLongString);
             };
 
             match c {
@@ -632,7 +690,7 @@ where
     // Reads a hex or decimal integer or floating point identifier.  Allows decimal integers (123),
     // hex integers (0xdeadbeef), decimal floating point with optional exponent and exponent sign
     // (3.21e+1), and hex floats with optional exponent and exponent sign (0xe.2fp-1c).
-    fn read_numeral(&mut self) -> Result<Token<S>, LexerError> {
+    fn read_numeral(&mut self) -> Result<Token<S>, LexerErrorKind> {
         let p1 = self.peek(0).unwrap().unwrap();
         assert!(p1 == b'.' || is_digit(p1));
 
@@ -704,11 +762,11 @@ where
             } else {
                 read_float(&self.string_buffer)
             }
-            .ok_or(LexerError::BadNumber)?,
+            .ok_or(LexerErrorKind::BadNumber)?,
         ))
     }
 
-    fn peek(&mut self, n: usize) -> Result<Option<u8>, LexerError> {
+    fn peek(&mut self, n: usize) -> Result<Option<u8>, LexerErrorKind> {
         if let Some(source) = self.source.as_mut() {
             while self.peek_buffer.len() <= n {
                 let mut c = [0];
@@ -723,7 +781,7 @@ where
                     Err(e) => {
                         if e.kind() != io::ErrorKind::Interrupted {
                             self.source = None;
-                            return Err(LexerError::IOError(e));
+                            return Err(LexerErrorKind::IOError(e));
                         }
                     }
                 }
@@ -739,6 +797,7 @@ where
             "cannot advance over un-peeked characters"
         );
         self.peek_buffer.drain(0..n);
+        self.offset += n as u64;
     }
 
     fn take_string(&mut self) -> S {
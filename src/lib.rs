@@ -4,37 +4,55 @@ mod closure;
 mod compiler;
 mod constant;
 mod error;
+mod finalizers;
 pub mod io;
 mod lexer;
 #[macro_use]
 mod lua;
+mod meta_ops;
+mod metatables;
 mod opcode;
+mod output;
 pub mod parser;
 mod string;
 mod table;
 mod thread;
 mod types;
+mod userdata;
+mod userdata_type;
 mod value;
 
 mod stdlib;
 
-pub use callback::{Callback, CallbackResult, CallbackReturn, Continuation};
+pub use callback::{Callback, CallbackResult, CallbackReturn, Continuation, ValueBuffer};
 pub use closure::{
-    Closure, ClosureError, ClosureState, FunctionProto, UpValue, UpValueDescriptor, UpValueState,
+    Closure, ClosureError, ClosureState, FunctionProto, LocalVariable, UpValue, UpValueDescriptor,
+    UpValueState,
 };
-pub use compiler::{compile, compile_chunk, CompilerError};
+pub use compiler::{compile, compile_chunk, compile_named, CompilerError};
 pub use constant::Constant;
-pub use error::{Error, RuntimeError, StaticError, TypeError};
-pub use lexer::{Lexer, LexerError, Token};
-pub use lua::{Lua, Root};
+pub use error::{
+    Error, FrameKind, FunctionName, RuntimeError, StaticError, Traceback, TracebackFrame,
+    TypeError, VariableName,
+};
+pub use finalizers::Finalizers;
+pub use lexer::{Lexer, LexerError, LexerErrorKind, Span, Token};
+pub use lua::{Budget, Execution, Lua, LuaBuilder, Root};
+pub use meta_ops::MetaMethod;
+pub use metatables::Metatables;
 pub use opcode::OpCode;
-pub use parser::{parse_chunk, ParserError};
+pub use output::{Output, WriteFn};
+pub use parser::{parse_chunk, LineNumber, ParserError, ParserErrorKind};
+pub use stdlib::StdLib;
 pub use string::{InternedStringSet, String, StringError};
 pub use table::{InvalidTableKey, Table, TableState};
 pub use thread::{
-    BadThreadMode, BinaryOperatorError, Thread, ThreadError, ThreadMode, ThreadSequence,
+    BadThreadMode, BinaryOperatorError, Hook, HookAction, HookEvent, HookFn, HookFrame, HookMask,
+    Thread, ThreadError, ThreadMode, ThreadSequence,
 };
 pub use types::{
     ConstantIndex16, ConstantIndex8, Opt254, PrototypeIndex, RegisterIndex, UpValueIndex, VarCount,
 };
+pub use userdata::{UserData, UserDataState};
+pub use userdata_type::{UserDataMethods, UserDataRegistry, UserDataRegistryState, UserDataType};
 pub use value::{Function, Value};
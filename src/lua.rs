@@ -1,9 +1,16 @@
+use std::io::Write;
+use std::time::{Duration, Instant};
+
 use gc_arena::{ArenaParameters, Collect, MutationContext};
-use gc_sequence::{make_sequencable_arena, Sequence};
+use gc_sequence::{
+    self as sequence, make_sequencable_arena, Sequence, SequenceExt, SequenceResultExt,
+};
 
 use crate::{
-    stdlib::{load_base, load_coroutine, load_math},
-    InternedStringSet, Table, Thread,
+    stdlib::StdLib,
+    thread::{with_instruction_limit, with_memory_limit},
+    Error, Finalizers, InternedStringSet, MetaMethod, Metatables, Output, StaticError, String,
+    Table, Thread, ThreadSequence, UserDataRegistry, Value,
 };
 
 #[derive(Collect, Clone, Copy)]
@@ -12,22 +19,36 @@ pub struct Root<'gc> {
     pub main_thread: Thread<'gc>,
     pub globals: Table<'gc>,
     pub interned_strings: InternedStringSet<'gc>,
+    pub finalizers: Finalizers<'gc>,
+    pub user_data: UserDataRegistry<'gc>,
+    pub output: Output<'gc>,
+    pub metatables: Metatables<'gc>,
 }
 
 impl<'gc> Root<'gc> {
     pub fn new(mc: MutationContext<'gc, '_>) -> Root<'gc> {
-        let root = Root {
-            main_thread: Thread::new(mc, false),
-            globals: Table::new(mc),
-            interned_strings: InternedStringSet::new(mc),
-        };
-
-        load_base(mc, root, root.globals);
-        load_coroutine(mc, root, root.globals);
-        load_math(mc, root, root.globals);
-
+        let root = Root::with_globals(mc, Table::new(mc));
+        for lib in StdLib::ALL {
+            lib.load(mc, root, root.globals);
+        }
         root
     }
+
+    /// Creates a root which uses the given table as its global environment, without loading any
+    /// standard libraries into it.
+    pub fn with_globals(mc: MutationContext<'gc, '_>, globals: Table<'gc>) -> Root<'gc> {
+        let finalizers = Finalizers::new(mc);
+        let metatables = Metatables::new(mc);
+        Root {
+            main_thread: Thread::new(mc, metatables, false),
+            globals,
+            interned_strings: InternedStringSet::new(mc),
+            finalizers,
+            user_data: UserDataRegistry::new(mc, finalizers),
+            output: Output::new(mc),
+            metatables,
+        }
+    }
 }
 
 make_sequencable_arena!(pub lua_arena, Root);
@@ -36,15 +57,55 @@ pub use lua_arena::Arena;
 pub use lua_arena::Sequencer;
 
 /// Simpler wrapper for `Arena` that automatically garbage collects at reasonable intervals.
-pub struct Lua(Option<lua_arena::Arena>);
+pub struct Lua {
+    arena: Option<lua_arena::Arena>,
+    memory_limit: Option<usize>,
+}
 
 const COLLECTOR_GRANULARITY: f64 = 1024.0;
 
 impl Lua {
     pub fn new() -> Lua {
-        Lua(Some(Arena::new(ArenaParameters::default(), |mc| {
-            Root::new(mc)
-        })))
+        LuaBuilder::new().build()
+    }
+
+    /// Returns a `LuaBuilder` for creating a `Lua` with a customized global environment.
+    pub fn builder() -> LuaBuilder {
+        LuaBuilder::new()
+    }
+
+    /// Limits the total number of bytes that the Lua arena may allocate, or removes the limit if
+    /// `None`.  The default is no limit.
+    ///
+    /// When running Lua code would go over the limit, a full garbage collection is run first, and
+    /// if the arena is still over the limit afterwards the running Lua code raises a "not enough
+    /// memory" error, which may be caught with `pcall`.  Standard library functions that build
+    /// arbitrarily large strings, such as `string.rep`, check the limit before allocating and raise
+    /// the same error.  The limit is only enforced while Lua code is running inside `Lua::sequence`
+    /// or an `Execution`, so `Lua::mutate` may still go over it.
+    pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+        self.memory_limit = memory_limit;
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.memory_limit
+    }
+
+    /// Replaces the standard output that `print` writes to, returning the previous one.
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) -> Box<dyn Write> {
+        let stdout: Box<dyn Write> = Box::new(stdout);
+        self.mutate(move |_, root| root.output.set_stdout(stdout))
+    }
+
+    /// Replaces the standard error output, returning the previous one.
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) -> Box<dyn Write> {
+        let stderr: Box<dyn Write> = Box::new(stderr);
+        self.mutate(move |_, root| root.output.set_stderr(stderr))
+    }
+
+    /// The total number of bytes currently allocated by the Lua arena.
+    pub fn total_allocated(&self) -> usize {
+        self.arena.as_ref().unwrap().total_allocated()
     }
 
     /// Runs a single action inside the Lua arena, during which no garbage collection may take place.
@@ -53,7 +114,7 @@ impl Lua {
         R: 'static,
         F: for<'gc> FnOnce(MutationContext<'gc, '_>, Root<'gc>) -> R,
     {
-        let arena = self.0.as_mut().unwrap();
+        let arena = self.arena.as_mut().unwrap();
         let r = arena.mutate(move |mc, root| f(mc, *root));
         if arena.allocation_debt() > COLLECTOR_GRANULARITY {
             arena.collect_debt();
@@ -68,12 +129,350 @@ impl Lua {
         R: 'static,
         F: for<'gc> FnOnce(Root<'gc>) -> Box<dyn Sequence<'gc, Output = R> + 'gc>,
     {
-        let mut sequencer = self.0.take().unwrap().sequence(move |root| f(*root));
+        let lua = Lua {
+            arena: self.arena.take(),
+            memory_limit: self.memory_limit,
+        };
+        match lua.start(f).run(Budget::default()) {
+            Ok((lua, output)) => {
+                *self = lua;
+                output
+            }
+            Err(_) => unreachable!("unlimited execution did not finish"),
+        }
+    }
+
+    /// Starts a sequence of actions inside the Lua arena without running any of it.  The returned
+    /// `Execution` can then be run a little at a time with `Execution::run`, which gives back this
+    /// `Lua` once the sequence is finished.
+    pub fn start<F, R>(mut self, f: F) -> Execution<R>
+    where
+        R: 'static,
+        F: for<'gc> FnOnce(Root<'gc>) -> Box<dyn Sequence<'gc, Output = R> + 'gc>,
+    {
+        Execution {
+            sequencer: Box::new(self.arena.take().unwrap().sequence(move |root| f(*root))),
+            memory_limit: self.memory_limit,
+        }
+    }
+
+    /// Runs a full garbage collection cycle.
+    pub fn collect_garbage(&mut self) {
+        let arena = self.arena.as_mut().unwrap();
+        // The first collection only finishes the cycle in progress, which may have already marked
+        // objects that have since become unreachable.
+        arena.collect_all();
+        arena.collect_all();
+    }
+
+    /// The number of objects that the garbage collector has found to be unreachable and that are
+    /// waiting for their `__gc` metamethod to be run by `Lua::finalize`.
+    pub fn pending_finalization(&self) -> usize {
+        self.arena.as_ref().unwrap().pending_finalization()
+    }
+
+    /// Runs the `__gc` metamethods of every object that the garbage collector has found to be
+    /// unreachable.  Finalizers are never run automatically, so the host decides when they run.
+    ///
+    /// Each finalizer is called on a new thread with the finalized object as its only argument.  If
+    /// a finalizer fails, its error is returned immediately and any remaining finalizers are left
+    /// pending, so calling `finalize` again will continue with the next one.  A finalizer is only
+    /// ever called once per registration, even if it fails.
+    pub fn finalize(&mut self) -> Result<(), StaticError> {
+        while self.pending_finalization() > 0 {
+            self.sequence(|root| {
+                sequence::from_fn_with(root, |mc, root| {
+                    Ok(match root.finalizers.take_ready(mc) {
+                        Some((function, object)) => ThreadSequence::call_function(
+                            mc,
+                            Thread::new(mc, root.metatables, false),
+                            function,
+                            &[object],
+                        )?
+                        .map_ok(|_| ())
+                        .boxed(),
+                        None => sequence::ok(()).boxed(),
+                    })
+                })
+                .flatten_ok()
+                .map_err(Error::to_static)
+                .boxed()
+            })?;
+        }
+        Ok(())
+    }
+}
+
+type GlobalsFn = Box<dyn for<'gc> FnOnce(MutationContext<'gc, '_>) -> Table<'gc>>;
+type SetupFn = Box<dyn for<'gc> FnOnce(MutationContext<'gc, '_>, Root<'gc>)>;
+
+/// Builds a `Lua` that loads only a chosen set of standard libraries, and whose global environment
+/// may be customized further before any Lua code runs.  This allows for running scripts in a
+/// restricted environment, such as one with no `print`, or one where `print` is replaced with a
+/// Rust callback.
+///
+/// Once the global table is created and the selected libraries are loaded into it, every
+/// `set_global`, `remove_global` and `setup` call is applied in the order it was made.
+pub struct LuaBuilder {
+    libs: Vec<StdLib>,
+    globals: Option<GlobalsFn>,
+    setup: Vec<SetupFn>,
+    stdout: Option<Box<dyn Write>>,
+    stderr: Option<Box<dyn Write>>,
+    memory_limit: Option<usize>,
+}
+
+impl Default for LuaBuilder {
+    fn default() -> LuaBuilder {
+        LuaBuilder::new()
+    }
+}
+
+impl LuaBuilder {
+    /// Returns a builder which loads every standard library into an empty global table, just like
+    /// `Lua::new`.
+    pub fn new() -> LuaBuilder {
+        LuaBuilder {
+            libs: StdLib::ALL.to_vec(),
+            globals: None,
+            setup: Vec::new(),
+            stdout: None,
+            stderr: None,
+            memory_limit: None,
+        }
+    }
+
+    /// Loads only the given standard libraries, in the given order.  Strings only have methods if
+    /// `StdLib::String` is loaded, as it is what sets the metatable shared by all strings.
+    pub fn set_libs(mut self, libs: &[StdLib]) -> LuaBuilder {
+        self.libs = libs.to_vec();
+        self
+    }
+
+    /// Uses the table returned by the given function as the global environment instead of a new
+    /// empty table.  The selected standard libraries are loaded into this table.
+    pub fn set_globals<F>(mut self, f: F) -> LuaBuilder
+    where
+        F: 'static + for<'gc> FnOnce(MutationContext<'gc, '_>) -> Table<'gc>,
+    {
+        self.globals = Some(Box::new(f));
+        self
+    }
+
+    /// Sets the global at the given path to the value returned by the given function, replacing
+    /// any existing value.  The path may name a field of a global table, such as `"math.random"`,
+    /// in which case any missing tables along the path are created.
+    pub fn set_global<F>(self, path: &'static str, f: F) -> LuaBuilder
+    where
+        F: 'static + for<'gc> FnOnce(MutationContext<'gc, '_>, Root<'gc>) -> Value<'gc>,
+    {
+        self.setup(move |mc, root| {
+            let value = f(mc, root);
+            if let Some((table, key)) = global_path(mc, root.globals, path, true) {
+                table.set(mc, key, value).unwrap();
+            }
+        })
+    }
+
+    /// Removes the global at the given path, which may name a field of a global table in the same
+    /// way as `set_global`.  Does nothing if the global does not exist.
+    ///
+    /// Strings share a metatable whose `__index` is the `string` table, so removing `"string"` also
+    /// removes this metatable, otherwise string methods would still be reachable as `s:rep(n)`.
+    /// Removing a single function such as `"string.rep"` removes the method as well.
+    pub fn remove_global(self, path: &'static str) -> LuaBuilder {
+        self.setup(move |mc, root| {
+            if let Some((table, key)) = global_path(mc, root.globals, path, false) {
+                let removed = table.get(key);
+                table.set(mc, key, Value::Nil).unwrap();
+                if let Some(metatable) = root.metatables.string() {
+                    if removed != Value::Nil && metatable.get(MetaMethod::Index) == removed {
+                        root.metatables.set_string(mc, None);
+                    }
+                }
+            }
+        })
+    }
+
+    /// Runs the given function on the root once the standard libraries are loaded, for any setup
+    /// that `set_global` and `remove_global` cannot express.
+    pub fn setup<F>(mut self, f: F) -> LuaBuilder
+    where
+        F: 'static + for<'gc> FnOnce(MutationContext<'gc, '_>, Root<'gc>),
+    {
+        self.setup.push(Box::new(f));
+        self
+    }
+
+    /// Sets the standard output of the `Lua`, see `Lua::set_stdout`.
+    pub fn set_stdout(mut self, stdout: impl Write + 'static) -> LuaBuilder {
+        self.stdout = Some(Box::new(stdout));
+        self
+    }
+
+    /// Sets the standard error output of the `Lua`, see `Lua::set_stderr`.
+    pub fn set_stderr(mut self, stderr: impl Write + 'static) -> LuaBuilder {
+        self.stderr = Some(Box::new(stderr));
+        self
+    }
+
+    /// Sets the initial memory limit of the `Lua`, see `Lua::set_memory_limit`.
+    pub fn set_memory_limit(mut self, memory_limit: Option<usize>) -> LuaBuilder {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    pub fn build(self) -> Lua {
+        let LuaBuilder {
+            libs,
+            globals,
+            setup,
+            stdout,
+            stderr,
+            memory_limit,
+        } = self;
+        let arena = Arena::new(ArenaParameters::default(), move |mc| {
+            let globals = match globals {
+                Some(globals) => globals(mc),
+                None => Table::new(mc),
+            };
+            let root = Root::with_globals(mc, globals);
+            if let Some(stdout) = stdout {
+                root.output.set_stdout(stdout);
+            }
+            if let Some(stderr) = stderr {
+                root.output.set_stderr(stderr);
+            }
+            for lib in libs {
+                lib.load(mc, root, root.globals);
+            }
+            for f in setup {
+                f(mc, root);
+            }
+            root
+        });
+        Lua {
+            arena: Some(arena),
+            memory_limit,
+        }
+    }
+}
+
+// Finds the table and key that a dotted global path such as `"math.random"` refers to.  Missing
+// tables along the path are created if `create` is set, otherwise `None` is returned if any part of
+// the path before the final key is not a table.
+fn global_path<'gc>(
+    mc: MutationContext<'gc, '_>,
+    globals: Table<'gc>,
+    path: &'static str,
+    create: bool,
+) -> Option<(Table<'gc>, String<'gc>)> {
+    let mut parts = path.split('.');
+    let mut key = parts.next().unwrap();
+    let mut table = globals;
+    for next in parts {
+        let name = String::new_static(key.as_bytes());
+        table = match table.get(name) {
+            Value::Table(t) => t,
+            Value::Nil if create => {
+                let t = Table::new(mc);
+                table.set(mc, name, t).unwrap();
+                t
+            }
+            _ => return None,
+        };
+        key = next;
+    }
+    Some((table, String::new_static(key.as_bytes())))
+}
+
+/// Limits how long a single call to `Execution::run` may run for.  The default budget is unlimited.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// The maximum number of VM instructions to run, across every thread
+    pub instructions: Option<u64>,
+    /// The maximum wall-clock time to run for.  This is only checked in-between sequence steps, so
+    /// it may be overrun by however long a single step takes.
+    pub duration: Option<Duration>,
+}
+
+impl Budget {
+    pub fn instructions(instructions: u64) -> Budget {
+        Budget {
+            instructions: Some(instructions),
+            duration: None,
+        }
+    }
+
+    pub fn duration(duration: Duration) -> Budget {
+        Budget {
+            instructions: None,
+            duration: Some(duration),
+        }
+    }
+}
+
+/// A sequence started with `Lua::start` that has not yet finished.
+///
+/// An `Execution` owns the `Lua` it was started on until the sequence finishes.  Dropping it
+/// cancels the sequence and drops the `Lua` along with it, `Execution::cancel` cancels the
+/// sequence but gives back the `Lua`.
+pub struct Execution<R: 'static> {
+    sequencer: Box<lua_arena::Sequencer<R>>,
+    memory_limit: Option<usize>,
+}
+
+impl<R: 'static> Execution<R> {
+    /// Runs the sequence until it finishes or the given budget is used up.  Returns the `Lua` along
+    /// with the output of the sequence if it finished, otherwise returns this `Execution` so that
+    /// it can be run again later.
+    ///
+    /// Lua code that runs forever, such as `while true do end`, still returns once the budget is
+    /// used up.
+    pub fn run(self, budget: Budget) -> Result<(Lua, R), Execution<R>> {
+        let start = Instant::now();
+        let mut instructions = budget.instructions;
+        let memory_limit = self.memory_limit;
+        let mut sequencer = self.sequencer;
         loop {
-            match sequencer.step() {
+            if instructions == Some(0) || matches!(budget.duration, Some(d) if start.elapsed() >= d)
+            {
+                return Err(Execution {
+                    sequencer,
+                    memory_limit,
+                });
+            }
+
+            // Lua threads stop as soon as the arena goes over the memory limit, so that an
+            // emergency collection can be run here before deciding whether they should fail.  As
+            // in `Lua::collect_garbage`, a second cycle is needed if the first one had already
+            // started.
+            let out_of_memory = match memory_limit {
+                Some(limit) if sequencer.total_allocated() > limit => {
+                    sequencer.collect_all();
+                    if sequencer.total_allocated() > limit {
+                        sequencer.collect_all();
+                    }
+                    sequencer.total_allocated() > limit
+                }
+                _ => false,
+            };
+
+            let (res, run) = with_instruction_limit(instructions, move || {
+                with_memory_limit(memory_limit, out_of_memory, move || {
+                    sequencer.step().map_err(Box::new)
+                })
+            });
+            instructions = instructions.map(|i| i.saturating_sub(run));
+            match res {
                 Ok((arena, output)) => {
-                    self.0 = Some(arena);
-                    return output;
+                    return Ok((
+                        Lua {
+                            arena: Some(arena),
+                            memory_limit,
+                        },
+                        output,
+                    ))
                 }
                 Err(s) => {
                     sequencer = s;
@@ -84,4 +483,15 @@ impl Lua {
             }
         }
     }
+
+    /// Cancels the sequence without running any more of it, returning the `Lua` it was started on.
+    /// The main thread is reset, so that it may be used to call functions again.
+    pub fn cancel(self) -> Lua {
+        let mut arena = self.sequencer.abort();
+        arena.mutate(|mc, root| root.main_thread.reset(mc));
+        Lua {
+            arena: Some(arena),
+            memory_limit: self.memory_limit,
+        }
+    }
 }
@@ -0,0 +1,564 @@
+use gc_arena::MutationContext;
+use gc_sequence as sequence;
+
+use crate::{
+    BinaryOperatorError, Callback, CallbackResult, CallbackReturn, Continuation, Error, Function,
+    Metatables, RuntimeError, String, StringError, Table, TypeError, Value, ValueBuffer,
+};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MetaMethod {
+    Index,
+    NewIndex,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Unm,
+    IDiv,
+    BAnd,
+    BOr,
+    BXor,
+    Shl,
+    Shr,
+    BNot,
+    Eq,
+    Lt,
+    Le,
+    Concat,
+    Len,
+    Call,
+    Gc,
+    Mode,
+    Close,
+    ToString,
+}
+
+impl MetaMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            MetaMethod::Index => "__index",
+            MetaMethod::NewIndex => "__newindex",
+            MetaMethod::Add => "__add",
+            MetaMethod::Sub => "__sub",
+            MetaMethod::Mul => "__mul",
+            MetaMethod::Div => "__div",
+            MetaMethod::Mod => "__mod",
+            MetaMethod::Pow => "__pow",
+            MetaMethod::Unm => "__unm",
+            MetaMethod::IDiv => "__idiv",
+            MetaMethod::BAnd => "__band",
+            MetaMethod::BOr => "__bor",
+            MetaMethod::BXor => "__bxor",
+            MetaMethod::Shl => "__shl",
+            MetaMethod::Shr => "__shr",
+            MetaMethod::BNot => "__bnot",
+            MetaMethod::Eq => "__eq",
+            MetaMethod::Lt => "__lt",
+            MetaMethod::Le => "__le",
+            MetaMethod::Concat => "__concat",
+            MetaMethod::Len => "__len",
+            MetaMethod::Call => "__call",
+            MetaMethod::Gc => "__gc",
+            MetaMethod::Mode => "__mode",
+            MetaMethod::Close => "__close",
+            MetaMethod::ToString => "__tostring",
+        }
+    }
+}
+
+impl<'gc> From<MetaMethod> for Value<'gc> {
+    fn from(method: MetaMethod) -> Value<'gc> {
+        Value::String(String::new_static(method.name().as_bytes()))
+    }
+}
+
+/// A metamethod function that must be called, along with the arguments it must be called with.
+pub struct MetaCall<'gc, const N: usize> {
+    pub function: Function<'gc>,
+    pub args: [Value<'gc>; N],
+}
+
+/// The result of a metamethod aware operation, either the operation completed immediately with a
+/// value, or a metamethod must be called to obtain the result.
+pub enum MetaResult<'gc, const N: usize> {
+    Value(Value<'gc>),
+    Call(MetaCall<'gc, N>),
+}
+
+// The maximum number of `__index` or `__newindex` tables that will be followed before giving up and
+// assuming there is a loop (this matches MAXTAGLOOP in PUC-Rio Lua).
+const MAX_META_CHAIN: usize = 2000;
+
+/// Performs the Lua index operation `table[key]`, following any `__index` metamethods.  Strings are
+/// indexed through the shared string metatable in `metatables`.
+pub fn index<'gc>(
+    metatables: Metatables<'gc>,
+    table: Value<'gc>,
+    key: Value<'gc>,
+) -> Result<MetaResult<'gc, 2>, Error<'gc>> {
+    let mut table = table;
+    for _ in 0..MAX_META_CHAIN {
+        let handler = match table {
+            Value::Table(t) => {
+                let value = t.get(key);
+                if value != Value::Nil {
+                    return Ok(MetaResult::Value(value));
+                }
+
+                match t.metatable() {
+                    Some(mt) => mt.get(MetaMethod::Index),
+                    None => Value::Nil,
+                }
+            }
+            value => {
+                let handler = match value {
+                    Value::String(_) => match metatables.string() {
+                        Some(mt) => mt.get(MetaMethod::Index),
+                        None => Value::Nil,
+                    },
+                    value => get_metamethod(value, MetaMethod::Index),
+                };
+                if handler == Value::Nil {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+                handler
+            }
+        };
+
+        match handler {
+            Value::Nil => return Ok(MetaResult::Value(Value::Nil)),
+            Value::Function(function) => {
+                return Ok(MetaResult::Call(MetaCall {
+                    function,
+                    args: [table, key],
+                }));
+            }
+            handler => table = handler,
+        }
+    }
+
+    Err(RuntimeError(Value::String(String::new_static(
+        b"'__index' chain too long; possible loop",
+    )))
+    .into())
+}
+
+/// Performs the Lua assignment operation `table[key] = value`, following any `__newindex`
+/// metamethods.  If a `__newindex` function must be called to complete the assignment, returns the
+/// call to be made.
+pub fn new_index<'gc>(
+    mc: MutationContext<'gc, '_>,
+    table: Value<'gc>,
+    key: Value<'gc>,
+    value: Value<'gc>,
+) -> Result<Option<MetaCall<'gc, 3>>, Error<'gc>> {
+    let mut table = table;
+    for _ in 0..MAX_META_CHAIN {
+        let handler = match table {
+            Value::Table(t) => {
+                let handler = match t.metatable() {
+                    Some(mt) if t.get(key) == Value::Nil => mt.get(MetaMethod::NewIndex),
+                    _ => Value::Nil,
+                };
+
+                if handler == Value::Nil {
+                    t.set(mc, key, value)?;
+                    return Ok(None);
+                }
+                handler
+            }
+            value => match get_metamethod(value, MetaMethod::NewIndex) {
+                Value::Nil => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+                handler => handler,
+            },
+        };
+
+        match handler {
+            Value::Function(function) => {
+                return Ok(Some(MetaCall {
+                    function,
+                    args: [table, key, value],
+                }));
+            }
+            handler => table = handler,
+        }
+    }
+
+    Err(RuntimeError(Value::String(String::new_static(
+        b"'__newindex' chain too long; possible loop",
+    )))
+    .into())
+}
+
+/// Looks up the metamethod for an arithmetic or bitwise operation that could not be performed
+/// directly on its operands.  The left operand is checked for a metamethod first, then the right.
+/// Unary operations (`__unm` and `__bnot`) should pass the single operand as both `left` and
+/// `right`, as PUC-Rio Lua does.
+pub fn arithmetic<'gc>(
+    method: MetaMethod,
+    left: Value<'gc>,
+    right: Value<'gc>,
+) -> Result<MetaCall<'gc, 2>, Error<'gc>> {
+    match get_binary_metamethod(left, right, method)? {
+        Some(function) => Ok(MetaCall {
+            function,
+            args: [left, right],
+        }),
+        None => Err(operator_error(method).into()),
+    }
+}
+
+/// Performs the Lua `==` operation.  The `__eq` metamethod is only consulted when both values are
+/// tables and they are not primitively equal.
+pub fn equal<'gc>(left: Value<'gc>, right: Value<'gc>) -> Result<MetaResult<'gc, 2>, Error<'gc>> {
+    if left == right {
+        return Ok(MetaResult::Value(Value::Boolean(true)));
+    }
+
+    match (left, right) {
+        (Value::Table(_), Value::Table(_)) | (Value::UserData(_), Value::UserData(_)) => {
+            match get_binary_metamethod(left, right, MetaMethod::Eq)? {
+                Some(function) => Ok(MetaResult::Call(MetaCall {
+                    function,
+                    args: [left, right],
+                })),
+                None => Ok(MetaResult::Value(Value::Boolean(false))),
+            }
+        }
+        _ => Ok(MetaResult::Value(Value::Boolean(false))),
+    }
+}
+
+/// Performs the Lua `<` operation, calling the `__lt` metamethod if the values cannot be compared
+/// directly.
+pub fn less_than<'gc>(
+    left: Value<'gc>,
+    right: Value<'gc>,
+) -> Result<MetaResult<'gc, 2>, Error<'gc>> {
+    if let Some(res) = left.less_than(right) {
+        return Ok(MetaResult::Value(Value::Boolean(res)));
+    }
+
+    match get_binary_metamethod(left, right, MetaMethod::Lt)? {
+        Some(function) => Ok(MetaResult::Call(MetaCall {
+            function,
+            args: [left, right],
+        })),
+        None => Err(BinaryOperatorError::LessThan.into()),
+    }
+}
+
+/// Performs the Lua `<=` operation, calling the `__le` metamethod if the values cannot be compared
+/// directly.
+///
+/// If there is no `__le` metamethod but there is an `__lt` metamethod, then `a <= b` is computed as
+/// `not (b < a)`, which requires calling a function that negates the result of `__lt`.
+pub fn less_equal<'gc>(
+    mc: MutationContext<'gc, '_>,
+    left: Value<'gc>,
+    right: Value<'gc>,
+) -> Result<MetaResult<'gc, 2>, Error<'gc>> {
+    if let Some(res) = left.less_equal(right) {
+        return Ok(MetaResult::Value(Value::Boolean(res)));
+    }
+
+    if let Some(function) = get_binary_metamethod(left, right, MetaMethod::Le)? {
+        return Ok(MetaResult::Call(MetaCall {
+            function,
+            args: [left, right],
+        }));
+    }
+
+    match get_binary_metamethod(left, right, MetaMethod::Lt)? {
+        Some(less_than) => {
+            let not_less_than = Callback::new_with(mc, less_than, |less_than, mut args| {
+                args.reverse();
+                CallbackReturn::Immediate(Ok(CallbackResult::TailCall {
+                    function: *less_than,
+                    args,
+                    continuation: Continuation::new_immediate(|res| {
+                        let res = res?;
+                        let less_than = res.first().cloned().unwrap_or(Value::Nil).to_bool();
+                        Ok(CallbackResult::Return(
+                            res.with_values(&[Value::Boolean(!less_than)]),
+                        ))
+                    }),
+                }))
+            });
+
+            Ok(MetaResult::Call(MetaCall {
+                function: Function::Callback(not_less_than),
+                args: [left, right],
+            }))
+        }
+        None => Err(BinaryOperatorError::LessEqual.into()),
+    }
+}
+
+/// Performs the Lua `#` operation, calling the `__len` metamethod if one is present.  Strings always
+/// have their primitive length, and tables without a `__len` metamethod have their border length.
+pub fn len<'gc>(value: Value<'gc>) -> Result<MetaResult<'gc, 1>, Error<'gc>> {
+    if let Value::String(s) = value {
+        return Ok(MetaResult::Value(Value::Integer(s.len() as i64)));
+    }
+
+    match get_metamethod(value, MetaMethod::Len) {
+        Value::Function(function) => {
+            return Ok(MetaResult::Call(MetaCall {
+                function,
+                args: [value],
+            }));
+        }
+        Value::Nil => {}
+        handler => {
+            return Err(TypeError {
+                expected: "function",
+                found: handler.type_name(),
+            }
+            .into());
+        }
+    }
+
+    match value {
+        Value::Table(t) => Ok(MetaResult::Value(Value::Integer(t.length()))),
+        value => Err(TypeError {
+            expected: "table or string",
+            found: value.type_name(),
+        }
+        .into()),
+    }
+}
+
+/// Performs the Lua `..` operation over the given values.
+///
+/// Concatenation is right associative, so values are concatenated starting from the right.  Runs of
+/// values which can be concatenated directly are, and the first pair of values which cannot be is
+/// passed to the `__concat` metamethod.  If there are more values to the left of that pair, the
+/// returned call will finish the rest of the concatenation once the metamethod returns.
+pub fn concat<'gc>(
+    mc: MutationContext<'gc, '_>,
+    values: &[Value<'gc>],
+) -> Result<MetaResult<'gc, 2>, Error<'gc>> {
+    fn can_concat(value: Value) -> bool {
+        matches!(
+            value,
+            Value::Nil
+                | Value::Boolean(_)
+                | Value::Integer(_)
+                | Value::Number(_)
+                | Value::String(_)
+        )
+    }
+
+    let mut values = values.to_vec();
+    loop {
+        match values.len() {
+            0 => return Ok(MetaResult::Value(Value::String(String::new_static(b"")))),
+            1 => return Ok(MetaResult::Value(values[0])),
+            len => {
+                let (left, right) = (values[len - 2], values[len - 1]);
+                if can_concat(left) && can_concat(right) {
+                    let start = values
+                        .iter()
+                        .rposition(|&v| !can_concat(v))
+                        .map(|i| i + 1)
+                        .unwrap_or(0);
+                    let s = String::concat(mc, &values[start..])?;
+                    values.truncate(start);
+                    values.push(Value::String(s));
+                    continue;
+                }
+
+                let function = match get_binary_metamethod(left, right, MetaMethod::Concat)? {
+                    Some(function) => function,
+                    None => {
+                        let bad_value = if can_concat(left) { right } else { left };
+                        return Err(StringError::Concat {
+                            bad_type: bad_value.type_name(),
+                        }
+                        .into());
+                    }
+                };
+
+                values.truncate(len - 2);
+                if values.is_empty() {
+                    return Ok(MetaResult::Call(MetaCall {
+                        function,
+                        args: [left, right],
+                    }));
+                }
+
+                // Call the metamethod, then finish concatenating the remaining values on the left
+                // with its result.
+                let finish =
+                    Callback::new_with(mc, (function, values), |(function, values), args| {
+                        CallbackReturn::Immediate(Ok(CallbackResult::TailCall {
+                            function: *function,
+                            args,
+                            continuation: Continuation::new_sequence_with(
+                                values.clone(),
+                                |mut values, res| {
+                                    values.push(res?.first().cloned().unwrap_or(Value::Nil));
+                                    Ok(sequence::from_fn_with(values, concat_callback))
+                                },
+                            ),
+                        }))
+                    });
+
+                return Ok(MetaResult::Call(MetaCall {
+                    function: Function::Callback(finish),
+                    args: [left, right],
+                }));
+            }
+        }
+    }
+}
+
+// Performs a concatenation from within a callback, tail calling any required metamethod.
+fn concat_callback<'gc>(
+    mc: MutationContext<'gc, '_>,
+    values: Vec<Value<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let res = concat(mc, &values)?;
+    let buffer = ValueBuffer::from(values);
+    Ok(match res {
+        MetaResult::Value(v) => CallbackResult::Return(buffer.with_values(&[v])),
+        MetaResult::Call(call) => CallbackResult::TailCall {
+            function: call.function,
+            args: buffer.with_values(&call.args),
+            continuation: Continuation::new_immediate(|res| Ok(CallbackResult::Return(res?))),
+        },
+    })
+}
+
+/// Converts the given value to a string as `tostring` does, calling the `__tostring` metamethod if
+/// one is present.  Values without a `__tostring` metamethod are converted the same way `print`
+/// converts them.
+pub fn tostring<'gc>(
+    mc: MutationContext<'gc, '_>,
+    value: Value<'gc>,
+) -> Result<MetaResult<'gc, 1>, Error<'gc>> {
+    match get_metamethod(value, MetaMethod::ToString) {
+        Value::Function(function) => Ok(MetaResult::Call(MetaCall {
+            function,
+            args: [value],
+        })),
+        Value::Nil => match value {
+            Value::String(_) => Ok(MetaResult::Value(value)),
+            value => {
+                let mut bytes = Vec::new();
+                value.display(&mut bytes).unwrap();
+                Ok(MetaResult::Value(Value::String(String::new(mc, &bytes))))
+            }
+        },
+        handler => Err(TypeError {
+            expected: "function",
+            found: handler.type_name(),
+        }
+        .into()),
+    }
+}
+
+/// Returns the function that should be called when calling the given value.  Functions are called
+/// directly, and any other value is called through its `__call` metamethod, with the value itself
+/// passed as the first argument.
+pub fn call<'gc>(value: Value<'gc>) -> Result<Function<'gc>, TypeError> {
+    match value {
+        Value::Function(function) => Ok(function),
+        value => match get_metamethod(value, MetaMethod::Call) {
+            Value::Function(function) => Ok(function),
+            _ => Err(TypeError {
+                expected: "function",
+                found: value.type_name(),
+            }),
+        },
+    }
+}
+
+/// Returns the `__close` metamethod call for a to-be-closed variable holding the given value.  The
+/// metamethod is called with the value and the error that caused the variable to go out of scope,
+/// or `nil` if it went out of scope normally.
+pub fn close<'gc>(value: Value<'gc>, error: Value<'gc>) -> Result<MetaCall<'gc, 2>, TypeError> {
+    match get_metamethod(value, MetaMethod::Close) {
+        Value::Function(function) => Ok(MetaCall {
+            function,
+            args: [value, error],
+        }),
+        _ => Err(TypeError {
+            expected: "closable value",
+            found: value.type_name(),
+        }),
+    }
+}
+
+// Returns the metatable of the given value, if it has one.
+fn get_metatable<'gc>(value: Value<'gc>) -> Option<Table<'gc>> {
+    match value {
+        Value::Table(t) => t.metatable(),
+        Value::UserData(u) => u.metatable(),
+        _ => None,
+    }
+}
+
+// Returns the given metamethod from the metatable of the given value, or `Value::Nil` if there is no
+// such metamethod.
+fn get_metamethod<'gc>(value: Value<'gc>, method: MetaMethod) -> Value<'gc> {
+    match get_metatable(value) {
+        Some(mt) => mt.get(method),
+        None => Value::Nil,
+    }
+}
+
+// Returns the metamethod for a binary operation, checking the left operand first and then the right.
+fn get_binary_metamethod<'gc>(
+    left: Value<'gc>,
+    right: Value<'gc>,
+    method: MetaMethod,
+) -> Result<Option<Function<'gc>>, TypeError> {
+    let handler = match get_metamethod(left, method) {
+        Value::Nil => get_metamethod(right, method),
+        handler => handler,
+    };
+
+    match handler {
+        Value::Nil => Ok(None),
+        Value::Function(function) => Ok(Some(function)),
+        handler => Err(TypeError {
+            expected: "function",
+            found: handler.type_name(),
+        }),
+    }
+}
+
+fn operator_error(method: MetaMethod) -> BinaryOperatorError {
+    match method {
+        MetaMethod::Add => BinaryOperatorError::Add,
+        MetaMethod::Sub => BinaryOperatorError::Subtract,
+        MetaMethod::Mul => BinaryOperatorError::Multiply,
+        MetaMethod::Div => BinaryOperatorError::FloatDivide,
+        MetaMethod::Mod => BinaryOperatorError::Modulo,
+        MetaMethod::Pow => BinaryOperatorError::Exponentiate,
+        MetaMethod::Unm => BinaryOperatorError::UnaryNegate,
+        MetaMethod::IDiv => BinaryOperatorError::FloorDivide,
+        MetaMethod::BAnd => BinaryOperatorError::BitAnd,
+        MetaMethod::BOr => BinaryOperatorError::BitOr,
+        MetaMethod::BXor => BinaryOperatorError::BitXor,
+        MetaMethod::Shl => BinaryOperatorError::ShiftLeft,
+        MetaMethod::Shr => BinaryOperatorError::ShiftRight,
+        MetaMethod::BNot => BinaryOperatorError::BitNot,
+        method => panic!("{} is not an arithmetic metamethod", method.name()),
+    }
+}
@@ -0,0 +1,65 @@
+use gc_arena::{Collect, GcCell, MutationContext};
+
+use crate::{Hook, Table};
+
+/// The metatables shared by every value of a type that has no metatable of its own, shared by every
+/// thread in a `Lua`.
+///
+/// Only strings currently have a shared metatable.  When the string library is loaded, it is set to
+/// a table whose `__index` field is the `string` table, so that string methods may be called as
+/// `s:upper()`.
+///
+/// Since this is the only state every thread shares, it also keeps track of the hook of the thread
+/// that is currently running a callback, so that coroutines inherit the hook of the thread that
+/// created them.
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct Metatables<'gc>(GcCell<'gc, MetatablesState<'gc>>);
+
+#[derive(Debug, Collect)]
+#[collect(empty_drop)]
+struct MetatablesState<'gc> {
+    string: Option<Table<'gc>>,
+    running_hook: Option<Hook<'gc>>,
+}
+
+impl<'gc> Metatables<'gc> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> Metatables<'gc> {
+        Metatables(GcCell::allocate(
+            mc,
+            MetatablesState {
+                string: None,
+                running_hook: None,
+            },
+        ))
+    }
+
+    /// Returns the metatable shared by all strings.
+    pub fn string(&self) -> Option<Table<'gc>> {
+        self.0.read().string
+    }
+
+    /// Sets the metatable shared by all strings, returning the previous one.
+    pub fn set_string(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        metatable: Option<Table<'gc>>,
+    ) -> Option<Table<'gc>> {
+        std::mem::replace(&mut self.0.write(mc).string, metatable)
+    }
+
+    // Returns the hook of the thread that is currently running a callback
+    pub(crate) fn running_hook(&self) -> Option<Hook<'gc>> {
+        self.0.read().running_hook
+    }
+
+    // Sets the hook of the thread that is about to run a callback, returning the previous one so
+    // that it may be restored once the callback returns to a possibly different thread.
+    pub(crate) fn set_running_hook(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        hook: Option<Hook<'gc>>,
+    ) -> Option<Hook<'gc>> {
+        std::mem::replace(&mut self.0.write(mc).running_hook, hook)
+    }
+}
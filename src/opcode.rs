@@ -108,9 +108,14 @@ pub enum OpCode {
     },
     Jump {
         offset: i16,
-        // If set, close upvalues >= `close_upvalues`
+        // If set, close upvalues and to-be-closed variables >= `close_upvalues`
         close_upvalues: Opt254,
     },
+    // Mark the register as a to-be-closed variable, which must be `nil`, `false`, or have a
+    // `__close` metamethod.
+    ToBeClosed {
+        value: RegisterIndex,
+    },
     // Test the register as a boolean, if its boolean value matches `is_true`, skip the next
     // instruction.
     Test {
@@ -0,0 +1,80 @@
+use std::cell::{RefCell, RefMut};
+use std::fmt::{self, Debug};
+use std::io::{self, Write};
+
+use gc_arena::{Collect, Gc, MutationContext};
+
+/// The output streams that `print` and the other standard library functions write to, shared by
+/// every thread in a `Lua`.
+///
+/// By default these are the standard output and standard error of the process, but either may be
+/// replaced with any `Write` implementation, such as a `Vec<u8>` to capture the output of a script,
+/// or a `WriteFn` to send it to a Rust closure.
+#[derive(Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct Output<'gc>(Gc<'gc, OutputState>);
+
+#[derive(Collect)]
+#[collect(require_static)]
+struct OutputState {
+    stdout: RefCell<Box<dyn Write>>,
+    stderr: RefCell<Box<dyn Write>>,
+}
+
+impl<'gc> Output<'gc> {
+    pub fn new(mc: MutationContext<'gc, '_>) -> Output<'gc> {
+        Output(Gc::allocate(
+            mc,
+            OutputState {
+                stdout: RefCell::new(Box::new(io::stdout())),
+                stderr: RefCell::new(Box::new(io::stderr())),
+            },
+        ))
+    }
+
+    /// Replaces the standard output stream, returning the previous one.
+    pub fn set_stdout(&self, stdout: Box<dyn Write>) -> Box<dyn Write> {
+        self.0.stdout.replace(stdout)
+    }
+
+    /// Replaces the standard error stream, returning the previous one.
+    pub fn set_stderr(&self, stderr: Box<dyn Write>) -> Box<dyn Write> {
+        self.0.stderr.replace(stderr)
+    }
+
+    /// Borrows the standard output stream.
+    ///
+    /// Panics if the stream is already borrowed, which may only happen if the stream itself calls
+    /// back into Lua.
+    pub fn stdout(&self) -> RefMut<'_, Box<dyn Write>> {
+        self.0.stdout.borrow_mut()
+    }
+
+    /// Borrows the standard error stream, with the same restrictions as `Output::stdout`.
+    pub fn stderr(&self) -> RefMut<'_, Box<dyn Write>> {
+        self.0.stderr.borrow_mut()
+    }
+}
+
+impl<'gc> Debug for Output<'gc> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("Output")
+            .field(&Gc::as_ptr(self.0))
+            .finish()
+    }
+}
+
+/// A `Write` implementation which passes everything written to it to a Rust closure.  Writes may be
+/// split at arbitrary points, so a single `print` call may result in several calls to the closure.
+pub struct WriteFn<F>(pub F);
+
+impl<F: FnMut(&[u8])> Write for WriteFn<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.0)(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -5,7 +5,18 @@ use std::rc::Rc;
 
 use gc_arena::Collect;
 
-use crate::{Lexer, LexerError, Token};
+use crate::{Lexer, LexerErrorKind, Span, Token};
+
+/// A 1-indexed line number in the source of a chunk.
+#[derive(Debug, Collect, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[collect(require_static)]
+pub struct LineNumber(pub u64);
+
+impl fmt::Display for LineNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Chunk<S> {
@@ -14,8 +25,8 @@ pub struct Chunk<S> {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Block<S> {
-    pub statements: Vec<Statement<S>>,
-    pub return_statement: Option<ReturnStatement<S>>,
+    pub statements: Vec<(LineNumber, Statement<S>)>,
+    pub return_statement: Option<(LineNumber, ReturnStatement<S>)>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -102,9 +113,17 @@ pub struct LocalFunctionStatement<S> {
 #[derive(Debug, PartialEq, Clone)]
 pub struct LocalStatement<S> {
     pub names: Vec<S>,
+    // The attribute of each name in `names`, if any
+    pub attributes: Vec<Option<LocalAttribute>>,
     pub values: Vec<Expression<S>>,
 }
 
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum LocalAttribute {
+    Const,
+    Close,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum BinaryOperator {
     Add,
@@ -142,6 +161,7 @@ pub enum UnaryOperator {
 pub struct Expression<S> {
     pub head: Box<HeadExpression<S>>,
     pub tail: Vec<(BinaryOperator, Expression<S>)>,
+    pub line: LineNumber,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -238,7 +258,37 @@ pub enum RecordKey<S> {
 
 #[derive(Debug, Collect)]
 #[collect(require_static)]
-pub enum ParserError {
+pub struct ParserError {
+    pub kind: ParserErrorKind,
+    /// Where in the source the error was found.
+    pub span: Span,
+    /// The name of the chunk being parsed, if it is known.
+    pub chunk_name: Option<String>,
+}
+
+impl ParserError {
+    pub fn with_chunk_name(self, chunk_name: String) -> ParserError {
+        ParserError {
+            chunk_name: Some(chunk_name),
+            ..self
+        }
+    }
+}
+
+impl StdError for ParserError {}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(chunk_name) = &self.chunk_name {
+            write!(f, "{}:", chunk_name)?;
+        }
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+#[derive(Debug, Collect)]
+#[collect(require_static)]
+pub enum ParserErrorKind {
     Unexpected {
         unexpected: String,
         expected: Option<String>,
@@ -248,13 +298,13 @@ pub enum ParserError {
     },
     AssignToExpression,
     ExpressionNotStatement,
+    MultipleToBeClosed,
+    UnknownAttribute(String),
     RecursionLimit,
-    LexerError(LexerError),
+    LexerError(LexerErrorKind),
 }
 
-impl StdError for ParserError {}
-
-impl fmt::Display for ParserError {
+impl fmt::Display for ParserErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let write_expected = |f: &mut fmt::Formatter, expected: &Option<String>| {
             match expected {
@@ -267,21 +317,25 @@ impl fmt::Display for ParserError {
         };
 
         match self {
-            ParserError::Unexpected {
+            ParserErrorKind::Unexpected {
                 unexpected,
                 expected,
             } => {
                 write!(f, "found {:?}", unexpected)?;
                 write_expected(f, expected)
             }
-            ParserError::EndOfStream { expected } => {
+            ParserErrorKind::EndOfStream { expected } => {
                 write!(f, "unexpected end of token stream")?;
                 write_expected(f, expected)
             }
-            ParserError::AssignToExpression => write!(f, "cannot assign to expression"),
-            ParserError::ExpressionNotStatement => write!(f, "expression is not a statement"),
-            ParserError::RecursionLimit => write!(f, "recursion limit reached"),
-            ParserError::LexerError(lexer_error) => write!(f, "{}", lexer_error),
+            ParserErrorKind::AssignToExpression => write!(f, "cannot assign to expression"),
+            ParserErrorKind::ExpressionNotStatement => write!(f, "expression is not a statement"),
+            ParserErrorKind::MultipleToBeClosed => {
+                write!(f, "multiple to-be-closed variables in local list")
+            }
+            ParserErrorKind::UnknownAttribute(name) => write!(f, "unknown attribute '{}'", name),
+            ParserErrorKind::RecursionLimit => write!(f, "recursion limit reached"),
+            ParserErrorKind::LexerError(lexer_error) => write!(f, "{}", lexer_error),
         }
     }
 }
@@ -289,12 +343,17 @@ impl fmt::Display for ParserError {
 pub fn parse_chunk<R, S, CS>(source: R, create_string: CS) -> Result<Chunk<S>, ParserError>
 where
     R: Read,
-    S: fmt::Debug + PartialEq,
+    S: AsRef<[u8]> + fmt::Debug + PartialEq,
     CS: FnMut(&[u8]) -> S,
 {
     Parser {
         lexer: Lexer::new(source, create_string),
         read_buffer: Vec::new(),
+        last_span: Span {
+            line: LineNumber(1),
+            column: 1,
+            offset: 0,
+        },
         recursion_guard: Rc::new(()),
     }
     .parse_chunk()
@@ -302,20 +361,24 @@ where
 
 struct Parser<R, S, CS> {
     lexer: Lexer<R, CS>,
-    read_buffer: Vec<Token<S>>,
+    // Tokens read ahead from the lexer, along with the line number each token ends on and the
+    // position it starts at.
+    read_buffer: Vec<(Token<S>, LineNumber, Span)>,
+    // The position of the last token taken from the read buffer.
+    last_span: Span,
     recursion_guard: Rc<()>,
 }
 
 impl<R, S, CS> Parser<R, S, CS>
 where
     R: Read,
-    S: fmt::Debug + PartialEq,
+    S: AsRef<[u8]> + fmt::Debug + PartialEq,
     CS: FnMut(&[u8]) -> S,
 {
     fn parse_chunk(&mut self) -> Result<Chunk<S>, ParserError> {
         let block = self.parse_block()?;
         if self.look_ahead(0)? != None {
-            Err(ParserError::EndOfStream { expected: None })
+            Err(self.error_at_next(ParserErrorKind::EndOfStream { expected: None }))
         } else {
             Ok(Chunk { block })
         }
@@ -333,12 +396,14 @@ where
                     self.take_next()?;
                 }
                 Some(&Token::Return) => {
-                    return_statement = Some(self.parse_return_statement()?);
+                    let line = self.line_number()?;
+                    return_statement = Some((line, self.parse_return_statement()?));
                     break;
                 }
                 None => break,
                 _ => {
-                    statements.push(self.parse_statement()?);
+                    let line = self.line_number()?;
+                    statements.push((line, self.parse_statement()?));
                 }
             }
         }
@@ -491,10 +556,7 @@ where
                 })
             }
 
-            token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("'=' or 'in'".to_owned()),
-            }),
+            _ => Err(self.unexpected_next("'=' or 'in'")),
         }
     }
 
@@ -549,10 +611,22 @@ where
     fn parse_local_statement(&mut self) -> Result<LocalStatement<S>, ParserError> {
         self.expect_next(Token::Local)?;
         let mut names = Vec::new();
+        let mut attributes = Vec::new();
         names.push(self.expect_name()?);
+        attributes.push(self.parse_local_attribute()?);
         while self.check_ahead(0, Token::Comma)? {
             self.take_next()?;
             names.push(self.expect_name()?);
+            attributes.push(self.parse_local_attribute()?);
+        }
+
+        if attributes
+            .iter()
+            .filter(|&&a| a == Some(LocalAttribute::Close))
+            .count()
+            > 1
+        {
+            return Err(self.error(ParserErrorKind::MultipleToBeClosed, self.last_span));
         }
 
         let values = if self.check_ahead(0, Token::Assign)? {
@@ -562,7 +636,35 @@ where
             Vec::new()
         };
 
-        Ok(LocalStatement { names, values })
+        Ok(LocalStatement {
+            names,
+            attributes,
+            values,
+        })
+    }
+
+    fn parse_local_attribute(&mut self) -> Result<Option<LocalAttribute>, ParserError> {
+        if !self.check_ahead(0, Token::LessThan)? {
+            return Ok(None);
+        }
+        self.take_next()?;
+
+        let name = self.expect_name()?;
+        let attribute = match name.as_ref() {
+            b"const" => LocalAttribute::Const,
+            b"close" => LocalAttribute::Close,
+            _ => {
+                return Err(self.error(
+                    ParserErrorKind::UnknownAttribute(
+                        String::from_utf8_lossy(name.as_ref()).into_owned(),
+                    ),
+                    self.last_span,
+                ))
+            }
+        };
+        self.expect_next(Token::GreaterThan)?;
+
+        Ok(Some(attribute))
     }
 
     fn parse_label_statement(&mut self) -> Result<LabelStatement<S>, ParserError> {
@@ -589,13 +691,13 @@ where
                             AssignmentTarget::Field(suffixed_expression, field_suffix)
                         }
                         SuffixPart::Call(_) => {
-                            return Err(ParserError::AssignToExpression);
+                            return Err(self.error_at_next(ParserErrorKind::AssignToExpression));
                         }
                     }
                 } else {
                     match suffixed_expression.primary {
                         PrimaryExpression::Name(name) => AssignmentTarget::Name(name),
-                        _ => return Err(ParserError::AssignToExpression),
+                        _ => return Err(self.error_at_next(ParserErrorKind::AssignToExpression)),
                     }
                 };
                 targets.push(assignment_target);
@@ -623,10 +725,12 @@ where
                         call: call_suffix,
                     }))
                 }
-                SuffixPart::Field(_) => Err(ParserError::ExpressionNotStatement),
+                SuffixPart::Field(_) => {
+                    Err(self.error_at_next(ParserErrorKind::ExpressionNotStatement))
+                }
             }
         } else {
-            Err(ParserError::ExpressionNotStatement)
+            Err(self.error_at_next(ParserErrorKind::ExpressionNotStatement))
         }
     }
 
@@ -647,6 +751,7 @@ where
     fn parse_sub_expression(&mut self, priority_limit: u8) -> Result<Expression<S>, ParserError> {
         let _recursion_guard = self.recursion_guard()?;
 
+        let line = self.line_number()?;
         let head = if let Some(unary_op) = get_unary_operator(self.get_next()?) {
             self.take_next()?;
             HeadExpression::UnaryOperator(unary_op, self.parse_sub_expression(UNARY_PRIORITY)?)
@@ -669,6 +774,7 @@ where
         Ok(Expression {
             head: Box::new(head),
             tail,
+            line,
         })
     }
 
@@ -716,10 +822,7 @@ where
                 Ok(PrimaryExpression::GroupedExpression(expr))
             }
             Token::Name(n) => Ok(PrimaryExpression::Name(n)),
-            token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("grouped expression or name".to_owned()),
-            }),
+            token => Err(self.unexpected(token, "grouped expression or name")),
         }
     }
 
@@ -735,10 +838,7 @@ where
                 self.expect_next(Token::RightBracket)?;
                 Ok(FieldSuffix::Indexed(expr))
             }
-            token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("field or suffix".to_owned()),
-            }),
+            _ => Err(self.unexpected_next("field or suffix")),
         }
     }
 
@@ -751,6 +851,7 @@ where
             _ => None,
         };
 
+        let line = self.line_number()?;
         let args = match self.get_next()? {
             Token::LeftParen => {
                 self.take_next()?;
@@ -767,19 +868,16 @@ where
                     self.parse_table_constructor()?,
                 ))),
                 tail: vec![],
+                line,
             }],
             Token::String(_) => vec![Expression {
                 head: Box::new(HeadExpression::Simple(SimpleExpression::String(
                     self.expect_string()?,
                 ))),
                 tail: vec![],
+                line,
             }],
-            token => {
-                return Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", token),
-                    expected: Some("function arguments".to_owned()),
-                });
-            }
+            _ => return Err(self.unexpected_next("function arguments")),
         };
 
         Ok(if let Some(method_name) = method_name {
@@ -795,10 +893,7 @@ where
             Token::Colon | Token::LeftParen | Token::LeftBrace | Token::String(_) => {
                 Ok(SuffixPart::Call(self.parse_call_suffix()?))
             }
-            token => Err(ParserError::Unexpected {
-                unexpected: format!("{:?}", token),
-                expected: Some("expression suffix".to_owned()),
-            }),
+            _ => Err(self.unexpected_next("expression suffix")),
         }
     }
 
@@ -836,10 +931,7 @@ where
                         break;
                     }
                     token => {
-                        return Err(ParserError::Unexpected {
-                            unexpected: format!("{:?}", token),
-                            expected: Some("parameter name or '...'".to_owned()),
-                        });
+                        return Err(self.unexpected(token, "parameter name or '...'"));
                     }
                 }
                 if self.check_ahead(0, Token::Comma)? {
@@ -910,97 +1002,100 @@ where
         if Rc::strong_count(&self.recursion_guard) < MAX_RECURSION {
             Ok(self.recursion_guard.clone())
         } else {
-            Err(ParserError::RecursionLimit)
+            Err(self.error(ParserErrorKind::RecursionLimit, self.last_span))
         }
     }
 
     // Return a reference to the next token in the stream, erroring if we are at the end.
     fn get_next(&mut self) -> Result<&Token<S>, ParserError> {
         self.read_ahead(1)?;
-        if let Some(token) = self.read_buffer.get(0) {
+        if let Some((token, _, _)) = self.read_buffer.get(0) {
             Ok(token)
         } else {
-            Err(ParserError::EndOfStream { expected: None })
+            Err(self.end_of_stream(None))
         }
     }
 
     // Consumes the next token, returning an error if it does not match the given token.
     fn expect_next(&mut self, token: Token<S>) -> Result<(), ParserError> {
-        self.read_ahead(1)?;
-        if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream {
-                expected: Some(format!("{:?}", token)),
-            })
-        } else {
-            let next_token = self.read_buffer.remove(0);
-            if next_token == token {
-                Ok(())
-            } else {
-                Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", next_token),
-                    expected: Some(format!("{:?}", token)),
-                })
-            }
+        match self.take_token()? {
+            None => Err(self.end_of_stream(Some(format!("{:?}", token)))),
+            Some(next_token) if next_token == token => Ok(()),
+            Some(next_token) => Err(self.unexpected(next_token, &format!("{:?}", token))),
         }
     }
 
     // Consume the next token which should be a name, and return it, otherwise error.
     fn expect_name(&mut self) -> Result<S, ParserError> {
-        self.read_ahead(1)?;
-        if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream {
-                expected: Some("name".to_owned()),
-            })
-        } else {
-            match self.read_buffer.remove(0) {
-                Token::Name(name) => Ok(name),
-                token => Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", token),
-                    expected: Some("name".to_owned()),
-                }),
-            }
+        match self.take_token()? {
+            None => Err(self.end_of_stream(Some("name".to_owned()))),
+            Some(Token::Name(name)) => Ok(name),
+            Some(token) => Err(self.unexpected(token, "name")),
         }
     }
 
     // Consume the next token which should be a string, and return it, otherwise error.
     fn expect_string(&mut self) -> Result<S, ParserError> {
-        self.read_ahead(1)?;
-        if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream {
-                expected: Some("string".to_owned()),
-            })
-        } else {
-            match self.read_buffer.remove(0) {
-                Token::String(string) => Ok(string),
-                token => Err(ParserError::Unexpected {
-                    unexpected: format!("{:?}", token),
-                    expected: Some("string".to_owned()),
-                }),
-            }
+        match self.take_token()? {
+            None => Err(self.end_of_stream(Some("string".to_owned()))),
+            Some(Token::String(string)) => Ok(string),
+            Some(token) => Err(self.unexpected(token, "string")),
         }
     }
 
     // Take the next token in the stream by value, erroring if we are at the end.
     fn take_next(&mut self) -> Result<Token<S>, ParserError> {
+        match self.take_token()? {
+            Some(token) => Ok(token),
+            None => Err(self.end_of_stream(None)),
+        }
+    }
+
+    // Take the next token in the stream by value, if we are not at the end.
+    fn take_token(&mut self) -> Result<Option<Token<S>>, ParserError> {
         self.read_ahead(1)?;
         if self.read_buffer.is_empty() {
-            Err(ParserError::EndOfStream { expected: None })
+            Ok(None)
         } else {
-            Ok(self.read_buffer.remove(0))
+            let (token, _, span) = self.read_buffer.remove(0);
+            self.last_span = span;
+            Ok(Some(token))
         }
     }
 
+    // Return the line number of the next token in the stream, or the last line of the source if we
+    // are at the end.
+    fn line_number(&mut self) -> Result<LineNumber, ParserError> {
+        self.read_ahead(1)?;
+        Ok(if let Some((_, line, _)) = self.read_buffer.first() {
+            *line
+        } else {
+            LineNumber(self.lexer.line_number() + 1)
+        })
+    }
+
+    // Return the position of the next token in the stream, or the end of the source if we are at
+    // the end.
+    fn next_span(&mut self) -> Result<Span, ParserError> {
+        self.read_ahead(1)?;
+        Ok(if let Some((_, _, span)) = self.read_buffer.first() {
+            *span
+        } else {
+            self.lexer.position()
+        })
+    }
+
     // Return the nth token ahead in the stream, if it is not past the end.
     fn look_ahead(&mut self, n: usize) -> Result<Option<&Token<S>>, ParserError> {
         self.read_ahead(n + 1)?;
-        Ok(self.read_buffer.get(n))
+        Ok(self.read_buffer.get(n).map(|(token, _, _)| token))
     }
 
     // Return true if the nth token ahead in the stream matches the given token.  If this would read
     // past the end of the stream, this will simply return false.
     fn check_ahead(&mut self, n: usize, token: Token<S>) -> Result<bool, ParserError> {
         self.read_ahead(n)?;
-        Ok(if let Some(t) = self.read_buffer.get(n) {
+        Ok(if let Some((t, _, _)) = self.read_buffer.get(n) {
             *t == token
         } else {
             false
@@ -1011,14 +1106,69 @@ where
     // possible).
     fn read_ahead(&mut self, n: usize) -> Result<(), ParserError> {
         while self.read_buffer.len() <= n {
-            if let Some(token) = self.lexer.read_token().map_err(ParserError::LexerError)? {
-                self.read_buffer.push(token);
+            let token = self
+                .lexer
+                .skip_whitespace()
+                .and_then(|()| {
+                    let span = self.lexer.position();
+                    Ok(self.lexer.read_token()?.map(|token| (token, span)))
+                })
+                .map_err(|err| self.error(ParserErrorKind::LexerError(err.kind), err.span))?;
+            if let Some((token, span)) = token {
+                let line = LineNumber(self.lexer.line_number() + 1);
+                self.read_buffer.push((token, line, span));
             } else {
                 break;
             }
         }
         Ok(())
     }
+
+    fn error(&self, kind: ParserErrorKind, span: Span) -> ParserError {
+        ParserError {
+            kind,
+            span,
+            chunk_name: None,
+        }
+    }
+
+    // Returns an error located at the next token in the stream.
+    fn error_at_next(&mut self, kind: ParserErrorKind) -> ParserError {
+        match self.next_span() {
+            Ok(span) => self.error(kind, span),
+            Err(err) => err,
+        }
+    }
+
+    // Returns an error for a token that was just taken from the stream.
+    fn unexpected(&self, token: Token<S>, expected: &str) -> ParserError {
+        self.error(
+            ParserErrorKind::Unexpected {
+                unexpected: format!("{:?}", token),
+                expected: Some(expected.to_owned()),
+            },
+            self.last_span,
+        )
+    }
+
+    // Returns an error for the next token in the stream, which must already have been read.
+    fn unexpected_next(&self, expected: &str) -> ParserError {
+        let (token, _, span) = &self.read_buffer[0];
+        self.error(
+            ParserErrorKind::Unexpected {
+                unexpected: format!("{:?}", token),
+                expected: Some(expected.to_owned()),
+            },
+            *span,
+        )
+    }
+
+    fn end_of_stream(&self, expected: Option<String>) -> ParserError {
+        self.error(
+            ParserErrorKind::EndOfStream { expected },
+            self.lexer.position(),
+        )
+    }
 }
 
 const MAX_RECURSION: usize = 200;
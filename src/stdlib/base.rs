@@ -1,27 +1,28 @@
-use std::io::{self, Write};
+use std::io::Write;
 
 use gc_arena::MutationContext;
 use gc_sequence as sequence;
 
 use crate::{
     Callback, CallbackResult, Continuation, Root, RuntimeError, String, Table, TypeError, Value,
+    ValueBuffer,
 };
 
 pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
     env.set(
         mc,
         String::new_static(b"print"),
-        Callback::new_immediate(mc, |args| {
-            let mut stdout = io::stdout();
+        Callback::new_immediate_with(mc, root.output, |output, args| {
+            let mut stdout = output.stdout();
             for i in 0..args.len() {
-                args[i].display(&mut stdout)?;
+                args[i].display(&mut *stdout)?;
                 if i != args.len() - 1 {
                     stdout.write_all(&b"\t"[..])?;
                 }
             }
             stdout.write_all(&b"\n"[..])?;
             stdout.flush()?;
-            Ok(CallbackResult::Return(vec![]))
+            Ok(CallbackResult::Return(args.with_values(&[])))
         }),
     )
     .unwrap();
@@ -66,10 +67,10 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
                                         res.insert(0, Value::Boolean(true));
                                         res
                                     }
-                                    Err(err) => vec![
+                                    Err(err) => ValueBuffer::from(vec![
                                         Value::Boolean(false),
                                         err.to_value(mc, interned_strings),
-                                    ],
+                                    ]),
                                 }))
                             },
                         ))
@@ -90,9 +91,10 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
                 )))
                 .into());
             }
-            Ok(CallbackResult::Return(vec![Value::String(
-                String::new_static(args.get(0).cloned().unwrap().type_name().as_bytes()),
-            )]))
+            let type_name = String::new_static(args[0].type_name().as_bytes());
+            Ok(CallbackResult::Return(
+                args.with_values(&[Value::String(type_name)]),
+            ))
         }),
     )
     .unwrap();
@@ -100,14 +102,17 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
     env.set(
         mc,
         String::new_static(b"select"),
-        Callback::new_immediate(mc, |args| {
+        Callback::new_immediate(mc, |mut args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_integer() {
-                Some(n) if n >= 1 && (n as usize) <= args.len() => Ok(CallbackResult::Return(
-                    args[n as usize..args.len()].to_vec(),
-                )),
+                Some(n) if n >= 1 && (n as usize) <= args.len() => {
+                    args.drain(..n as usize);
+                    Ok(CallbackResult::Return(args))
+                }
                 // This is required because Rust will panic if the starting slice index is out of
                 // range by more than one
-                Some(n) if n as usize > args.len() => Ok(CallbackResult::Return(vec![])),
+                Some(n) if n as usize > args.len() => {
+                    Ok(CallbackResult::Return(args.with_values(&[])))
+                }
                 _ => Err(RuntimeError(Value::String(String::new_static(
                     b"Bad argument to select",
                 )))
@@ -116,4 +121,163 @@ pub fn load_base<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<
         }),
     )
     .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"getmetatable"),
+        Callback::new_immediate_with(mc, root.metatables, |metatables, args| {
+            let metatable = match args.first().cloned().unwrap_or(Value::Nil) {
+                Value::Table(t) => protect_metatable(t.metatable()),
+                Value::UserData(u) => protect_metatable(u.metatable()),
+                Value::String(_) => protect_metatable(metatables.string()),
+                _ => Value::Nil,
+            };
+            Ok(CallbackResult::Return(args.with_values(&[metatable])))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"setmetatable"),
+        Callback::new_sequence_with(mc, root.finalizers, |finalizers, args| {
+            let table = match args.first().cloned().unwrap_or(Value::Nil) {
+                Value::Table(t) => t,
+                value => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+
+            let metatable = match args.get(1).cloned().unwrap_or(Value::Nil) {
+                Value::Table(t) => Some(t),
+                Value::Nil => None,
+                value => {
+                    return Err(TypeError {
+                        expected: "nil or table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+
+            if let Some(mt) = table.metatable() {
+                if mt.get(String::new_static(b"__metatable")) != Value::Nil {
+                    return Err(RuntimeError(Value::String(String::new_static(
+                        b"cannot change a protected metatable",
+                    )))
+                    .into());
+                }
+            }
+
+            Ok(sequence::from_fn_with(
+                (table, metatable, *finalizers, args),
+                |mc, (table, metatable, finalizers, args)| {
+                    table.set_metatable(mc, metatable);
+                    finalizers.register_table(mc, table);
+                    Ok(CallbackResult::Return(
+                        args.with_values(&[Value::Table(table)]),
+                    ))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawget"),
+        Callback::new_immediate(mc, |args| {
+            match args.first().cloned().unwrap_or(Value::Nil) {
+                Value::Table(t) => {
+                    let value = t.get(args.get(1).cloned().unwrap_or(Value::Nil));
+                    Ok(CallbackResult::Return(args.with_values(&[value])))
+                }
+                value => Err(TypeError {
+                    expected: "table",
+                    found: value.type_name(),
+                }
+                .into()),
+            }
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawset"),
+        Callback::new_sequence(mc, |args| {
+            let table = match args.first().cloned().unwrap_or(Value::Nil) {
+                Value::Table(t) => t,
+                value => {
+                    return Err(TypeError {
+                        expected: "table",
+                        found: value.type_name(),
+                    }
+                    .into());
+                }
+            };
+            let key = args.get(1).cloned().unwrap_or(Value::Nil);
+            let value = args.get(2).cloned().unwrap_or(Value::Nil);
+
+            Ok(sequence::from_fn_with(
+                (table, key, value, args),
+                |mc, (table, key, value, args)| {
+                    table.set(mc, key, value)?;
+                    Ok(CallbackResult::Return(
+                        args.with_values(&[Value::Table(table)]),
+                    ))
+                },
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawequal"),
+        Callback::new_immediate(mc, |args| {
+            let a = args.first().cloned().unwrap_or(Value::Nil);
+            let b = args.get(1).cloned().unwrap_or(Value::Nil);
+            Ok(CallbackResult::Return(
+                args.with_values(&[Value::Boolean(a == b)]),
+            ))
+        }),
+    )
+    .unwrap();
+
+    env.set(
+        mc,
+        String::new_static(b"rawlen"),
+        Callback::new_immediate(mc, |args| {
+            match args.first().cloned().unwrap_or(Value::Nil) {
+                Value::Table(t) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Integer(t.length())]),
+                )),
+                Value::String(s) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Integer(s.len() as i64)]),
+                )),
+                value => Err(TypeError {
+                    expected: "table or string",
+                    found: value.type_name(),
+                }
+                .into()),
+            }
+        }),
+    )
+    .unwrap();
+}
+
+// Returns the given metatable, or its `__metatable` field if it has one.
+fn protect_metatable<'gc>(metatable: Option<Table<'gc>>) -> Value<'gc> {
+    match metatable {
+        Some(mt) => match mt.get(String::new_static(b"__metatable")) {
+            Value::Nil => Value::Table(mt),
+            protected => protected,
+        },
+        None => Value::Nil,
+    }
 }
@@ -3,7 +3,7 @@ use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
 
 use crate::{
     Callback, CallbackResult, Root, RuntimeError, String, Table, Thread, ThreadMode,
-    ThreadSequence, TypeError, Value,
+    ThreadSequence, TypeError, Value, ValueBuffer,
 };
 
 pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
@@ -13,7 +13,7 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
         .set(
             mc,
             String::new_static(b"create"),
-            Callback::new_sequence(mc, |args| {
+            Callback::new_sequence_with(mc, root.metatables, |metatables, args| {
                 let function = match args.get(0).cloned().unwrap_or(Value::Nil) {
                     Value::Function(function) => function,
                     value => {
@@ -25,11 +25,19 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                     }
                 };
 
-                Ok(sequence::from_fn_with(function, |mc, function| {
-                    let thread = Thread::new(mc, true);
-                    thread.start_suspended(mc, function).unwrap();
-                    Ok(CallbackResult::Return(vec![Value::Thread(thread)]))
-                }))
+                Ok(sequence::from_fn_with(
+                    (*metatables, function, args),
+                    |mc, (metatables, function, args)| {
+                        // As in PUC-Rio Lua, a new coroutine inherits the hook of the thread
+                        // that created it.
+                        let thread = Thread::new(mc, metatables, true);
+                        thread.set_hook(mc, metatables.running_hook());
+                        thread.start_suspended(mc, function).unwrap();
+                        Ok(CallbackResult::Return(
+                            args.with_values(&[Value::Thread(thread)]),
+                        ))
+                    },
+                ))
             }),
         )
         .unwrap();
@@ -69,11 +77,12 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                             Ok(CallbackResult::Return(match res {
                                 Ok(mut res) => {
                                     res.insert(0, Value::Boolean(true));
-                                    res
-                                }
-                                Err(err) => {
-                                    vec![Value::Boolean(false), err.to_value(mc, interned_strings)]
+                                    ValueBuffer::from(res)
                                 }
+                                Err(err) => ValueBuffer::from(vec![
+                                    Value::Boolean(false),
+                                    err.to_value(mc, interned_strings),
+                                ]),
                             }))
                         },
                     ),
@@ -98,7 +107,7 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                     }
                 };
 
-                Ok(CallbackResult::Return(vec![Value::String(
+                Ok(CallbackResult::Return(args.with_values(&[Value::String(
                     // TODO: When the current thread is available again for callbacks, whether or
                     // not the active thread matches will determine 'normal' from 'running'.
                     String::new_static(match thread.mode() {
@@ -106,7 +115,7 @@ pub fn load_coroutine<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: T
                         ThreadMode::Running => b"running",
                         ThreadMode::Suspended => b"suspended",
                     }),
-                )]))
+                )])))
             }),
         )
         .unwrap();
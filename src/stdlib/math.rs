@@ -16,9 +16,13 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"abs"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil) {
-                Value::Integer(a) => Ok(CallbackResult::Return(vec![Value::Integer(a.abs())])),
+                Value::Integer(a) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Integer(a.abs())]),
+                )),
                 a => match a.to_number() {
-                    Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.abs())])),
+                    Some(f) => Ok(CallbackResult::Return(
+                        args.with_values(&[Value::Number(f.abs())]),
+                    )),
                     _ => Err(RuntimeError(Value::String(String::new_static(
                         b"Bad argument to abs",
                     )))
@@ -34,7 +38,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"acos"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.acos())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.acos())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to acos"))).into(),
                 ),
@@ -48,7 +54,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"asin"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.asin())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.asin())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to asin"))).into(),
                 ),
@@ -62,7 +70,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"atan"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.atan())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.atan())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to atan"))).into(),
                 ),
@@ -79,7 +89,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
                 args.get(0).cloned().unwrap_or(Value::Nil).to_number(),
                 args.get(1).cloned().unwrap_or(Value::Nil).to_number(),
             ) {
-                (Some(f), Some(g)) => Ok(CallbackResult::Return(vec![Value::Number(f.atan2(g))])),
+                (Some(f), Some(g)) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.atan2(g))]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to atan2")))
                         .into(),
@@ -94,9 +106,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"ceil"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![
-                    Value::Integer(f.ceil() as i64),
-                ])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Integer(f.ceil() as i64)]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to ceil"))).into(),
                 ),
@@ -110,7 +122,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"cos"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.cos())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.cos())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to cos"))).into(),
                 ),
@@ -124,7 +138,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"cosh"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.cosh())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.cosh())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to cosh"))).into(),
                 ),
@@ -138,7 +154,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"deg"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.to_degrees())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.to_degrees())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to deg"))).into(),
                 ),
@@ -152,9 +170,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"exp"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(
-                    std::f64::consts::E.powf(f),
-                )])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(std::f64::consts::E.powf(f))]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to exp"))).into(),
                 ),
@@ -168,9 +186,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"floor"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Integer(
-                    f.floor() as i64
-                )])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Integer(f.floor() as i64)]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to floor")))
                         .into(),
@@ -190,11 +208,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
             ) {
                 (Some(f), Some(g)) => {
                     let result = (f % g).abs();
-                    Ok(CallbackResult::Return(vec![Value::Number(if f < 0.0 {
-                        -result
-                    } else {
-                        result
-                    })]))
+                    Ok(CallbackResult::Return(args.with_values(&[Value::Number(
+                        if f < 0.0 { -result } else { result },
+                    )])))
                 }
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to fmod"))).into(),
@@ -218,15 +234,13 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
                     // put into range of result
                     let e = ((bits >> 52) & 0x7ff) as i64 - 1023 + 1;
 
-                    Ok(CallbackResult::Return(vec![
-                        Value::Number(m),
-                        Value::Integer(e),
-                    ]))
+                    Ok(CallbackResult::Return(
+                        args.with_values(&[Value::Number(m), Value::Integer(e)]),
+                    ))
                 }
-                Some(f) => Ok(CallbackResult::Return(vec![
-                    Value::Number(f),
-                    Value::Integer(0),
-                ])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f), Value::Integer(0)]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to frexp")))
                         .into(),
@@ -251,9 +265,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
                 args.get(0).cloned().unwrap_or(Value::Nil).to_number(),
                 args.get(1).cloned().unwrap_or(Value::Nil).to_number(),
             ) {
-                (Some(f), Some(g)) => Ok(CallbackResult::Return(vec![Value::Number(
-                    f * 2.0_f64.powf(g),
-                )])),
+                (Some(f), Some(g)) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f * 2.0_f64.powf(g))]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to ldexp")))
                         .into(),
@@ -268,7 +282,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"log"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.ln())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.ln())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to log"))).into(),
                 ),
@@ -282,7 +298,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"log10"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.log10())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.log10())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to log10")))
                         .into(),
@@ -311,7 +329,7 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
                         )
                         .and_then(|less| if less { Ok(entry) } else { Ok(max) })
                 })
-                .map(|a| CallbackResult::Return(vec![a]))
+                .map(|a| CallbackResult::Return(args.with_values(&[a])))
         }),
     )
     .unwrap();
@@ -343,7 +361,7 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
                         )
                         .and_then(|less| if less { Ok(entry) } else { Ok(min) })
                 })
-                .map(|a| CallbackResult::Return(vec![a]))
+                .map(|a| CallbackResult::Return(args.with_values(&[a])))
         }),
     )
     .unwrap();
@@ -360,10 +378,12 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"modf"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![
-                    Value::Integer(f as i64 / 1),
-                    Value::Number(f % 1.0),
-                ])),
+                Some(f) => {
+                    Ok(CallbackResult::Return(args.with_values(&[
+                        Value::Integer(f as i64 / 1),
+                        Value::Number(f % 1.0),
+                    ])))
+                }
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to modf"))).into(),
                 ),
@@ -384,7 +404,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"rad"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.to_radians())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.to_radians())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to rad"))).into(),
                 ),
@@ -404,18 +426,18 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
                 args.get(0).cloned().unwrap_or(Value::Nil),
                 args.get(1).cloned().unwrap_or(Value::Nil),
             ) {
-                (Value::Nil, Value::Nil) => Ok(CallbackResult::Return(vec![Value::Number(
-                    rng.borrow_mut().gen::<f64>(),
-                )])),
+                (Value::Nil, Value::Nil) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(rng.borrow_mut().gen::<f64>())]),
+                )),
                 (a, b) => {
                     if let (Some(first), Value::Nil) = (a.to_integer(), b) {
-                        Ok(CallbackResult::Return(vec![Value::Integer(
+                        Ok(CallbackResult::Return(args.with_values(&[Value::Integer(
                             rng.borrow_mut().gen_range(1, first + 1),
-                        )]))
+                        )])))
                     } else if let (Some(first), Some(second)) = (a.to_integer(), b.to_integer()) {
-                        Ok(CallbackResult::Return(vec![Value::Integer(
+                        Ok(CallbackResult::Return(args.with_values(&[Value::Integer(
                             rng.borrow_mut().gen_range(first, second + 1),
-                        )]))
+                        )])))
                     } else {
                         Err(RuntimeError(Value::String(String::new_static(
                             b"Bad argument to random",
@@ -437,7 +459,7 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
                 Some(f) => {
                     *(rng.borrow_mut().deref_mut()) = Xoshiro256StarStar::seed_from_u64(f as u64);
-                    Ok(CallbackResult::Return(vec![]))
+                    Ok(CallbackResult::Return(args.with_values(&[])))
                 }
                 _ => Err(RuntimeError(Value::String(String::new_static(
                     b"Bad argument to randomseed",
@@ -453,7 +475,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"sin"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.sin())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.sin())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to sin"))).into(),
                 ),
@@ -467,7 +491,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"sqrt"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.sqrt())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.sqrt())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to sqrt"))).into(),
                 ),
@@ -481,7 +507,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"tan"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_number() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Number(f.tan())])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Number(f.tan())]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to tan"))).into(),
                 ),
@@ -495,8 +523,10 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"tointeger"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil).to_integer() {
-                Some(f) => Ok(CallbackResult::Return(vec![Value::Integer(f)])),
-                _ => Ok(CallbackResult::Return(vec![Value::Nil])),
+                Some(f) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Integer(f)]),
+                )),
+                _ => Ok(CallbackResult::Return(args.with_values(&[Value::Nil]))),
             }
         }),
     )
@@ -507,13 +537,13 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
         String::new_static(b"type"),
         Callback::new_immediate(mc, |args| {
             match args.get(0).cloned().unwrap_or(Value::Nil) {
-                Value::Integer(_) => Ok(CallbackResult::Return(vec![Value::String(
-                    String::new_static(b"integer"),
-                )])),
-                Value::Number(_) => Ok(CallbackResult::Return(vec![Value::String(
-                    String::new_static(b"float"),
-                )])),
-                _ => Ok(CallbackResult::Return(vec![Value::Nil])),
+                Value::Integer(_) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::String(String::new_static(b"integer"))]),
+                )),
+                Value::Number(_) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::String(String::new_static(b"float"))]),
+                )),
+                _ => Ok(CallbackResult::Return(args.with_values(&[Value::Nil]))),
             }
         }),
     )
@@ -527,9 +557,9 @@ pub fn load_math<'gc>(mc: MutationContext<'gc, '_>, _: Root<'gc>, env: Table<'gc
                 args.get(0).cloned().unwrap_or(Value::Nil).to_integer(),
                 args.get(1).cloned().unwrap_or(Value::Nil).to_integer(),
             ) {
-                (Some(f), Some(g)) => Ok(CallbackResult::Return(vec![Value::Boolean(
-                    (f as u64) < (g as u64),
-                )])),
+                (Some(f), Some(g)) => Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Boolean((f as u64) < (g as u64))]),
+                )),
                 _ => Err(
                     RuntimeError(Value::String(String::new_static(b"Bad argument to ult"))).into(),
                 ),
@@ -1,7 +1,45 @@
 mod base;
 mod coroutine;
 mod math;
+mod string;
+
+use gc_arena::MutationContext;
+
+use crate::{Root, Table};
 
 pub use base::load_base;
 pub use coroutine::load_coroutine;
 pub use math::load_math;
+pub use string::load_string;
+
+/// One of the standard libraries that may be loaded into a global environment.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StdLib {
+    /// The basic functions, such as `print`, `pcall` and `setmetatable`
+    Base,
+    /// The `coroutine` table
+    Coroutine,
+    /// The `math` table
+    Math,
+    /// The `string` table
+    String,
+}
+
+impl StdLib {
+    /// Every standard library, in the order `Root::new` loads them.
+    pub const ALL: &'static [StdLib] = &[
+        StdLib::Base,
+        StdLib::Coroutine,
+        StdLib::Math,
+        StdLib::String,
+    ];
+
+    pub fn load<'gc>(self, mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
+        match self {
+            StdLib::Base => load_base(mc, root, env),
+            StdLib::Coroutine => load_coroutine(mc, root, env),
+            StdLib::Math => load_math(mc, root, env),
+            StdLib::String => load_string(mc, root, env),
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use gc_arena::MutationContext;
+
+use crate::{
+    meta_ops::{self, MetaCall, MetaResult},
+    Error, RuntimeError, String, Value,
+};
+
+use super::{bad_argument, check_integer, check_number, check_string};
+
+// The flags, width and precision of a single conversion, as in C's `printf`
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+// The result of `format`, either the formatted string, or the `__tostring` metamethod that must be
+// called for the argument at `arg` before it can be formatted with `%s`.
+pub enum Formatted<'gc> {
+    Done(Vec<u8>),
+    ToString { arg: usize, call: MetaCall<'gc, 1> },
+}
+
+// Formats the arguments following the format string in `args[0]`, as `string.format` does.
+pub fn format<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+) -> Result<Formatted<'gc>, Error<'gc>> {
+    let fmt = check_string(mc, args, 0)?;
+    let mut out = Vec::with_capacity(fmt.len());
+    let mut arg = 0;
+    let mut i = 0;
+    while i < fmt.len() {
+        let c = fmt[i];
+        i += 1;
+        if c != b'%' {
+            out.push(c);
+            continue;
+        }
+        if fmt.get(i) == Some(&b'%') {
+            i += 1;
+            out.push(b'%');
+            continue;
+        }
+
+        let start = i;
+        let mut spec = Spec::default();
+        while let Some(&c) = fmt.get(i) {
+            match c {
+                b'-' => spec.left = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alt = true,
+                b'0' => spec.zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+        if i - start > 5 {
+            return Err(format_error(mc, "invalid format (repeated flags)"));
+        }
+        let (width, next) = read_digits(&fmt, i);
+        spec.width = width;
+        i = next;
+        if fmt.get(i) == Some(&b'.') {
+            let (precision, next) = read_digits(&fmt, i + 1);
+            spec.precision = Some(precision);
+            i = next;
+        }
+        if fmt.get(i).is_some_and(u8::is_ascii_digit) {
+            return Err(format_error(
+                mc,
+                "invalid format (width or precision too long)",
+            ));
+        }
+
+        let conversion = match fmt.get(i) {
+            Some(&c) => c,
+            None => {
+                let message = format!(
+                    "invalid conversion '%{}' to 'format'",
+                    std::string::String::from_utf8_lossy(&fmt[start..])
+                );
+                return Err(format_error(mc, &message));
+            }
+        };
+        i += 1;
+        if !b"cdiuoxXaAeEfFgGqs".contains(&conversion) {
+            let message = format!(
+                "invalid conversion '%{}' to 'format'",
+                std::string::String::from_utf8_lossy(&fmt[start..i])
+            );
+            return Err(format_error(mc, &message));
+        }
+
+        arg += 1;
+        if arg >= args.len() {
+            return Err(bad_argument(mc, arg, "format", "no value"));
+        }
+
+        match conversion {
+            b'c' => {
+                let c = check_integer(args, arg, None)?;
+                pad(&mut out, &spec, b"", &[c as u8], false);
+            }
+            b'd' | b'i' => {
+                let n = check_integer(args, arg, None)?;
+                let sign = sign_prefix(&spec, n < 0);
+                let digits = n.unsigned_abs().to_string().into_bytes();
+                format_integer(&mut out, &spec, sign, digits, n == 0);
+            }
+            b'u' | b'o' | b'x' | b'X' => {
+                let n = check_integer(args, arg, None)? as u64;
+                let mut digits = match conversion {
+                    b'u' => n.to_string(),
+                    b'o' => format!("{:o}", n),
+                    b'x' => format!("{:x}", n),
+                    _ => format!("{:X}", n),
+                }
+                .into_bytes();
+                let mut prefix: &[u8] = b"";
+                if spec.alt {
+                    match conversion {
+                        b'o' if digits[0] != b'0' => digits.insert(0, b'0'),
+                        b'x' if n != 0 => prefix = b"0x",
+                        b'X' if n != 0 => prefix = b"0X",
+                        _ => {}
+                    }
+                }
+                format_integer(
+                    &mut out,
+                    &spec,
+                    prefix,
+                    digits,
+                    n == 0 && !(spec.alt && conversion == b'o'),
+                );
+            }
+            b'a' | b'A' | b'e' | b'E' | b'f' | b'F' | b'g' | b'G' => {
+                let n = check_number(args, arg)?;
+                format_float(&mut out, &spec, conversion, n);
+            }
+            b'q' => {
+                if i - start > 1 {
+                    return Err(format_error(mc, "specifier '%q' cannot have modifiers"));
+                }
+                add_quoted(mc, &mut out, args[arg])?;
+            }
+            b's' => {
+                let s = match meta_ops::tostring(mc, args[arg])? {
+                    MetaResult::Value(value) => display_bytes(value),
+                    MetaResult::Call(call) => return Ok(Formatted::ToString { arg, call }),
+                };
+                let s = match spec.precision {
+                    Some(p) if p < s.len() => &s[..p],
+                    _ => &s[..],
+                };
+                pad(&mut out, &spec, b"", s, false);
+            }
+            _ => unreachable!(),
+        }
+    }
+    Ok(Formatted::Done(out))
+}
+
+fn format_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
+
+// Reads at most two decimal digits starting at `i`, returning their value and the index after them
+fn read_digits(fmt: &[u8], mut i: usize) -> (usize, usize) {
+    let mut n = 0;
+    for _ in 0..2 {
+        match fmt.get(i) {
+            Some(&c) if c.is_ascii_digit() => {
+                n = n * 10 + (c - b'0') as usize;
+                i += 1;
+            }
+            _ => break,
+        }
+    }
+    (n, i)
+}
+
+// Converts any value to a string the same way `print` does
+fn display_bytes(value: Value) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    value.display(&mut bytes).unwrap();
+    bytes
+}
+
+fn sign_prefix(spec: &Spec, negative: bool) -> &'static [u8] {
+    if negative {
+        b"-"
+    } else if spec.plus {
+        b"+"
+    } else if spec.space {
+        b" "
+    } else {
+        b""
+    }
+}
+
+// Writes `prefix` and `body` padded to the width of the spec.  Zero padding goes in-between the
+// prefix and the body, and is only used if `zero_pad` is set.
+fn pad(out: &mut Vec<u8>, spec: &Spec, prefix: &[u8], body: &[u8], zero_pad: bool) {
+    let fill = spec.width.saturating_sub(prefix.len() + body.len());
+    if spec.left {
+        out.extend_from_slice(prefix);
+        out.extend_from_slice(body);
+        out.resize(out.len() + fill, b' ');
+    } else if zero_pad && spec.zero {
+        out.extend_from_slice(prefix);
+        out.resize(out.len() + fill, b'0');
+        out.extend_from_slice(body);
+    } else {
+        out.resize(out.len() + fill, b' ');
+        out.extend_from_slice(prefix);
+        out.extend_from_slice(body);
+    }
+}
+
+// Writes the digits of an integer, where the precision is the minimum number of digits.  A zero
+// formatted with a precision of zero has no digits at all.
+fn format_integer(out: &mut Vec<u8>, spec: &Spec, prefix: &[u8], mut digits: Vec<u8>, zero: bool) {
+    match spec.precision {
+        Some(p) => {
+            if p == 0 && zero {
+                digits.clear();
+            } else if digits.len() < p {
+                digits.splice(0..0, vec![b'0'; p - digits.len()]);
+            }
+            pad(out, spec, prefix, &digits, false);
+        }
+        None => pad(out, spec, prefix, &digits, true),
+    }
+}
+
+fn format_float(out: &mut Vec<u8>, spec: &Spec, conversion: u8, n: f64) {
+    let upper = conversion.is_ascii_uppercase();
+    let sign = sign_prefix(spec, n.is_sign_negative());
+    let x = n.abs();
+
+    if !x.is_finite() {
+        let body: &[u8] = match (x.is_nan(), upper) {
+            (true, false) => b"nan",
+            (true, true) => b"NAN",
+            (false, false) => b"inf",
+            (false, true) => b"INF",
+        };
+        pad(out, spec, sign, body, false);
+        return;
+    }
+
+    let body = match conversion.to_ascii_lowercase() {
+        b'a' => {
+            let mut prefix = sign.to_vec();
+            prefix.extend_from_slice(if upper { b"0X" } else { b"0x" });
+            let body = format_hex_float(x, spec.precision, spec.alt);
+            let body = if upper {
+                body.to_ascii_uppercase()
+            } else {
+                body
+            };
+            pad(out, spec, &prefix, body.as_bytes(), true);
+            return;
+        }
+        b'e' => format_exp(x, spec.precision.unwrap_or(6), spec.alt),
+        b'f' => format_fixed(x, spec.precision.unwrap_or(6), spec.alt),
+        _ => format_general(x, spec.precision.unwrap_or(6), spec.alt),
+    };
+    let body = if upper {
+        body.to_ascii_uppercase()
+    } else {
+        body
+    };
+    pad(out, spec, sign, body.as_bytes(), true);
+}
+
+// Formats a non-negative number like C's `%f`
+fn format_fixed(x: f64, precision: usize, alt: bool) -> std::string::String {
+    let mut s = format!("{:.*}", precision, x);
+    if alt && precision == 0 {
+        s.push('.');
+    }
+    s
+}
+
+// Formats a non-negative number like C's `%e`, with a sign and at least two digits in the exponent
+fn format_exp(x: f64, precision: usize, alt: bool) -> std::string::String {
+    let s = format!("{:.*e}", precision, x);
+    let (mantissa, exp) = s.split_at(s.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    format!(
+        "{}{}e{}{:02}",
+        mantissa,
+        if alt && precision == 0 { "." } else { "" },
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    )
+}
+
+// Formats a non-negative number like C's `%g`, which uses the shorter of `%e` and `%f` for the
+// given number of significant digits, and removes trailing zeros unless `alt` is set.
+fn format_general(x: f64, precision: usize, alt: bool) -> std::string::String {
+    let precision = precision.max(1);
+    let exp = if x == 0.0 {
+        0
+    } else {
+        let s = format!("{:.*e}", precision - 1, x);
+        s[s.find('e').unwrap() + 1..].parse::<i32>().unwrap()
+    };
+
+    let mut s = if exp >= -4 && exp < precision as i32 {
+        format_fixed(x, (precision as i32 - 1 - exp) as usize, alt)
+    } else {
+        format_exp(x, precision - 1, alt)
+    };
+
+    if !alt {
+        let exp_start = s.find('e').unwrap_or(s.len());
+        let (mantissa, exp) = s.split_at(exp_start);
+        if mantissa.contains('.') {
+            let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+            s = format!("{}{}", mantissa, exp);
+        }
+    }
+    s
+}
+
+// Formats a non-negative, finite number like C's `%a` without the leading "0x".  Without a
+// precision, as many hex digits as are needed to represent the number exactly are used.
+fn format_hex_float(x: f64, precision: Option<usize>, alt: bool) -> std::string::String {
+    const MANTISSA_DIGITS: usize = 13;
+
+    let bits = x.to_bits();
+    let biased_exp = ((bits >> 52) & 0x7ff) as i32;
+    let mut mantissa = bits & ((1 << 52) - 1);
+    let (mut lead, exp) = if x == 0.0 {
+        (0, 0)
+    } else if biased_exp == 0 {
+        (0, -1022)
+    } else {
+        (1, biased_exp - 1023)
+    };
+
+    let digits = match precision {
+        Some(p) if p < MANTISSA_DIGITS => {
+            // Round to the nearest representable value, ties to even
+            let shift = (MANTISSA_DIGITS - p) * 4;
+            let full = (lead << 52) | mantissa;
+            let rem = full & ((1 << shift) - 1);
+            let half = 1 << (shift - 1);
+            let mut rounded = full >> shift;
+            if rem > half || (rem == half && rounded & 1 == 1) {
+                rounded += 1;
+            }
+            lead = rounded >> (p * 4);
+            mantissa = rounded & ((1 << (p * 4)) - 1);
+            if p == 0 {
+                std::string::String::new()
+            } else {
+                format!("{:0width$x}", mantissa, width = p)
+            }
+        }
+        Some(p) => format!("{:013x}{}", mantissa, "0".repeat(p - MANTISSA_DIGITS)),
+        None => {
+            let digits = format!("{:013x}", mantissa);
+            digits.trim_end_matches('0').to_owned()
+        }
+    };
+
+    format!(
+        "{}{}{}p{}{}",
+        lead,
+        if !digits.is_empty() || alt { "." } else { "" },
+        digits,
+        if exp < 0 { '-' } else { '+' },
+        exp.abs()
+    )
+}
+
+// Writes a value in a form that Lua can read back, as `%q` does
+fn add_quoted<'gc>(
+    mc: MutationContext<'gc, '_>,
+    out: &mut Vec<u8>,
+    value: Value<'gc>,
+) -> Result<(), Error<'gc>> {
+    match value {
+        Value::String(s) => {
+            out.push(b'"');
+            for (i, &c) in s.iter().enumerate() {
+                match c {
+                    b'"' | b'\\' | b'\n' => {
+                        out.push(b'\\');
+                        out.push(c);
+                    }
+                    b'\r' => out.extend_from_slice(b"\\r"),
+                    c if c.is_ascii_control() => {
+                        if s.get(i + 1).is_some_and(u8::is_ascii_digit) {
+                            out.extend_from_slice(format!("\\{:03}", c).as_bytes());
+                        } else {
+                            out.extend_from_slice(format!("\\{}", c).as_bytes());
+                        }
+                    }
+                    c => out.push(c),
+                }
+            }
+            out.push(b'"');
+        }
+        Value::Integer(i) => {
+            if i == i64::MIN {
+                out.extend_from_slice(b"0x8000000000000000");
+            } else {
+                out.extend_from_slice(i.to_string().as_bytes());
+            }
+        }
+        Value::Number(n) => {
+            if n == f64::INFINITY {
+                out.extend_from_slice(b"1e9999");
+            } else if n == f64::NEG_INFINITY {
+                out.extend_from_slice(b"-1e9999");
+            } else if n.is_nan() {
+                out.extend_from_slice(b"(0/0)");
+            } else {
+                format_float(out, &Spec::default(), b'a', n);
+            }
+        }
+        Value::Nil | Value::Boolean(_) => value.display(out).unwrap(),
+        _ => {
+            return Err(format_error(mc, "value has no literal form"));
+        }
+    }
+    Ok(())
+}
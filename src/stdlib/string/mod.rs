@@ -0,0 +1,720 @@
+mod format;
+mod pack;
+mod pattern;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gc_arena::{Collect, MutationContext};
+use gc_sequence as sequence;
+
+use crate::{
+    thread::check_alloc, Callback, CallbackResult, Continuation, Error, MetaMethod, Root,
+    RuntimeError, String, Table, TypeError, Value, ValueBuffer,
+};
+
+use self::format::Formatted;
+use self::pattern::{Capture, Matcher, PatternError};
+
+// The largest string that `string.rep` will build, whether or not a memory limit is set
+const MAX_REP_SIZE: usize = i32::MAX as usize;
+
+pub fn load_string<'gc>(mc: MutationContext<'gc, '_>, root: Root<'gc>, env: Table<'gc>) {
+    let string = Table::new(mc);
+
+    string
+        .set(
+            mc,
+            String::new_static(b"len"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                Ok(args.with_values(&[Value::Integer(s.len() as i64)]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"sub"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                let i = check_integer(&args, 1, Some(1))?;
+                let j = check_integer(&args, 2, Some(-1))?;
+                let sub = match substring_range(s.len(), i, j) {
+                    Some((start, end)) => String::new(mc, &s[start..end]),
+                    None => String::new_static(b""),
+                };
+                Ok(args.with_values(&[Value::String(sub)]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"upper"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                let upper = String::new(mc, &s.to_ascii_uppercase());
+                Ok(args.with_values(&[Value::String(upper)]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"lower"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                let lower = String::new(mc, &s.to_ascii_lowercase());
+                Ok(args.with_values(&[Value::String(lower)]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"rep"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                let n = check_integer(&args, 1, None)?;
+                let sep = match args.get(2).cloned().unwrap_or(Value::Nil) {
+                    Value::Nil => String::new_static(b""),
+                    _ => check_string(mc, &args, 2)?,
+                };
+                if n <= 0 {
+                    return Ok(args.with_values(&[Value::String(String::new_static(b""))]));
+                }
+
+                let n = n as usize;
+                let len = s
+                    .len()
+                    .checked_add(sep.len())
+                    .and_then(|l| l.checked_mul(n))
+                    .filter(|&len| len <= MAX_REP_SIZE)
+                    .ok_or_else(|| runtime_error(b"resulting string too large"))?;
+                // Check the memory limit up front, rather than allocating an arbitrarily large
+                // string and only failing afterwards.
+                check_alloc(mc, len)?;
+
+                let mut bytes = Vec::with_capacity(len);
+                for i in 0..n {
+                    if i != 0 {
+                        bytes.extend_from_slice(&sep);
+                    }
+                    bytes.extend_from_slice(&s);
+                }
+                Ok(args.with_values(&[Value::String(String::new(mc, &bytes))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"reverse"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                let mut bytes = s.to_vec();
+                bytes.reverse();
+                Ok(args.with_values(&[Value::String(String::new(mc, &bytes))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"byte"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                let i = check_integer(&args, 1, Some(1))?;
+                let j = check_integer(&args, 2, Some(i))?;
+                let bytes = match substring_range(s.len(), i, j) {
+                    Some((start, end)) => s[start..end]
+                        .iter()
+                        .map(|&b| Value::Integer(b as i64))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                Ok(args.with_values(&bytes))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"char"),
+            string_callback(mc, |mc, args| {
+                let mut bytes = Vec::with_capacity(args.len());
+                for i in 0..args.len() {
+                    let c = check_integer(&args, i, None)?;
+                    if !(0..=255).contains(&c) {
+                        return Err(bad_argument(mc, i, "char", "value out of range"));
+                    }
+                    bytes.push(c as u8);
+                }
+                Ok(args.with_values(&[Value::String(String::new(mc, &bytes))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"format"),
+            Callback::new_sequence(mc, |args| Ok(sequence::from_fn_with(args, string_format))),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"pack"),
+            string_callback(mc, |mc, args| {
+                let bytes = pack::pack(mc, &args)?;
+                Ok(args.with_values(&[Value::String(String::new(mc, &bytes))]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"packsize"),
+            string_callback(mc, |mc, args| {
+                let size = pack::packsize(mc, &args)?;
+                Ok(args.with_values(&[Value::Integer(size)]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"unpack"),
+            string_callback(mc, |mc, args| {
+                let values = pack::unpack(mc, &args)?;
+                Ok(args.with_values(&values))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"find"),
+            string_callback(mc, |mc, args| find(mc, args, true)),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"match"),
+            string_callback(mc, |mc, args| find(mc, args, false)),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"gmatch"),
+            string_callback(mc, |mc, args| {
+                let s = check_string(mc, &args, 0)?;
+                let pat = check_string(mc, &args, 1)?;
+                let iterator = gmatch_iterator(mc, s, pat);
+                Ok(args.with_values(&[iterator.into()]))
+            }),
+        )
+        .unwrap();
+
+    string
+        .set(
+            mc,
+            String::new_static(b"gsub"),
+            Callback::new_sequence(mc, |args| {
+                Ok(sequence::from_fn_with(args, |mc, args| {
+                    let src = check_string(mc, &args, 0)?;
+                    let pat = check_string(mc, &args, 1)?;
+                    let repl = args.get(2).cloned().unwrap_or(Value::Nil);
+                    match repl {
+                        Value::String(_)
+                        | Value::Integer(_)
+                        | Value::Number(_)
+                        | Value::Table(_)
+                        | Value::Function(_) => {}
+                        _ => {
+                            return Err(TypeError {
+                                expected: "string/function/table",
+                                found: repl.type_name(),
+                            }
+                            .into());
+                        }
+                    }
+                    let max = check_integer(&args, 3, Some(src.len() as i64 + 1))?;
+
+                    let anchor = pat.first() == Some(&b'^');
+                    let state = Gsub {
+                        src,
+                        pat,
+                        repl,
+                        anchor,
+                        max,
+                        count: 0,
+                        pos: 0,
+                        last_match: None,
+                        current: (0, 0),
+                        finished: false,
+                        out: Vec::new(),
+                    };
+                    state.step(mc, args)
+                }))
+            }),
+        )
+        .unwrap();
+
+    env.set(mc, String::new_static(b"string"), string).unwrap();
+
+    // Share a metatable between all strings with the string table as its `__index`, so that string
+    // methods may be called as `s:upper()`.
+    let metatable = Table::new(mc);
+    metatable.set(mc, MetaMethod::Index, string).unwrap();
+    root.metatables.set_string(mc, Some(metatable));
+}
+
+// Creates a callback for a string library function, which unlike an immediate callback is given a
+// `MutationContext` so that it may allocate the strings it returns.
+fn string_callback<'gc, F>(mc: MutationContext<'gc, '_>, f: F) -> Callback<'gc>
+where
+    F: 'static
+        + Fn(MutationContext<'gc, '_>, ValueBuffer<'gc>) -> Result<ValueBuffer<'gc>, Error<'gc>>,
+{
+    let f = Rc::new(f);
+    Callback::new_sequence(mc, move |args| {
+        let f = f.clone();
+        Ok(sequence::from_fn_with(args, move |mc, args| {
+            Ok(CallbackResult::Return(f(mc, args)?))
+        }))
+    })
+}
+
+// Returns the string argument at the given index, converting numbers to strings as Lua does.
+fn check_string<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+    index: usize,
+) -> Result<String<'gc>, Error<'gc>> {
+    match args.get(index).cloned().unwrap_or(Value::Nil) {
+        Value::String(s) => Ok(s),
+        value @ Value::Integer(_) | value @ Value::Number(_) => {
+            Ok(String::concat(mc, &[value]).unwrap())
+        }
+        value => Err(TypeError {
+            expected: "string",
+            found: value.type_name(),
+        }
+        .into()),
+    }
+}
+
+// Returns the integer argument at the given index, or the given default if the argument is nil or
+// missing and there is a default.
+fn check_integer<'gc>(
+    args: &[Value<'gc>],
+    index: usize,
+    default: Option<i64>,
+) -> Result<i64, Error<'gc>> {
+    match (args.get(index).cloned().unwrap_or(Value::Nil), default) {
+        (Value::Nil, Some(default)) => Ok(default),
+        (value, _) => value.to_integer().ok_or_else(|| {
+            TypeError {
+                expected: "integer",
+                found: value.type_name(),
+            }
+            .into()
+        }),
+    }
+}
+
+// Returns the number argument at the given index, converting strings to numbers as Lua does.
+fn check_number<'gc>(args: &[Value<'gc>], index: usize) -> Result<f64, Error<'gc>> {
+    let value = args.get(index).cloned().unwrap_or(Value::Nil);
+    value.to_number().ok_or_else(|| {
+        TypeError {
+            expected: "number",
+            found: value.type_name(),
+        }
+        .into()
+    })
+}
+
+fn runtime_error<'gc>(message: &'static [u8]) -> Error<'gc> {
+    RuntimeError(Value::String(String::new_static(message))).into()
+}
+
+fn bad_argument<'gc>(
+    mc: MutationContext<'gc, '_>,
+    index: usize,
+    function: &str,
+    message: &str,
+) -> Error<'gc> {
+    let message = format!(
+        "bad argument #{} to '{}' ({})",
+        index + 1,
+        function,
+        message
+    );
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
+
+// Converts a 1-based position in a string with the given length, where negative positions count
+// back from the end of the string, to a non-negative position.
+fn relative_position(pos: i64, len: usize) -> i64 {
+    let len = len as i64;
+    if pos >= 0 {
+        pos
+    } else if pos < -len {
+        0
+    } else {
+        len + pos + 1
+    }
+}
+
+// Converts the 1-based, inclusive positions `i` and `j` of a string with the given length to a
+// range of bytes, following `string.sub`.  Negative positions count back from the end of the
+// string, so -1 is the last byte.  Returns `None` if the range is empty.
+fn substring_range(len: usize, i: i64, j: i64) -> Option<(usize, usize)> {
+    let start = relative_position(i, len).max(1);
+    let end = relative_position(j, len).min(len as i64);
+    if start > end {
+        None
+    } else {
+        Some((start as usize - 1, end as usize))
+    }
+}
+
+fn pattern_error<'gc>(mc: MutationContext<'gc, '_>, error: PatternError) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, error.to_string().as_bytes()))).into()
+}
+
+fn capture_value<'gc>(mc: MutationContext<'gc, '_>, src: &[u8], capture: Capture) -> Value<'gc> {
+    match capture {
+        Capture::Bytes(range) => Value::String(String::new(mc, &src[range])),
+        Capture::Position(pos) => Value::Integer(pos as i64),
+    }
+}
+
+// Implements `string.find` if `find` is set, otherwise `string.match`.  Both search for the first
+// match of the pattern starting at the optional initial position, but `string.find` returns the
+// position of the match followed by any captures, and `string.match` returns only the captures.
+fn find<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: ValueBuffer<'gc>,
+    find: bool,
+) -> Result<ValueBuffer<'gc>, Error<'gc>> {
+    let s = check_string(mc, &args, 0)?;
+    let pat = check_string(mc, &args, 1)?;
+    let init = relative_position(check_integer(&args, 2, Some(1))?, s.len()).max(1) as usize;
+    if init > s.len() + 1 {
+        return Ok(args.with_values(&[Value::Nil]));
+    }
+
+    if find {
+        let plain = args.get(3).cloned().unwrap_or(Value::Nil).to_bool();
+        if plain || pattern::is_plain(&pat) {
+            return Ok(match pattern::find_plain(&s, &pat, init - 1) {
+                Some(start) => args.with_values(&[
+                    Value::Integer(start as i64 + 1),
+                    Value::Integer((start + pat.len()) as i64),
+                ]),
+                None => args.with_values(&[Value::Nil]),
+            });
+        }
+    }
+
+    let (anchor, p) = if pat.first() == Some(&b'^') {
+        (true, 1)
+    } else {
+        (false, 0)
+    };
+    let mut matcher = Matcher::new(&s, &pat);
+    let mut start = init - 1;
+    loop {
+        if let Some(end) = matcher
+            .match_at(start, p)
+            .map_err(|e| pattern_error(mc, e))?
+        {
+            let captures = matcher
+                .captures(start, end, !find)
+                .map_err(|e| pattern_error(mc, e))?;
+            let mut values = Vec::with_capacity(captures.len() + 2);
+            if find {
+                values.push(Value::Integer(start as i64 + 1));
+                values.push(Value::Integer(end as i64));
+            }
+            values.extend(captures.into_iter().map(|c| capture_value(mc, &s, c)));
+            return Ok(args.with_values(&values));
+        }
+        start += 1;
+        if anchor || start > s.len() {
+            return Ok(args.with_values(&[Value::Nil]));
+        }
+    }
+}
+
+// Creates the iterator function returned by `string.gmatch`, which returns the captures of the next
+// match each time it is called.  A '^' in the pattern has no special meaning, as an anchored
+// pattern could only ever match once.
+fn gmatch_iterator<'gc>(
+    mc: MutationContext<'gc, '_>,
+    s: String<'gc>,
+    pat: String<'gc>,
+) -> Callback<'gc> {
+    // The position to start searching from and the end of the last match, which the next match may
+    // not also end at, so that an empty match directly after another match is skipped.
+    let position = Rc::new(Cell::new((0, None)));
+    Callback::new_sequence_with(mc, (s, pat), move |&(s, pat), args| {
+        let position = position.clone();
+        Ok(sequence::from_fn_with(
+            (s, pat, args),
+            move |mc, (s, pat, args)| {
+                let (mut start, last_match) = position.get();
+                let mut matcher = Matcher::new(&s, &pat);
+                while start <= s.len() {
+                    match matcher
+                        .match_at(start, 0)
+                        .map_err(|e| pattern_error(mc, e))?
+                    {
+                        Some(end) if Some(end) != last_match => {
+                            position.set((end, Some(end)));
+                            let captures = matcher
+                                .captures(start, end, true)
+                                .map_err(|e| pattern_error(mc, e))?;
+                            let values = captures
+                                .into_iter()
+                                .map(|c| capture_value(mc, &s, c))
+                                .collect::<Vec<_>>();
+                            return Ok(CallbackResult::Return(args.with_values(&values)));
+                        }
+                        _ => start += 1,
+                    }
+                }
+                position.set((start, last_match));
+                Ok(CallbackResult::Return(args.with_values(&[Value::Nil])))
+            },
+        ))
+    })
+}
+
+// Formats the arguments as `string.format` does.  Arguments formatted with `%s` that have a
+// `__tostring` metamethod are replaced with the result of tail calling it, and formatting then starts
+// over from the beginning.
+fn string_format<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: ValueBuffer<'gc>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    match format::format(mc, &args)? {
+        Formatted::Done(bytes) => Ok(CallbackResult::Return(
+            args.with_values(&[Value::String(String::new(mc, &bytes))]),
+        )),
+        Formatted::ToString { arg, call } => {
+            let format_args = args.to_vec();
+            Ok(CallbackResult::TailCall {
+                function: call.function,
+                args: args.with_values(&call.args),
+                continuation: Continuation::new_sequence_with(
+                    format_args,
+                    move |format_args, res| {
+                        Ok(sequence::from_fn_with(
+                            (format_args, res?),
+                            move |mc, (mut format_args, res)| match res.first() {
+                                Some(&Value::String(s)) => {
+                                    format_args[arg] = Value::String(s);
+                                    string_format(mc, res.with_values(&format_args))
+                                }
+                                _ => Err(runtime_error(b"'__tostring' must return a string")),
+                            },
+                        ))
+                    },
+                ),
+            })
+        }
+    }
+}
+
+// The state of a `string.gsub` call, which is kept across calls to a replacement function.
+#[derive(Collect)]
+#[collect(empty_drop)]
+struct Gsub<'gc> {
+    src: String<'gc>,
+    pat: String<'gc>,
+    repl: Value<'gc>,
+    anchor: bool,
+    // The maximum number of substitutions
+    max: i64,
+    count: i64,
+    // The position in `src` to search from next, everything before which has been written to `out`
+    pos: usize,
+    last_match: Option<usize>,
+    // The range of the match a replacement function is being called for
+    current: (usize, usize),
+    finished: bool,
+    out: Vec<u8>,
+}
+
+impl<'gc> Gsub<'gc> {
+    // Makes substitutions until either the end of the string is reached or a replacement function
+    // must be called.  Replacement functions are tail called with a continuation that resumes the
+    // substitution, so that they run on the calling Lua thread like any other function call.
+    fn step(
+        mut self,
+        mc: MutationContext<'gc, '_>,
+        args: ValueBuffer<'gc>,
+    ) -> Result<CallbackResult<'gc>, Error<'gc>> {
+        let (src, pat) = (self.src, self.pat);
+        let p = if self.anchor { 1 } else { 0 };
+        let mut matcher = Matcher::new(&src, &pat);
+        while !self.finished && self.count < self.max {
+            let start = self.pos;
+            self.finished = self.anchor;
+            match matcher
+                .match_at(start, p)
+                .map_err(|e| pattern_error(mc, e))?
+            {
+                Some(end) if Some(end) != self.last_match => {
+                    self.count += 1;
+                    self.pos = end;
+                    self.last_match = Some(end);
+                    match self.repl {
+                        Value::Table(table) => {
+                            let key = matcher
+                                .capture(0, start, end)
+                                .map_err(|e| pattern_error(mc, e))?;
+                            let value = table.get(capture_value(mc, &src, key));
+                            self.add_value(mc, start, end, value)?;
+                        }
+                        Value::Function(function) => {
+                            let captures = matcher
+                                .captures(start, end, true)
+                                .map_err(|e| pattern_error(mc, e))?
+                                .into_iter()
+                                .map(|c| capture_value(mc, &src, c))
+                                .collect::<Vec<_>>();
+                            self.current = (start, end);
+                            return Ok(CallbackResult::TailCall {
+                                function,
+                                args: args.with_values(&captures),
+                                continuation: Continuation::new_sequence_with(
+                                    self,
+                                    |state, res| {
+                                        Ok(sequence::from_fn_with(
+                                            (state, res?),
+                                            |mc, (mut state, res)| {
+                                                let (start, end) = state.current;
+                                                let value =
+                                                    res.first().cloned().unwrap_or(Value::Nil);
+                                                state.add_value(mc, start, end, value)?;
+                                                state.step(mc, res)
+                                            },
+                                        ))
+                                    },
+                                ),
+                            });
+                        }
+                        _ => self.add_string(mc, &matcher, start, end)?,
+                    }
+                }
+                _ if start < src.len() => {
+                    self.out.push(src[start]);
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+
+        self.out.extend_from_slice(&src[self.pos..]);
+        Ok(CallbackResult::Return(args.with_values(&[
+            Value::String(String::new(mc, &self.out)),
+            Value::Integer(self.count),
+        ])))
+    }
+
+    // Adds the replacement string for the match from `start` to `end`, where `%0` to `%9` stand for
+    // the whole match and the captures, and `%%` stands for a single '%'.
+    fn add_string(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        matcher: &Matcher,
+        start: usize,
+        end: usize,
+    ) -> Result<(), Error<'gc>> {
+        let repl = match self.repl {
+            Value::String(s) => s,
+            repl => String::concat(mc, &[repl]).unwrap(),
+        };
+        let mut i = 0;
+        while i < repl.len() {
+            let c = repl[i];
+            i += 1;
+            if c != b'%' {
+                self.out.push(c);
+                continue;
+            }
+            match repl.get(i) {
+                Some(b'%') => self.out.push(b'%'),
+                Some(b'0') => self.out.extend_from_slice(&self.src[start..end]),
+                Some(&d) if d.is_ascii_digit() => {
+                    let capture = matcher
+                        .capture((d - b'1') as usize, start, end)
+                        .map_err(|e| pattern_error(mc, e))?;
+                    match capture {
+                        Capture::Bytes(range) => self.out.extend_from_slice(&self.src[range]),
+                        Capture::Position(pos) => {
+                            self.out.extend_from_slice(pos.to_string().as_bytes())
+                        }
+                    }
+                }
+                _ => return Err(runtime_error(b"invalid use of '%' in replacement string")),
+            }
+            self.check_len(mc)?;
+            i += 1;
+        }
+        self.check_len(mc)
+    }
+
+    // Adds the value returned from a replacement table or function for the match from `start` to
+    // `end`.  If the value is false or nil, the match is kept as it is.
+    fn add_value(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        start: usize,
+        end: usize,
+        value: Value<'gc>,
+    ) -> Result<(), Error<'gc>> {
+        match value {
+            Value::Nil | Value::Boolean(false) => {
+                self.out.extend_from_slice(&self.src[start..end]);
+            }
+            Value::String(s) => self.out.extend_from_slice(&s),
+            Value::Integer(_) | Value::Number(_) => value.display(&mut self.out).unwrap(),
+            value => {
+                let message = format!("invalid replacement value (a {})", value.type_name());
+                return Err(
+                    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into(),
+                );
+            }
+        }
+        self.check_len(mc)
+    }
+
+    // Checks the result built so far against the memory limit.  Every replacement may repeat the
+    // match any number of times, so the result can grow far larger than the strings it is built from.
+    fn check_len(&self, mc: MutationContext<'gc, '_>) -> Result<(), Error<'gc>> {
+        check_alloc(mc, self.out.len())
+    }
+}
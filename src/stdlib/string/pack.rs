@@ -0,0 +1,496 @@
+use gc_arena::MutationContext;
+
+use crate::{thread::check_alloc, Error, RuntimeError, String, Value};
+
+use super::{bad_argument, check_integer, check_number, check_string, relative_position};
+
+// The largest integer size that may be given to the 'i', 'I' and 's' options
+const MAX_INT_SIZE: usize = 16;
+// The default maximum alignment for the '!' option, which is the alignment of the largest native
+// type
+const MAX_ALIGN: usize = 8;
+// The limit on sizes and on the total size of a format
+const MAX_SIZE: usize = i32::MAX as usize;
+const INTEGER_SIZE: usize = 8;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Int,
+    Uint,
+    Float,
+    // A fixed size string
+    Char,
+    // A string preceded by its length
+    String,
+    // A zero terminated string
+    ZString,
+    Padding,
+    PadAlign,
+    Nop,
+}
+
+// A single option of a format string, along with the number of padding bytes needed to align it
+struct PackOption {
+    kind: Kind,
+    size: usize,
+    align_padding: usize,
+}
+
+// Reads the options of a `string.pack` format string, keeping track of the current endianness and
+// maximum alignment.
+struct Format<'a> {
+    fmt: &'a [u8],
+    pos: usize,
+    function: &'static str,
+    little: bool,
+    max_align: usize,
+}
+
+impl<'a> Format<'a> {
+    fn new(fmt: &'a [u8], function: &'static str) -> Format<'a> {
+        Format {
+            fmt,
+            pos: 0,
+            function,
+            little: cfg!(target_endian = "little"),
+            max_align: 1,
+        }
+    }
+
+    // Returns the next option, given the total size of everything before it, or `None` at the end of
+    // the format string.
+    fn next<'gc>(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        total: usize,
+    ) -> Result<Option<PackOption>, Error<'gc>> {
+        if self.pos == self.fmt.len() {
+            return Ok(None);
+        }
+
+        let (kind, size) = self.read_option(mc)?;
+        let mut align = size;
+        if kind == Kind::PadAlign {
+            // 'X' takes its alignment from the option after it
+            let next = if self.pos == self.fmt.len() {
+                None
+            } else {
+                Some(self.read_option(mc)?)
+            };
+            match next {
+                Some((next_kind, next_size)) if next_kind != Kind::Char && next_size != 0 => {
+                    align = next_size;
+                }
+                _ => {
+                    return Err(bad_argument(
+                        mc,
+                        0,
+                        self.function,
+                        "invalid next option for option 'X'",
+                    ));
+                }
+            }
+        }
+
+        let align_padding = if align <= 1 || kind == Kind::Char {
+            0
+        } else {
+            let align = align.min(self.max_align);
+            if !align.is_power_of_two() {
+                return Err(bad_argument(
+                    mc,
+                    0,
+                    self.function,
+                    "format asks for alignment not power of 2",
+                ));
+            }
+            (align - (total & (align - 1))) & (align - 1)
+        };
+
+        Ok(Some(PackOption {
+            kind,
+            size,
+            align_padding,
+        }))
+    }
+
+    fn read_option<'gc>(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+    ) -> Result<(Kind, usize), Error<'gc>> {
+        let c = self.fmt[self.pos];
+        self.pos += 1;
+        Ok(match c {
+            b'b' => (Kind::Int, 1),
+            b'B' => (Kind::Uint, 1),
+            b'h' => (Kind::Int, 2),
+            b'H' => (Kind::Uint, 2),
+            b'l' | b'j' => (Kind::Int, INTEGER_SIZE),
+            b'L' | b'J' | b'T' => (Kind::Uint, INTEGER_SIZE),
+            b'f' => (Kind::Float, 4),
+            b'd' | b'n' => (Kind::Float, 8),
+            b'i' => (Kind::Int, self.read_size(mc, 4)?),
+            b'I' => (Kind::Uint, self.read_size(mc, 4)?),
+            b's' => (Kind::String, self.read_size(mc, INTEGER_SIZE)?),
+            b'c' => match self.read_number() {
+                Some(size) => (Kind::Char, size),
+                None => {
+                    return Err(format_error(mc, "missing size for format option 'c'"));
+                }
+            },
+            b'z' => (Kind::ZString, 0),
+            b'x' => (Kind::Padding, 1),
+            b'X' => (Kind::PadAlign, 0),
+            b' ' => (Kind::Nop, 0),
+            b'<' => {
+                self.little = true;
+                (Kind::Nop, 0)
+            }
+            b'>' => {
+                self.little = false;
+                (Kind::Nop, 0)
+            }
+            b'=' => {
+                self.little = cfg!(target_endian = "little");
+                (Kind::Nop, 0)
+            }
+            b'!' => {
+                self.max_align = self.read_size(mc, MAX_ALIGN)?;
+                (Kind::Nop, 0)
+            }
+            c => {
+                let message = format!("invalid format option '{}'", c as char);
+                return Err(format_error(mc, &message));
+            }
+        })
+    }
+
+    // Reads an optional decimal number following an option
+    fn read_number(&mut self) -> Option<usize> {
+        let mut n = None;
+        while let Some(&c) = self.fmt.get(self.pos) {
+            let a = n.unwrap_or(0);
+            if !c.is_ascii_digit() || (n.is_some() && a > (MAX_SIZE - 9) / 10) {
+                break;
+            }
+            n = Some(a * 10 + (c - b'0') as usize);
+            self.pos += 1;
+        }
+        n
+    }
+
+    // Reads an optional integer size following an option, which must be between 1 and
+    // `MAX_INT_SIZE`
+    fn read_size<'gc>(
+        &mut self,
+        mc: MutationContext<'gc, '_>,
+        default: usize,
+    ) -> Result<usize, Error<'gc>> {
+        let size = self.read_number().unwrap_or(default);
+        if size == 0 || size > MAX_INT_SIZE {
+            let message = format!(
+                "integral size ({}) out of limits [1,{}]",
+                size, MAX_INT_SIZE
+            );
+            return Err(format_error(mc, &message));
+        }
+        Ok(size)
+    }
+}
+
+// Packs the arguments following the format string in `args[0]`, as `string.pack` does.
+pub fn pack<'gc>(mc: MutationContext<'gc, '_>, args: &[Value<'gc>]) -> Result<Vec<u8>, Error<'gc>> {
+    let fmt = check_string(mc, args, 0)?;
+    // Check the memory limit up front, a single option such as 'c' may ask for an arbitrarily large
+    // amount of padding.
+    let size = packed_size(mc, &fmt, args)?;
+    check_alloc(mc, size)?;
+
+    let mut format = Format::new(&fmt, "pack");
+    let mut out = Vec::with_capacity(size);
+    let mut arg = 0;
+    while let Some(opt) = format.next(mc, out.len())? {
+        out.resize(out.len() + opt.align_padding, 0);
+        match opt.kind {
+            Kind::Int => {
+                arg += 1;
+                let n = check_integer(args, arg, None)?;
+                if opt.size < INTEGER_SIZE {
+                    let limit = 1 << (opt.size * 8 - 1);
+                    if n < -limit || n >= limit {
+                        return Err(bad_argument(mc, arg, "pack", "integer overflow"));
+                    }
+                }
+                pack_int(&mut out, n as u64, format.little, opt.size, n < 0);
+            }
+            Kind::Uint => {
+                arg += 1;
+                let n = check_integer(args, arg, None)?;
+                if opt.size < INTEGER_SIZE && n as u64 >= 1 << (opt.size * 8) {
+                    return Err(bad_argument(mc, arg, "pack", "unsigned overflow"));
+                }
+                pack_int(&mut out, n as u64, format.little, opt.size, false);
+            }
+            Kind::Float => {
+                arg += 1;
+                let n = check_number(args, arg)?;
+                let bytes = if opt.size == 4 {
+                    let n = n as f32;
+                    if format.little {
+                        n.to_le_bytes().to_vec()
+                    } else {
+                        n.to_be_bytes().to_vec()
+                    }
+                } else if format.little {
+                    n.to_le_bytes().to_vec()
+                } else {
+                    n.to_be_bytes().to_vec()
+                };
+                out.extend_from_slice(&bytes);
+            }
+            Kind::Char => {
+                arg += 1;
+                let s = check_string(mc, args, arg)?;
+                if s.len() > opt.size {
+                    return Err(bad_argument(
+                        mc,
+                        arg,
+                        "pack",
+                        "string longer than given size",
+                    ));
+                }
+                out.extend_from_slice(&s);
+                out.resize(out.len() + opt.size - s.len(), 0);
+            }
+            Kind::String => {
+                arg += 1;
+                let s = check_string(mc, args, arg)?;
+                if opt.size < INTEGER_SIZE && s.len() as u64 >= 1 << (opt.size * 8) {
+                    return Err(bad_argument(
+                        mc,
+                        arg,
+                        "pack",
+                        "string length does not fit in given size",
+                    ));
+                }
+                pack_int(&mut out, s.len() as u64, format.little, opt.size, false);
+                out.extend_from_slice(&s);
+            }
+            Kind::ZString => {
+                arg += 1;
+                let s = check_string(mc, args, arg)?;
+                if s.contains(&0) {
+                    return Err(bad_argument(mc, arg, "pack", "string contains zeros"));
+                }
+                out.extend_from_slice(&s);
+                out.push(0);
+            }
+            Kind::Padding => out.push(0),
+            Kind::PadAlign | Kind::Nop => {}
+        }
+    }
+    Ok(out)
+}
+
+// Returns the size of the string that `pack` produces for the given format string and arguments.
+// Arguments are not checked here, and any argument that is not a string counts as an empty string for
+// the variable length options, as numbers only ever convert to short strings.
+fn packed_size<'gc>(
+    mc: MutationContext<'gc, '_>,
+    fmt: &[u8],
+    args: &[Value<'gc>],
+) -> Result<usize, Error<'gc>> {
+    let mut format = Format::new(fmt, "pack");
+    let mut total: usize = 0;
+    let mut arg = 0;
+    while let Some(opt) = format.next(mc, total)? {
+        let mut size = opt.size + opt.align_padding;
+        match opt.kind {
+            Kind::Int | Kind::Uint | Kind::Float | Kind::Char => arg += 1,
+            Kind::String | Kind::ZString => {
+                arg += 1;
+                if let Some(Value::String(s)) = args.get(arg) {
+                    size += s.len();
+                }
+                if opt.kind == Kind::ZString {
+                    size += 1;
+                }
+            }
+            Kind::Padding | Kind::PadAlign | Kind::Nop => {}
+        }
+        total = total.saturating_add(size);
+    }
+    Ok(total)
+}
+
+// Returns the size of a string produced by `string.pack` with the format string in `args[0]`, which
+// may not contain any variable length options.
+pub fn packsize<'gc>(mc: MutationContext<'gc, '_>, args: &[Value<'gc>]) -> Result<i64, Error<'gc>> {
+    let fmt = check_string(mc, args, 0)?;
+    let mut format = Format::new(&fmt, "packsize");
+    let mut total = 0;
+    while let Some(opt) = format.next(mc, total)? {
+        let size = opt.size + opt.align_padding;
+        if total > MAX_SIZE - size {
+            return Err(bad_argument(mc, 0, "packsize", "format result too large"));
+        }
+        total += size;
+        if opt.kind == Kind::String || opt.kind == Kind::ZString {
+            return Err(bad_argument(mc, 0, "packsize", "variable-length format"));
+        }
+    }
+    Ok(total as i64)
+}
+
+// Unpacks the string in `args[1]` according to the format string in `args[0]`, as `string.unpack`
+// does.  The unpacked values are followed by the position after the last byte read.
+pub fn unpack<'gc>(
+    mc: MutationContext<'gc, '_>,
+    args: &[Value<'gc>],
+) -> Result<Vec<Value<'gc>>, Error<'gc>> {
+    let fmt = check_string(mc, args, 0)?;
+    let data = check_string(mc, args, 1)?;
+    let init = relative_position(check_integer(args, 2, Some(1))?, data.len()) - 1;
+    if init < 0 || init as usize > data.len() {
+        return Err(bad_argument(
+            mc,
+            2,
+            "unpack",
+            "initial position out of string",
+        ));
+    }
+
+    let mut format = Format::new(&fmt, "unpack");
+    let mut pos = init as usize;
+    let mut values = Vec::new();
+    while let Some(opt) = format.next(mc, pos)? {
+        if opt.align_padding + opt.size > data.len() - pos {
+            return Err(bad_argument(mc, 1, "unpack", "data string too short"));
+        }
+        pos += opt.align_padding;
+        match opt.kind {
+            Kind::Int | Kind::Uint => {
+                let n = unpack_int(
+                    mc,
+                    &data[pos..pos + opt.size],
+                    format.little,
+                    opt.kind == Kind::Int,
+                )?;
+                values.push(Value::Integer(n));
+            }
+            Kind::Float => {
+                let bytes = &data[pos..pos + opt.size];
+                let n = if opt.size == 4 {
+                    let mut buf = [0; 4];
+                    buf.copy_from_slice(bytes);
+                    if format.little {
+                        f32::from_le_bytes(buf) as f64
+                    } else {
+                        f32::from_be_bytes(buf) as f64
+                    }
+                } else {
+                    let mut buf = [0; 8];
+                    buf.copy_from_slice(bytes);
+                    if format.little {
+                        f64::from_le_bytes(buf)
+                    } else {
+                        f64::from_be_bytes(buf)
+                    }
+                };
+                values.push(Value::Number(n));
+            }
+            Kind::Char => {
+                values.push(Value::String(String::new(mc, &data[pos..pos + opt.size])));
+            }
+            Kind::String => {
+                let len = unpack_int(mc, &data[pos..pos + opt.size], format.little, false)?;
+                let start = pos + opt.size;
+                if len as u64 > (data.len() - start) as u64 {
+                    return Err(bad_argument(mc, 1, "unpack", "data string too short"));
+                }
+                let len = len as usize;
+                values.push(Value::String(String::new(mc, &data[start..start + len])));
+                pos += len;
+            }
+            Kind::ZString => {
+                let len = match data[pos..].iter().position(|&c| c == 0) {
+                    Some(len) => len,
+                    None => {
+                        return Err(bad_argument(
+                            mc,
+                            1,
+                            "unpack",
+                            "unfinished string for format 'z'",
+                        ));
+                    }
+                };
+                values.push(Value::String(String::new(mc, &data[pos..pos + len])));
+                pos += len + 1;
+            }
+            Kind::Padding | Kind::PadAlign | Kind::Nop => {}
+        }
+        pos += opt.size;
+    }
+    values.push(Value::Integer(pos as i64 + 1));
+    Ok(values)
+}
+
+fn format_error<'gc>(mc: MutationContext<'gc, '_>, message: &str) -> Error<'gc> {
+    RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+}
+
+// Writes the low `size` bytes of `n`, sign extending it if `size` is larger than an integer and
+// `negative` is set.
+fn pack_int(out: &mut Vec<u8>, n: u64, little: bool, size: usize, negative: bool) {
+    let start = out.len();
+    for i in 0..size {
+        let byte = if i < INTEGER_SIZE {
+            (n >> (i * 8)) as u8
+        } else if negative {
+            0xff
+        } else {
+            0
+        };
+        out.push(byte);
+    }
+    if !little {
+        out[start..].reverse();
+    }
+}
+
+// Reads an integer of any size from 1 to `MAX_INT_SIZE` bytes, which must fit in a Lua integer.
+fn unpack_int<'gc>(
+    mc: MutationContext<'gc, '_>,
+    bytes: &[u8],
+    little: bool,
+    signed: bool,
+) -> Result<i64, Error<'gc>> {
+    let size = bytes.len();
+    // Returns the `i`th least significant byte
+    let byte = |i: usize| {
+        if little {
+            bytes[i]
+        } else {
+            bytes[size - 1 - i]
+        }
+    };
+
+    let mut n: u64 = 0;
+    for i in (0..size.min(INTEGER_SIZE)).rev() {
+        n = (n << 8) | byte(i) as u64;
+    }
+
+    if size < INTEGER_SIZE {
+        if signed {
+            let mask = 1 << (size * 8 - 1);
+            n = (n ^ mask).wrapping_sub(mask);
+        }
+    } else if size > INTEGER_SIZE {
+        let extension = if !signed || (n as i64) >= 0 { 0 } else { 0xff };
+        if (INTEGER_SIZE..size).any(|i| byte(i) != extension) {
+            let message = format!("{}-byte integer does not fit into Lua Integer", size);
+            return Err(format_error(mc, &message));
+        }
+    }
+    Ok(n as i64)
+}
@@ -0,0 +1,438 @@
+use std::fmt;
+use std::ops::Range;
+
+// A port of the pattern matcher from PUC-Rio Lua's `lstrlib.c`, working on byte indexes rather than
+// pointers.
+
+const MAX_CAPTURES: usize = 32;
+// The maximum recursion depth of `Matcher::do_match`
+const MAX_MATCH_DEPTH: usize = 200;
+const ESCAPE: u8 = b'%';
+const SPECIALS: &[u8] = b"^$*+?.([%-";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    EndsWithEscape,
+    MissingBracket,
+    MissingBalanceArguments,
+    MissingFrontierBracket,
+    InvalidCaptureIndex(usize),
+    InvalidPatternCapture,
+    UnfinishedCapture,
+    TooManyCaptures,
+    TooComplex,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::EndsWithEscape => write!(fmt, "malformed pattern (ends with '%')"),
+            PatternError::MissingBracket => write!(fmt, "malformed pattern (missing ']')"),
+            PatternError::MissingBalanceArguments => write!(fmt, "missing arguments to '%b'"),
+            PatternError::MissingFrontierBracket => {
+                write!(fmt, "missing '[' after '%f' in pattern")
+            }
+            PatternError::InvalidCaptureIndex(i) => write!(fmt, "invalid capture index %{}", i),
+            PatternError::InvalidPatternCapture => write!(fmt, "invalid pattern capture"),
+            PatternError::UnfinishedCapture => write!(fmt, "unfinished capture"),
+            PatternError::TooManyCaptures => write!(fmt, "too many captures"),
+            PatternError::TooComplex => write!(fmt, "pattern too complex"),
+        }
+    }
+}
+
+/// A capture made by a successful match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Capture {
+    /// The captured range of the subject
+    Bytes(Range<usize>),
+    /// A position capture `()`, which captures a 1-based position in the subject
+    Position(usize),
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum CaptureLen {
+    Unclosed,
+    Position,
+    Len(usize),
+}
+
+pub struct Matcher<'a> {
+    src: &'a [u8],
+    pat: &'a [u8],
+    depth: usize,
+    level: usize,
+    captures: [(usize, CaptureLen); MAX_CAPTURES],
+}
+
+/// Returns true if the pattern has no special characters, so it can be searched for as a plain
+/// substring.
+pub fn is_plain(pat: &[u8]) -> bool {
+    !pat.iter().any(|c| SPECIALS.contains(c))
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack` at or after `start`.
+pub fn find_plain(haystack: &[u8], needle: &[u8], start: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(start);
+    }
+    haystack[start..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| start + i)
+}
+
+impl<'a> Matcher<'a> {
+    pub fn new(src: &'a [u8], pat: &'a [u8]) -> Matcher<'a> {
+        Matcher {
+            src,
+            pat,
+            depth: MAX_MATCH_DEPTH,
+            level: 0,
+            captures: [(0, CaptureLen::Unclosed); MAX_CAPTURES],
+        }
+    }
+
+    /// Tries to match the pattern starting from index `p` against the subject starting at index
+    /// `s`, returning the end of the match.
+    pub fn match_at(&mut self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        self.level = 0;
+        self.depth = MAX_MATCH_DEPTH;
+        self.do_match(s, p)
+    }
+
+    /// Returns the captures of the last successful match from `s` to `e`.  If the pattern has no
+    /// captures and `whole` is set, the whole match is returned as the only capture.
+    pub fn captures(&self, s: usize, e: usize, whole: bool) -> Result<Vec<Capture>, PatternError> {
+        let count = if self.level == 0 && whole {
+            1
+        } else {
+            self.level
+        };
+        (0..count).map(|i| self.capture(i, s, e)).collect()
+    }
+
+    /// Returns capture `i` of the last successful match from `s` to `e`, where capture 0 is the
+    /// whole match if the pattern has no captures.
+    pub fn capture(&self, i: usize, s: usize, e: usize) -> Result<Capture, PatternError> {
+        if i >= self.level {
+            if i == 0 {
+                Ok(Capture::Bytes(s..e))
+            } else {
+                Err(PatternError::InvalidCaptureIndex(i + 1))
+            }
+        } else {
+            let (start, len) = self.captures[i];
+            match len {
+                CaptureLen::Unclosed => Err(PatternError::UnfinishedCapture),
+                CaptureLen::Position => Ok(Capture::Position(start + 1)),
+                CaptureLen::Len(len) => Ok(Capture::Bytes(start..start + len)),
+            }
+        }
+    }
+
+    fn do_match(&mut self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        if self.depth == 0 {
+            return Err(PatternError::TooComplex);
+        }
+        self.depth -= 1;
+        let res = self.do_match_inner(s, p);
+        self.depth += 1;
+        res
+    }
+
+    fn do_match_inner(
+        &mut self,
+        mut s: usize,
+        mut p: usize,
+    ) -> Result<Option<usize>, PatternError> {
+        let pat = self.pat;
+        loop {
+            if p == pat.len() {
+                return Ok(Some(s));
+            }
+
+            match pat[p] {
+                b'(' => {
+                    return if pat.get(p + 1) == Some(&b')') {
+                        self.start_capture(s, p + 2, CaptureLen::Position)
+                    } else {
+                        self.start_capture(s, p + 1, CaptureLen::Unclosed)
+                    };
+                }
+                b')' => return self.end_capture(s, p + 1),
+                b'$' if p + 1 == pat.len() => {
+                    return Ok(if s == self.src.len() { Some(s) } else { None });
+                }
+                ESCAPE if pat.get(p + 1) == Some(&b'b') => match self.match_balance(s, p + 2)? {
+                    Some(e) => {
+                        s = e;
+                        p += 4;
+                        continue;
+                    }
+                    None => return Ok(None),
+                },
+                ESCAPE if pat.get(p + 1) == Some(&b'f') => {
+                    p += 2;
+                    if pat.get(p) != Some(&b'[') {
+                        return Err(PatternError::MissingFrontierBracket);
+                    }
+                    let ep = self.class_end(p)?;
+                    let previous = if s == 0 { 0 } else { self.src[s - 1] };
+                    let current = self.src.get(s).cloned().unwrap_or(0);
+                    if !self.match_bracket_class(previous, p, ep - 1)
+                        && self.match_bracket_class(current, p, ep - 1)
+                    {
+                        p = ep;
+                        continue;
+                    }
+                    return Ok(None);
+                }
+                ESCAPE if pat.get(p + 1).is_some_and(u8::is_ascii_digit) => {
+                    match self.match_capture(s, pat[p + 1])? {
+                        Some(e) => {
+                            s = e;
+                            p += 2;
+                            continue;
+                        }
+                        None => return Ok(None),
+                    }
+                }
+                _ => {}
+            }
+
+            let ep = self.class_end(p)?;
+            let suffix = pat.get(ep).cloned();
+            if !self.single_match(s, p, ep) {
+                match suffix {
+                    // Accept an empty match
+                    Some(b'*') | Some(b'?') | Some(b'-') => {
+                        p = ep + 1;
+                        continue;
+                    }
+                    _ => return Ok(None),
+                }
+            }
+
+            match suffix {
+                Some(b'?') => {
+                    if let Some(e) = self.do_match(s + 1, ep + 1)? {
+                        return Ok(Some(e));
+                    }
+                    p = ep + 1;
+                }
+                Some(b'+') => return self.max_expand(s + 1, p, ep),
+                Some(b'*') => return self.max_expand(s, p, ep),
+                Some(b'-') => return self.min_expand(s, p, ep),
+                _ => {
+                    s += 1;
+                    p = ep;
+                }
+            }
+        }
+    }
+
+    // Returns the index just past the single character class starting at `p`
+    fn class_end(&self, mut p: usize) -> Result<usize, PatternError> {
+        let pat = self.pat;
+        let c = pat[p];
+        p += 1;
+        if c == ESCAPE {
+            if p >= pat.len() {
+                return Err(PatternError::EndsWithEscape);
+            }
+            Ok(p + 1)
+        } else if c == b'[' {
+            if pat.get(p) == Some(&b'^') {
+                p += 1;
+            }
+            // Look for the closing ']', skipping escapes such as '%]'
+            loop {
+                if p >= pat.len() {
+                    return Err(PatternError::MissingBracket);
+                }
+                let c = pat[p];
+                p += 1;
+                if c == ESCAPE && p < pat.len() {
+                    p += 1;
+                }
+                if pat.get(p) == Some(&b']') {
+                    return Ok(p + 1);
+                }
+            }
+        } else {
+            Ok(p)
+        }
+    }
+
+    fn single_match(&self, s: usize, p: usize, ep: usize) -> bool {
+        match self.src.get(s) {
+            None => false,
+            Some(&c) => match self.pat[p] {
+                b'.' => true,
+                ESCAPE => match_class(c, self.pat[p + 1]),
+                b'[' => self.match_bracket_class(c, p, ep - 1),
+                pc => pc == c,
+            },
+        }
+    }
+
+    // Matches a set such as `[a-z%d]`, where `p` is the index of the '[' and `ec` is the index of
+    // the closing ']'
+    fn match_bracket_class(&self, c: u8, mut p: usize, ec: usize) -> bool {
+        let pat = self.pat;
+        let mut sig = true;
+        if pat[p + 1] == b'^' {
+            sig = false;
+            p += 1;
+        }
+        p += 1;
+        while p < ec {
+            if pat[p] == ESCAPE {
+                p += 1;
+                if match_class(c, pat[p]) {
+                    return sig;
+                }
+            } else if pat[p + 1] == b'-' && p + 2 < ec {
+                if pat[p] <= c && c <= pat[p + 2] {
+                    return sig;
+                }
+                p += 2;
+            } else if pat[p] == c {
+                return sig;
+            }
+            p += 1;
+        }
+        !sig
+    }
+
+    fn max_expand(&mut self, s: usize, p: usize, ep: usize) -> Result<Option<usize>, PatternError> {
+        let mut i = 0;
+        while self.single_match(s + i, p, ep) {
+            i += 1;
+        }
+        // Try with the maximum number of repetitions, then with one less each time
+        loop {
+            if let Some(e) = self.do_match(s + i, ep + 1)? {
+                return Ok(Some(e));
+            }
+            if i == 0 {
+                return Ok(None);
+            }
+            i -= 1;
+        }
+    }
+
+    fn min_expand(
+        &mut self,
+        mut s: usize,
+        p: usize,
+        ep: usize,
+    ) -> Result<Option<usize>, PatternError> {
+        loop {
+            if let Some(e) = self.do_match(s, ep + 1)? {
+                return Ok(Some(e));
+            } else if self.single_match(s, p, ep) {
+                s += 1;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn start_capture(
+        &mut self,
+        s: usize,
+        p: usize,
+        len: CaptureLen,
+    ) -> Result<Option<usize>, PatternError> {
+        if self.level >= MAX_CAPTURES {
+            return Err(PatternError::TooManyCaptures);
+        }
+        self.captures[self.level] = (s, len);
+        self.level += 1;
+        let res = self.do_match(s, p)?;
+        if res.is_none() {
+            self.level -= 1;
+        }
+        Ok(res)
+    }
+
+    fn end_capture(&mut self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        let l = self.capture_to_close()?;
+        self.captures[l].1 = CaptureLen::Len(s - self.captures[l].0);
+        let res = self.do_match(s, p)?;
+        if res.is_none() {
+            self.captures[l].1 = CaptureLen::Unclosed;
+        }
+        Ok(res)
+    }
+
+    fn capture_to_close(&self) -> Result<usize, PatternError> {
+        (0..self.level)
+            .rev()
+            .find(|&l| self.captures[l].1 == CaptureLen::Unclosed)
+            .ok_or(PatternError::InvalidPatternCapture)
+    }
+
+    fn match_balance(&self, s: usize, p: usize) -> Result<Option<usize>, PatternError> {
+        if p + 1 >= self.pat.len() {
+            return Err(PatternError::MissingBalanceArguments);
+        }
+        let (open, close) = (self.pat[p], self.pat[p + 1]);
+        if self.src.get(s) != Some(&open) {
+            return Ok(None);
+        }
+        let mut depth = 1;
+        for (i, &c) in self.src[s + 1..].iter().enumerate() {
+            if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(Some(s + 1 + i + 1));
+                }
+            } else if c == open {
+                depth += 1;
+            }
+        }
+        Ok(None)
+    }
+
+    // Matches a back reference such as `%1` against the subject at `s`
+    fn match_capture(&self, s: usize, l: u8) -> Result<Option<usize>, PatternError> {
+        let l = (l - b'1') as usize;
+        let len = match self.captures.get(l) {
+            Some(&(_, CaptureLen::Len(len))) if l < self.level => len,
+            Some(&(_, CaptureLen::Position)) if l < self.level => 0,
+            _ => return Err(PatternError::InvalidCaptureIndex(l + 1)),
+        };
+        let start = self.captures[l].0;
+        if self.src.len() - s >= len && self.src[start..start + len] == self.src[s..s + len] {
+            Ok(Some(s + len))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+// Matches a character class such as `%a`, where an upper case class is the complement of the lower
+// case one, and any other escaped character matches itself.
+fn match_class(c: u8, class: u8) -> bool {
+    let matches = match class.to_ascii_lowercase() {
+        b'a' => c.is_ascii_alphabetic(),
+        b'c' => c.is_ascii_control(),
+        b'd' => c.is_ascii_digit(),
+        b'g' => c.is_ascii_graphic(),
+        b'l' => c.is_ascii_lowercase(),
+        b'p' => c.is_ascii_punctuation(),
+        b's' => c == b' ' || (b'\t'..=b'\r').contains(&c),
+        b'u' => c.is_ascii_uppercase(),
+        b'w' => c.is_ascii_alphanumeric(),
+        b'x' => c.is_ascii_hexdigit(),
+        _ => return class == c,
+    };
+    if class.is_ascii_uppercase() {
+        !matches
+    } else {
+        matches
+    }
+}
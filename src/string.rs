@@ -48,7 +48,7 @@ impl<'gc> String<'gc> {
             b[..len].copy_from_slice(s);
             String::Short32(len as u8, Gc::allocate(mc, b))
         } else {
-            String::Long(Gc::allocate(mc, s.to_vec().into_boxed_slice()))
+            String::new_long(mc, s.to_vec().into_boxed_slice())
         }
     }
 
@@ -77,9 +77,21 @@ impl<'gc> String<'gc> {
                 Value::Thread(_) => {
                     return Err(StringError::Concat { bad_type: "thread" });
                 }
+                Value::UserData(_) => {
+                    return Err(StringError::Concat {
+                        bad_type: "userdata",
+                    });
+                }
             }
         }
-        Ok(String::Long(Gc::allocate(mc, bytes.into_boxed_slice())))
+        Ok(String::new_long(mc, bytes.into_boxed_slice()))
+    }
+
+    fn new_long(mc: MutationContext<'gc, '_>, bytes: Box<[u8]>) -> String<'gc> {
+        let len = bytes.len();
+        let gc = Gc::allocate(mc, bytes);
+        Gc::set_external_size(mc, gc, len);
+        String::Long(gc)
     }
 
     pub fn as_bytes(&self) -> &[u8] {
@@ -5,9 +5,9 @@ use std::{fmt, i64, mem};
 use num_traits::cast;
 use rustc_hash::FxHashMap;
 
-use gc_arena::{Collect, GcCell, MutationContext};
+use gc_arena::{Collect, CollectWeak, CollectionContext, Gc, GcCell, MutationContext};
 
-use crate::Value;
+use crate::{Function, MetaMethod, Value};
 
 #[derive(Debug, Copy, Clone, Collect)]
 #[collect(require_copy)]
@@ -60,19 +60,95 @@ impl<'gc> Table<'gc> {
         key: K,
         value: V,
     ) -> Result<Value<'gc>, InvalidTableKey> {
-        self.0.write(mc).set(key.into(), value.into())
+        let mut state = self.0.write(mc);
+        let res = state.set(key.into(), value.into());
+        self.0.set_external_size(mc, state.allocated_size());
+        res
     }
 
     pub fn length(&self) -> i64 {
         self.0.read().length()
     }
+
+    pub fn metatable(&self) -> Option<Table<'gc>> {
+        self.0.read().metatable
+    }
+
+    /// Sets the metatable for this table, returning the previous metatable.
+    pub fn set_metatable(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        metatable: Option<Table<'gc>>,
+    ) -> Option<Table<'gc>> {
+        mem::replace(&mut self.0.write(mc).metatable, metatable)
+    }
 }
 
-#[derive(Debug, Collect, Default)]
-#[collect(empty_drop)]
+#[derive(Debug, Default)]
 pub struct TableState<'gc> {
     array: Vec<Value<'gc>>,
     map: FxHashMap<TableKey<'gc>, Value<'gc>>,
+    metatable: Option<Table<'gc>>,
+    // Whether this table is currently registered with `Finalizers`
+    pub(crate) finalizer_registered: bool,
+}
+
+// Tables whose metatable has a `__mode` field containing 'k' and / or 'v' hold their keys and / or
+// values weakly.  The mode is checked every time the table is traced, so changing the `__mode` of a
+// metatable that is already in use may not take effect until the next collection.  A table with
+// only weak keys is an ephemeron table, and its values are only kept alive if their keys are.
+unsafe impl<'gc> Collect for TableState<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        let (weak_keys, weak_values) = self.weak_mode();
+        if weak_keys || weak_values {
+            unsafe {
+                cc.register_weak(self);
+            }
+        }
+
+        self.metatable.trace(cc);
+
+        for value in &self.array {
+            if !weak_values || !is_collectable(*value) {
+                value.trace(cc);
+            }
+        }
+
+        for (key, value) in &self.map {
+            let strong_key = !weak_keys || !is_collectable(key.0);
+            if strong_key {
+                key.0.trace(cc);
+            }
+            if (!weak_values || !is_collectable(*value)) && (strong_key || is_marked(key.0, cc)) {
+                value.trace(cc);
+            }
+        }
+    }
+}
+
+unsafe impl<'gc> CollectWeak for TableState<'gc> {
+    fn trace_weak(&self, cc: CollectionContext) {
+        let (weak_keys, weak_values) = self.weak_mode();
+        if weak_keys && !weak_values {
+            for (key, value) in &self.map {
+                if is_marked(key.0, cc) {
+                    value.trace(cc);
+                }
+            }
+        }
+    }
+
+    fn clear_dead(&mut self, cc: CollectionContext) {
+        // Entries are removed based on whether they are marked rather than on the current mode, so
+        // that anything left untraced by a since changed `__mode` cannot be left dangling.
+        for value in &mut self.array {
+            if !is_marked(*value, cc) {
+                *value = Value::Nil;
+            }
+        }
+        self.map
+            .retain(|key, value| is_marked(key.0, cc) && is_marked(*value, cc));
+    }
 }
 
 impl<'gc> TableState<'gc> {
@@ -196,6 +272,13 @@ impl<'gc> TableState<'gc> {
         }
     }
 
+    /// The number of bytes used by the array and map parts of this table, not counting the
+    /// `TableState` itself.
+    pub fn allocated_size(&self) -> usize {
+        self.array.capacity() * mem::size_of::<Value>()
+            + self.map.capacity() * mem::size_of::<(TableKey, Value)>()
+    }
+
     /// Returns a 'border' for this table.
     ///
     /// A 'border' for a table is any i >= 0 where:
@@ -254,6 +337,19 @@ impl<'gc> TableState<'gc> {
     }
 }
 
+impl<'gc> TableState<'gc> {
+    // Returns whether this table has weak keys and whether it has weak values.
+    fn weak_mode(&self) -> (bool, bool) {
+        if let Some(metatable) = self.metatable {
+            if let Value::String(mode) = metatable.get(MetaMethod::Mode) {
+                let mode = mode.as_bytes();
+                return (mode.contains(&b'k'), mode.contains(&b'v'));
+            }
+        }
+        (false, false)
+    }
+}
+
 // Value which implements Hash and Eq, and cannot contain Nil or NaN values.
 #[derive(Debug, Collect, PartialEq)]
 #[collect(empty_drop)]
@@ -293,6 +389,10 @@ impl<'gc> Hash for TableKey<'gc> {
                 Hash::hash(&7, state);
                 t.hash(state);
             }
+            Value::UserData(u) => {
+                Hash::hash(&8, state);
+                u.hash(state);
+            }
         }
     }
 }
@@ -317,6 +417,28 @@ impl<'gc> TableKey<'gc> {
     }
 }
 
+// Whether the given value may be removed from a weak table.  As in PUC-Rio Lua, strings are
+// considered values rather than objects, and are never removed.
+fn is_collectable<'gc>(value: Value<'gc>) -> bool {
+    matches!(
+        value,
+        Value::Table(_) | Value::Function(_) | Value::Thread(_) | Value::UserData(_)
+    )
+}
+
+// Whether the given value has been reached during the current collection, always true for values
+// that are not collectable.
+fn is_marked<'gc>(value: Value<'gc>, cc: CollectionContext) -> bool {
+    match value {
+        Value::Table(table) => table.0.is_marked(cc),
+        Value::Function(Function::Closure(closure)) => Gc::is_marked(closure.0, cc),
+        Value::Function(Function::Callback(callback)) => Gc::is_marked(callback.0, cc),
+        Value::Thread(thread) => thread.0.is_marked(cc),
+        Value::UserData(user_data) => user_data.0.is_marked(cc),
+        _ => true,
+    }
+}
+
 // Returns the closest i64 to a given f64 such that casting the i64 back to an f64 results in an
 // equal value, if such an integer exists.
 fn f64_to_i64(n: f64) -> Option<i64> {
@@ -0,0 +1,128 @@
+use std::cell::Cell;
+
+use gc_arena::MutationContext;
+
+use crate::{Error, RuntimeError, String, Value};
+
+// A sequence step always runs to completion on the OS thread that calls it, so the instruction
+// budget and memory limit of the sequence being stepped are kept per OS thread rather than being
+// passed through every `Sequence::step` call.
+thread_local! {
+    // The number of VM instructions that may still run before `Thread::step` stops running Lua
+    // code, or `None` if there is no limit.
+    static INSTRUCTION_LIMIT: Cell<Option<u64>> = const { Cell::new(None) };
+    // The total number of VM instructions run on this OS thread
+    static INSTRUCTIONS_RUN: Cell<u64> = const { Cell::new(0) };
+    // The number of bytes the arena may have allocated before `Thread::step` stops running Lua
+    // code
+    static MEMORY_LIMIT: Cell<usize> = const { Cell::new(usize::MAX) };
+    // Set once a garbage collection has failed to bring the arena back under the memory limit
+    static OUT_OF_MEMORY: Cell<bool> = const { Cell::new(false) };
+}
+
+// Runs the given function with every Lua thread stepped inside it limited to running at most `limit`
+// VM instructions in total.  Returns the result of the function along with the number of
+// instructions that were run.
+pub(crate) fn with_instruction_limit<R>(limit: Option<u64>, f: impl FnOnce() -> R) -> (R, u64) {
+    // Restores the limit of the enclosing sequence step, less the instructions run inside this one,
+    // even if `f` panics.
+    struct Restore {
+        outer_limit: Option<u64>,
+        start: u64,
+    }
+
+    impl Restore {
+        fn run(&self) -> u64 {
+            INSTRUCTIONS_RUN.with(|r| r.get()) - self.start
+        }
+    }
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let run = self.run();
+            INSTRUCTION_LIMIT.with(|l| {
+                l.set(self.outer_limit.map(|outer| outer.saturating_sub(run)));
+            });
+        }
+    }
+
+    let restore = Restore {
+        outer_limit: INSTRUCTION_LIMIT.with(|l| l.replace(limit)),
+        start: INSTRUCTIONS_RUN.with(|r| r.get()),
+    };
+    let res = f();
+    (res, restore.run())
+}
+
+// Returns how many of the given number of instructions may be run under the current limit.
+pub(crate) fn allowed_instructions(instructions: u32) -> u32 {
+    INSTRUCTION_LIMIT.with(|l| match l.get() {
+        Some(limit) if limit < instructions as u64 => limit as u32,
+        _ => instructions,
+    })
+}
+
+// Records that the given number of instructions have been run, counting them against the current
+// limit.
+pub(crate) fn consume_instructions(instructions: u32) {
+    INSTRUCTIONS_RUN.with(|r| r.set(r.get() + instructions as u64));
+    INSTRUCTION_LIMIT.with(|l| {
+        if let Some(limit) = l.get() {
+            l.set(Some(limit.saturating_sub(instructions as u64)));
+        }
+    });
+}
+
+// Runs the given function with every Lua thread stepped inside it stopping once the arena has
+// allocated more than `limit` bytes.  If `out_of_memory` is set, the arena is already over the limit
+// even after a full collection, and the next Lua thread to be stepped raises a "not enough memory"
+// error.
+pub(crate) fn with_memory_limit<R>(
+    limit: Option<usize>,
+    out_of_memory: bool,
+    f: impl FnOnce() -> R,
+) -> R {
+    // Restores the limit of the enclosing sequence step, even if `f` panics.
+    struct Restore {
+        outer_limit: usize,
+        outer_out_of_memory: bool,
+    }
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            MEMORY_LIMIT.with(|l| l.set(self.outer_limit));
+            OUT_OF_MEMORY.with(|o| o.set(self.outer_out_of_memory));
+        }
+    }
+
+    let _restore = Restore {
+        outer_limit: MEMORY_LIMIT.with(|l| l.replace(limit.unwrap_or(usize::MAX))),
+        outer_out_of_memory: OUT_OF_MEMORY.with(|o| o.replace(out_of_memory)),
+    };
+    f()
+}
+
+pub(crate) fn memory_limit() -> usize {
+    MEMORY_LIMIT.with(|l| l.get())
+}
+
+// Checks whether `len` more bytes may be allocated without going over the current memory limit.
+// Callbacks that build arbitrarily large buffers must call this before allocating them, because the
+// limit is otherwise only checked between VM instructions.
+pub(crate) fn check_alloc<'gc>(mc: MutationContext<'gc, '_>, len: usize) -> Result<(), Error<'gc>> {
+    if mc.total_allocated().saturating_add(len) > memory_limit() {
+        Err(not_enough_memory())
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn not_enough_memory<'gc>() -> Error<'gc> {
+    RuntimeError(Value::String(String::new_static(b"not enough memory"))).into()
+}
+
+// Returns whether the current Lua thread should raise a "not enough memory" error, clearing the flag
+// so that only one error is raised per failed collection.
+pub(crate) fn take_out_of_memory() -> bool {
+    OUT_OF_MEMORY.with(|o| o.replace(false))
+}
@@ -0,0 +1,217 @@
+use std::fmt::{self, Debug};
+
+use gc_arena::{Collect, Gc, MutationContext, StaticCollect};
+
+use crate::{Closure, Error, LineNumber};
+
+/// An event that a `Hook` is called for, always just before a Lua frame runs its next instruction.
+///
+/// Only Lua functions fire events, calling a callback fires neither a `Call` nor a `Return` event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub enum HookEvent {
+    /// A Lua function has been called and is about to run its first instruction.  This includes
+    /// Lua functions that are tail called.
+    Call,
+    /// A Lua function is about to return.  A function that ends in a tail call fires no `Return`
+    /// event, since its frame is replaced by the frame of the called function, only the called
+    /// function fires one once it returns.
+    Return,
+    /// A Lua function is about to run the first instruction of a new line, or has jumped backwards
+    /// to an earlier instruction.
+    Line(LineNumber),
+    /// The number of instructions given in the hook's mask have run since the last count event.
+    Count,
+}
+
+/// Selects the events that a `Hook` is called for.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub struct HookMask {
+    pub call: bool,
+    pub ret: bool,
+    pub line: bool,
+    /// Fire a count event after every `count` instructions, or never if this is 0.
+    pub count: u32,
+}
+
+/// The Lua frame that a hook event occurred in.
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_copy)]
+pub struct HookFrame<'gc> {
+    /// The function running in this frame
+    pub closure: Closure<'gc>,
+    /// The index of the instruction that is about to run
+    pub pc: usize,
+    /// The line of the instruction that is about to run, if known
+    pub line: Option<LineNumber>,
+}
+
+/// What a thread should do once a hook returns.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HookAction {
+    /// Keep running the Lua frame.
+    Continue,
+    /// Suspend the thread as though it had yielded with no values.  Once the thread is resumed, any
+    /// values it is resumed with are ignored and the Lua frame continues with its next instruction.
+    Yield,
+}
+
+pub trait HookFn<'gc>: Collect {
+    fn call(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        event: HookEvent,
+        frame: HookFrame<'gc>,
+    ) -> Result<HookAction, Error<'gc>>;
+}
+
+/// A Rust function that a `Thread` calls on the events selected by the hook's `HookMask`.
+///
+/// The hook is called while the thread is running, so it must not access the thread it is set on.
+/// If the hook returns an error, the thread stops immediately with that error.  The error cannot be
+/// caught by `pcall` or by any other callback continuation, and no `__close` metamethods are run,
+/// so that a hook can reliably interrupt a script.
+///
+/// Coroutines created with `coroutine.create` inherit the hook of the thread that created them,
+/// with a fresh instruction count.  When the hook stops a coroutine, `coroutine.resume` returns the
+/// error like any other error raised by the coroutine, and the creating thread keeps running under
+/// its own hook.
+#[derive(Clone, Copy, Collect)]
+#[collect(require_copy)]
+pub struct Hook<'gc> {
+    function: Gc<'gc, Box<dyn HookFn<'gc> + 'gc>>,
+    mask: HookMask,
+}
+
+impl<'gc> Hook<'gc> {
+    pub fn new<F>(mc: MutationContext<'gc, '_>, mask: HookMask, f: F) -> Hook<'gc>
+    where
+        F: 'static
+            + Fn(
+                MutationContext<'gc, '_>,
+                HookEvent,
+                HookFrame<'gc>,
+            ) -> Result<HookAction, Error<'gc>>,
+    {
+        #[derive(Collect)]
+        #[collect(require_static)]
+        struct StaticHookFn<F>(F);
+
+        impl<'gc, F> HookFn<'gc> for StaticHookFn<F>
+        where
+            F: 'static
+                + Fn(
+                    MutationContext<'gc, '_>,
+                    HookEvent,
+                    HookFrame<'gc>,
+                ) -> Result<HookAction, Error<'gc>>,
+        {
+            fn call(
+                &self,
+                mc: MutationContext<'gc, '_>,
+                event: HookEvent,
+                frame: HookFrame<'gc>,
+            ) -> Result<HookAction, Error<'gc>> {
+                self.0(mc, event, frame)
+            }
+        }
+
+        Hook {
+            function: Gc::allocate(mc, Box::new(StaticHookFn(f))),
+            mask,
+        }
+    }
+
+    pub fn new_with<C, F>(mc: MutationContext<'gc, '_>, mask: HookMask, c: C, f: F) -> Hook<'gc>
+    where
+        C: 'gc + Collect,
+        F: 'static
+            + Fn(
+                &C,
+                MutationContext<'gc, '_>,
+                HookEvent,
+                HookFrame<'gc>,
+            ) -> Result<HookAction, Error<'gc>>,
+    {
+        #[derive(Collect)]
+        #[collect(empty_drop)]
+        struct ContextHookFn<C, F>(C, StaticCollect<F>);
+
+        impl<'gc, C, F> HookFn<'gc> for ContextHookFn<C, F>
+        where
+            C: 'gc + Collect,
+            F: 'static
+                + Fn(
+                    &C,
+                    MutationContext<'gc, '_>,
+                    HookEvent,
+                    HookFrame<'gc>,
+                ) -> Result<HookAction, Error<'gc>>,
+        {
+            fn call(
+                &self,
+                mc: MutationContext<'gc, '_>,
+                event: HookEvent,
+                frame: HookFrame<'gc>,
+            ) -> Result<HookAction, Error<'gc>> {
+                (self.1).0(&self.0, mc, event, frame)
+            }
+        }
+
+        Hook {
+            function: Gc::allocate(mc, Box::new(ContextHookFn(c, StaticCollect(f)))),
+            mask,
+        }
+    }
+
+    pub fn mask(&self) -> HookMask {
+        self.mask
+    }
+
+    pub fn call(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        event: HookEvent,
+        frame: HookFrame<'gc>,
+    ) -> Result<HookAction, Error<'gc>> {
+        self.function.call(mc, event, frame)
+    }
+}
+
+impl<'gc> Debug for Hook<'gc> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Hook")
+            .field("function", &Gc::as_ptr(self.function))
+            .field("mask", &self.mask)
+            .finish()
+    }
+}
+
+// The hook of a thread along with the bookkeeping needed to decide when its events fire
+#[derive(Collect)]
+#[collect(empty_drop)]
+pub(crate) struct HookState<'gc> {
+    pub hook: Option<Hook<'gc>>,
+    // The number of instructions left to run before the next count event
+    pub count: u32,
+    // Set when a Lua function has been called which has not yet run any instructions
+    pub call_pending: bool,
+    // The frame depth and pc of the last instruction that was run while a line hook was set
+    pub last_position: Option<(usize, usize)>,
+    // Set when the hook has yielded, so that the events for the next instruction are not fired a
+    // second time once the thread is resumed
+    pub yielded: bool,
+}
+
+impl<'gc> HookState<'gc> {
+    pub fn new(hook: Option<Hook<'gc>>) -> HookState<'gc> {
+        HookState {
+            hook,
+            count: hook.map(|h| h.mask.count).unwrap_or(0),
+            call_pending: false,
+            last_position: None,
+            yielded: false,
+        }
+    }
+}
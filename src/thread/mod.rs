@@ -1,9 +1,14 @@
+mod budget;
 mod error;
+mod hook;
+mod names;
 mod thread;
 mod vm;
 
 pub use error::{BadThreadMode, BinaryOperatorError, ThreadError};
+pub use hook::{Hook, HookAction, HookEvent, HookFn, HookFrame, HookMask};
 pub use thread::{Thread, ThreadMode, ThreadSequence};
 
-pub(crate) use thread::LuaFrame;
+pub(crate) use budget::{check_alloc, with_instruction_limit, with_memory_limit};
+pub(crate) use thread::{LuaFrame, MetaReturn};
 pub(crate) use vm::run_vm;
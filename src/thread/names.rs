@@ -0,0 +1,528 @@
+use std::string::String as StdString;
+
+use crate::{
+    BinaryOperatorError, Constant, Error, FunctionName, FunctionProto, MetaMethod, OpCode,
+    RegisterIndex, StringError, ThreadError, TypeError, UpValueIndex, Value, VariableName,
+};
+
+/// Where an instruction reads one of its operands from.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Operand {
+    Register(RegisterIndex),
+    Constant(usize),
+    UpValue(UpValueIndex),
+}
+
+// Returns the name of the function called by the instruction at `pc`.  Functions called by `Call`
+// are named after wherever the called value was loaded from, functions called by any other
+// instruction are the metamethod of that instruction.
+pub(crate) fn function_name(proto: &FunctionProto, pc: usize) -> Option<FunctionName> {
+    let meta_method = match *proto.opcodes.get(pc)? {
+        OpCode::Call { func, .. } | OpCode::TailCall { func, .. } => {
+            return match register_name(proto, pc, func)? {
+                VariableName::Method(name) => Some(FunctionName::Method(name)),
+                VariableName::Global(name)
+                | VariableName::Local(name)
+                | VariableName::UpValue(name)
+                | VariableName::Field(name) => Some(FunctionName::Function(name)),
+                VariableName::Constant(_) => None,
+            };
+        }
+        OpCode::GetTableR { .. }
+        | OpCode::GetTableC { .. }
+        | OpCode::GetUpTableR { .. }
+        | OpCode::GetUpTableC { .. }
+        | OpCode::SelfR { .. }
+        | OpCode::SelfC { .. } => MetaMethod::Index,
+        OpCode::SetTableRR { .. }
+        | OpCode::SetTableRC { .. }
+        | OpCode::SetTableCR { .. }
+        | OpCode::SetTableCC { .. }
+        | OpCode::SetUpTableRR { .. }
+        | OpCode::SetUpTableRC { .. }
+        | OpCode::SetUpTableCR { .. }
+        | OpCode::SetUpTableCC { .. } => MetaMethod::NewIndex,
+        OpCode::AddRR { .. }
+        | OpCode::AddRC { .. }
+        | OpCode::AddCR { .. }
+        | OpCode::AddCC { .. } => MetaMethod::Add,
+        OpCode::SubRR { .. }
+        | OpCode::SubRC { .. }
+        | OpCode::SubCR { .. }
+        | OpCode::SubCC { .. } => MetaMethod::Sub,
+        OpCode::MulRR { .. }
+        | OpCode::MulRC { .. }
+        | OpCode::MulCR { .. }
+        | OpCode::MulCC { .. } => MetaMethod::Mul,
+        OpCode::DivRR { .. }
+        | OpCode::DivRC { .. }
+        | OpCode::DivCR { .. }
+        | OpCode::DivCC { .. } => MetaMethod::Div,
+        OpCode::IDivRR { .. }
+        | OpCode::IDivRC { .. }
+        | OpCode::IDivCR { .. }
+        | OpCode::IDivCC { .. } => MetaMethod::IDiv,
+        OpCode::ModRR { .. }
+        | OpCode::ModRC { .. }
+        | OpCode::ModCR { .. }
+        | OpCode::ModCC { .. } => MetaMethod::Mod,
+        OpCode::PowRR { .. }
+        | OpCode::PowRC { .. }
+        | OpCode::PowCR { .. }
+        | OpCode::PowCC { .. } => MetaMethod::Pow,
+        OpCode::BitAndRR { .. }
+        | OpCode::BitAndRC { .. }
+        | OpCode::BitAndCR { .. }
+        | OpCode::BitAndCC { .. } => MetaMethod::BAnd,
+        OpCode::BitOrRR { .. }
+        | OpCode::BitOrRC { .. }
+        | OpCode::BitOrCR { .. }
+        | OpCode::BitOrCC { .. } => MetaMethod::BOr,
+        OpCode::BitXorRR { .. }
+        | OpCode::BitXorRC { .. }
+        | OpCode::BitXorCR { .. }
+        | OpCode::BitXorCC { .. } => MetaMethod::BXor,
+        OpCode::ShiftLeftRR { .. }
+        | OpCode::ShiftLeftRC { .. }
+        | OpCode::ShiftLeftCR { .. }
+        | OpCode::ShiftLeftCC { .. } => MetaMethod::Shl,
+        OpCode::ShiftRightRR { .. }
+        | OpCode::ShiftRightRC { .. }
+        | OpCode::ShiftRightCR { .. }
+        | OpCode::ShiftRightCC { .. } => MetaMethod::Shr,
+        OpCode::EqRR { .. } | OpCode::EqRC { .. } | OpCode::EqCR { .. } | OpCode::EqCC { .. } => {
+            MetaMethod::Eq
+        }
+        OpCode::LessRR { .. }
+        | OpCode::LessRC { .. }
+        | OpCode::LessCR { .. }
+        | OpCode::LessCC { .. } => MetaMethod::Lt,
+        OpCode::LessEqRR { .. }
+        | OpCode::LessEqRC { .. }
+        | OpCode::LessEqCR { .. }
+        | OpCode::LessEqCC { .. } => MetaMethod::Le,
+        OpCode::Minus { .. } => MetaMethod::Unm,
+        OpCode::BitNot { .. } => MetaMethod::BNot,
+        OpCode::Length { .. } => MetaMethod::Len,
+        OpCode::Concat { .. } => MetaMethod::Concat,
+        OpCode::Jump { .. } => MetaMethod::Close,
+        _ => return None,
+    };
+    Some(FunctionName::MetaMethod(meta_method))
+}
+
+// Returns a message describing an error raised by the instruction at `pc`, in the style of "attempt
+// to index a nil value (local 't')", if the error was caused by the values of the instruction's
+// operands.
+pub(crate) fn error_message<'gc>(
+    proto: &FunctionProto<'gc>,
+    pc: usize,
+    operand_value: impl Fn(Operand) -> Value<'gc>,
+    error: &Error<'gc>,
+) -> Option<StdString> {
+    let op = *proto.opcodes.get(pc)?;
+    let describe = |action: &str, operand: Operand| {
+        let mut message = format!(
+            "attempt to {} a {} value",
+            action,
+            operand_value(operand).type_name()
+        );
+        if let Some(name) = operand_name(proto, pc, operand) {
+            message.push_str(&format!(" ({})", name));
+        }
+        message
+    };
+
+    match *error {
+        Error::TypeError(TypeError {
+            expected: "table",
+            found,
+        }) => {
+            // Errors from following an `__index` or `__newindex` chain are not caused by the
+            // operand itself.
+            let table = indexed_operand(op)?;
+            match operand_value(table) {
+                Value::Table(_) => None,
+                value if value.type_name() == found => Some(describe("index", table)),
+                _ => None,
+            }
+        }
+        Error::TypeError(TypeError {
+            expected: "table or string",
+            ..
+        }) => match op {
+            OpCode::Length { source, .. } => {
+                Some(describe("get length of", Operand::Register(source)))
+            }
+            _ => None,
+        },
+        Error::TypeError(TypeError {
+            expected: "closable value",
+            ..
+        }) => match op {
+            OpCode::ToBeClosed { value } => match register_name(proto, pc, value)? {
+                VariableName::Local(name) => {
+                    Some(format!("variable '{}' got a non-closable value", name))
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        Error::ThreadError(ThreadError::BadCall(TypeError { found, .. })) => {
+            let (func, named) = match op {
+                OpCode::Call { func, .. } | OpCode::TailCall { func, .. } => (func, true),
+                OpCode::GenericForCall { base, .. } => (base, false),
+                _ => return None,
+            };
+            let func = Operand::Register(func);
+            if operand_value(func).type_name() != found {
+                None
+            } else if named {
+                Some(describe("call", func))
+            } else {
+                Some(format!("attempt to call a {} value", found))
+            }
+        }
+        Error::BinaryOperatorError(err) => {
+            let (left, right) = match op {
+                OpCode::Minus { source, .. } | OpCode::BitNot { source, .. } => {
+                    (Operand::Register(source), Operand::Register(source))
+                }
+                op => binary_operands(op)?,
+            };
+            let (left_value, right_value) = (operand_value(left), operand_value(right));
+            match err {
+                BinaryOperatorError::LessThan | BinaryOperatorError::LessEqual => {
+                    let (left_type, right_type) = (left_value.type_name(), right_value.type_name());
+                    if left_type == right_type {
+                        Some(format!("attempt to compare two {} values", left_type))
+                    } else {
+                        Some(format!(
+                            "attempt to compare {} with {}",
+                            left_type, right_type
+                        ))
+                    }
+                }
+                BinaryOperatorError::BitAnd
+                | BinaryOperatorError::BitOr
+                | BinaryOperatorError::BitXor
+                | BinaryOperatorError::BitNot
+                | BinaryOperatorError::ShiftLeft
+                | BinaryOperatorError::ShiftRight => {
+                    if left_value.to_number().is_some() && right_value.to_number().is_some() {
+                        Some("number has no integer representation".to_owned())
+                    } else if left_value.to_number().is_none() {
+                        Some(describe("perform bitwise operation on", left))
+                    } else {
+                        Some(describe("perform bitwise operation on", right))
+                    }
+                }
+                _ => {
+                    if left_value.to_number().is_none() {
+                        Some(describe("perform arithmetic on", left))
+                    } else {
+                        Some(describe("perform arithmetic on", right))
+                    }
+                }
+            }
+        }
+        Error::StringError(StringError::Concat { bad_type }) => match op {
+            OpCode::Concat { source, count, .. } => {
+                let culprit = (source.0..source.0 + count)
+                    .rev()
+                    .map(|reg| Operand::Register(RegisterIndex(reg)))
+                    .find(|&operand| operand_value(operand).type_name() == bad_type)?;
+                Some(describe("concatenate", culprit))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Returns the table operand of an instruction that indexes a table.
+fn indexed_operand(op: OpCode) -> Option<Operand> {
+    match op {
+        OpCode::GetTableR { table, .. }
+        | OpCode::GetTableC { table, .. }
+        | OpCode::SetTableRR { table, .. }
+        | OpCode::SetTableRC { table, .. }
+        | OpCode::SetTableCR { table, .. }
+        | OpCode::SetTableCC { table, .. }
+        | OpCode::SelfR { table, .. }
+        | OpCode::SelfC { table, .. } => Some(Operand::Register(table)),
+        OpCode::GetUpTableR { table, .. }
+        | OpCode::GetUpTableC { table, .. }
+        | OpCode::SetUpTableRR { table, .. }
+        | OpCode::SetUpTableRC { table, .. }
+        | OpCode::SetUpTableCR { table, .. }
+        | OpCode::SetUpTableCC { table, .. } => Some(Operand::UpValue(table)),
+        _ => None,
+    }
+}
+
+// Returns the left and right operands of a binary arithmetic, bitwise or comparison instruction.
+fn binary_operands(op: OpCode) -> Option<(Operand, Operand)> {
+    Some(match op {
+        OpCode::AddRR { left, right, .. }
+        | OpCode::SubRR { left, right, .. }
+        | OpCode::MulRR { left, right, .. }
+        | OpCode::DivRR { left, right, .. }
+        | OpCode::IDivRR { left, right, .. }
+        | OpCode::ModRR { left, right, .. }
+        | OpCode::PowRR { left, right, .. }
+        | OpCode::BitAndRR { left, right, .. }
+        | OpCode::BitOrRR { left, right, .. }
+        | OpCode::BitXorRR { left, right, .. }
+        | OpCode::ShiftLeftRR { left, right, .. }
+        | OpCode::ShiftRightRR { left, right, .. }
+        | OpCode::LessRR { left, right, .. }
+        | OpCode::LessEqRR { left, right, .. } => {
+            (Operand::Register(left), Operand::Register(right))
+        }
+        OpCode::AddRC { left, right, .. }
+        | OpCode::SubRC { left, right, .. }
+        | OpCode::MulRC { left, right, .. }
+        | OpCode::DivRC { left, right, .. }
+        | OpCode::IDivRC { left, right, .. }
+        | OpCode::ModRC { left, right, .. }
+        | OpCode::PowRC { left, right, .. }
+        | OpCode::BitAndRC { left, right, .. }
+        | OpCode::BitOrRC { left, right, .. }
+        | OpCode::BitXorRC { left, right, .. }
+        | OpCode::ShiftLeftRC { left, right, .. }
+        | OpCode::ShiftRightRC { left, right, .. }
+        | OpCode::LessRC { left, right, .. }
+        | OpCode::LessEqRC { left, right, .. } => {
+            (Operand::Register(left), Operand::Constant(right.0 as usize))
+        }
+        OpCode::AddCR { left, right, .. }
+        | OpCode::SubCR { left, right, .. }
+        | OpCode::MulCR { left, right, .. }
+        | OpCode::DivCR { left, right, .. }
+        | OpCode::IDivCR { left, right, .. }
+        | OpCode::ModCR { left, right, .. }
+        | OpCode::PowCR { left, right, .. }
+        | OpCode::BitAndCR { left, right, .. }
+        | OpCode::BitOrCR { left, right, .. }
+        | OpCode::BitXorCR { left, right, .. }
+        | OpCode::ShiftLeftCR { left, right, .. }
+        | OpCode::ShiftRightCR { left, right, .. }
+        | OpCode::LessCR { left, right, .. }
+        | OpCode::LessEqCR { left, right, .. } => {
+            (Operand::Constant(left.0 as usize), Operand::Register(right))
+        }
+        OpCode::AddCC { left, right, .. }
+        | OpCode::SubCC { left, right, .. }
+        | OpCode::MulCC { left, right, .. }
+        | OpCode::DivCC { left, right, .. }
+        | OpCode::IDivCC { left, right, .. }
+        | OpCode::ModCC { left, right, .. }
+        | OpCode::PowCC { left, right, .. }
+        | OpCode::BitAndCC { left, right, .. }
+        | OpCode::BitOrCC { left, right, .. }
+        | OpCode::BitXorCC { left, right, .. }
+        | OpCode::ShiftLeftCC { left, right, .. }
+        | OpCode::ShiftRightCC { left, right, .. }
+        | OpCode::LessCC { left, right, .. }
+        | OpCode::LessEqCC { left, right, .. } => (
+            Operand::Constant(left.0 as usize),
+            Operand::Constant(right.0 as usize),
+        ),
+        _ => return None,
+    })
+}
+
+// Returns a name for an instruction operand at the instruction `pc`.
+fn operand_name(proto: &FunctionProto, pc: usize, operand: Operand) -> Option<VariableName> {
+    match operand {
+        Operand::Register(reg) => register_name(proto, pc, reg),
+        Operand::Constant(constant) => constant_name(proto, constant).map(VariableName::Constant),
+        Operand::UpValue(upvalue) => upvalue_name(proto, upvalue),
+    }
+}
+
+// Returns a name for the value in the given register at the instruction `pc`.  Registers holding a
+// local variable are named after it, otherwise the name is based on the last instruction that set
+// the register.
+fn register_name(proto: &FunctionProto, pc: usize, reg: RegisterIndex) -> Option<VariableName> {
+    if let Some(name) = proto.local_name(reg, pc) {
+        return Some(VariableName::Local(to_std_string(name.as_bytes())));
+    }
+
+    let set_pc = find_set_register(proto, pc, reg)?;
+    match proto.opcodes[set_pc] {
+        OpCode::Move { dest, source } if source.0 < dest.0 => register_name(proto, set_pc, source),
+        OpCode::GetTableR { table, key, .. } => {
+            let is_env = is_environment(register_name(proto, set_pc, table));
+            Some(field_name(is_env, register_constant(proto, set_pc, key)?))
+        }
+        OpCode::GetTableC { table, key, .. } => {
+            let is_env = is_environment(register_name(proto, set_pc, table));
+            Some(field_name(is_env, constant_name(proto, key.0 as usize)?))
+        }
+        OpCode::GetUpTableR { table, key, .. } => {
+            let is_env = is_environment(upvalue_name(proto, table));
+            Some(field_name(is_env, register_constant(proto, set_pc, key)?))
+        }
+        OpCode::GetUpTableC { table, key, .. } => {
+            let is_env = is_environment(upvalue_name(proto, table));
+            Some(field_name(is_env, constant_name(proto, key.0 as usize)?))
+        }
+        OpCode::GetUpValue { source, .. } => upvalue_name(proto, source),
+        OpCode::LoadConstant { constant, .. } => {
+            constant_name(proto, constant.0 as usize).map(VariableName::Constant)
+        }
+        OpCode::SelfR { key, .. } => {
+            register_constant(proto, set_pc, key).map(VariableName::Method)
+        }
+        OpCode::SelfC { key, .. } => constant_name(proto, key.0 as usize).map(VariableName::Method),
+        _ => None,
+    }
+}
+
+fn upvalue_name(proto: &FunctionProto, upvalue: UpValueIndex) -> Option<VariableName> {
+    let name = proto.upvalue_names.get(upvalue.0 as usize)?;
+    Some(VariableName::UpValue(to_std_string(name.as_bytes())))
+}
+
+// Returns the string constant at the given index.
+fn constant_name(proto: &FunctionProto, constant: usize) -> Option<StdString> {
+    match proto.constants.get(constant)? {
+        Constant::String(name) => Some(to_std_string(name.as_bytes())),
+        _ => None,
+    }
+}
+
+// Returns the string constant held in the given register at the instruction `pc`.
+fn register_constant(proto: &FunctionProto, pc: usize, reg: RegisterIndex) -> Option<StdString> {
+    if proto.local_name(reg, pc).is_some() {
+        return None;
+    }
+    match proto.opcodes[find_set_register(proto, pc, reg)?] {
+        OpCode::LoadConstant { constant, .. } => constant_name(proto, constant.0 as usize),
+        _ => None,
+    }
+}
+
+// Fields of the `_ENV` table are globals.
+fn field_name(is_env: bool, name: StdString) -> VariableName {
+    if is_env {
+        VariableName::Global(name)
+    } else {
+        VariableName::Field(name)
+    }
+}
+
+fn is_environment(table: Option<VariableName>) -> bool {
+    match table {
+        Some(VariableName::Local(name)) | Some(VariableName::UpValue(name)) => name == "_ENV",
+        _ => false,
+    }
+}
+
+fn to_std_string(bytes: &[u8]) -> StdString {
+    StdString::from_utf8_lossy(bytes).into_owned()
+}
+
+// Finds the last instruction before `pc` that sets the given register.  If the only setting
+// instruction found may be jumped over on the way to `pc`, then which instruction set the register
+// is not known and this returns None.
+fn find_set_register(proto: &FunctionProto, pc: usize, reg: RegisterIndex) -> Option<usize> {
+    let mut set_register = None;
+    let mut jump_target = 0;
+    for (i, &op) in proto.opcodes[..pc].iter().enumerate() {
+        if let OpCode::Jump { offset, .. } = op {
+            let target = (i as isize + 1 + offset as isize) as usize;
+            if target <= pc && target > jump_target {
+                jump_target = target;
+            }
+        } else if sets_register(op, reg.0) {
+            set_register = if i < jump_target { None } else { Some(i) };
+        }
+    }
+    set_register
+}
+
+// Returns true if the given instruction may change the value of the given register.
+fn sets_register(op: OpCode, reg: u8) -> bool {
+    match op {
+        OpCode::Move { dest, .. }
+        | OpCode::LoadConstant { dest, .. }
+        | OpCode::LoadBool { dest, .. }
+        | OpCode::NewTable { dest }
+        | OpCode::GetTableR { dest, .. }
+        | OpCode::GetTableC { dest, .. }
+        | OpCode::GetUpTableR { dest, .. }
+        | OpCode::GetUpTableC { dest, .. }
+        | OpCode::TestSet { dest, .. }
+        | OpCode::Closure { dest, .. }
+        | OpCode::Concat { dest, .. }
+        | OpCode::GetUpValue { dest, .. }
+        | OpCode::Length { dest, .. }
+        | OpCode::Not { dest, .. }
+        | OpCode::Minus { dest, .. }
+        | OpCode::BitNot { dest, .. }
+        | OpCode::AddRR { dest, .. }
+        | OpCode::AddRC { dest, .. }
+        | OpCode::AddCR { dest, .. }
+        | OpCode::AddCC { dest, .. }
+        | OpCode::SubRR { dest, .. }
+        | OpCode::SubRC { dest, .. }
+        | OpCode::SubCR { dest, .. }
+        | OpCode::SubCC { dest, .. }
+        | OpCode::MulRR { dest, .. }
+        | OpCode::MulRC { dest, .. }
+        | OpCode::MulCR { dest, .. }
+        | OpCode::MulCC { dest, .. }
+        | OpCode::DivRR { dest, .. }
+        | OpCode::DivRC { dest, .. }
+        | OpCode::DivCR { dest, .. }
+        | OpCode::DivCC { dest, .. }
+        | OpCode::IDivRR { dest, .. }
+        | OpCode::IDivRC { dest, .. }
+        | OpCode::IDivCR { dest, .. }
+        | OpCode::IDivCC { dest, .. }
+        | OpCode::ModRR { dest, .. }
+        | OpCode::ModRC { dest, .. }
+        | OpCode::ModCR { dest, .. }
+        | OpCode::ModCC { dest, .. }
+        | OpCode::PowRR { dest, .. }
+        | OpCode::PowRC { dest, .. }
+        | OpCode::PowCR { dest, .. }
+        | OpCode::PowCC { dest, .. }
+        | OpCode::BitAndRR { dest, .. }
+        | OpCode::BitAndRC { dest, .. }
+        | OpCode::BitAndCR { dest, .. }
+        | OpCode::BitAndCC { dest, .. }
+        | OpCode::BitOrRR { dest, .. }
+        | OpCode::BitOrRC { dest, .. }
+        | OpCode::BitOrCR { dest, .. }
+        | OpCode::BitOrCC { dest, .. }
+        | OpCode::BitXorRR { dest, .. }
+        | OpCode::BitXorRC { dest, .. }
+        | OpCode::BitXorCR { dest, .. }
+        | OpCode::BitXorCC { dest, .. }
+        | OpCode::ShiftLeftRR { dest, .. }
+        | OpCode::ShiftLeftRC { dest, .. }
+        | OpCode::ShiftLeftCR { dest, .. }
+        | OpCode::ShiftLeftCC { dest, .. }
+        | OpCode::ShiftRightRR { dest, .. }
+        | OpCode::ShiftRightRC { dest, .. }
+        | OpCode::ShiftRightCR { dest, .. }
+        | OpCode::ShiftRightCC { dest, .. } => dest.0 == reg,
+        OpCode::LoadNil { dest, count } => reg >= dest.0 && (reg - dest.0) < count,
+        OpCode::Call { func: base, .. }
+        | OpCode::TailCall { func: base, .. }
+        | OpCode::VarArgs { dest: base, .. }
+        | OpCode::GenericForCall { base, .. } => reg >= base.0,
+        OpCode::NumericForPrep { base, .. } | OpCode::NumericForLoop { base, .. } => {
+            reg >= base.0 && reg - base.0 <= 3
+        }
+        OpCode::GenericForLoop { base, .. } => reg == base.0,
+        OpCode::SelfR { base, .. } | OpCode::SelfC { base, .. } => {
+            reg == base.0 || reg as u16 == base.0 as u16 + 1
+        }
+        _ => false,
+    }
+}
@@ -1,16 +1,27 @@
 use std::collections::btree_map::Entry as BTreeEntry;
 use std::collections::BTreeMap;
+use std::convert::TryInto;
 use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
+use std::mem;
+use std::ops::Range;
 
 use gc_arena::{Collect, GcCell, MutationContext};
-use gc_sequence::Sequence;
+use gc_sequence::{self as sequence, Sequence};
 
 use crate::{
-    thread::run_vm, BadThreadMode, CallbackResult, CallbackReturn, Closure, Continuation, Error,
-    Function, RegisterIndex, ThreadError, TypeError, UpValue, UpValueState, Value, VarCount,
+    meta_ops, thread::run_vm, BadThreadMode, CallbackResult, CallbackReturn, Closure, Continuation,
+    Error, FrameKind, Function, FunctionName, Hook, HookAction, HookEvent, HookFrame, Metatables,
+    OpCode, RegisterIndex, RuntimeError, String, ThreadError, Traceback, TracebackFrame, TypeError,
+    UpValue, UpValueState, Value, ValueBuffer, VarCount,
 };
 
+use super::budget::{
+    allowed_instructions, consume_instructions, memory_limit, not_enough_memory, take_out_of_memory,
+};
+use super::hook::HookState;
+use super::names::{error_message, function_name, Operand};
+
 #[derive(Clone, Copy, Collect)]
 #[collect(require_copy)]
 pub struct Thread<'gc>(pub(crate) GcCell<'gc, ThreadState<'gc>>);
@@ -51,14 +62,34 @@ pub enum ThreadMode {
 #[collect(empty_drop)]
 pub struct ThreadSequence<'gc>(pub Thread<'gc>);
 
+// Every Lua frame owns exactly this many registers.  Since a `RegisterIndex` is a `u8`, every
+// register index is always in bounds of a frame's registers.
+const LUA_REGISTERS: usize = 256;
+
+// The maximum number of unused value buffers that a thread keeps around for reuse
+const MAX_POOLED_BUFFERS: usize = 16;
+
 #[derive(Collect)]
 #[collect(empty_drop)]
 pub(crate) struct ThreadState<'gc> {
-    values: Vec<Value<'gc>>,
+    // The registers of every Lua frame, `LUA_REGISTERS` for each.  Registers above
+    // `registers_top` are kept allocated and are always `Nil`, so that pushing a new frame does not
+    // need to clear them.
+    registers: Vec<Value<'gc>>,
+    registers_top: usize,
+    // The variable arguments of every Lua frame, followed by any pending variable values (such as
+    // the results of a call with a variable number of returns) of the top Lua frame
+    varargs: Vec<Value<'gc>>,
     frames: Vec<Frame<'gc>>,
     open_upvalues: BTreeMap<usize, UpValue<'gc>>,
+    // The register indexes of every active to-be-closed variable, in the order they were marked
+    to_be_closed: Vec<usize>,
+    // Empty value buffers available to be handed out to callbacks and continuations
+    buffers: Vec<ValueBuffer<'gc>>,
     result: Option<Result<Vec<Value<'gc>>, Error<'gc>>>,
     allow_yield: bool,
+    hooks: HookState<'gc>,
+    metatables: Metatables<'gc>,
 }
 
 pub(crate) struct LuaFrame<'gc, 'a> {
@@ -68,10 +99,11 @@ pub(crate) struct LuaFrame<'gc, 'a> {
 
 pub(crate) struct LuaRegisters<'gc, 'a> {
     pub pc: &'a mut usize,
-    pub stack_frame: &'a mut [Value<'gc>],
+    pub stack_frame: &'a mut [Value<'gc>; LUA_REGISTERS],
     upper_stack: &'a mut [Value<'gc>],
     base: usize,
     open_upvalues: &'a mut BTreeMap<usize, UpValue<'gc>>,
+    to_be_closed: &'a mut Vec<usize>,
     thread: Thread<'gc>,
 }
 
@@ -108,15 +140,27 @@ impl<'gc> Sequence<'gc> for ThreadSequence<'gc> {
 }
 
 impl<'gc> Thread<'gc> {
-    pub fn new(mc: MutationContext<'gc, '_>, allow_yield: bool) -> Thread<'gc> {
+    /// Creates a new thread, which looks up the metatables of strings and other values without
+    /// metatables of their own in the given `Metatables`.
+    pub fn new(
+        mc: MutationContext<'gc, '_>,
+        metatables: Metatables<'gc>,
+        allow_yield: bool,
+    ) -> Thread<'gc> {
         Thread(GcCell::allocate(
             mc,
             ThreadState {
-                values: Vec::new(),
+                registers: Vec::new(),
+                registers_top: 0,
+                varargs: Vec::new(),
                 frames: Vec::new(),
                 open_upvalues: BTreeMap::new(),
+                to_be_closed: Vec::new(),
+                buffers: Vec::new(),
                 result: None,
                 allow_yield,
+                hooks: HookState::new(None),
+                metatables,
             },
         ))
     }
@@ -154,6 +198,32 @@ impl<'gc> Thread<'gc> {
         Ok(())
     }
 
+    /// Sets the hook that is called while this thread runs Lua code, replacing any previous hook.
+    /// Setting `None` removes the current hook.  Coroutines this thread creates afterwards inherit
+    /// the new hook.
+    pub fn set_hook(self, mc: MutationContext<'gc, '_>, hook: Option<Hook<'gc>>) {
+        self.0.write(mc).hooks = HookState::new(hook);
+    }
+
+    pub fn hook(self) -> Option<Hook<'gc>> {
+        self.0.read().hooks.hook
+    }
+
+    /// Stops whatever this thread is running, in any mode, and leaves it `Stopped`.  Every frame is
+    /// discarded without running anything further, so pending `__close` metamethods are never
+    /// called, but open upvalues are closed so that closures still see their last values.
+    pub fn reset(self, mc: MutationContext<'gc, '_>) {
+        let mut state = self.0.write(mc);
+        close_upvalues(self, &mut state, mc, 0);
+        state.registers.clear();
+        state.registers_top = 0;
+        state.varargs.clear();
+        state.frames.clear();
+        state.to_be_closed.clear();
+        state.result = None;
+        state.hooks = HookState::new(state.hooks.hook);
+    }
+
     /// Take any results if they are available
     pub fn take_results(
         self,
@@ -174,7 +244,8 @@ impl<'gc> Thread<'gc> {
             Some(Frame::StartCoroutine(function)) => {
                 state.frames.pop();
                 assert!(
-                    state.values.is_empty()
+                    state.registers.is_empty()
+                        && state.varargs.is_empty()
                         && state.open_upvalues.is_empty()
                         && state.frames.is_empty()
                         && state.result.is_none()
@@ -182,12 +253,24 @@ impl<'gc> Thread<'gc> {
                 ext_call_function(self, &mut state, mc, function, args);
             }
             Some(Frame::ResumeCoroutine) => match state.frames.last_mut() {
-                Some(Frame::Continuation { continuation, .. }) => {
+                Some(Frame::Continuation {
+                    continuation,
+                    tail_call,
+                    ..
+                }) => {
                     let continuation = continuation.take().expect("continuation missing");
-                    let ret = continuation.call(Ok(args.to_vec()));
+                    let tail_call = *tail_call;
+                    let mut buffer = take_buffer(&mut state);
+                    buffer.extend_from_slice(args);
+                    let ret = continuation.call(Ok(buffer));
                     state.frames.pop();
-                    callback_return(self, &mut state, mc, ret);
+                    callback_return(self, &mut state, mc, ret, tail_call);
                 }
+                // A Lua frame that is not waiting on any returns was suspended by its hook.
+                Some(Frame::Lua {
+                    expected_return: None,
+                    ..
+                }) => {}
                 Some(Frame::Lua { .. }) => {
                     return_to_lua(&mut state, args);
                 }
@@ -203,18 +286,33 @@ impl<'gc> Thread<'gc> {
 
     /// If the thread is in `Running` mode, either run the Lua VM for a while or step any callback
     /// that we are waiting on.
+    ///
+    /// When stepped inside `Execution::run`, the VM never runs more instructions than are left in
+    /// the execution's budget, and does nothing at all once the budget is used up.
     pub fn step(self, mc: MutationContext<'gc, '_>) -> Result<(), BadThreadMode> {
         let mut state = self.0.write(mc);
         check_mode(&state, ThreadMode::Running)?;
         match state.frames.last_mut() {
-            Some(Frame::Callback(sequence)) => {
+            Some(Frame::Callback {
+                sequence,
+                tail_call,
+            }) => {
                 let mut sequence = sequence.take().expect("pending callback missing");
+                let tail_call = *tail_call;
+                let metatables = state.metatables;
+                let hook = state.hooks.hook;
                 drop(state);
-                match sequence.step(mc) {
+                let prev_hook = metatables.set_running_hook(mc, hook);
+                let res = sequence.step(mc);
+                metatables.set_running_hook(mc, prev_hook);
+                match res {
                     None => {
                         let mut state = self.0.write(mc);
                         match state.frames.last_mut() {
-                            Some(Frame::Callback(empty_sequence)) => {
+                            Some(Frame::Callback {
+                                sequence: empty_sequence,
+                                ..
+                            }) => {
                                 *empty_sequence = Some(sequence);
                             }
                             _ => panic!("thread left callback state without finishing callback"),
@@ -223,36 +321,79 @@ impl<'gc> Thread<'gc> {
                     Some(res) => {
                         let mut state = self.0.write(mc);
                         state.frames.pop();
-                        return_ext(self, &mut state, mc, res);
+                        return_ext(self, &mut state, mc, res, tail_call);
                     }
                 }
             }
             Some(Frame::Lua { .. }) => {
                 const VM_GRANULARITY: u32 = 256;
-                let mut instructions = VM_GRANULARITY;
+                let allowed = allowed_instructions(VM_GRANULARITY);
+                let mut instructions = allowed;
+                let memory_limit = memory_limit();
+
+                while instructions > 0 {
+                    self.0.set_external_size(mc, allocated_size(&state));
+                    if mc.total_allocated() > memory_limit {
+                        // Give the collector a chance to free memory before failing, the error is
+                        // only raised once a full collection has left the arena over the limit.
+                        if take_out_of_memory() {
+                            unwind(self, &mut state, mc, not_enough_memory());
+                        }
+                        break;
+                    }
+
+                    let mut run = instructions;
+                    if let Some(hook) = state.hooks.hook {
+                        match dispatch_hooks(&mut state, mc, hook) {
+                            Ok(HookAction::Continue) => {}
+                            Ok(HookAction::Yield) => {
+                                if state.allow_yield {
+                                    state.hooks.yielded = true;
+                                    state.frames.push(Frame::ResumeCoroutine);
+                                    state.result = Some(Ok(Vec::new()));
+                                } else {
+                                    unwind(self, &mut state, mc, ThreadError::BadYield.into());
+                                }
+                                break;
+                            }
+                            Err(err) => {
+                                abort(self, &mut state, mc, err);
+                                break;
+                            }
+                        }
+
+                        // Line and return events are checked before every instruction, count
+                        // events only once enough instructions have run.
+                        let mask = hook.mask();
+                        if mask.line || mask.ret {
+                            run = 1;
+                        } else if mask.count != 0 {
+                            run = run.min(state.hooks.count);
+                        }
+                    }
 
-                loop {
                     let lua_frame = LuaFrame {
                         state: &mut state,
                         thread: self,
                     };
-                    match run_vm(mc, lua_frame, instructions) {
+                    match run_vm(mc, lua_frame, run, memory_limit) {
                         Err(err) => {
+                            let err = describe_error(self, &state, mc, err);
                             unwind(self, &mut state, mc, err);
                             break;
                         }
                         Ok(i) => {
-                            if let Some(Frame::Lua { .. }) = state.frames.last() {
-                                instructions = i;
-                                if instructions == 0 {
-                                    break;
-                                }
-                            } else {
-                                break;
+                            instructions -= run - i;
+                            state.hooks.count = state.hooks.count.saturating_sub(run - i);
+                            match state.frames.last() {
+                                Some(Frame::Lua { .. }) => {}
+                                _ => break,
                             }
                         }
                     }
                 }
+                consume_instructions(allowed - instructions);
+                self.0.set_external_size(mc, allocated_size(&state));
             }
             _ => panic!("no callback or lua frame"),
         }
@@ -265,25 +406,30 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
     // Returns the active closure for this Lua frame
     pub(crate) fn closure(&self) -> Closure<'gc> {
         match self.state.frames.last() {
-            Some(Frame::Lua { bottom, .. }) => match self.state.values[*bottom] {
-                Value::Function(Function::Closure(c)) => c,
-                _ => panic!("thread bottom is not a closure"),
-            },
+            Some(Frame::Lua { closure, .. }) => *closure,
             _ => panic!("top frame is not lua frame"),
         }
     }
 
+    // Returns the metatables shared by every thread
+    pub(crate) fn metatables(&self) -> Metatables<'gc> {
+        self.state.metatables
+    }
+
     // returns a view of the Lua frame's registers
     pub(crate) fn registers<'b>(&'b mut self) -> LuaRegisters<'gc, 'b> {
         match self.state.frames.last_mut() {
             Some(Frame::Lua { base, pc, .. }) => {
-                let (upper_stack, stack_frame) = self.state.values.split_at_mut(*base);
+                let (upper_stack, stack_frame) = self.state.registers.split_at_mut(*base);
                 LuaRegisters {
                     pc,
-                    stack_frame,
+                    stack_frame: (&mut stack_frame[..LUA_REGISTERS])
+                        .try_into()
+                        .expect("lua frame does not own exactly LUA_REGISTERS registers"),
                     upper_stack,
                     base: *base,
                     open_upvalues: &mut self.state.open_upvalues,
+                    to_be_closed: &mut self.state.to_be_closed,
                     thread: self.thread,
                 }
             }
@@ -299,32 +445,29 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
     ) -> Result<(), ThreadError> {
         match self.state.frames.last_mut() {
             Some(Frame::Lua {
-                bottom,
                 base,
-                is_variable,
+                varargs_start,
+                varargs_len,
+                variable,
                 ..
             }) => {
-                if *is_variable {
+                if variable.is_some() {
                     return Err(ThreadError::ExpectedVariable(false));
                 }
 
-                let varargs_start = *bottom + 1;
-                let varargs_len = *base - varargs_start;
-                let dest = *base + dest.0 as usize;
+                let varargs = *varargs_start..*varargs_start + *varargs_len;
                 if let Some(count) = count.to_constant() {
+                    let dest = *base + dest.0 as usize;
                     for i in 0..count as usize {
-                        self.state.values[dest + i] = if i < varargs_len {
-                            self.state.values[varargs_start + i]
+                        self.state.registers[dest + i] = if i < varargs.len() {
+                            self.state.varargs[varargs.start + i]
                         } else {
                             Value::Nil
                         };
                     }
                 } else {
-                    *is_variable = true;
-                    self.state.values.resize(dest + varargs_len, Value::Nil);
-                    for i in 0..varargs_len {
-                        self.state.values[dest + i] = self.state.values[varargs_start + i];
-                    }
+                    *variable = Some(dest);
+                    self.state.varargs.extend_from_within(varargs);
                 }
             }
             _ => panic!("top frame is not lua frame"),
@@ -341,71 +484,14 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
         args: VarCount,
         returns: VarCount,
     ) -> Result<(), ThreadError> {
-        match self.state.frames.last_mut() {
-            Some(Frame::Lua {
-                expected_returns,
-                is_variable,
-                base,
-                ..
-            }) => {
-                if *is_variable != args.is_variable() {
-                    return Err(ThreadError::ExpectedVariable(*is_variable));
-                }
-
-                *expected_returns = Some(returns);
-                let function_index = *base + func.0 as usize;
-                let arg_count = args
-                    .to_constant()
-                    .map(|c| c as usize)
-                    .unwrap_or(self.state.values.len() - function_index - 1);
-
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
-                        let fixed_params = closure.0.proto.fixed_params as usize;
-                        let stack_size = closure.0.proto.stack_size as usize;
-
-                        let base = if arg_count > fixed_params {
-                            self.state.values.truncate(function_index + 1 + arg_count);
-                            self.state.values[function_index + 1..].rotate_left(fixed_params);
-                            function_index + 1 + (arg_count - fixed_params)
-                        } else {
-                            function_index + 1
-                        };
-
-                        self.state.values.resize(base + stack_size, Value::Nil);
-
-                        self.state.frames.push(Frame::Lua {
-                            bottom: function_index,
-                            base,
-                            is_variable: false,
-                            pc: 0,
-                            stack_size,
-                            expected_returns: None,
-                        });
-                        Ok(())
-                    }
-                    Value::Function(Function::Callback(callback)) => {
-                        let ret = callback.call(
-                            self.state.values[function_index + 1..function_index + 1 + arg_count]
-                                .to_vec(),
-                        );
-                        self.state.values.resize(function_index, Value::Nil);
-                        callback_return(self.thread, &mut self.state, mc, ret);
-                        Ok(())
-                    }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
-                }
-            }
-            _ => panic!("top frame is not lua frame"),
-        }
+        let (function, args_start) = self.push_arguments(func, args)?;
+        self.set_expected_return(LuaReturn::Normal(func, returns));
+        call_pushed(self.thread, self.state, mc, function, args_start, false)
     }
 
     // Calls the function at the given index with a constant number of arguments without
     // invalidating the function or its arguments.  Returns are placed *after* the function and its
-    // aruments, and all registers past this are invalidated as normal.
+    // aruments.
     pub(crate) fn call_function_non_destructive(
         mut self,
         mc: MutationContext<'gc, '_>,
@@ -413,70 +499,46 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
         arg_count: u8,
         returns: VarCount,
     ) -> Result<(), ThreadError> {
-        match self.state.frames.last_mut() {
-            Some(Frame::Lua {
-                expected_returns,
-                is_variable,
-                base,
-                ..
-            }) => {
-                if *is_variable {
-                    return Err(ThreadError::ExpectedVariable(false));
-                }
-
-                let arg_count = arg_count as usize;
-                *expected_returns = Some(returns);
-                let given_function_index = *base + func.0 as usize;
-                let function_index = given_function_index + 1 + arg_count;
-                self.state
-                    .values
-                    .resize(function_index + 1 + arg_count, Value::Nil);
-                for i in 0..arg_count + 1 {
-                    self.state.values[function_index + i] =
-                        self.state.values[given_function_index + i];
-                }
-
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
-                        let fixed_params = closure.0.proto.fixed_params as usize;
-                        let stack_size = closure.0.proto.stack_size as usize;
-
-                        let base = if arg_count > fixed_params {
-                            self.state.values[function_index + 1..].rotate_left(fixed_params);
-                            function_index + 1 + (arg_count - fixed_params)
-                        } else {
-                            function_index + 1
-                        };
+        let (function, args_start) = self.push_arguments(func, VarCount::constant(arg_count))?;
+        let dest = func
+            .0
+            .checked_add(arg_count)
+            .and_then(|i| i.checked_add(1))
+            .expect("return register out of range");
+        self.set_expected_return(LuaReturn::Normal(RegisterIndex(dest), returns));
+        call_pushed(self.thread, self.state, mc, function, args_start, false)
+    }
 
-                        self.state.values.resize(base + stack_size, Value::Nil);
-
-                        self.state.frames.push(Frame::Lua {
-                            bottom: function_index,
-                            base,
-                            is_variable: false,
-                            pc: 0,
-                            stack_size,
-                            expected_returns: None,
-                        });
-                        Ok(())
-                    }
-                    Value::Function(Function::Callback(callback)) => {
-                        let ret = callback.call(
-                            self.state.values[function_index + 1..function_index + 1 + arg_count]
-                                .to_vec(),
-                        );
-                        self.state.values.resize(function_index, Value::Nil);
-                        callback_return(self.thread, &mut self.state, mc, ret);
-                        Ok(())
-                    }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
+    // Calls the given function as a metamethod with the given arguments.  No registers are
+    // invalidated.  When the function returns, the first return value is used to perform the given
+    // `MetaReturn` operation.
+    pub(crate) fn call_meta_function(
+        mut self,
+        mc: MutationContext<'gc, '_>,
+        function: Function<'gc>,
+        args: &[Value<'gc>],
+        meta_ret: MetaReturn,
+    ) -> Result<(), ThreadError> {
+        match self.state.frames.last() {
+            Some(Frame::Lua { variable, .. }) => {
+                if variable.is_some() {
+                    return Err(ThreadError::ExpectedVariable(false));
                 }
             }
             _ => panic!("top frame is not lua frame"),
         }
+
+        let args_start = self.state.varargs.len();
+        self.state.varargs.extend_from_slice(args);
+        self.set_expected_return(LuaReturn::Meta(meta_ret));
+        call_pushed(
+            self.thread,
+            self.state,
+            mc,
+            Value::Function(function),
+            args_start,
+            false,
+        )
     }
 
     // Tail-call the function at the given register with the given arguments.  Pops the current Lua
@@ -487,70 +549,20 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
         func: RegisterIndex,
         args: VarCount,
     ) -> Result<(), ThreadError> {
+        let (function, args_start) = self.push_arguments(func, args)?;
+        // Uncallable values must be reported before the calling frame is gone.
+        meta_ops::call(function).map_err(ThreadError::BadCall)?;
         match self.state.frames.pop() {
             Some(Frame::Lua {
-                bottom,
+                closure,
                 base,
-                is_variable,
+                varargs_start,
                 ..
             }) => {
-                if is_variable != args.is_variable() {
-                    return Err(ThreadError::ExpectedVariable(is_variable));
-                }
-
-                close_upvalues(self.thread, self.state, mc, bottom);
-
-                let function_index = base + func.0 as usize;
-                let arg_count = args
-                    .to_constant()
-                    .map(|c| c as usize)
-                    .unwrap_or(self.state.values.len() - function_index - 1);
-
-                match self.state.values[function_index] {
-                    Value::Function(Function::Closure(closure)) => {
-                        self.state.values[bottom] = self.state.values[function_index];
-                        for i in 0..arg_count {
-                            self.state.values[bottom + 1 + i] =
-                                self.state.values[function_index + 1 + i];
-                        }
-
-                        let fixed_params = closure.0.proto.fixed_params as usize;
-                        let stack_size = closure.0.proto.stack_size as usize;
-
-                        let base = if arg_count > fixed_params {
-                            self.state.values.truncate(bottom + 1 + arg_count);
-                            self.state.values[bottom + 1..].rotate_left(fixed_params);
-                            bottom + 1 + (arg_count - fixed_params)
-                        } else {
-                            bottom + 1
-                        };
-
-                        self.state.values.resize(base + stack_size, Value::Nil);
-
-                        self.state.frames.push(Frame::Lua {
-                            bottom,
-                            base,
-                            is_variable: false,
-                            pc: 0,
-                            stack_size,
-                            expected_returns: None,
-                        });
-                        Ok(())
-                    }
-                    Value::Function(Function::Callback(callback)) => {
-                        let ret = callback.call(
-                            self.state.values[function_index + 1..function_index + 1 + arg_count]
-                                .to_vec(),
-                        );
-                        self.state.values.truncate(bottom);
-                        callback_return(self.thread, &mut self.state, mc, ret);
-                        Ok(())
-                    }
-                    val => Err(ThreadError::BadCall(TypeError {
-                        expected: "function",
-                        found: val.type_name(),
-                    })),
-                }
+                close_upvalues(self.thread, self.state, mc, base);
+                pop_registers(self.state, closure, base);
+                self.state.varargs.drain(varargs_start..args_start);
+                call_pushed(self.thread, self.state, mc, function, varargs_start, true)
             }
             _ => panic!("top frame is not lua frame"),
         }
@@ -558,79 +570,175 @@ impl<'gc, 'a> LuaFrame<'gc, 'a> {
 
     // Return to the upper frame with results starting at the given register index.
     pub(crate) fn return_upper(
-        mut self,
+        self,
         mc: MutationContext<'gc, '_>,
         start: RegisterIndex,
         count: VarCount,
     ) -> Result<(), ThreadError> {
-        match self.state.frames.pop() {
+        let state = self.state;
+        let (closure, base, varargs_start, varargs_len, variable) = match state.frames.pop() {
             Some(Frame::Lua {
-                bottom,
+                closure,
                 base,
-                is_variable,
+                varargs_start,
+                varargs_len,
+                variable,
+                ..
+            }) => (closure, base, varargs_start, varargs_len, variable),
+            _ => panic!("top frame is not lua frame"),
+        };
+        if variable.is_some() != count.is_variable() {
+            return Err(ThreadError::ExpectedVariable(variable.is_some()));
+        }
+        close_upvalues(self.thread, state, mc, base);
+
+        // The returned values are the registers from `start` to `end`, followed by any pending
+        // variable values.
+        let start = base + start.0 as usize;
+        let end = match count.to_constant() {
+            Some(count) => start + count as usize,
+            None => base + variable.unwrap().0 as usize,
+        };
+        let variable_start = varargs_start + varargs_len;
+
+        let closing = take_to_be_closed(state, base);
+        if !closing.is_empty() {
+            let ret_vals = collect_returns(state, start..end, variable_start);
+            pop_registers(state, closure, base);
+            state.varargs.truncate(varargs_start);
+            close_variables(self.thread, state, mc, closing, Ok(ret_vals));
+            return Ok(());
+        }
+
+        match state.frames.last_mut() {
+            Some(Frame::Continuation {
+                continuation,
+                tail_call,
                 ..
             }) => {
-                if is_variable != count.is_variable() {
-                    return Err(ThreadError::ExpectedVariable(is_variable));
-                }
-                close_upvalues(self.thread, self.state, mc, bottom);
-
-                let start = base + start.0 as usize;
-                let count = count
-                    .to_constant()
-                    .map(|c| c as usize)
-                    .unwrap_or(self.state.values.len() - start);
-
-                match self.state.frames.last_mut() {
-                    Some(Frame::Continuation { continuation, .. }) => {
-                        let continuation = continuation.take().expect("continuation missing");
-                        let ret_vals = self.state.values[start..start + count].to_vec();
-                        self.state.values.truncate(bottom);
-                        let ret = continuation.call(Ok(ret_vals));
-                        self.state.frames.pop();
-                        callback_return(self.thread, &mut self.state, mc, ret);
-                    }
-                    Some(Frame::Lua {
-                        expected_returns,
-                        is_variable,
-                        base,
-                        stack_size,
-                        ..
-                    }) => {
-                        let expected_returns =
-                            expected_returns.expect("no expected returns for upper lua frame");
-                        let returning = expected_returns
-                            .to_constant()
-                            .map(|c| c as usize)
-                            .unwrap_or(count);
-
-                        for i in 0..returning.min(count) {
-                            self.state.values[bottom + i] = self.state.values[start + i]
+                let continuation = continuation.take().expect("continuation missing");
+                let tail_call = *tail_call;
+                let ret_vals = collect_returns(state, start..end, variable_start);
+                pop_registers(state, closure, base);
+                state.varargs.truncate(varargs_start);
+                let ret = continuation.call(Ok(ret_vals));
+                state.frames.pop();
+                callback_return(self.thread, state, mc, ret, tail_call);
+            }
+            Some(Frame::Lua {
+                base: upper_base,
+                variable: upper_variable,
+                expected_return,
+                pc,
+                ..
+            }) => match expected_return.take() {
+                Some(LuaReturn::Normal(dest, expected_returns)) => {
+                    if let Some(expected_returns) = expected_returns.to_constant() {
+                        let dest = *upper_base + dest.0 as usize;
+                        let returned = end - start;
+                        for i in 0..expected_returns as usize {
+                            state.registers[dest + i] = if i < returned {
+                                state.registers[start + i]
+                            } else {
+                                state
+                                    .varargs
+                                    .get(variable_start + i - returned)
+                                    .copied()
+                                    .unwrap_or(Value::Nil)
+                            };
                         }
+                        state.varargs.truncate(varargs_start);
+                    } else {
+                        // The returned values become the pending variable values of the upper
+                        // frame, which start where this frame's varargs started.
+                        state.varargs.splice(
+                            varargs_start..variable_start,
+                            state.registers[start..end].iter().copied(),
+                        );
+                        *upper_variable = Some(dest);
+                    }
+                    pop_registers(state, closure, base);
+                }
+                Some(LuaReturn::Meta(meta_ret)) => {
+                    let meta_val = if end > start {
+                        state.registers[start]
+                    } else {
+                        state
+                            .varargs
+                            .get(variable_start)
+                            .copied()
+                            .unwrap_or(Value::Nil)
+                    };
+                    meta_return(&mut state.registers[*upper_base..], pc, meta_ret, meta_val);
+                    pop_registers(state, closure, base);
+                    state.varargs.truncate(varargs_start);
+                }
+                None => panic!("no expected returns for upper lua frame"),
+            },
+            None => {
+                let ret_vals = collect_returns(state, start..end, variable_start);
+                state.result = Some(Ok(ret_vals.into_vec()));
+                state.registers.clear();
+                state.registers_top = 0;
+                state.varargs.clear();
+            }
+            _ => panic!("lua frame must be above a continuation or lua frame"),
+        }
+        Ok(())
+    }
 
-                        for i in count..returning {
-                            self.state.values[bottom + i] = Value::Nil;
-                        }
+    // Pushes the arguments for a call of the function at the given register onto the top of the
+    // varargs stack, consuming any pending variable values.  Returns the function value along with
+    // the varargs stack index of the first argument.
+    fn push_arguments(
+        &mut self,
+        func: RegisterIndex,
+        args: VarCount,
+    ) -> Result<(Value<'gc>, usize), ThreadError> {
+        match self.state.frames.last_mut() {
+            Some(Frame::Lua {
+                base,
+                varargs_start,
+                varargs_len,
+                variable,
+                ..
+            }) => {
+                if variable.is_some() != args.is_variable() {
+                    return Err(ThreadError::ExpectedVariable(variable.is_some()));
+                }
 
-                        if expected_returns.is_variable() {
-                            self.state.values.truncate(bottom + returning);
-                            *is_variable = true;
-                        } else {
-                            self.state.values.resize(*base + *stack_size, Value::Nil);
-                            *is_variable = false;
-                        }
+                let func = *base + func.0 as usize;
+                let args_start = *varargs_start + *varargs_len;
+                match args.to_constant() {
+                    Some(count) => {
+                        let args_end = func + 1 + count as usize;
+                        self.state
+                            .varargs
+                            .extend_from_slice(&self.state.registers[func + 1..args_end]);
                     }
                     None => {
-                        let ret_vals = self.state.values[start..start + count].to_vec();
-                        self.state.result = Some(Ok(ret_vals));
-                        self.state.values.clear();
+                        // The pending variable values are the last arguments, so the constant
+                        // arguments are placed before them.
+                        let args_end = *base + variable.take().unwrap().0 as usize;
+                        self.state.varargs.splice(
+                            args_start..args_start,
+                            self.state.registers[func + 1..args_end].iter().copied(),
+                        );
                     }
-                    _ => panic!("lua frame must be above a continuation or lua frame"),
                 }
+                Ok((self.state.registers[func], args_start))
             }
             _ => panic!("top frame is not lua frame"),
         }
-        Ok(())
+    }
+
+    fn set_expected_return(&mut self, ret: LuaReturn) {
+        match self.state.frames.last_mut() {
+            Some(Frame::Lua {
+                expected_return, ..
+            }) => *expected_return = Some(ret),
+            _ => panic!("top frame is not lua frame"),
+        }
     }
 }
 
@@ -661,7 +769,7 @@ impl<'gc, 'a> LuaRegisters<'gc, 'a> {
                         self.stack_frame[ind - self.base]
                     }
                 } else {
-                    thread.0.read().values[ind]
+                    thread.0.read().registers[ind]
                 }
             }
             UpValueState::Closed(v) => v,
@@ -684,13 +792,36 @@ impl<'gc, 'a> LuaRegisters<'gc, 'a> {
                         self.stack_frame[*ind - self.base] = value;
                     }
                 } else {
-                    thread.0.write(mc).values[*ind] = value;
+                    thread.0.write(mc).registers[*ind] = value;
                 }
             }
             UpValueState::Closed(v) => *v = value,
         }
     }
 
+    // Marks the given register as a to-be-closed variable.  `nil` and `false` are accepted and
+    // ignored, any other value must have a `__close` metamethod.
+    pub fn mark_to_be_closed(&mut self, register: RegisterIndex) -> Result<(), TypeError> {
+        let value = self.stack_frame[register.0 as usize];
+        if value.to_bool() {
+            meta_ops::close(value, Value::Nil)?;
+            self.to_be_closed.push(self.base + register.0 as usize);
+        }
+        Ok(())
+    }
+
+    // Removes the most recently marked to-be-closed variable at or above the given register,
+    // returning its value.
+    pub fn take_to_be_closed(&mut self, register: RegisterIndex) -> Option<Value<'gc>> {
+        let index = *self.to_be_closed.last()?;
+        if index >= self.base + register.0 as usize {
+            self.to_be_closed.pop();
+            Some(self.stack_frame[index - self.base])
+        } else {
+            None
+        }
+    }
+
     pub fn close_upvalues(&mut self, mc: MutationContext<'gc, '_>, register: RegisterIndex) {
         for (_, upval) in self
             .open_upvalues
@@ -709,26 +840,62 @@ impl<'gc, 'a> LuaRegisters<'gc, 'a> {
     }
 }
 
+// The operation to perform with the first return value of a metamethod call
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_static)]
+pub(crate) enum MetaReturn {
+    // No return value expected
+    None,
+    // Place the return value into the given register
+    Register(RegisterIndex),
+    // Skip the next instruction if the return value, converted to a boolean, equals the given
+    // value
+    SkipIf(bool),
+}
+
+#[derive(Debug, Copy, Clone, Collect)]
+#[collect(require_static)]
+enum LuaReturn {
+    // Normal function call, place the expected return values starting at the given register
+    Normal(RegisterIndex, VarCount),
+    // Metamethod call, perform the specified operation with the first return value
+    Meta(MetaReturn),
+}
+
 #[derive(Collect)]
 #[collect(empty_drop)]
 enum Frame<'gc> {
     Lua {
-        bottom: usize,
+        closure: Closure<'gc>,
+        // The index of this frame's first register
         base: usize,
-        is_variable: bool,
+        // The location of this frame's variable arguments on the varargs stack
+        varargs_start: usize,
+        varargs_len: usize,
+        // If set, the frame has pending variable values, which are logically placed starting at the
+        // given register but are stored on the varargs stack directly after this frame's varargs.
+        variable: Option<RegisterIndex>,
         pc: usize,
-        stack_size: usize,
-        expected_returns: Option<VarCount>,
+        expected_return: Option<LuaReturn>,
+        // Whether the function was entered by a tail call, which replaced the frame of the function
+        // that called it.
+        tail_call: bool,
     },
     Continuation {
+        // The heights of the register and varargs stacks when the continuation was pushed
         bottom: usize,
+        varargs_bottom: usize,
         continuation: Option<Continuation<'gc>>,
+        // Whether the callback that the continuation belongs to was entered by a tail call
+        tail_call: bool,
     },
     StartCoroutine(Function<'gc>),
     ResumeCoroutine,
-    Callback(
-        Option<Box<dyn Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>> + 'gc>>,
-    ),
+    Callback {
+        sequence:
+            Option<Box<dyn Sequence<'gc, Output = Result<CallbackResult<'gc>, Error<'gc>>> + 'gc>>,
+        tail_call: bool,
+    },
 }
 
 fn get_mode<'gc>(state: &ThreadState<'gc>) -> ThreadMode {
@@ -738,14 +905,15 @@ fn get_mode<'gc>(state: &ThreadState<'gc>) -> ThreadMode {
         match state.frames.last() {
             None => {
                 assert!(
-                    state.values.is_empty()
+                    state.registers.is_empty()
+                        && state.varargs.is_empty()
                         && state.open_upvalues.is_empty()
                         && state.result.is_none(),
                 );
                 ThreadMode::Stopped
             }
             Some(frame) => match frame {
-                Frame::Callback(_) | Frame::Continuation { .. } | Frame::Lua { .. } => {
+                Frame::Callback { .. } | Frame::Continuation { .. } | Frame::Lua { .. } => {
                     ThreadMode::Running
                 }
                 Frame::StartCoroutine(_) | Frame::ResumeCoroutine => ThreadMode::Suspended,
@@ -766,6 +934,12 @@ fn check_mode<'gc>(state: &ThreadState<'gc>, expected: ThreadMode) -> Result<(),
     }
 }
 
+// The number of bytes used by the stacks of this thread, not counting the `ThreadState` itself
+fn allocated_size<'gc>(state: &ThreadState<'gc>) -> usize {
+    (state.registers.capacity() + state.varargs.capacity()) * mem::size_of::<Value>()
+        + state.frames.capacity() * mem::size_of::<Frame>()
+}
+
 fn ext_call_function<'gc>(
     thread: Thread<'gc>,
     state: &mut ThreadState<'gc>,
@@ -773,77 +947,233 @@ fn ext_call_function<'gc>(
     function: Function<'gc>,
     args: &[Value<'gc>],
 ) {
+    let args_start = state.varargs.len();
+    state.varargs.extend_from_slice(args);
+    call_pushed(
+        thread,
+        state,
+        mc,
+        Value::Function(function),
+        args_start,
+        false,
+    )
+    .expect("functions are always callable");
+}
+
+// Calls the given value with the arguments on the varargs stack starting at the given index,
+// consuming them.  If the value is not a function, its `__call` metamethod is called instead with
+// the original value inserted as the first argument.
+//
+// A Lua function gets a new frame with all of its registers, and any extra arguments are left in
+// place as the frame's varargs.  `tail_call` is recorded in the frame of the called function so that
+// tracebacks do not name it after the call instruction below it, which called some other function.
+fn call_pushed<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    function: Value<'gc>,
+    args_start: usize,
+    tail_call: bool,
+) -> Result<(), ThreadError> {
+    let function = match function {
+        Value::Function(function) => function,
+        value => {
+            let function = meta_ops::call(value).map_err(ThreadError::BadCall)?;
+            state.varargs.insert(args_start, value);
+            function
+        }
+    };
+
     match function {
         Function::Closure(closure) => {
-            let fixed_params = closure.0.proto.fixed_params as usize;
-            let stack_size = closure.0.proto.stack_size as usize;
+            let base = state.registers_top;
+            state.registers_top += LUA_REGISTERS;
+            if state.registers.len() < state.registers_top {
+                state.registers.resize(state.registers_top, Value::Nil);
+            }
 
-            let var_params = if args.len() > fixed_params {
-                args.len() - fixed_params
+            let fixed_params = closure.0.proto.fixed_params as usize;
+            let fixed_args = (state.varargs.len() - args_start).min(fixed_params);
+            state.registers[base..base + fixed_args]
+                .copy_from_slice(&state.varargs[args_start..args_start + fixed_args]);
+            if closure.0.proto.has_varargs {
+                state.varargs.drain(args_start..args_start + fixed_args);
             } else {
-                0
-            };
-            let bottom = state.values.len();
-            let base = bottom + 1 + var_params;
-
-            state.values.resize(base + stack_size, Value::Nil);
-
-            state.values[bottom] = Value::Function(Function::Closure(closure));
-            for i in 0..fixed_params {
-                state.values[base + i] = args.get(i).cloned().unwrap_or(Value::Nil);
-            }
-            for i in 0..var_params {
-                state.values[1 + i] = args[fixed_params + i]
+                state.varargs.truncate(args_start);
             }
 
             state.frames.push(Frame::Lua {
-                bottom,
+                closure,
                 base,
-                is_variable: false,
+                varargs_start: args_start,
+                varargs_len: state.varargs.len() - args_start,
+                variable: None,
                 pc: 0,
-                stack_size,
-                expected_returns: None,
+                expected_return: None,
+                tail_call,
             });
+            state.hooks.call_pending = state.hooks.hook.is_some();
         }
         Function::Callback(callback) => {
-            let ret = callback.call(args.to_vec());
-            callback_return(thread, state, mc, ret);
+            let mut args = take_buffer(state);
+            args.extend_from_slice(&state.varargs[args_start..]);
+            state.varargs.truncate(args_start);
+            let ret = callback.call(args);
+            callback_return(thread, state, mc, ret, tail_call);
         }
     }
+    Ok(())
+}
+
+// Pops the registers of a finished Lua frame.  Only the registers that the frame's function may
+// have used need to be cleared.
+fn pop_registers<'gc>(state: &mut ThreadState<'gc>, closure: Closure<'gc>, base: usize) {
+    let stack_size = closure.0.proto.stack_size as usize;
+    for register in &mut state.registers[base..base + stack_size] {
+        *register = Value::Nil;
+    }
+    state.registers_top = base;
+}
+
+// Collects the values returned from a Lua frame, which are the registers in the given range followed
+// by any pending variable values starting at the given varargs stack index.
+fn collect_returns<'gc>(
+    state: &mut ThreadState<'gc>,
+    registers: Range<usize>,
+    variable_start: usize,
+) -> ValueBuffer<'gc> {
+    let mut ret_vals = take_buffer(state);
+    ret_vals.extend_from_slice(&state.registers[registers]);
+    ret_vals.extend_from_slice(&state.varargs[variable_start..]);
+    ret_vals
+}
+
+// Takes an empty value buffer from the thread's pool, only allocating a new one if the pool is empty.
+fn take_buffer<'gc>(state: &mut ThreadState<'gc>) -> ValueBuffer<'gc> {
+    state.buffers.pop().unwrap_or_default()
+}
+
+// Returns a value buffer that is no longer needed to the thread's pool, so that it can be reused.
+fn recycle_buffer<'gc>(state: &mut ThreadState<'gc>, mut buffer: ValueBuffer<'gc>) {
+    if state.buffers.len() < MAX_POOLED_BUFFERS {
+        buffer.clear();
+        state.buffers.push(buffer);
+    }
 }
 
 // Return to the top Lua frame from an external call
 fn return_to_lua<'gc>(state: &mut ThreadState<'gc>, rets: &[Value<'gc>]) {
     match state.frames.last_mut() {
         Some(Frame::Lua {
-            expected_returns,
-            is_variable,
             base,
-            stack_size,
+            variable,
+            expected_return,
+            pc,
             ..
-        }) => {
-            let ret_count = expected_returns
-                .take()
-                .expect("no expected returns for lua frame");
-            let return_len = ret_count
-                .to_constant()
-                .map(|c| c as usize)
-                .unwrap_or(rets.len());
-
-            let bottom = state.values.len();
-            state.values.resize(bottom + return_len, Value::Nil);
-
-            for i in 0..return_len.min(rets.len()) {
-                state.values[bottom + i] = rets[i];
+        }) => match expected_return.take() {
+            Some(LuaReturn::Normal(dest, ret_count)) => {
+                if let Some(ret_count) = ret_count.to_constant() {
+                    let dest = *base + dest.0 as usize;
+                    for i in 0..ret_count as usize {
+                        state.registers[dest + i] = rets.get(i).cloned().unwrap_or(Value::Nil);
+                    }
+                } else {
+                    state.varargs.extend_from_slice(rets);
+                    *variable = Some(dest);
+                }
             }
+            Some(LuaReturn::Meta(meta_ret)) => {
+                let meta_val = rets.first().cloned().unwrap_or(Value::Nil);
+                meta_return(&mut state.registers[*base..], pc, meta_ret, meta_val);
+            }
+            None => panic!("no expected returns for lua frame"),
+        },
+        _ => panic!("no lua frame to return to"),
+    };
+}
 
-            *is_variable = ret_count.is_variable();
-            if !ret_count.is_variable() {
-                state.values.resize(*base + *stack_size, Value::Nil);
+// Perform the given `MetaReturn` operation on the registers and pc of a Lua frame
+fn meta_return<'gc>(
+    registers: &mut [Value<'gc>],
+    pc: &mut usize,
+    meta_ret: MetaReturn,
+    value: Value<'gc>,
+) {
+    match meta_ret {
+        MetaReturn::None => {}
+        MetaReturn::Register(reg) => {
+            registers[reg.0 as usize] = value;
+        }
+        MetaReturn::SkipIf(skip_if) => {
+            if value.to_bool() == skip_if {
+                *pc += 1;
             }
         }
-        _ => panic!("no lua frame to return to"),
+    }
+}
+
+// Calls the given hook for every event selected by its mask that occurs before the top Lua frame
+// runs its next instruction.  If the hook yields on any event, the remaining events are still
+// fired before yielding.
+fn dispatch_hooks<'gc>(
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    hook: Hook<'gc>,
+) -> Result<HookAction, Error<'gc>> {
+    if std::mem::replace(&mut state.hooks.yielded, false) {
+        return Ok(HookAction::Continue);
+    }
+
+    let depth = state.frames.len();
+    let (closure, pc) = match state.frames.last() {
+        Some(&Frame::Lua { closure, pc, .. }) => (closure, pc),
+        _ => panic!("top frame is not lua frame"),
     };
+    let proto = &closure.0.proto;
+    let line = proto.opcode_line(pc);
+    let frame = HookFrame { closure, pc, line };
+    let mask = hook.mask();
+
+    let mut action = HookAction::Continue;
+    let mut fire = |event| -> Result<(), Error<'gc>> {
+        if hook.call(mc, event, frame)? == HookAction::Yield {
+            action = HookAction::Yield;
+        }
+        Ok(())
+    };
+
+    if std::mem::replace(&mut state.hooks.call_pending, false) && mask.call {
+        fire(HookEvent::Call)?;
+    }
+    if mask.count != 0 && state.hooks.count == 0 {
+        state.hooks.count = mask.count;
+        fire(HookEvent::Count)?;
+    }
+    if mask.line {
+        // A new line starts when entering a function, when jumping backwards, or when running an
+        // instruction from a different line than the last one.  After returning to this frame, the
+        // last instruction it ran was the call just before `pc`.
+        let new_line = match state.hooks.last_position {
+            Some((last_depth, last_pc)) if last_depth == depth => {
+                pc <= last_pc || proto.opcode_line(last_pc) != line
+            }
+            Some((last_depth, _)) if last_depth > depth => {
+                pc == 0 || proto.opcode_line(pc - 1) != line
+            }
+            _ => true,
+        };
+        state.hooks.last_position = Some((depth, pc));
+        if let (true, Some(line)) = (new_line, line) {
+            fire(HookEvent::Line(line))?;
+        }
+    }
+    if mask.ret {
+        if let OpCode::Return { .. } = proto.opcodes[pc] {
+            fire(HookEvent::Return)?;
+        }
+    }
+
+    Ok(action)
 }
 
 // TODO: `unwind`, `return_ext`, and `callback_return` have to be merged somehow, because otherwise
@@ -855,55 +1185,239 @@ fn unwind<'gc>(
     mc: MutationContext<'gc, '_>,
     error: Error<'gc>,
 ) {
+    let error = with_traceback(state, error, None);
     while let Some(mut top_frame) = state.frames.pop() {
-        if let Frame::Continuation {
-            continuation,
-            bottom,
-        } = &mut top_frame
-        {
-            close_upvalues(thread, state, mc, *bottom);
-            state.values.truncate(*bottom);
-            let continuation = continuation.take().expect("missing continuation");
-            let ret = continuation.call(Err(error));
-            callback_return(thread, state, mc, ret);
-            return;
+        match &mut top_frame {
+            Frame::Continuation {
+                continuation,
+                bottom,
+                varargs_bottom,
+                tail_call,
+            } => {
+                close_upvalues(thread, state, mc, *bottom);
+                state.registers.truncate(*bottom);
+                state.registers_top = *bottom;
+                state.varargs.truncate(*varargs_bottom);
+                let continuation = continuation.take().expect("missing continuation");
+                let ret = continuation.call(Err(error));
+                callback_return(thread, state, mc, ret, *tail_call);
+                return;
+            }
+            Frame::Lua {
+                base,
+                varargs_start,
+                ..
+            } => {
+                let closing = take_to_be_closed(state, *base);
+                if !closing.is_empty() {
+                    close_upvalues(thread, state, mc, *base);
+                    state.registers.truncate(*base);
+                    state.registers_top = *base;
+                    state.varargs.truncate(*varargs_start);
+                    close_variables(thread, state, mc, closing, Err(error));
+                    return;
+                }
+            }
+            _ => {}
         }
     }
     close_upvalues(thread, state, mc, 0);
-    state.values.clear();
+    state.registers.clear();
+    state.registers_top = 0;
+    state.varargs.clear();
     state.result = Some(Err(error));
 }
 
+// Ends the thread with the given error without running any continuations or `__close` metamethods,
+// so that nothing running on the thread can catch the error.
+fn abort<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    error: Error<'gc>,
+) {
+    let error = with_traceback(state, error, None);
+    state.frames.clear();
+    state.to_be_closed.clear();
+    close_upvalues(thread, state, mc, 0);
+    state.registers.clear();
+    state.registers_top = 0;
+    state.varargs.clear();
+    state.result = Some(Err(error));
+}
+
+// Replaces an error raised by the instruction that the top Lua frame was running with a runtime
+// error message such as "chunk:1: attempt to index a nil value (local 't')", if the error was
+// caused by the values the instruction operated on.
+fn describe_error<'gc>(
+    thread: Thread<'gc>,
+    state: &ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    error: Error<'gc>,
+) -> Error<'gc> {
+    let (closure, base, pc) = match state.frames.last() {
+        Some(&Frame::Lua {
+            closure, base, pc, ..
+        }) if pc > 0 => (closure, base, pc - 1),
+        _ => return error,
+    };
+    let proto = &closure.0.proto;
+
+    let operand_value = |operand| match operand {
+        Operand::Register(reg) => state.registers[base + reg.0 as usize],
+        Operand::Constant(constant) => proto.constants[constant].to_value(),
+        Operand::UpValue(upvalue) => match *closure.0.upvalues[upvalue.0 as usize].0.read() {
+            UpValueState::Open(upvalue_thread, ind) if upvalue_thread == thread => {
+                state.registers[ind]
+            }
+            UpValueState::Open(upvalue_thread, ind) => upvalue_thread.0.read().registers[ind],
+            UpValueState::Closed(value) => value,
+        },
+    };
+
+    match error_message(proto, pc, operand_value, &error) {
+        Some(message) => {
+            let mut position =
+                std::string::String::from_utf8_lossy(proto.chunk_name.as_bytes()).into_owned();
+            if let Some(line) = proto.opcode_line(pc) {
+                position.push_str(&format!(":{}", line));
+            }
+            let message = format!("{}: {}", position, message);
+            RuntimeError(Value::String(String::new(mc, message.as_bytes()))).into()
+        }
+        None => error,
+    }
+}
+
+// Attaches a traceback of the current frames to an error which does not already have one.  If
+// `from_callback` is set, the error was returned by a callback which no longer has a frame of its
+// own, and it holds whether that callback was entered by a tail call.
+fn with_traceback<'gc>(
+    state: &ThreadState<'gc>,
+    error: Error<'gc>,
+    from_callback: Option<bool>,
+) -> Error<'gc> {
+    if error.traceback().is_some() {
+        return error;
+    }
+
+    let mut traceback = Traceback::default();
+    let mut push_frame = |frame| {
+        if traceback.frames.len() < Traceback::HEAD_FRAMES + Traceback::TAIL_FRAMES {
+            traceback.frames.push(frame);
+        } else {
+            traceback.frames.remove(Traceback::HEAD_FRAMES);
+            traceback.frames.push(frame);
+            traceback.skipped += 1;
+        }
+    };
+
+    // The name of the function running in the frame at the given index, from the instruction in
+    // the Lua frame below it that called it.  A function entered by a tail call was not called by
+    // that instruction, so it has no name.
+    let called_name = |i: usize, tail_call: bool| {
+        if tail_call {
+            return None;
+        }
+        match state.frames.get(i.checked_sub(1)?)? {
+            Frame::Lua { closure, pc, .. } => function_name(&closure.0.proto, pc.checked_sub(1)?),
+            _ => None,
+        }
+    };
+
+    if let Some(tail_call) = from_callback {
+        push_frame(TracebackFrame {
+            kind: FrameKind::Callback,
+            function: called_name(state.frames.len(), tail_call),
+            chunk: None,
+            line: None,
+            tail_call,
+        });
+    }
+    for i in (0..state.frames.len()).rev() {
+        match &state.frames[i] {
+            &Frame::Lua {
+                closure,
+                pc,
+                tail_call,
+                ..
+            } => {
+                let proto = &closure.0.proto;
+                let function = called_name(i, tail_call).or_else(|| {
+                    if proto.is_main_chunk {
+                        Some(FunctionName::MainChunk)
+                    } else {
+                        None
+                    }
+                });
+                push_frame(TracebackFrame {
+                    kind: FrameKind::Lua,
+                    function,
+                    chunk: Some(
+                        std::string::String::from_utf8_lossy(proto.chunk_name.as_bytes())
+                            .into_owned(),
+                    ),
+                    line: pc.checked_sub(1).and_then(|pc| proto.opcode_line(pc)),
+                    tail_call,
+                });
+            }
+            &Frame::Continuation { tail_call, .. } | &Frame::Callback { tail_call, .. } => {
+                push_frame(TracebackFrame {
+                    kind: FrameKind::Callback,
+                    function: called_name(i, tail_call),
+                    chunk: None,
+                    line: None,
+                    tail_call,
+                });
+            }
+            Frame::StartCoroutine(_) | Frame::ResumeCoroutine => {}
+        }
+    }
+
+    Error::Traceback {
+        error: Box::new(error),
+        traceback,
+    }
+}
+
 fn return_ext<'gc>(
     thread: Thread<'gc>,
     state: &mut ThreadState<'gc>,
     mc: MutationContext<'gc, '_>,
     res: Result<CallbackResult<'gc>, Error<'gc>>,
+    tail_call: bool,
 ) {
     match res {
         Err(err) => {
+            let err = with_traceback(state, err, Some(tail_call));
             unwind(thread, state, mc, err);
         }
         Ok(CallbackResult::Yield(res)) => {
             if state.allow_yield {
                 state.frames.push(Frame::ResumeCoroutine);
-                state.result = Some(Ok(res));
+                state.result = Some(Ok(res.into_vec()));
             } else {
                 unwind(thread, state, mc, ThreadError::BadYield.into());
             }
         }
         Ok(CallbackResult::Return(res)) => match state.frames.last_mut() {
-            Some(Frame::Continuation { continuation, .. }) => {
+            Some(Frame::Continuation {
+                continuation,
+                tail_call,
+                ..
+            }) => {
                 let continuation = continuation.take().expect("continuation missing");
+                let tail_call = *tail_call;
                 let ret = continuation.call(Ok(res));
                 state.frames.pop();
-                callback_return(thread, state, mc, ret);
+                callback_return(thread, state, mc, ret, tail_call);
             }
             Some(Frame::Lua { .. }) => {
                 return_to_lua(state, &res);
+                recycle_buffer(state, res);
             }
             None => {
-                state.result = Some(Ok(res));
+                state.result = Some(Ok(res.into_vec()));
             }
             _ => panic!("frame above callback must be continuation or lua frame"),
         },
@@ -912,12 +1426,14 @@ fn return_ext<'gc>(
             args,
             continuation,
         }) => {
-            let bottom = state.values.len();
             state.frames.push(Frame::Continuation {
                 continuation: Some(continuation),
-                bottom,
+                bottom: state.registers_top,
+                varargs_bottom: state.varargs.len(),
+                tail_call,
             });
             ext_call_function(thread, state, mc, function, &args);
+            recycle_buffer(state, args);
         }
     }
 }
@@ -927,13 +1443,17 @@ fn callback_return<'gc>(
     state: &mut ThreadState<'gc>,
     mc: MutationContext<'gc, '_>,
     ret: CallbackReturn<'gc>,
+    tail_call: bool,
 ) {
     match ret {
         CallbackReturn::Immediate(ret) => {
-            return_ext(thread, state, mc, ret);
+            return_ext(thread, state, mc, ret, tail_call);
         }
         CallbackReturn::Sequence(seq) => {
-            state.frames.push(Frame::Callback(Some(seq)));
+            state.frames.push(Frame::Callback {
+                sequence: Some(seq),
+                tail_call,
+            });
         }
     }
 }
@@ -948,7 +1468,81 @@ fn close_upvalues<'gc>(
         let mut upval = upval.0.write(mc);
         if let UpValueState::Open(upvalue_thread, ind) = *upval {
             assert!(upvalue_thread == thread);
-            *upval = UpValueState::Closed(state.values[ind]);
+            *upval = UpValueState::Closed(state.registers[ind]);
         }
     }
 }
+
+// Removes every to-be-closed variable at or above the given register index, returning their values in
+// the order they were marked.
+fn take_to_be_closed<'gc>(state: &mut ThreadState<'gc>, bottom: usize) -> Vec<Value<'gc>> {
+    let split = state
+        .to_be_closed
+        .iter()
+        .position(|&i| i >= bottom)
+        .unwrap_or(state.to_be_closed.len());
+    let registers = &state.registers;
+    state
+        .to_be_closed
+        .split_off(split)
+        .into_iter()
+        .map(|i| registers[i])
+        .collect()
+}
+
+// Calls the `__close` metamethods of the given to-be-closed values, most recently marked first,
+// then finishes with the given result as though it were returned from a callback.  Each `__close`
+// metamethod is passed the current error, if any, and an error raised by a `__close` metamethod
+// replaces the current result.
+fn close_variables<'gc>(
+    thread: Thread<'gc>,
+    state: &mut ThreadState<'gc>,
+    mc: MutationContext<'gc, '_>,
+    closing: Vec<Value<'gc>>,
+    res: Result<ValueBuffer<'gc>, Error<'gc>>,
+) {
+    let ret = close_next(mc, closing, res);
+    return_ext(thread, state, mc, ret, false);
+}
+
+fn close_next<'gc>(
+    mc: MutationContext<'gc, '_>,
+    mut closing: Vec<Value<'gc>>,
+    res: Result<ValueBuffer<'gc>, Error<'gc>>,
+) -> Result<CallbackResult<'gc>, Error<'gc>> {
+    let value = match closing.pop() {
+        Some(value) => value,
+        None => return res.map(CallbackResult::Return),
+    };
+
+    let error = match &res {
+        Ok(_) => Value::Nil,
+        Err(err) => match err.inner() {
+            Error::RuntimeError(err) => err.0,
+            err => Value::String(String::new(mc, err.to_string().as_bytes())),
+        },
+    };
+
+    match meta_ops::close(value, error) {
+        Ok(call) => Ok(CallbackResult::TailCall {
+            function: call.function,
+            args: ValueBuffer::from(call.args.to_vec()),
+            continuation: Continuation::new_sequence_with(
+                (closing, res),
+                |(closing, res), close_res| {
+                    Ok(sequence::from_fn_with(
+                        (closing, res, close_res),
+                        |mc, (closing, res, close_res)| {
+                            let res = match close_res {
+                                Ok(_) => res,
+                                Err(err) => Err(err),
+                            };
+                            close_next(mc, closing, res)
+                        },
+                    ))
+                },
+            ),
+        }),
+        Err(err) => close_next(mc, closing, Err(err.into())),
+    }
+}
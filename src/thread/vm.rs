@@ -1,26 +1,31 @@
 use gc_arena::{Gc, MutationContext};
 
 use crate::{
-    thread::LuaFrame, BinaryOperatorError, Closure, ClosureState, Error, Function, OpCode,
-    RegisterIndex, String, Table, TypeError, UpValueDescriptor, Value, VarCount,
+    meta_ops::{self, MetaMethod, MetaResult},
+    thread::{LuaFrame, MetaReturn},
+    BinaryOperatorError, Closure, ClosureState, Error, Function, OpCode, RegisterIndex, Table,
+    UpValueDescriptor, Value, VarCount,
 };
 
-// Runs the VM for the given number of instructions or until the current LuaFrame may have been
-// changed.  Returns the number of instructions that were not run, or 0 if all requested
+// Runs the VM for the given number of instructions, until the current LuaFrame may have been changed,
+// or until the arena has allocated more than `memory_limit` bytes.  Returns the number of instructions that were not run, or 0 if all requested
 // instructions were run.
 pub(crate) fn run_vm<'gc>(
     mc: MutationContext<'gc, '_>,
     mut lua_frame: LuaFrame<'gc, '_>,
     mut instructions: u32,
+    memory_limit: usize,
 ) -> Result<u32, Error<'gc>> {
     assert_ne!(instructions, 0);
 
     let current_function = lua_frame.closure();
+    let metatables = lua_frame.metatables();
     let mut registers = lua_frame.registers();
 
     loop {
         let op = current_function.0.proto.opcodes[*registers.pc];
         *registers.pc += 1;
+        instructions -= 1;
 
         match op {
             OpCode::Move { dest, source } => {
@@ -54,96 +59,199 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::GetTableR { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.stack_frame[table.0 as usize])?
-                        .get(registers.stack_frame[key.0 as usize]);
+                let table = registers.stack_frame[table.0 as usize];
+                let key = registers.stack_frame[key.0 as usize];
+                match meta_ops::index(metatables, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(dest),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetTableC { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.stack_frame[table.0 as usize])?
-                        .get(current_function.0.proto.constants[key.0 as usize].to_value())
+                let table = registers.stack_frame[table.0 as usize];
+                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                match meta_ops::index(metatables, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(dest),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SetTableRR { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    registers.stack_frame[key.0 as usize],
-                    registers.stack_frame[value.0 as usize],
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = registers.stack_frame[key.0 as usize];
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
+                    )?;
+                    break;
+                }
             }
 
             OpCode::SetTableRC { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    registers.stack_frame[key.0 as usize],
-                    current_function.0.proto.constants[value.0 as usize].to_value(),
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = registers.stack_frame[key.0 as usize];
+                let value = current_function.0.proto.constants[value.0 as usize].to_value();
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
+                    )?;
+                    break;
+                }
             }
 
             OpCode::SetTableCR { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    current_function.0.proto.constants[key.0 as usize].to_value(),
-                    registers.stack_frame[value.0 as usize],
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
+                    )?;
+                    break;
+                }
             }
 
             OpCode::SetTableCC { table, key, value } => {
-                get_table(registers.stack_frame[table.0 as usize])?.set(
-                    mc,
-                    current_function.0.proto.constants[key.0 as usize].to_value(),
-                    current_function.0.proto.constants[value.0 as usize].to_value(),
-                )?;
+                let table = registers.stack_frame[table.0 as usize];
+                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let value = current_function.0.proto.constants[value.0 as usize].to_value();
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
+                    )?;
+                    break;
+                }
             }
 
             OpCode::GetUpTableR { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] = get_table(
-                    registers.get_upvalue(current_function.0.upvalues[table.0 as usize]),
-                )?
-                .get(registers.stack_frame[key.0 as usize]);
+                let table = registers.get_upvalue(current_function.0.upvalues[table.0 as usize]);
+                let key = registers.stack_frame[key.0 as usize];
+                match meta_ops::index(metatables, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(dest),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetUpTableC { dest, table, key } => {
-                registers.stack_frame[dest.0 as usize] =
-                    get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                        .get(current_function.0.proto.constants[key.0 as usize].to_value())
+                let table = registers.get_upvalue(current_function.0.upvalues[table.0 as usize]);
+                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                match meta_ops::index(metatables, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(dest),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SetUpTableRR { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
+                let table = registers.get_upvalue(current_function.0.upvalues[table.0 as usize]);
+                let key = registers.stack_frame[key.0 as usize];
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
                         mc,
-                        registers.stack_frame[key.0 as usize],
-                        registers.stack_frame[value.0 as usize],
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
                     )?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableRC { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
+                let table = registers.get_upvalue(current_function.0.upvalues[table.0 as usize]);
+                let key = registers.stack_frame[key.0 as usize];
+                let value = current_function.0.proto.constants[value.0 as usize].to_value();
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
                         mc,
-                        registers.stack_frame[key.0 as usize],
-                        current_function.0.proto.constants[value.0 as usize].to_value(),
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
                     )?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableCR { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
+                let table = registers.get_upvalue(current_function.0.upvalues[table.0 as usize]);
+                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let value = registers.stack_frame[value.0 as usize];
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
                         mc,
-                        current_function.0.proto.constants[key.0 as usize].to_value(),
-                        registers.stack_frame[value.0 as usize],
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
                     )?;
+                    break;
+                }
             }
 
             OpCode::SetUpTableCC { table, key, value } => {
-                get_table(registers.get_upvalue(current_function.0.upvalues[table.0 as usize]))?
-                    .set(
+                let table = registers.get_upvalue(current_function.0.upvalues[table.0 as usize]);
+                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let value = current_function.0.proto.constants[value.0 as usize].to_value();
+                if let Some(call) = meta_ops::new_index(mc, table, key, value)? {
+                    lua_frame.call_meta_function(
                         mc,
-                        current_function.0.proto.constants[key.0 as usize].to_value(),
-                        current_function.0.proto.constants[value.0 as usize].to_value(),
+                        call.function,
+                        &call.args,
+                        MetaReturn::None,
                     )?;
+                    break;
+                }
             }
 
             OpCode::Call {
@@ -174,10 +282,27 @@ pub(crate) fn run_vm<'gc>(
                 offset,
                 close_upvalues,
             } => {
-                *registers.pc = add_offset(*registers.pc, offset);
                 if let Some(r) = close_upvalues.to_u8() {
                     registers.close_upvalues(mc, RegisterIndex(r));
+                    if let Some(value) = registers.take_to_be_closed(RegisterIndex(r)) {
+                        // Run this jump again once the `__close` metamethod returns, closing any
+                        // remaining to-be-closed variables before the jump takes place.
+                        *registers.pc -= 1;
+                        let call = meta_ops::close(value, Value::Nil)?;
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::None,
+                        )?;
+                        break;
+                    }
                 }
+                *registers.pc = add_offset(*registers.pc, offset);
+            }
+
+            OpCode::ToBeClosed { value } => {
+                registers.mark_to_be_closed(value)?;
             }
 
             OpCode::Test { value, is_true } => {
@@ -292,16 +417,42 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::SelfR { base, table, key } => {
                 let table = registers.stack_frame[table.0 as usize];
-                let key = current_function.0.proto.constants[key.0 as usize].to_value();
+                let key = registers.stack_frame[key.0 as usize];
                 registers.stack_frame[base.0 as usize + 1] = table;
-                registers.stack_frame[base.0 as usize] = get_table(table)?.get(key);
+                match meta_ops::index(metatables, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[base.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(base),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::SelfC { base, table, key } => {
                 let table = registers.stack_frame[table.0 as usize];
                 let key = current_function.0.proto.constants[key.0 as usize].to_value();
                 registers.stack_frame[base.0 as usize + 1] = table;
-                registers.stack_frame[base.0 as usize] = get_table(table)?.get(key);
+                match meta_ops::index(metatables, table, key)? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[base.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(base),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::Concat {
@@ -309,14 +460,23 @@ pub(crate) fn run_vm<'gc>(
                 source,
                 count,
             } => {
-                registers.stack_frame[dest.0 as usize] = Value::String(
-                    String::concat(
-                        mc,
-                        &registers.stack_frame
-                            [source.0 as usize..source.0 as usize + count as usize],
-                    )
-                    .unwrap(),
-                );
+                match meta_ops::concat(
+                    mc,
+                    &registers.stack_frame[source.0 as usize..source.0 as usize + count as usize],
+                )? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(dest),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::GetUpValue { source, dest } => {
@@ -333,8 +493,20 @@ pub(crate) fn run_vm<'gc>(
             }
 
             OpCode::Length { dest, source } => {
-                registers.stack_frame[dest.0 as usize] =
-                    Value::Integer(get_table(registers.stack_frame[source.0 as usize])?.length());
+                match meta_ops::len(registers.stack_frame[source.0 as usize])? {
+                    MetaResult::Value(v) => {
+                        registers.stack_frame[dest.0 as usize] = v;
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::Register(dest),
+                        )?;
+                        break;
+                    }
+                }
             }
 
             OpCode::EqRR {
@@ -344,8 +516,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -356,8 +541,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -368,8 +566,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -380,8 +591,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left == right) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::equal(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -392,8 +616,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -404,8 +641,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -416,8 +666,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -428,8 +691,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left.less_than(right).ok_or(BinaryOperatorError::LessThan)?) == skip_if {
-                    *registers.pc += 1;
+                match meta_ops::less_than(left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -440,12 +716,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(mc, left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -456,12 +741,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(mc, left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -472,12 +766,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(mc, left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -488,12 +791,21 @@ pub(crate) fn run_vm<'gc>(
             } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                if (left
-                    .less_equal(right)
-                    .ok_or(BinaryOperatorError::LessEqual)?)
-                    == skip_if
-                {
-                    *registers.pc += 1;
+                match meta_ops::less_equal(mc, left, right)? {
+                    MetaResult::Value(v) => {
+                        if v.to_bool() == skip_if {
+                            *registers.pc += 1;
+                        }
+                    }
+                    MetaResult::Call(call) => {
+                        lua_frame.call_meta_function(
+                            mc,
+                            call.function,
+                            &call.args,
+                            MetaReturn::SkipIf(skip_if),
+                        )?;
+                        break;
+                    }
                 }
             }
 
@@ -504,394 +816,861 @@ pub(crate) fn run_vm<'gc>(
 
             OpCode::Minus { dest, source } => {
                 let value = registers.stack_frame[source.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    value.negate().ok_or(BinaryOperatorError::UnaryNegate)?;
+                if let Some(res) = value.negate() {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Unm, value, value)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitNot { dest, source } => {
                 let value = registers.stack_frame[source.0 as usize];
-                registers.stack_frame[dest.0 as usize] = value
-                    .bitwise_not()
-                    .ok_or(BinaryOperatorError::BitNot)?;
+                if let Some(res) = value.bitwise_not() {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BNot, value, value)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::AddRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                if let Some(res) = left.add(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Add, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::AddRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                if let Some(res) = left.add(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Add, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::AddCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                if let Some(res) = left.add(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Add, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::AddCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.add(right).ok_or(BinaryOperatorError::Add)?;
+                if let Some(res) = left.add(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Add, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::SubRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Add)?;
+                if let Some(res) = left.subtract(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Sub, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::SubRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                if let Some(res) = left.subtract(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Sub, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::SubCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                if let Some(res) = left.subtract(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Sub, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::SubCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.subtract(right).ok_or(BinaryOperatorError::Subtract)?;
+                if let Some(res) = left.subtract(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Sub, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::MulRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                if let Some(res) = left.multiply(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mul, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::MulRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                if let Some(res) = left.multiply(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mul, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::MulCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                if let Some(res) = left.multiply(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mul, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::MulCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.multiply(right).ok_or(BinaryOperatorError::Multiply)?;
+                if let Some(res) = left.multiply(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mul, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::DivRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                if let Some(res) = left.float_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Div, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::DivRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                if let Some(res) = left.float_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Div, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::DivCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                if let Some(res) = left.float_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Div, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::DivCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .float_divide(right)
-                    .ok_or(BinaryOperatorError::FloatDivide)?;
+                if let Some(res) = left.float_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Div, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::IDivRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                if let Some(res) = left.floor_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::IDiv, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::IDivRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                if let Some(res) = left.floor_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::IDiv, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::IDivCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                if let Some(res) = left.floor_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::IDiv, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::IDivCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .floor_divide(right)
-                    .ok_or(BinaryOperatorError::FloorDivide)?;
+                if let Some(res) = left.floor_divide(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::IDiv, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ModRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                if let Some(res) = left.modulo(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mod, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ModRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                if let Some(res) = left.modulo(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mod, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ModCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                if let Some(res) = left.modulo(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mod, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ModCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.modulo(right).ok_or(BinaryOperatorError::Modulo)?;
+                if let Some(res) = left.modulo(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Mod, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::PowRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                if let Some(res) = left.exponentiate(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Pow, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::PowRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                if let Some(res) = left.exponentiate(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Pow, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::PowCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                if let Some(res) = left.exponentiate(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Pow, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::PowCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .exponentiate(right)
-                    .ok_or(BinaryOperatorError::Exponentiate)?;
+                if let Some(res) = left.exponentiate(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Pow, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitAndRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                if let Some(res) = left.bitwise_and(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BAnd, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitAndRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                if let Some(res) = left.bitwise_and(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BAnd, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitAndCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                if let Some(res) = left.bitwise_and(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BAnd, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitAndCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_and(right).ok_or(BinaryOperatorError::BitAnd)?;
+                if let Some(res) = left.bitwise_and(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BAnd, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitOrRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                if let Some(res) = left.bitwise_or(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BOr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitOrRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                if let Some(res) = left.bitwise_or(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BOr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitOrCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                if let Some(res) = left.bitwise_or(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BOr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitOrCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_or(right).ok_or(BinaryOperatorError::BitOr)?;
+                if let Some(res) = left.bitwise_or(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BOr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitXorRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                if let Some(res) = left.bitwise_xor(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BXor, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitXorRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                if let Some(res) = left.bitwise_xor(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BXor, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitXorCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                if let Some(res) = left.bitwise_xor(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BXor, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::BitXorCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] =
-                    left.bitwise_xor(right).ok_or(BinaryOperatorError::BitXor)?;
+                if let Some(res) = left.bitwise_xor(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::BXor, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftLeftRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                if let Some(res) = left.shift_left(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shl, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftLeftRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                if let Some(res) = left.shift_left(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shl, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftLeftCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                if let Some(res) = left.shift_left(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shl, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftLeftCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_left(right)
-                    .ok_or(BinaryOperatorError::ShiftLeft)?;
+                if let Some(res) = left.shift_left(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shl, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftRightRR { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                if let Some(res) = left.shift_right(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftRightRC { dest, left, right } => {
                 let left = registers.stack_frame[left.0 as usize];
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                if let Some(res) = left.shift_right(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftRightCR { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = registers.stack_frame[right.0 as usize];
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                if let Some(res) = left.shift_right(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
 
             OpCode::ShiftRightCC { dest, left, right } => {
                 let left = current_function.0.proto.constants[left.0 as usize].to_value();
                 let right = current_function.0.proto.constants[right.0 as usize].to_value();
-                registers.stack_frame[dest.0 as usize] = left
-                    .shift_right(right)
-                    .ok_or(BinaryOperatorError::ShiftRight)?;
+                if let Some(res) = left.shift_right(right) {
+                    registers.stack_frame[dest.0 as usize] = res;
+                } else {
+                    let call = meta_ops::arithmetic(MetaMethod::Shr, left, right)?;
+                    lua_frame.call_meta_function(
+                        mc,
+                        call.function,
+                        &call.args,
+                        MetaReturn::Register(dest),
+                    )?;
+                    break;
+                }
             }
         }
 
-        if instructions == 0 {
+        if instructions == 0 || mc.total_allocated() > memory_limit {
             break;
-        } else {
-            instructions -= 1
         }
     }
 
     Ok(instructions)
 }
 
-fn get_table<'gc>(value: Value<'gc>) -> Result<Table<'gc>, TypeError> {
-    match value {
-        Value::Table(t) => Ok(t),
-        val => Err(TypeError {
-            expected: "table",
-            found: val.type_name(),
-        }),
-    }
-}
-
 fn add_offset(pc: usize, offset: i16) -> usize {
     if offset > 0 {
         pc.checked_add(offset as usize).unwrap()
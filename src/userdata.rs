@@ -0,0 +1,148 @@
+use std::any::Any;
+use std::cell::{Ref, RefMut};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use gc_arena::{Collect, CollectionContext, GcCell, MutationContext};
+
+use crate::Table;
+
+/// A Lua userdata value, holding an arbitrary `'static` Rust value along with an optional
+/// metatable.
+///
+/// Since the held value is `'static`, it cannot hold `Gc` pointers.  Any garbage collected values
+/// associated with a userdata should instead be reachable through its metatable.
+#[derive(Clone, Copy, Collect)]
+#[collect(require_copy)]
+pub struct UserData<'gc>(pub GcCell<'gc, UserDataState<'gc>>);
+
+pub struct UserDataState<'gc> {
+    data: Box<dyn Any>,
+    metatable: Option<Table<'gc>>,
+    // Whether this userdata is currently registered with `Finalizers`
+    pub(crate) finalizer_registered: bool,
+}
+
+unsafe impl<'gc> Collect for UserDataState<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        self.metatable.trace(cc);
+    }
+}
+
+impl<'gc> Debug for UserData<'gc> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_tuple("UserData").field(&self.0.as_ptr()).finish()
+    }
+}
+
+impl<'gc> PartialEq for UserData<'gc> {
+    fn eq(&self, other: &UserData<'gc>) -> bool {
+        GcCell::ptr_eq(self.0, other.0)
+    }
+}
+
+impl<'gc> Eq for UserData<'gc> {}
+
+impl<'gc> Hash for UserData<'gc> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        GcCell::as_ptr(self.0).hash(state)
+    }
+}
+
+impl<'gc> UserData<'gc> {
+    pub fn new<T: 'static>(mc: MutationContext<'gc, '_>, data: T) -> UserData<'gc> {
+        UserData(GcCell::allocate(
+            mc,
+            UserDataState {
+                data: Box::new(data),
+                metatable: None,
+                finalizer_registered: false,
+            },
+        ))
+    }
+
+    /// Returns whether the held value is of type `T`.
+    pub fn is<T: 'static>(&self) -> bool {
+        self.0.read().data.is::<T>()
+    }
+
+    /// Borrows the held value if it is of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the held value is currently mutably borrowed.
+    pub fn read<'a, T: 'static>(&'a self) -> Option<Ref<'a, T>> {
+        let state = self.0.read();
+        if state.data.is::<T>() {
+            Some(Ref::map(state, |state| state.data.downcast_ref().unwrap()))
+        } else {
+            None
+        }
+    }
+
+    /// Mutably borrows the held value if it is of type `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the held value is currently borrowed.
+    pub fn write<'a, T: 'static>(&'a self, mc: MutationContext<'gc, '_>) -> Option<RefMut<'a, T>> {
+        let state = self.0.write(mc);
+        if state.data.is::<T>() {
+            Some(RefMut::map(state, |state| {
+                state.data.downcast_mut().unwrap()
+            }))
+        } else {
+            None
+        }
+    }
+
+    // Moves the held value out of the userdata if it is of type `T` and calls `f` with it, putting
+    // it back once `f` returns.  Unlike `write`, the userdata is not borrowed while `f` runs, and
+    // simply does not hold a `T` until then.
+    pub(crate) fn with_taken<T: 'static, R>(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        // Held in place of a value that has been moved out
+        struct Taken;
+
+        struct Restore<'gc, 'a> {
+            user_data: UserData<'gc>,
+            mc: MutationContext<'gc, 'a>,
+            data: Box<dyn Any>,
+        }
+
+        impl<'gc, 'a> Drop for Restore<'gc, 'a> {
+            fn drop(&mut self) {
+                let data = mem::replace(&mut self.data, Box::new(Taken));
+                self.user_data.0.write(self.mc).data = data;
+            }
+        }
+
+        if !self.is::<T>() {
+            return None;
+        }
+        let data = mem::replace(&mut self.0.write(mc).data, Box::new(Taken));
+        let mut restore = Restore {
+            user_data: *self,
+            mc,
+            data,
+        };
+        Some(f(restore.data.downcast_mut().unwrap()))
+    }
+
+    pub fn metatable(&self) -> Option<Table<'gc>> {
+        self.0.read().metatable
+    }
+
+    /// Sets the metatable for this userdata, returning the previous metatable.
+    pub fn set_metatable(
+        &self,
+        mc: MutationContext<'gc, '_>,
+        metatable: Option<Table<'gc>>,
+    ) -> Option<Table<'gc>> {
+        mem::replace(&mut self.0.write(mc).metatable, metatable)
+    }
+}
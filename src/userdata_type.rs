@@ -0,0 +1,387 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use gc_arena::{Collect, CollectionContext, GcCell, MutationContext};
+use gc_sequence as sequence;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Callback, CallbackResult, Continuation, Error, Finalizers, MetaMethod, RuntimeError, String,
+    Table, TypeError, UserData, Value, ValueBuffer,
+};
+
+/// A Rust type that may be exposed to Lua as userdata with methods, fields and metamethods.
+///
+/// The methods, fields and metamethods of a type are declared once in `UserDataType::add_methods`,
+/// and are used to build a single metatable that is shared by every userdata of that type created
+/// through `UserDataRegistry::create`.  Every generated method checks that it was called with a
+/// userdata of the right type as its first argument.
+pub trait UserDataType: 'static + Sized {
+    /// The name of this type, used in error messages.
+    const NAME: &'static str;
+
+    fn add_methods<'gc>(_methods: &mut UserDataMethods<'gc, '_, Self>) {}
+}
+
+/// Declares the methods, fields and metamethods of a `UserDataType`.
+///
+/// Method arguments do not include the userdata itself, and methods return the values to be
+/// returned to Lua.
+pub struct UserDataMethods<'gc, 'a, T> {
+    mc: MutationContext<'gc, 'a>,
+    methods: Table<'gc>,
+    getters: Option<Table<'gc>>,
+    setters: Option<Table<'gc>>,
+    metatable: Table<'gc>,
+    _type: PhantomData<T>,
+}
+
+impl<'gc, 'a, T: UserDataType> UserDataMethods<'gc, 'a, T> {
+    /// Adds a method, callable from Lua as `value:name(...)`.
+    ///
+    /// The userdata is borrowed while the method runs, so if it is also passed as an argument it
+    /// may be read with `UserData::read`, but not written.
+    pub fn add_method<F>(&mut self, name: &'static str, method: F)
+    where
+        F: 'static
+            + Fn(
+                MutationContext<'gc, '_>,
+                &T,
+                ValueBuffer<'gc>,
+            ) -> Result<ValueBuffer<'gc>, Error<'gc>>,
+    {
+        let method = method_callback(self.mc, method);
+        self.methods
+            .set(self.mc, String::new_static(name.as_bytes()), method)
+            .unwrap();
+    }
+
+    /// Adds a method which may mutate the held value.
+    ///
+    /// The value is moved out of the userdata rather than borrowed while the method runs, so if the
+    /// same userdata is also passed as an argument, as in `v:add(v)`, it will not hold a `T` until
+    /// the method returns.
+    pub fn add_method_mut<F>(&mut self, name: &'static str, method: F)
+    where
+        F: 'static
+            + Fn(
+                MutationContext<'gc, '_>,
+                &mut T,
+                ValueBuffer<'gc>,
+            ) -> Result<ValueBuffer<'gc>, Error<'gc>>,
+    {
+        let method = method_mut_callback(self.mc, method);
+        self.methods
+            .set(self.mc, String::new_static(name.as_bytes()), method)
+            .unwrap();
+    }
+
+    /// Adds a field that can be read from Lua as `value.name`.
+    pub fn add_field_getter<F>(&mut self, name: &'static str, getter: F)
+    where
+        F: 'static + Fn(MutationContext<'gc, '_>, &T) -> Result<Value<'gc>, Error<'gc>>,
+    {
+        let mc = self.mc;
+        let getter = method_callback(mc, move |mc, this: &T, args: ValueBuffer<'gc>| {
+            Ok(args.with_values(&[getter(mc, this)?]))
+        });
+        self.getters
+            .get_or_insert_with(|| Table::new(mc))
+            .set(self.mc, String::new_static(name.as_bytes()), getter)
+            .unwrap();
+    }
+
+    /// Adds a field that can be assigned from Lua as `value.name = x`.
+    pub fn add_field_setter<F>(&mut self, name: &'static str, setter: F)
+    where
+        F: 'static + Fn(MutationContext<'gc, '_>, &mut T, Value<'gc>) -> Result<(), Error<'gc>>,
+    {
+        let mc = self.mc;
+        let setter = method_mut_callback(mc, move |mc, this: &mut T, args| {
+            setter(mc, this, args.first().cloned().unwrap_or(Value::Nil))?;
+            Ok(args.with_values(&[]))
+        });
+        self.setters
+            .get_or_insert_with(|| Table::new(mc))
+            .set(self.mc, String::new_static(name.as_bytes()), setter)
+            .unwrap();
+    }
+
+    /// Adds a metamethod.  Like other methods, the userdata must be the first argument, so for
+    /// binary operators this is only called when the userdata is the left operand.
+    ///
+    /// An `__index` or `__newindex` metamethod is only called for keys which are not methods or
+    /// fields.
+    pub fn add_meta_method<F>(&mut self, method: MetaMethod, function: F)
+    where
+        F: 'static
+            + Fn(
+                MutationContext<'gc, '_>,
+                &T,
+                ValueBuffer<'gc>,
+            ) -> Result<ValueBuffer<'gc>, Error<'gc>>,
+    {
+        let function = method_callback(self.mc, function);
+        self.metatable.set(self.mc, method, function).unwrap();
+    }
+
+    /// Adds a metamethod which may mutate the held value.
+    pub fn add_meta_method_mut<F>(&mut self, method: MetaMethod, function: F)
+    where
+        F: 'static
+            + Fn(
+                MutationContext<'gc, '_>,
+                &mut T,
+                ValueBuffer<'gc>,
+            ) -> Result<ValueBuffer<'gc>, Error<'gc>>,
+    {
+        let function = method_mut_callback(self.mc, function);
+        self.metatable.set(self.mc, method, function).unwrap();
+    }
+}
+
+/// Creates userdata for `UserDataType`s, and holds the metatable that is generated for each type.
+#[derive(Clone, Copy, Collect)]
+#[collect(require_copy)]
+pub struct UserDataRegistry<'gc>(GcCell<'gc, UserDataRegistryState<'gc>>);
+
+pub struct UserDataRegistryState<'gc> {
+    finalizers: Finalizers<'gc>,
+    metatables: FxHashMap<TypeId, Table<'gc>>,
+}
+
+unsafe impl<'gc> Collect for UserDataRegistryState<'gc> {
+    fn trace(&self, cc: CollectionContext) {
+        self.finalizers.trace(cc);
+        for metatable in self.metatables.values() {
+            metatable.trace(cc);
+        }
+    }
+}
+
+impl<'gc> UserDataRegistry<'gc> {
+    /// Userdata with a `__gc` metamethod are registered with the given `Finalizers`.
+    pub fn new(mc: MutationContext<'gc, '_>, finalizers: Finalizers<'gc>) -> UserDataRegistry<'gc> {
+        UserDataRegistry(GcCell::allocate(
+            mc,
+            UserDataRegistryState {
+                finalizers,
+                metatables: FxHashMap::default(),
+            },
+        ))
+    }
+
+    /// Creates a new userdata holding the given value, with the metatable generated for its type.
+    pub fn create<T: UserDataType>(&self, mc: MutationContext<'gc, '_>, data: T) -> UserData<'gc> {
+        let user_data = UserData::new(mc, data);
+        user_data.set_metatable(mc, Some(self.metatable::<T>(mc)));
+        self.0.read().finalizers.register_user_data(mc, user_data);
+        user_data
+    }
+
+    /// Returns the metatable for the given type, generating it if this is the first time it has
+    /// been requested.
+    pub fn metatable<T: UserDataType>(&self, mc: MutationContext<'gc, '_>) -> Table<'gc> {
+        if let Some(metatable) = self.0.read().metatables.get(&TypeId::of::<T>()) {
+            return *metatable;
+        }
+
+        let mut methods = UserDataMethods {
+            mc,
+            methods: Table::new(mc),
+            getters: None,
+            setters: None,
+            metatable: Table::new(mc),
+            _type: PhantomData,
+        };
+        T::add_methods(&mut methods);
+        let UserDataMethods {
+            methods,
+            getters,
+            setters,
+            metatable,
+            ..
+        } = methods;
+
+        // If there are no fields and no user provided `__index`, methods can be looked up
+        // directly without going through a callback.
+        let index = metatable.get(MetaMethod::Index);
+        if getters.is_none() && index == Value::Nil {
+            metatable.set(mc, MetaMethod::Index, methods).unwrap();
+        } else {
+            let getters = getters.unwrap_or_else(|| Table::new(mc));
+            metatable
+                .set(
+                    mc,
+                    MetaMethod::Index,
+                    index_callback::<T>(mc, methods, getters, index),
+                )
+                .unwrap();
+        }
+
+        let new_index = metatable.get(MetaMethod::NewIndex);
+        if setters.is_some() || new_index != Value::Nil {
+            let setters = setters.unwrap_or_else(|| Table::new(mc));
+            metatable
+                .set(
+                    mc,
+                    MetaMethod::NewIndex,
+                    new_index_callback::<T>(mc, setters, new_index),
+                )
+                .unwrap();
+        }
+
+        self.0
+            .write(mc)
+            .metatables
+            .insert(TypeId::of::<T>(), metatable);
+        metatable
+    }
+}
+
+// Returns the userdata in the first argument, or an error if it does not hold a `T`.
+fn check_self<'gc, T: UserDataType>(args: &[Value<'gc>]) -> Result<UserData<'gc>, Error<'gc>> {
+    match args.first().cloned().unwrap_or(Value::Nil) {
+        Value::UserData(user_data) if user_data.is::<T>() => Ok(user_data),
+        value => Err(TypeError {
+            expected: T::NAME,
+            found: value.type_name(),
+        }
+        .into()),
+    }
+}
+
+fn method_callback<'gc, T, F>(mc: MutationContext<'gc, '_>, method: F) -> Callback<'gc>
+where
+    T: UserDataType,
+    F: 'static
+        + Fn(MutationContext<'gc, '_>, &T, ValueBuffer<'gc>) -> Result<ValueBuffer<'gc>, Error<'gc>>,
+{
+    let method = Rc::new(method);
+    Callback::new_sequence(mc, move |args| {
+        let this = check_self::<T>(&args)?;
+        let method = method.clone();
+        Ok(sequence::from_fn_with(
+            (this, args),
+            move |mc, (this, mut args)| {
+                args.remove(0);
+                let ret = method(mc, &this.read::<T>().unwrap(), args)?;
+                Ok(CallbackResult::Return(ret))
+            },
+        ))
+    })
+}
+
+fn method_mut_callback<'gc, T, F>(mc: MutationContext<'gc, '_>, method: F) -> Callback<'gc>
+where
+    T: UserDataType,
+    F: 'static
+        + Fn(
+            MutationContext<'gc, '_>,
+            &mut T,
+            ValueBuffer<'gc>,
+        ) -> Result<ValueBuffer<'gc>, Error<'gc>>,
+{
+    let method = Rc::new(method);
+    Callback::new_sequence(mc, move |args| {
+        let this = check_self::<T>(&args)?;
+        let method = method.clone();
+        Ok(sequence::from_fn_with(
+            (this, args),
+            move |mc, (this, mut args)| {
+                args.remove(0);
+                let ret = this
+                    .with_taken::<T, _>(mc, |this| method(mc, this, args))
+                    .unwrap()?;
+                Ok(CallbackResult::Return(ret))
+            },
+        ))
+    })
+}
+
+// Looks up methods first, then field getters, and finally falls back to any user provided `__index`
+// metamethod.
+fn index_callback<'gc, T: UserDataType>(
+    mc: MutationContext<'gc, '_>,
+    methods: Table<'gc>,
+    getters: Table<'gc>,
+    fallback: Value<'gc>,
+) -> Callback<'gc> {
+    Callback::new_immediate_with(
+        mc,
+        (methods, getters, fallback),
+        |&(methods, getters, fallback), args| {
+            let this = Value::UserData(check_self::<T>(&args)?);
+            let key = args.get(1).cloned().unwrap_or(Value::Nil);
+            let method = methods.get(key);
+            if method != Value::Nil {
+                return Ok(CallbackResult::Return(args.with_values(&[method])));
+            }
+
+            match (getters.get(key), fallback) {
+                (Value::Function(getter), _) => Ok(CallbackResult::TailCall {
+                    function: getter,
+                    args: args.with_values(&[this]),
+                    continuation: Continuation::new_immediate(|res| {
+                        Ok(CallbackResult::Return(res?))
+                    }),
+                }),
+                (_, Value::Function(fallback)) => Ok(CallbackResult::TailCall {
+                    function: fallback,
+                    args,
+                    continuation: Continuation::new_immediate(|res| {
+                        Ok(CallbackResult::Return(res?))
+                    }),
+                }),
+                _ => Ok(CallbackResult::Return(args.with_values(&[Value::Nil]))),
+            }
+        },
+    )
+}
+
+// Looks up field setters, then falls back to any user provided `__newindex` metamethod, and
+// otherwise errors.
+fn new_index_callback<'gc, T: UserDataType>(
+    mc: MutationContext<'gc, '_>,
+    setters: Table<'gc>,
+    fallback: Value<'gc>,
+) -> Callback<'gc> {
+    Callback::new_sequence_with(mc, (setters, fallback), |&(setters, fallback), args| {
+        Ok(sequence::from_fn_with(
+            (setters, fallback, args),
+            |mc, (setters, fallback, args)| {
+                let this = Value::UserData(check_self::<T>(&args)?);
+                let key = args.get(1).cloned().unwrap_or(Value::Nil);
+                let value = args.get(2).cloned().unwrap_or(Value::Nil);
+                match (setters.get(key), fallback) {
+                    (Value::Function(setter), _) => Ok(CallbackResult::TailCall {
+                        function: setter,
+                        args: args.with_values(&[this, value]),
+                        continuation: Continuation::new_immediate(|res| {
+                            Ok(CallbackResult::Return(res?))
+                        }),
+                    }),
+                    (_, Value::Function(fallback)) => Ok(CallbackResult::TailCall {
+                        function: fallback,
+                        args,
+                        continuation: Continuation::new_immediate(|res| {
+                            Ok(CallbackResult::Return(res?))
+                        }),
+                    }),
+                    _ => {
+                        let mut message = b"cannot set field '".to_vec();
+                        if let Value::String(key) = key {
+                            message.extend(key.as_bytes());
+                        } else {
+                            message.extend(key.type_name().as_bytes());
+                        }
+                        message.extend(b"' of ");
+                        message.extend(T::NAME.as_bytes());
+                        Err(RuntimeError(Value::String(String::new(mc, &message))).into())
+                    }
+                }
+            },
+        ))
+    })
+}
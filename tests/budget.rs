@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, Budget, Closure, Error, Execution, Function, Lua, StaticError, ThreadSequence, Value,
+};
+
+fn start(lua: Lua, code: &'static str) -> Execution<Result<Option<i64>, StaticError>> {
+    lua.start(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile(mc, root.interned_strings, code.as_bytes())?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|ret| match ret.first() {
+            Some(Value::Integer(i)) => Some(*i),
+            _ => None,
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })
+}
+
+#[test]
+fn instruction_budget() -> Result<(), StaticError> {
+    let mut execution = start(
+        Lua::new(),
+        r#"
+            local sum = 0
+            for i = 1, 10000 do
+                sum = sum + i
+            end
+            return sum
+        "#,
+    );
+
+    let mut runs = 0;
+    let lua = loop {
+        runs += 1;
+        match execution.run(Budget::instructions(1000)) {
+            Ok((lua, res)) => {
+                assert_eq!(res?, Some(50005000));
+                break lua;
+            }
+            Err(e) => execution = e,
+        }
+    };
+    assert!(runs > 10);
+
+    // The `Lua` is usable again once the execution has finished.
+    let (_, res) = start(lua, "return 3").run(Budget::default()).ok().unwrap();
+    assert_eq!(res?, Some(3));
+
+    Ok(())
+}
+
+#[test]
+fn cancel_infinite_loop() -> Result<(), StaticError> {
+    let mut execution = start(Lua::new(), "while true do end");
+    for _ in 0..10 {
+        execution = match execution.run(Budget::instructions(10000)) {
+            Ok(_) => panic!("infinite loop finished"),
+            Err(e) => e,
+        };
+    }
+    execution = match execution.run(Budget::duration(Duration::from_millis(10))) {
+        Ok(_) => panic!("infinite loop finished"),
+        Err(e) => e,
+    };
+
+    let lua = execution.cancel();
+    let (_, res) = start(lua, "return 4").run(Budget::default()).ok().unwrap();
+    assert_eq!(res?, Some(4));
+
+    Ok(())
+}
+
+#[test]
+fn budget_in_coroutine() {
+    let execution = start(
+        Lua::new(),
+        r#"
+            local co = coroutine.create(function()
+                while true do end
+            end)
+            coroutine.resume(co)
+        "#,
+    );
+    assert!(execution.run(Budget::instructions(100000)).is_err());
+}
@@ -1,3 +1,6 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
 use luster::{
     compile, Callback, CallbackResult, Closure, Error, Function, Lua, StaticError, String,
@@ -9,10 +12,9 @@ fn callback() -> Result<(), Box<StaticError>> {
     let mut lua = Lua::new();
     lua.sequence(|root| {
         sequence::from_fn_with(root, |mc, root| {
-            let callback = Callback::new_immediate(mc, |args| {
-                let mut ret = args.to_vec();
-                ret.push(Value::Integer(42));
-                Ok(CallbackResult::Return(ret))
+            let callback = Callback::new_immediate(mc, |mut args| {
+                args.push(Value::Integer(42));
+                Ok(CallbackResult::Return(args))
             });
             root.globals
                 .set(mc, String::new_static(b"callback"), callback)?;
@@ -53,10 +55,9 @@ fn tail_call_trivial_callback() -> Result<(), Box<StaticError>> {
     let mut lua = Lua::new();
     lua.sequence(|root| {
         sequence::from_fn_with(root, |mc, root| {
-            let callback = Callback::new_immediate(mc, |args| {
-                let mut ret = args.to_vec();
-                ret.push(Value::Integer(3));
-                Ok(CallbackResult::Return(ret))
+            let callback = Callback::new_immediate(mc, |mut args| {
+                args.push(Value::Integer(3));
+                Ok(CallbackResult::Return(args))
             });
             root.globals
                 .set(mc, String::new_static(b"callback"), callback)?;
@@ -95,3 +96,63 @@ fn tail_call_trivial_callback() -> Result<(), Box<StaticError>> {
 
     Ok(())
 }
+
+#[test]
+fn callback_buffer_reuse() -> Result<(), Box<StaticError>> {
+    let buffers = Rc::new(Cell::new((None, 0)));
+
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        let buffers = buffers.clone();
+        sequence::from_fn_with(root, move |mc, root| {
+            let callback = Callback::new_immediate(mc, move |args| {
+                let (first, reused) = buffers.get();
+                match first {
+                    None => buffers.set((Some(args.as_ptr() as usize), reused)),
+                    Some(first) if first == args.as_ptr() as usize => {
+                        buffers.set((Some(first), reused + 1))
+                    }
+                    Some(_) => {}
+                }
+                let sum = args.iter().filter_map(|v| v.to_integer()).sum();
+                Ok(CallbackResult::Return(
+                    args.with_values(&[Value::Integer(sum)]),
+                ))
+            });
+            root.globals
+                .set(mc, String::new_static(b"callback"), callback)?;
+            Ok(())
+        })
+        .and_then_with(root, |mc, root, _| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    &br#"
+                        local sum = 0
+                        for i = 1, 10 do
+                            sum = callback(sum, i)
+                        end
+                        return sum == 55
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|b| assert_eq!(b, vec![Value::Boolean(true)]))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    assert_eq!(buffers.get().1, 9);
+    Ok(())
+}
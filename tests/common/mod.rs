@@ -0,0 +1,32 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{compile, Closure, Error, Function, Lua, StaticError, ThreadSequence, Value};
+
+// Compiles and runs the given code on the main thread, and returns its first return value.  Only
+// values that do not live in the arena may be returned, any other value is returned as nil.
+pub fn run(lua: &mut Lua, code: &'static str) -> Result<Value<'static>, StaticError> {
+    lua.sequence(move |root| {
+        sequence::from_fn_with(root, move |mc, root| {
+            Ok(Closure::new(
+                mc,
+                compile(mc, root.interned_strings, code.as_bytes())?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|ret| match ret.first() {
+            Some(Value::Boolean(b)) => Value::Boolean(*b),
+            Some(Value::Integer(i)) => Value::Integer(*i),
+            Some(Value::Number(n)) => Value::Number(*n),
+            _ => Value::Nil,
+        })
+        .map_err(Error::to_static)
+        .boxed()
+    })
+}
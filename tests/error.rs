@@ -1,5 +1,9 @@
 use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
-use luster::{compile, Closure, Error, Function, Lua, StaticError, ThreadSequence};
+use luster::{
+    compile, compile_named, Closure, CompilerError, Error, FrameKind, Function, FunctionName,
+    LexerErrorKind, LineNumber, Lua, ParserError, ParserErrorKind, Span, StaticError,
+    ThreadSequence, Traceback, TracebackFrame,
+};
 
 #[test]
 fn error_unwind() -> Result<(), Box<StaticError>> {
@@ -30,7 +34,7 @@ fn error_unwind() -> Result<(), Box<StaticError>> {
                 &[],
             )?
             .map(|res| match res {
-                Err(Error::RuntimeError(_)) => Ok(()),
+                Err(err) if matches!(err.inner(), Error::RuntimeError(_)) => Ok(()),
                 _ => panic!(),
             })
             .and_chain_with((root, closure), |mc, (root, closure), _| {
@@ -42,7 +46,7 @@ fn error_unwind() -> Result<(), Box<StaticError>> {
                 )?)
             })
             .map(|res| match res {
-                Err(Error::RuntimeError(_)) => Ok(()),
+                Err(err) if matches!(err.inner(), Error::RuntimeError(_)) => Ok(()),
                 _ => panic!(),
             }))
         })
@@ -52,3 +56,299 @@ fn error_unwind() -> Result<(), Box<StaticError>> {
 
     Ok(())
 }
+
+#[test]
+fn local_attribute_errors() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let compile_str = |source: &[u8]| compile(mc, root.interned_strings, source).map(|_| ());
+
+        match compile_str(b"local a <const> = 1; a = 2") {
+            Err(Error::CompilerError(err @ CompilerError::AssignToConst(_))) => {
+                assert_eq!(err.to_string(), "attempt to assign to const variable 'a'");
+            }
+            _ => panic!("assignment to const local was not an error"),
+        }
+        match compile_str(b"local a <close> = nil; local function f() a = 2 end") {
+            Err(Error::CompilerError(CompilerError::AssignToConst(_))) => {}
+            _ => panic!("assignment to close upvalue was not an error"),
+        }
+        match compile_str(b"local a <close>, b <close> = nil, nil") {
+            Err(Error::ParserError(ParserError {
+                kind: ParserErrorKind::MultipleToBeClosed,
+                ..
+            })) => {}
+            _ => panic!("multiple to-be-closed variables was not an error"),
+        }
+        match compile_str(b"local a <other> = 1") {
+            Err(Error::ParserError(ParserError {
+                kind: kind @ ParserErrorKind::UnknownAttribute(_),
+                ..
+            })) => {
+                assert_eq!(kind.to_string(), "unknown attribute 'other'");
+            }
+            _ => panic!("unknown attribute was not an error"),
+        }
+        assert!(compile_str(b"local a <const> = 1; do local a = 2; a = 3 end").is_ok());
+    });
+}
+
+#[test]
+fn parser_error_positions() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        match compile_named(
+            mc,
+            root.interned_strings,
+            b"config.lua",
+            &b"local a = 1\nlocal b = = 2\n"[..],
+        ) {
+            Err(Error::ParserError(err)) => {
+                assert!(matches!(err.kind, ParserErrorKind::Unexpected { .. }));
+                assert_eq!(
+                    err.span,
+                    Span {
+                        line: LineNumber(2),
+                        column: 11,
+                        offset: 22,
+                    }
+                );
+                assert!(err.to_string().starts_with("config.lua:2:11: "));
+            }
+            _ => panic!("unexpected token was not an error"),
+        }
+
+        match compile(mc, root.interned_strings, &b"return 1,\n  \"abc"[..]) {
+            Err(Error::ParserError(err)) => {
+                assert!(matches!(
+                    err.kind,
+                    ParserErrorKind::LexerError(LexerErrorKind::UnfinishedShortString(b'"'))
+                ));
+                assert_eq!(err.span.line, LineNumber(2));
+                assert_eq!(err.span.column, 7);
+                assert_eq!(err.span.offset, 16);
+                assert!(err.to_string().starts_with("?:2:7: "));
+            }
+            _ => panic!("unfinished string was not an error"),
+        }
+    });
+}
+
+#[test]
+fn error_traceback() {
+    let mut lua = Lua::new();
+    let err = lua
+        .sequence(|root| {
+            sequence::from_fn_with(root, |mc, root| {
+                Ok(Closure::new(
+                    mc,
+                    compile_named(
+                        mc,
+                        root.interned_strings,
+                        b"traceback.lua",
+                        &br#"
+                            local t = {}
+                            function t:method()
+                                error('test error')
+                            end
+
+                            function do_error()
+                                t:method()
+                            end
+
+                            if pcall(do_error) then
+                                return
+                            end
+                            do_error()
+                        "#[..],
+                    )?,
+                    Some(root.globals),
+                )?)
+            })
+            .and_chain_with(root, |mc, root, closure| {
+                Ok(ThreadSequence::call_function(
+                    mc,
+                    root.main_thread,
+                    Function::Closure(closure),
+                    &[],
+                )?)
+            })
+            .map_ok(|_| ())
+            .map_err(Error::to_static)
+            .boxed()
+        })
+        .unwrap_err();
+
+    let traceback = err.traceback().expect("error has no traceback");
+    assert_eq!(
+        traceback.frames,
+        vec![
+            TracebackFrame {
+                kind: FrameKind::Callback,
+                function: Some(FunctionName::Function("error".to_owned())),
+                chunk: None,
+                line: None,
+                tail_call: false,
+            },
+            TracebackFrame {
+                kind: FrameKind::Lua,
+                function: Some(FunctionName::Method("method".to_owned())),
+                chunk: Some("traceback.lua".to_owned()),
+                line: Some(LineNumber(4)),
+                tail_call: false,
+            },
+            TracebackFrame {
+                kind: FrameKind::Lua,
+                function: Some(FunctionName::Function("do_error".to_owned())),
+                chunk: Some("traceback.lua".to_owned()),
+                line: Some(LineNumber(8)),
+                tail_call: false,
+            },
+            TracebackFrame {
+                kind: FrameKind::Lua,
+                function: Some(FunctionName::MainChunk),
+                chunk: Some("traceback.lua".to_owned()),
+                line: Some(LineNumber(14)),
+                tail_call: false,
+            },
+        ]
+    );
+    assert!(matches!(err.inner(), StaticError::RuntimeError(_)));
+    assert!(err.to_string().ends_with(
+        "stack traceback:\n\t\
+         [callback]: in function 'error'\n\t\
+         traceback.lua:4: in method 'method'\n\t\
+         traceback.lua:8: in function 'do_error'\n\t\
+         traceback.lua:14: in main chunk"
+    ));
+}
+
+#[test]
+fn tail_call_traceback() {
+    let mut lua = Lua::new();
+    let err = lua
+        .sequence(|root| {
+            sequence::from_fn_with(root, |mc, root| {
+                Ok(Closure::new(
+                    mc,
+                    compile_named(
+                        mc,
+                        root.interned_strings,
+                        b"traceback.lua",
+                        &br#"
+                            local function cnt(...)
+                                return select(...)
+                            end
+
+                            local function count()
+                                local n = cnt('#', 1, 2)
+                                return n
+                            end
+                            count()
+                        "#[..],
+                    )?,
+                    Some(root.globals),
+                )?)
+            })
+            .and_chain_with(root, |mc, root, closure| {
+                Ok(ThreadSequence::call_function(
+                    mc,
+                    root.main_thread,
+                    Function::Closure(closure),
+                    &[],
+                )?)
+            })
+            .map_ok(|_| ())
+            .map_err(Error::to_static)
+            .boxed()
+        })
+        .unwrap_err();
+
+    let traceback = err.traceback().expect("error has no traceback");
+    assert_eq!(
+        traceback.frames,
+        vec![
+            TracebackFrame {
+                kind: FrameKind::Callback,
+                function: None,
+                chunk: None,
+                line: None,
+                tail_call: true,
+            },
+            TracebackFrame {
+                kind: FrameKind::Lua,
+                function: Some(FunctionName::Function("count".to_owned())),
+                chunk: Some("traceback.lua".to_owned()),
+                line: Some(LineNumber(7)),
+                tail_call: false,
+            },
+            TracebackFrame {
+                kind: FrameKind::Lua,
+                function: Some(FunctionName::MainChunk),
+                chunk: Some("traceback.lua".to_owned()),
+                line: Some(LineNumber(10)),
+                tail_call: false,
+            },
+        ]
+    );
+    assert!(err.to_string().ends_with(
+        "stack traceback:\n\t\
+         [callback]: in ?\n\t\
+         (...tail calls...)\n\t\
+         traceback.lua:7: in function 'count'\n\t\
+         traceback.lua:10: in main chunk"
+    ));
+}
+
+#[test]
+fn deep_error_traceback() {
+    let mut lua = Lua::new();
+    let err = lua
+        .sequence(|root| {
+            sequence::from_fn_with(root, |mc, root| {
+                Ok(Closure::new(
+                    mc,
+                    compile(
+                        mc,
+                        root.interned_strings,
+                        &br#"
+                            local recurse
+                            recurse = function(n)
+                                if n == 0 then
+                                    return nil + 1
+                                end
+                                recurse(n - 1)
+                            end
+                            recurse(100)
+                        "#[..],
+                    )?,
+                    Some(root.globals),
+                )?)
+            })
+            .and_chain_with(root, |mc, root, closure| {
+                Ok(ThreadSequence::call_function(
+                    mc,
+                    root.main_thread,
+                    Function::Closure(closure),
+                    &[],
+                )?)
+            })
+            .map_ok(|_| ())
+            .map_err(Error::to_static)
+            .boxed()
+        })
+        .unwrap_err();
+
+    let traceback = err.traceback().expect("error has no traceback");
+    assert_eq!(
+        traceback.frames.len(),
+        Traceback::HEAD_FRAMES + Traceback::TAIL_FRAMES
+    );
+    assert_eq!(traceback.skipped, 102 - traceback.frames.len());
+    assert_eq!(traceback.frames[0].line, Some(LineNumber(5)));
+    assert_eq!(traceback.frames[1].line, Some(LineNumber(7)));
+    assert_eq!(traceback.frames.last().unwrap().line, Some(LineNumber(9)));
+    assert!(err
+        .to_string()
+        .contains("\n\t...\t(skipping 81 levels)\n\t"));
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use luster::{Lua, StaticError, Value};
+
+use common::run;
+
+#[test]
+fn finalize_unreachable() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            finalized = 0
+            local mt = {__gc = function(t) finalized = finalized + t.n end}
+            setmetatable({n = 1}, mt)
+            setmetatable({n = 2}, mt)
+            kept = setmetatable({n = 4}, mt)
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 2);
+    lua.finalize()?;
+    assert_eq!(lua.pending_finalization(), 0);
+    assert_eq!(run(&mut lua, "return finalized")?, Value::Integer(3));
+
+    // Finalized objects are not finalized a second time
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 0);
+    assert_eq!(run(&mut lua, "return finalized")?, Value::Integer(3));
+
+    run(&mut lua, "kept = nil")?;
+    lua.collect_garbage();
+    lua.finalize()?;
+    assert_eq!(run(&mut lua, "return finalized")?, Value::Integer(7));
+
+    Ok(())
+}
+
+#[test]
+fn finalize_resurrect() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            setmetatable({inner = {value = 42}}, {__gc = function(t) resurrected = t end})
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    lua.finalize()?;
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 0);
+    assert_eq!(
+        run(&mut lua, "return resurrected.inner.value")?,
+        Value::Integer(42)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn finalize_late_gc_field() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            finalized = false
+            local mt = {}
+            setmetatable({}, mt)
+            mt.__gc = function() finalized = true end
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 0);
+    lua.finalize()?;
+    assert_eq!(run(&mut lua, "return finalized")?, Value::Boolean(false));
+
+    Ok(())
+}
+
+#[test]
+fn finalize_error() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            finalized = 0
+            setmetatable({}, {__gc = function() finalized = finalized + 1 end})
+            setmetatable({}, {__gc = function() error("finalizer error") end})
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 2);
+    assert!(lua.finalize().is_err());
+    assert_eq!(lua.pending_finalization(), 1);
+    lua.finalize()?;
+    assert_eq!(lua.pending_finalization(), 0);
+    assert_eq!(run(&mut lua, "return finalized")?, Value::Integer(1));
+
+    Ok(())
+}
+
+#[test]
+fn finalize_registered_during_collection() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            finalized = 0
+            for i = 1, 20000 do
+                setmetatable({}, {__gc = function() finalized = finalized + 1 end})
+            end
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    lua.finalize()?;
+    lua.collect_garbage();
+    lua.finalize()?;
+    assert_eq!(lua.pending_finalization(), 0);
+    assert_eq!(run(&mut lua, "return finalized")?, Value::Integer(20000));
+
+    Ok(())
+}
@@ -0,0 +1,258 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use luster::{
+    compile, Closure, Function, Hook, HookAction, HookEvent, HookMask, Lua, RuntimeError,
+    StaticError, String, ThreadMode, Value,
+};
+
+use common::run;
+
+#[test]
+fn hook_events() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let hook_events = events.clone();
+    lua.mutate(move |mc, root| {
+        root.main_thread.set_hook(
+            mc,
+            Some(Hook::new(
+                mc,
+                HookMask {
+                    call: true,
+                    ret: true,
+                    line: true,
+                    count: 0,
+                },
+                move |_, event, frame| {
+                    hook_events
+                        .borrow_mut()
+                        .push((event, frame.line.unwrap().0));
+                    Ok(HookAction::Continue)
+                },
+            )),
+        );
+    });
+
+    assert_eq!(
+        run(
+            &mut lua,
+            "local x = 1\n\
+             local function add(a, b)\n\
+                 return a + b\n\
+             end\n\
+             for i = 1, 2 do\n\
+                 x = add(x, i)\n\
+             end\n\
+             return x",
+        )?,
+        Value::Integer(4)
+    );
+
+    let line = |line| HookEvent::Line(luster::LineNumber(line));
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            (HookEvent::Call, 1),
+            (line(1), 1),
+            (line(2), 2),
+            (line(5), 5),
+            (line(6), 6),
+            (HookEvent::Call, 3),
+            (line(3), 3),
+            (HookEvent::Return, 3),
+            (line(5), 5),
+            (line(6), 6),
+            (HookEvent::Call, 3),
+            (line(3), 3),
+            (HookEvent::Return, 3),
+            (line(5), 5),
+            (line(8), 8),
+            (HookEvent::Return, 8),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn hook_tail_calls_and_callbacks() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let hook_events = events.clone();
+    lua.mutate(move |mc, root| {
+        root.main_thread.set_hook(
+            mc,
+            Some(Hook::new(
+                mc,
+                HookMask {
+                    call: true,
+                    ret: true,
+                    ..HookMask::default()
+                },
+                move |_, event, frame| {
+                    hook_events
+                        .borrow_mut()
+                        .push((event, frame.line.unwrap().0));
+                    Ok(HookAction::Continue)
+                },
+            )),
+        );
+    });
+
+    assert_eq!(
+        run(
+            &mut lua,
+            "local function g() return 1 end\n\
+             local function f() return g() end\n\
+             local x = f()\n\
+             local n = select(2, 1, 2)\n\
+             return x + n",
+        )?,
+        Value::Integer(3)
+    );
+
+    // The tail call from `f` to `g` fires no return event for `f`, and calling the `select`
+    // callback fires no events at all.
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            (HookEvent::Call, 1),
+            (HookEvent::Call, 2),
+            (HookEvent::Call, 1),
+            (HookEvent::Return, 1),
+            (HookEvent::Return, 5),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn hook_abort() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        root.main_thread.set_hook(
+            mc,
+            Some(Hook::new(
+                mc,
+                HookMask {
+                    count: 1000,
+                    ..HookMask::default()
+                },
+                |mc, _, _| {
+                    Err(RuntimeError(Value::String(String::new(mc, b"instruction limit"))).into())
+                },
+            )),
+        );
+    });
+
+    let err = run(&mut lua, "while true do end").unwrap_err();
+    assert_eq!(err.inner().to_string(), "runtime error: instruction limit");
+
+    // Errors from hooks cannot be caught by the code being interrupted
+    let err = run(
+        &mut lua,
+        r#"
+            local ok = pcall(function()
+                while true do end
+            end)
+            return ok
+        "#,
+    )
+    .unwrap_err();
+    assert_eq!(err.inner().to_string(), "runtime error: instruction limit");
+
+    // Coroutines inherit the hook of the thread that created them, so the hook also stops code
+    // running inside `coroutine.resume`
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+                local n = 0
+                local co = coroutine.create(function()
+                    for i = 1, 10000000 do n = n + 1 end
+                end)
+                local ok = coroutine.resume(co)
+                return not ok and n < 1000 and coroutine.status(co) == "dead"
+            "#,
+        )
+        .unwrap(),
+        Value::Boolean(true)
+    );
+}
+
+#[test]
+fn hook_yield() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let thread = luster::Thread::new(mc, root.metatables, true);
+        thread.set_hook(
+            mc,
+            Some(Hook::new(
+                mc,
+                HookMask {
+                    count: 100,
+                    ..HookMask::default()
+                },
+                |_, event, _| {
+                    assert_eq!(event, HookEvent::Count);
+                    Ok(HookAction::Yield)
+                },
+            )),
+        );
+        let closure = Closure::new(
+            mc,
+            compile(
+                mc,
+                root.interned_strings,
+                &b"local x = 0 for i = 1, 1000 do x = x + i end return x"[..],
+            )
+            .unwrap(),
+            Some(root.globals),
+        )
+        .unwrap();
+        thread.start(mc, Function::Closure(closure), &[]).unwrap();
+        root.globals
+            .set(mc, String::new_static(b"thread"), Value::Thread(thread))
+            .unwrap();
+    });
+
+    let mut yields = 0;
+    loop {
+        let done = lua.mutate(|mc, root| {
+            let thread = match root.globals.get(String::new_static(b"thread")) {
+                Value::Thread(thread) => thread,
+                _ => panic!("thread missing"),
+            };
+            match thread.mode() {
+                ThreadMode::Running => {
+                    thread.step(mc).unwrap();
+                    None
+                }
+                ThreadMode::Results => {
+                    let results = thread.take_results(mc).unwrap().unwrap();
+                    if thread.mode() == ThreadMode::Suspended {
+                        assert!(results.is_empty());
+                        thread.resume(mc, &[Value::Integer(1)]).unwrap();
+                        Some(false)
+                    } else {
+                        assert_eq!(results, vec![Value::Integer(500500)]);
+                        Some(true)
+                    }
+                }
+                mode => panic!("unexpected thread mode {:?}", mode),
+            }
+        });
+        match done {
+            Some(true) => break,
+            Some(false) => yields += 1,
+            None => {}
+        }
+    }
+    assert!(yields > 10);
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use luster::{compile, Lua, OpCode};
+
+fn jump_target(i: usize, op: OpCode) -> Option<usize> {
+    match op {
+        OpCode::Jump { offset, .. } => Some((i as isize + 1 + offset as isize) as usize),
+        _ => None,
+    }
+}
+
+fn check_opcodes(source: &[u8], check: impl Fn(&[OpCode])) {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let proto = compile(mc, root.interned_strings, source).unwrap();
+        check(&proto.opcodes);
+    });
+}
+
+#[test]
+fn jump_chaining() {
+    check_opcodes(
+        &br#"
+            local a, b, c = ...
+            while a do
+                if b then
+                    if c then
+                        break
+                    end
+                else
+                    a = b
+                end
+            end
+        "#[..],
+        |opcodes| {
+            for (i, &op) in opcodes.iter().enumerate() {
+                if let Some(target) = jump_target(i, op) {
+                    assert_ne!(target, i + 1, "jump to next instruction at {}", i);
+                    if let OpCode::Jump { close_upvalues, .. } = opcodes[target] {
+                        assert!(
+                            close_upvalues.is_some(),
+                            "jump to jump at {} in {:?}",
+                            i,
+                            opcodes
+                        );
+                    }
+                }
+            }
+        },
+    );
+}
+
+#[test]
+fn jump_threading() {
+    check_opcodes(
+        &br#"
+            local a, b = ...
+            if a and b then
+                a = 1
+            end
+        "#[..],
+        |opcodes| {
+            for (i, &op) in opcodes.iter().enumerate() {
+                if let Some(target) = jump_target(i, op) {
+                    if let OpCode::TestSet { .. } = opcodes[i - 1] {
+                        if let OpCode::Test { .. } = opcodes[target] {
+                            panic!("jump to test at {}", i);
+                        }
+                    }
+                }
+            }
+        },
+    );
+}
@@ -0,0 +1,66 @@
+use luster::{compile, compile_named, FunctionProto, LineNumber, Lua};
+
+fn opcode_lines(proto: &FunctionProto) -> Vec<u64> {
+    (0..proto.opcodes.len())
+        .map(|i| proto.opcode_line(i).unwrap().0)
+        .collect()
+}
+
+#[test]
+fn opcode_lines_and_chunk_name() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let proto = compile_named(
+            mc,
+            root.interned_strings,
+            b"lines.lua",
+            &br#"local a = 1
+local b = a
+
+print(a,
+    b)
+local function f()
+    local c = {}
+
+    return c.d
+end
+"#[..],
+        )
+        .unwrap();
+
+        assert_eq!(proto.chunk_name.as_bytes(), b"lines.lua");
+        // LoadConstant, Move, GetUpTableC, Move, Move, Call, Closure, Return
+        assert_eq!(opcode_lines(&proto), vec![1, 2, 4, 4, 4, 4, 6, 6]);
+
+        let f = &proto.prototypes[0];
+        assert_eq!(f.chunk_name.as_bytes(), b"lines.lua");
+        // NewTable, GetTableC, Return, Return
+        assert_eq!(opcode_lines(f), vec![7, 9, 9, 9]);
+
+        let proto = compile(mc, root.interned_strings, &b"\n\nreturn"[..]).unwrap();
+        assert_eq!(proto.chunk_name.as_bytes(), b"?");
+        assert_eq!(proto.opcode_line(0), Some(LineNumber(3)));
+    });
+}
+
+#[test]
+fn nested_expression_lines() {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let proto = compile(
+            mc,
+            root.interned_strings,
+            &br#"local t = {
+    a = 1,
+    b = tostring(
+        2
+    ),
+}
+"#[..],
+        )
+        .unwrap();
+
+        // NewTable, SetTableCC, GetUpTableC, LoadConstant, Call, SetTableCR, Return
+        assert_eq!(opcode_lines(&proto), vec![1, 1, 3, 3, 3, 1, 1]);
+    });
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use luster::{Lua, StaticError, Value};
+
+use common::run;
+
+#[test]
+fn catch_out_of_memory() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.set_memory_limit(Some(lua.total_allocated() + 1024 * 1024));
+
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+            local count = 0
+            local ok, err = pcall(function()
+                local t = {}
+                while true do
+                    count = count + 1
+                    t[count] = "some string that is long enough to be allocated " .. count
+                end
+            end)
+            if ok or err ~= "not enough memory" or count < 1000 then
+                return false
+            end
+
+            -- Once the memory is released, scripts may allocate again
+            local u = {}
+            for i = 1, 1000 do
+                u[i] = {}
+            end
+            return true
+        "#,
+        )?,
+        Value::Boolean(true)
+    );
+
+    let err = run(
+        &mut lua,
+        r#"
+            local t = {}
+            local i = 1
+            while true do
+                t[i] = {i}
+                i = i + 1
+            end
+        "#,
+    )
+    .unwrap_err();
+    assert_eq!(err.inner().to_string(), "runtime error: not enough memory");
+
+    Ok(())
+}
+
+#[test]
+fn collect_before_failing() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.set_memory_limit(Some(lua.total_allocated() + 64 * 1024));
+
+    // Far more than the limit is allocated in total, but very little of it is live at once.
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+            local s = ""
+            for i = 1, 2000 do
+                s = s .. "0123456789"
+                if #s > 10000 then
+                    s = ""
+                end
+            end
+            for i = 1, 10000 do
+                local t = {i, i, i, i}
+            end
+            return true
+        "#,
+        )?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn string_rep_limit() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.set_memory_limit(Some(lua.total_allocated() + 1024 * 1024));
+
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+            local ok, err = pcall(string.rep, "x", 1000000000)
+            local ok2, err2 = pcall(string.rep, "x", 1 << 62)
+            return not ok and err == "not enough memory" and #string.rep("x", 1000) == 1000 and
+                not ok2 and err2 == "resulting string too large"
+        "#,
+        )?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn string_pack_limit() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.set_memory_limit(Some(lua.total_allocated() + 1024 * 1024));
+
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+            local ok, err = pcall(string.pack, "c1500000000", "a")
+            local ok2, err2 = pcall(string.pack, "xc1000000000", "a")
+            return
+                not ok and err == "not enough memory" and
+                not ok2 and err2 == "not enough memory" and
+                string.pack("c5", "a") == "a\0\0\0\0"
+        "#,
+        )?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn string_gsub_limit() -> Result<(), StaticError> {
+    let mut lua = Lua::new();
+    lua.set_memory_limit(Some(lua.total_allocated() + 1024 * 1024));
+
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+            local s = string.rep("x", 100000)
+            local ok, err = pcall(string.gsub, s, ".", s)
+            local ok2, err2 = pcall(string.gsub, s, ".+", string.rep("%0", 50000))
+            local ok3, err3 = pcall(string.gsub, s, ".", function() return s end)
+            return
+                not ok and err == "not enough memory" and
+                not ok2 and err2 == "not enough memory" and
+                not ok3 and err3 == "not enough memory" and
+                string.gsub("abc", "%w", "%0%0") == "aabbcc"
+        "#,
+        )?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use luster::{Lua, StaticError, WriteFn};
+
+use common::run;
+
+fn capture() -> (Rc<RefCell<Vec<u8>>>, impl Write) {
+    let captured = Rc::new(RefCell::new(Vec::new()));
+    let sink = captured.clone();
+    (
+        captured,
+        WriteFn(move |buf: &[u8]| sink.borrow_mut().extend_from_slice(buf)),
+    )
+}
+
+#[test]
+fn capture_print() -> Result<(), StaticError> {
+    let (captured, stdout) = capture();
+    let mut lua = Lua::builder().set_stdout(stdout).build();
+
+    run(&mut lua, r#"print("hello", 1, nil, true)"#)?;
+    run(&mut lua, "print()")?;
+    assert_eq!(&captured.borrow()[..], &b"hello\t1\tnil\ttrue\n\n"[..]);
+
+    Ok(())
+}
+
+#[test]
+fn replace_output() -> Result<(), StaticError> {
+    let (first, stdout) = capture();
+    let mut lua = Lua::new();
+    lua.set_stdout(stdout);
+    run(&mut lua, "print('first')")?;
+
+    let (second, stdout) = capture();
+    let mut previous = lua.set_stdout(stdout);
+    run(&mut lua, "print('second')")?;
+    previous.write_all(b"done\n").unwrap();
+
+    assert_eq!(&first.borrow()[..], &b"first\ndone\n"[..]);
+    assert_eq!(&second.borrow()[..], &b"second\n"[..]);
+
+    let (errors, stderr) = capture();
+    lua.set_stderr(stderr);
+    lua.mutate(|_, root| root.output.stderr().write_all(b"error\n").unwrap());
+    assert_eq!(&errors.borrow()[..], &b"error\n"[..]);
+
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use luster::parser::{
     parse_chunk, Block, CallSuffix, Chunk, ConstructorField, Expression, FunctionCallStatement,
-    HeadExpression, PrimaryExpression, SimpleExpression, Statement, SuffixedExpression,
+    HeadExpression, LineNumber, PrimaryExpression, SimpleExpression, Statement, SuffixedExpression,
     TableConstructor,
 };
 
@@ -14,63 +14,77 @@ fn test_function_call() {
         Chunk {
             block: Block {
                 statements: vec![
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![
-                            Expression {
-                                head: Box::new(HeadExpression::Simple(SimpleExpression::Integer(
-                                    10,
-                                ))),
-                                tail: vec![],
+                    (
+                        LineNumber(1),
+                        Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
                             },
-                            Expression {
-                                head: Box::new(HeadExpression::Simple(SimpleExpression::Integer(
-                                    20,
+                            call: CallSuffix::Function(vec![
+                                Expression {
+                                    head: Box::new(HeadExpression::Simple(
+                                        SimpleExpression::Integer(10,)
+                                    )),
+                                    tail: vec![],
+                                    line: LineNumber(1),
+                                },
+                                Expression {
+                                    head: Box::new(HeadExpression::Simple(
+                                        SimpleExpression::Integer(20,)
+                                    )),
+                                    tail: vec![],
+                                    line: LineNumber(1),
+                                },
+                            ]),
+                        })
+                    ),
+                    (
+                        LineNumber(1),
+                        Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
+                            },
+                            call: CallSuffix::Function(vec![Expression {
+                                head: Box::new(HeadExpression::Simple(SimpleExpression::String(
+                                    "foo".as_bytes().to_vec().into_boxed_slice(),
                                 ))),
                                 tail: vec![],
+                                line: LineNumber(1),
+                            },]),
+                        })
+                    ),
+                    (
+                        LineNumber(1),
+                        Statement::FunctionCall(FunctionCallStatement {
+                            head: SuffixedExpression {
+                                primary: PrimaryExpression::Name(
+                                    "print".as_bytes().to_vec().into_boxed_slice(),
+                                ),
+                                suffixes: vec![],
                             },
-                        ]),
-                    }),
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![Expression {
-                            head: Box::new(HeadExpression::Simple(SimpleExpression::String(
-                                "foo".as_bytes().to_vec().into_boxed_slice(),
-                            ))),
-                            tail: vec![],
-                        },]),
-                    }),
-                    Statement::FunctionCall(FunctionCallStatement {
-                        head: SuffixedExpression {
-                            primary: PrimaryExpression::Name(
-                                "print".as_bytes().to_vec().into_boxed_slice(),
-                            ),
-                            suffixes: vec![],
-                        },
-                        call: CallSuffix::Function(vec![Expression {
-                            head: Box::new(HeadExpression::Simple(
-                                SimpleExpression::TableConstructor(TableConstructor {
-                                    fields: vec![ConstructorField::Array(Expression {
-                                        head: Box::new(HeadExpression::Simple(
-                                            SimpleExpression::Float(30.0),
-                                        )),
-                                        tail: vec![],
-                                    }),],
-                                }),
-                            )),
-                            tail: vec![],
-                        },]),
-                    }),
+                            call: CallSuffix::Function(vec![Expression {
+                                head: Box::new(HeadExpression::Simple(
+                                    SimpleExpression::TableConstructor(TableConstructor {
+                                        fields: vec![ConstructorField::Array(Expression {
+                                            head: Box::new(HeadExpression::Simple(
+                                                SimpleExpression::Float(30.0),
+                                            )),
+                                            tail: vec![],
+                                            line: LineNumber(1),
+                                        }),],
+                                    }),
+                                )),
+                                tail: vec![],
+                                line: LineNumber(1),
+                            },]),
+                        })
+                    ),
                 ],
                 return_statement: None,
             },
@@ -0,0 +1,127 @@
+local closed = ""
+
+local function closer(name)
+    return setmetatable({}, {
+        __close = function(self, err)
+            if err ~= nil then
+                closed = closed .. name .. "(" .. err .. ")"
+            else
+                closed = closed .. name
+            end
+        end
+    })
+end
+
+function test1()
+    closed = ""
+    do
+        local a <close> = closer("a")
+        local b <const> = 1
+        local c <close> = closer("c")
+        local d <close> = nil
+        local e <close> = false
+    end
+    return closed == "ca"
+end
+
+function test2()
+    closed = ""
+    for i = 1, 3 do
+        local a <close> = closer(i)
+        if i == 2 then
+            break
+        end
+    end
+    return closed == "12"
+end
+
+function test3()
+    closed = ""
+    local i = 0
+    ::top::
+    do
+        local a <close> = closer(i)
+        i = i + 1
+        if i < 3 then
+            goto top
+        end
+    end
+    return closed == "012"
+end
+
+function test4()
+    closed = ""
+    local function f()
+        local a <close> = closer("a")
+        local b <close> = closer("b")
+        return closed
+    end
+    local function g()
+        local a <close> = closer("g")
+        return f()
+    end
+    if f() ~= "" or closed ~= "ba" then
+        return false
+    end
+    closed = ""
+    return g() == "" and closed == "bag"
+end
+
+function test5()
+    closed = ""
+    local ok, err = pcall(function()
+        local a <close> = closer("a")
+        local b <close> = closer("b")
+        error("oops")
+    end)
+    return not ok and err == "oops" and closed == "b(oops)a(oops)"
+end
+
+function test6()
+    closed = ""
+    local ok, err = pcall(function()
+        local a <close> = closer("a")
+        local b <close> = setmetatable({}, {
+            __close = function()
+                error("close")
+            end
+        })
+    end)
+    return not ok and err == "close" and closed == "a(close)"
+end
+
+function test7()
+    return not pcall(function()
+        local a <close> = {}
+    end)
+end
+
+function test8()
+    closed = ""
+    local i = 0
+    while true do
+        local a <close> = closer(i)
+        i = i + 1
+        if i == 2 then
+            break
+        end
+    end
+    local co = coroutine.create(function()
+        local b <close> = closer("b")
+        coroutine.yield()
+    end)
+    coroutine.resume(co)
+    local before = closed
+    coroutine.resume(co)
+    return before == "01" and closed == "01b"
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5() and
+    test6() and
+    test7() and
+    test8()
@@ -0,0 +1,30 @@
+local t = {}
+local up
+
+local function message(f, ...)
+    local ok, err = pcall(f, ...)
+    return err
+end
+
+return
+    message(function() return config.x end) == "?:10: attempt to index a nil value (global 'config')" and
+    message(function() return t.a.b end) == "?:11: attempt to index a nil value (field 'a')" and
+    message(function(a) return a.y end) == "?:12: attempt to index a nil value (local 'a')" and
+    message(function() return up.y end) == "?:13: attempt to index a nil value (upvalue 'up')" and
+    message(function() t.a.b = 1 end) == "?:14: attempt to index a nil value (field 'a')" and
+    message(function() return t.x + 1 end) == "?:15: attempt to perform arithmetic on a nil value (field 'x')" and
+    message(function() return 1 + t end) == "?:16: attempt to perform arithmetic on a table value (upvalue 't')" and
+    message(function() return "abc" * 2 end) == "?:17: attempt to perform arithmetic on a string value (constant 'abc')" and
+    message(function() return -t end) == "?:18: attempt to perform arithmetic on a table value (upvalue 't')" and
+    message(function() return t | 1 end) == "?:19: attempt to perform bitwise operation on a table value (upvalue 't')" and
+    message(function() return 1.5 | 1 end) == "?:20: number has no integer representation" and
+    message(function() return t < 1 end) == "?:21: attempt to compare table with number" and
+    message(function() return {} <= {} end) == "?:22: attempt to compare two table values" and
+    message(function() return #t.n end) == "?:23: attempt to get length of a nil value (field 'n')" and
+    message(function() local s = "a" return s .. t end) == "?:24: attempt to concatenate a table value (upvalue 't')" and
+    message(function() undefined() end) == "?:25: attempt to call a nil value (global 'undefined')" and
+    message(function() t:method() end) == "?:26: attempt to call a nil value (method 'method')" and
+    message(function() return t.f() end) == "?:27: attempt to call a nil value (field 'f')" and
+    message(function() local f = 1 f() end) == "?:28: attempt to call a number value (local 'f')" and
+    message(function() for k in nil do end end) == "?:29: attempt to call a nil value" and
+    message(function() local x <close> = 1 end) == "?:30: variable 'x' got a non-closable value"
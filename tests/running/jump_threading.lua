@@ -0,0 +1,94 @@
+local function count_calls()
+    local calls = 0
+    return function(v)
+        calls = calls + 1
+        return v
+    end, function()
+        return calls
+    end
+end
+
+function test1()
+    local values = {false, true}
+    local results = {}
+    local i = 1
+    for x = 1, 2 do
+        for y = 1, 2 do
+            local a, b = values[x], values[y]
+            local r = 0
+            if a and b then
+                r = r + 1
+            end
+            if a or b then
+                r = r + 2
+            end
+            if not (a and b) then
+                r = r + 4
+            end
+            if (a or b) and not a then
+                r = r + 8
+            end
+            results[i] = r
+            i = i + 1
+        end
+    end
+    return results[1] == 4 and results[2] == 14 and results[3] == 6 and results[4] == 3
+end
+
+function test2()
+    local f, calls = count_calls()
+    local r = 0
+    if f(false) and f(true) then
+        r = 1
+    end
+    if f(nil) or f(false) or f(1) then
+        r = r + 2
+    end
+    return r == 2 and calls() == 4
+end
+
+function test3()
+    local i = 0
+    local total = 0
+    while true do
+        i = i + 1
+        if i > 10 then
+            break
+        end
+        if i % 2 == 0 then
+            goto continue
+        end
+        total = total + i
+        ::continue::
+    end
+    return total == 25
+end
+
+function test4()
+    local closures = {}
+    local i = 1
+    repeat
+        local j = i
+        closures[i] = function() return j end
+        i = i + 1
+        if i > 3 then
+            break
+        end
+    until false
+    return closures[1]() == 1 and closures[2]() == 2 and closures[3]() == 3
+end
+
+function test5()
+    local a, b = nil, 2
+    local c = a and b or 3
+    local d = b and a or 4
+    local e = (a or b) and (b or a)
+    return c == 3 and d == 4 and e == 2
+end
+
+return
+    test1() and
+    test2() and
+    test3() and
+    test4() and
+    test5()
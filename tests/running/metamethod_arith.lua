@@ -0,0 +1,82 @@
+local function ok_arith()
+    local mt = {}
+    mt.__add = function(a, b) return "add" end
+    mt.__sub = function(a, b) return "sub" end
+    mt.__mul = function(a, b) return "mul" end
+    mt.__div = function(a, b) return "div" end
+    mt.__mod = function(a, b) return "mod" end
+    mt.__pow = function(a, b) return "pow" end
+    mt.__unm = function(a) return "unm" end
+    mt.__idiv = function(a, b) return "idiv" end
+
+    local t = setmetatable({}, mt)
+    return
+        t + 1 == "add" and 1 + t == "add" and
+        t - 1 == "sub" and t * 1 == "mul" and
+        t / 1 == "div" and t % 1 == "mod" and
+        t ^ 1 == "pow" and -t == "unm" and
+        t // 1 == "idiv"
+end
+
+local function ok_bitwise()
+    local mt = {}
+    mt.__band = function(a, b) return "band" end
+    mt.__bor = function(a, b) return "bor" end
+    mt.__bxor = function(a, b) return "bxor" end
+    mt.__shl = function(a, b) return "shl" end
+    mt.__shr = function(a, b) return "shr" end
+    mt.__bnot = function(a) return "bnot" end
+
+    local t = setmetatable({}, mt)
+    return
+        (t & 1) == "band" and (1 | t) == "bor" and
+        (t ~ 1) == "bxor" and (t << 1) == "shl" and
+        (t >> 1) == "shr" and ~t == "bnot"
+end
+
+local function ok_operands()
+    local Vec = {}
+    Vec.__index = Vec
+    local function vec(x, y) return setmetatable({x = x, y = y}, Vec) end
+    Vec.__add = function(a, b) return vec(a.x + b.x, a.y + b.y) end
+    Vec.__mul = function(a, b)
+        if type(a) == "number" then
+            return vec(a * b.x, a * b.y)
+        else
+            return vec(a.x * b, a.y * b)
+        end
+    end
+    Vec.__unm = function(a) return vec(-a.x, -a.y) end
+
+    local v = vec(1, 2) + vec(3, 4)
+    local w = 2 * v
+    local u = -(w * 2)
+    return v.x == 4 and v.y == 6 and w.x == 8 and w.y == 12 and u.x == -16 and u.y == -24
+end
+
+local function ok_right_operand()
+    local left = setmetatable({}, {})
+    local right = {}
+    setmetatable(right, {__sub = function(a, b) return a == left and b == right end})
+    return left - right
+end
+
+local function ok_callback_handler()
+    local t = setmetatable({}, {__add = rawget})
+    local u = setmetatable({ [3] = "three" }, {__add = rawget})
+    return t + 3 == nil and u + 3 == "three"
+end
+
+local function ok_error()
+    return not pcall(function() return {} + 1 end) and
+        not pcall(function() return -{} end) and
+        not pcall(function() return ~setmetatable({}, {}) end)
+end
+
+return
+    ok_arith() and
+    ok_bitwise() and
+    ok_operands() and
+    ok_right_operand() and
+    ok_callback_handler() and
+    ok_error()
@@ -0,0 +1,122 @@
+local function ok_eq()
+    local mt = {__eq = function(a, b) return a.id == b.id end}
+    local a = setmetatable({id = 1}, mt)
+    local b = setmetatable({id = 1}, mt)
+    local c = setmetatable({id = 2}, mt)
+    local d = {id = 1}
+    return a == b and not (a ~= b) and a ~= c and a == d and d == a and
+        a ~= 1 and not rawequal(a, b) and rawequal(a, a)
+end
+
+local function ok_lt_le()
+    local mt = {}
+    mt.__lt = function(a, b) return a.v < b.v end
+    mt.__le = function(a, b) return a.v <= b.v end
+    local function new(v) return setmetatable({v = v}, mt) end
+    local a, b, c = new(1), new(2), new(2)
+    local x = a < b
+    return x and not (b < a) and b <= c and not (b < c) and b > a and c >= b and
+        not (a >= b)
+end
+
+local function ok_le_fallback()
+    local mt = {__lt = function(a, b) return a.v < b.v end}
+    local a = setmetatable({v = 1}, mt)
+    local b = setmetatable({v = 2}, mt)
+    return a <= b and not (b <= a) and a <= a
+end
+
+local function ok_len()
+    local t = setmetatable({1, 2, 3}, {__len = function(t) return 42 end})
+    return #t == 42 and rawlen(t) == 3 and #"abc" == 3 and rawlen("ab") == 2
+end
+
+local function ok_concat()
+    local mt = {}
+    mt.__concat = function(a, b)
+        local l = type(a) == "table" and a.s or a
+        local r = type(b) == "table" and b.s or b
+        return setmetatable({s = l .. r}, mt)
+    end
+    local a = setmetatable({s = "a"}, mt)
+    local r1 = a .. "b"
+    local r2 = "x" .. a
+    local r3 = "x" .. "y" .. a .. "b" .. "c"
+    local r4 = 1 .. a
+    return r1.s == "ab" and r2.s == "xa" and r3.s == "xyabc" and r4.s == "1a" and
+        "a" .. "b" .. 1 == "ab1"
+end
+
+local function ok_call()
+    local mt = {__call = function(self, a, b) return self.v + a + b, "second" end}
+    local t = setmetatable({v = 1}, mt)
+    local r, s = t(2, 3)
+    local function pass(...) return ... end
+    local u1, u2 = pass(t(4, 5))
+    local function tail() return t(1, 1) end
+    local results = {}
+    for i, v in setmetatable({}, {__call = function(_, s, i)
+        if i < 3 then return i + 1, i * 10 end
+    end}), nil, 0 do
+        results[i] = v
+    end
+    return r == 6 and s == "second" and u1 == 10 and u2 == "second" and tail() == 3 and
+        results[1] == 0 and results[3] == 20
+end
+
+local function ok_yield()
+    local mt = {}
+    mt.__lt = function(a, b) return coroutine.yield("lt") end
+    mt.__add = function(a, b) return coroutine.yield("add") end
+    mt.__concat = function(a, b) return coroutine.yield("concat") end
+    mt.__len = function(a) return coroutine.yield("len") end
+    mt.__call = function(self, a) return coroutine.yield("call") end
+    mt.__index = function(t, k) return coroutine.yield("index") end
+    local a = setmetatable({}, mt)
+
+    local co = coroutine.create(function()
+        local r = {}
+        if a < a then r.lt = true end
+        r.add = a + 1
+        r.concat = "x" .. a .. "y"
+        r.len = #a
+        r.call = a(1)
+        r.index = a.foo
+        return r
+    end)
+
+    local function check(...)
+        local ok, v = ...
+        return ok and v
+    end
+
+    return check(coroutine.resume(co)) == "lt" and
+        check(coroutine.resume(co, true)) == "add" and
+        check(coroutine.resume(co, 2)) == "concat" and
+        check(coroutine.resume(co, "c")) == "len" and
+        check(coroutine.resume(co, 4)) == "call" and
+        check(coroutine.resume(co, 5)) == "index" and
+        (function()
+            local r = check(coroutine.resume(co, 6))
+            return r.lt and r.add == 2 and r.concat == "xc" and r.len == 4 and
+                r.call == 5 and r.index == 6
+        end)()
+end
+
+local function ok_errors()
+    return not pcall(function() return {} < {} end) and
+        not pcall(function() return {} <= {} end) and
+        not pcall(function() return "a" .. {} end) and
+        not pcall(function() return #nil end) and
+        not pcall(function() local t = {} t() end)
+end
+
+return
+    ok_eq() and
+    ok_lt_le() and
+    ok_le_fallback() and
+    ok_len() and
+    ok_concat() and
+    ok_call() and
+    ok_yield() and
+    ok_errors()
@@ -0,0 +1,84 @@
+function test_index_table()
+    local base = { a = 1, b = 2 }
+    local t = setmetatable({ b = 3 }, { __index = base })
+    return t.a == 1 and t.b == 3 and t.c == nil and rawget(t, "a") == nil
+end
+
+function test_index_function()
+    local calls = 0
+    local t = setmetatable({}, {
+        __index = function(self, key)
+            calls = calls + 1
+            return key .. "!"
+        end
+    })
+    local a = t.foo
+    local b = t[1]
+    return a == "foo!" and b == "1!" and calls == 2
+end
+
+function test_index_chain()
+    local a = { x = 1 }
+    local b = setmetatable({}, { __index = a })
+    local c = setmetatable({}, { __index = b })
+    return c.x == 1
+end
+
+function test_newindex_function()
+    local log = {}
+    local t = setmetatable({ existing = 1 }, {
+        __newindex = function(self, key, value)
+            rawset(self, key, value * 2)
+            log[#log + 1] = key
+        end
+    })
+    t.existing = 5
+    t.new = 10
+    return t.existing == 5 and t.new == 20 and #log == 1 and log[1] == "new"
+end
+
+function test_newindex_table()
+    local store = {}
+    local t = setmetatable({}, { __newindex = store })
+    t.x = 4
+    return rawget(t, "x") == nil and store.x == 4
+end
+
+function test_class()
+    local Point = {}
+    Point.__index = Point
+
+    function Point.new(x, y)
+        return setmetatable({ x = x, y = y }, Point)
+    end
+
+    function Point:length2()
+        return self.x * self.x + self.y * self.y
+    end
+
+    local p = Point.new(3, 4)
+    return p:length2() == 25 and getmetatable(p) == Point
+end
+
+function test_protected()
+    local t = setmetatable({}, { __metatable = "locked" })
+    local ok = pcall(setmetatable, t, {})
+    return getmetatable(t) == "locked" and not ok
+end
+
+function test_global_env()
+    setmetatable(_ENV, { __index = function(_, k) return k end })
+    local r = undefined_global_name
+    setmetatable(_ENV, nil)
+    return r == "undefined_global_name"
+end
+
+return
+    test_index_table() and
+    test_index_function() and
+    test_index_chain() and
+    test_newindex_function() and
+    test_newindex_table() and
+    test_class() and
+    test_protected() and
+    test_global_env()
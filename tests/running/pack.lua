@@ -0,0 +1,120 @@
+-- Checks that the given values are exactly the expected ones
+local function returns(expected, ...)
+    for i = 1, #expected do
+        if (select(i, ...)) ~= expected[i] then
+            return false
+        end
+    end
+    return (select(#expected + 1, ...)) == nil
+end
+
+function test_integers()
+    return
+        string.pack("<i4", 1) == "\1\0\0\0" and
+        string.pack(">i4", 1) == "\0\0\0\1" and
+        string.pack("<i2", -2) == "\xfe\xff" and
+        string.pack("b", -1) == "\xff" and
+        string.pack("B", 255) == "\xff" and
+        string.pack(">H", 0x1234) == "\x12\x34" and
+        string.pack("<j", -1) == string.rep("\xff", 8) and
+        string.pack("<i16", -1) == string.rep("\xff", 16) and
+        string.pack(">I3", 0x010203) == "\1\2\3" and
+        string.pack("<i3", "7") == "\7\0\0" and
+        returns({-2, 3}, string.unpack("<i2", "\xfe\xff")) and
+        returns({65534, 3}, string.unpack("<I2", "\xfe\xff")) and
+        returns({0x010203, 4}, string.unpack(">I3", "\1\2\3")) and
+        returns({-1, 17}, string.unpack("<i16", string.rep("\xff", 16))) and
+        returns({1, 2, 3, 8}, string.unpack("<bhi4", "\1\2\0\3\0\0\0"))
+end
+
+function test_floats()
+    return
+        string.pack("<d", 1.5) == "\0\0\0\0\0\0\xf8\x3f" and
+        string.pack(">f", 1.5) == "\x3f\xc0\0\0" and
+        string.pack("n", 2) == string.pack("d", 2.0) and
+        returns({1.5, 9}, string.unpack("<d", "\0\0\0\0\0\0\xf8\x3f")) and
+        returns({1.5, 5}, string.unpack(">f", "\x3f\xc0\0\0")) and
+        returns({-0.25, 9}, string.unpack(">n", string.pack(">n", -0.25)))
+end
+
+function test_strings()
+    return
+        string.pack("z", "abc") == "abc\0" and
+        string.pack("s1", "abc") == "\3abc" and
+        string.pack(">s2", "abc") == "\0\3abc" and
+        string.pack("c5", "abc") == "abc\0\0" and
+        string.pack("c0", "") == "" and
+        returns({"abc", 5}, string.unpack("z", "abc\0def")) and
+        returns({"abc", "def", 9}, string.unpack("zz", "abc\0def\0")) and
+        returns({"abc", 5}, string.unpack("s1", "\3abc")) and
+        returns({"ab", 3}, string.unpack("c2", "abc")) and
+        returns({"", 1}, string.unpack("c0", ""))
+end
+
+function test_alignment()
+    return
+        string.pack("!<bi4", 1, 2) == "\1\0\0\0\2\0\0\0" and
+        string.pack("!2<bi4", 1, 2) == "\1\0\2\0\0\0" and
+        string.pack("<bi4", 1, 2) == "\1\2\0\0\0" and
+        string.pack("!<bXi4", 1) == "\1\0\0\0" and
+        string.pack("<bxb", 1, 2) == "\1\0\2" and
+        string.pack("!4 <b d", 1, 0) == "\1\0\0\0" .. string.rep("\0", 8) and
+        returns({1, 2, 9}, string.unpack("!<bi4", "\1\0\0\0\2\0\0\0")) and
+        returns({2, 9}, string.unpack("!<i4", "\1\0\0\0\2\0\0\0", 2))
+end
+
+function test_packsize()
+    return
+        string.packsize("") == 0 and
+        string.packsize("i4i8") == 12 and
+        string.packsize("!i4i8") == 16 and
+        string.packsize("bhij") == 15 and
+        string.packsize("c10") == 10 and
+        string.packsize("!8 b Xd") == 8
+end
+
+function test_positions()
+    return
+        returns({2, 3}, string.unpack("b", "\1\2\3", 2)) and
+        returns({3, 4}, string.unpack("b", "\1\2\3", -1)) and
+        returns({4}, string.unpack("", "\1\2\3", 4))
+end
+
+function test_round_trip()
+    local packed = string.pack(">I2s1zd", 42, "name", "value", 0.5)
+    return returns({42, "name", "value", 0.5, #packed + 1}, string.unpack(">I2s1zd", packed))
+end
+
+function test_errors()
+    return
+        not pcall(string.pack, "i17", 1) and
+        not pcall(string.pack, "i0", 1) and
+        not pcall(string.pack, "c", "a") and
+        not pcall(string.pack, "y", 1) and
+        not pcall(string.pack, "b", 128) and
+        not pcall(string.pack, "B", -1) and
+        not pcall(string.pack, "i4") and
+        not pcall(string.pack, "c2", "abc") and
+        not pcall(string.pack, "s1", string.rep("a", 256)) and
+        not pcall(string.pack, "z", "a\0b") and
+        not pcall(string.pack, "!3 i4", 1) and
+        not pcall(string.pack, "X", 1) and
+        not pcall(string.pack, "Xc1", 1) and
+        not pcall(string.packsize, "s") and
+        not pcall(string.packsize, "z") and
+        not pcall(string.unpack, "i4", "abc") and
+        not pcall(string.unpack, "z", "abc") and
+        not pcall(string.unpack, "s1", "\5abc") and
+        not pcall(string.unpack, "b", "abc", 5) and
+        not pcall(string.unpack, "i9", "\0\0\0\0\0\0\0\0\1")
+end
+
+return
+    test_integers() and
+    test_floats() and
+    test_strings() and
+    test_alignment() and
+    test_packsize() and
+    test_positions() and
+    test_round_trip() and
+    test_errors()
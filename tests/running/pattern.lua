@@ -0,0 +1,227 @@
+local function equal(a, b)
+    if #a ~= #b then
+        return false
+    end
+    for i = 1, #a do
+        if a[i] ~= b[i] then
+            return false
+        end
+    end
+    return true
+end
+
+-- Checks that the given values are exactly the expected ones
+local function returns(expected, ...)
+    for i = 1, #expected do
+        if (select(i, ...)) ~= expected[i] then
+            return false
+        end
+    end
+    return (select(#expected + 1, ...)) == nil
+end
+
+function test_find()
+    return
+        returns({7, 9}, string.find("hello world", "wor")) and
+        returns({3, 3}, string.find("hello world", "l")) and
+        returns({10, 10}, string.find("hello world", "l", 5)) and
+        returns({10, 10}, string.find("hello world", "l", -2)) and
+        returns({1, 0}, string.find("hello", "")) and
+        returns({6, 5}, string.find("hello", "", 6)) and
+        string.find("hello", "", 7) == nil and
+        string.find("hello", "xyz") == nil and
+        returns({2, 2}, string.find("a.b", ".", 1, true)) and
+        returns({2, 2}, string.find("a+b", "+", 1, true)) and
+        returns({1, 11, "key", "value"}, string.find("key = value", "(%w+) = (%w+)")) and
+        returns({3, 4}, string.find("hello", "l+")) and
+        returns({3, 4, 3, 5}, string.find("hello", "()ll()"))
+end
+
+function test_match()
+    return
+        string.match("hello world", "%a+") == "hello" and
+        string.match("hello world", "%a+$") == "world" and
+        string.match("hello world", "^world") == nil and
+        string.match("  trim  ", "^%s*(.-)%s*$") == "trim" and
+        string.match("2024-01-15", "(%d+)-(%d+)-(%d+)") == "2024" and
+        returns({"2024", "01", "15"}, string.match("2024-01-15", "(%d+)-(%d+)-(%d+)")) and
+        string.match("hello", "()ll") == 3 and
+        string.match("hello", ".-l") == "hel" and
+        string.match("hello", ".*l") == "hell" and
+        string.match("abc", "a?b") == "ab" and
+        string.match("bc", "a?b") == "b" and
+        string.match("x = 10", "%w+%s*=%s*(%d+)") == "10" and
+        string.match("hello", "l", -2) == "l" and
+        string.match("hello", "h", 2) == nil
+end
+
+function test_classes()
+    return
+        string.match("abc123", "%d+") == "123" and
+        string.match("abc123", "%D+") == "abc" and
+        string.match("  \t\nx", "%S") == "x" and
+        string.match("Hello", "%u%l+") == "Hello" and
+        string.match("a_b!c", "%p") == "_" and
+        string.match("0x1F", "0x(%x+)") == "1F" and
+        string.match("\1\2abc", "%c+") == "\1\2" and
+        string.match("a%b", "%%") == "%" and
+        string.match("a.b", "%.") == "." and
+        string.match("word1 word2", "%w+", 6) == "word2"
+end
+
+function test_sets()
+    return
+        string.match("hello", "[aeiou]") == "e" and
+        string.match("hello", "[^aeiou]+") == "h" and
+        string.match("hello123", "[a-z]+") == "hello" and
+        string.match("hello123", "[%d]+") == "123" and
+        string.match("a-b", "[a%-]+") == "a-" and
+        string.match("]]", "[]]+") == "]]" and
+        string.match("x^y", "[%^]") == "^" and
+        string.match("ABCdef", "[A-C]+") == "ABC"
+end
+
+function test_balance_frontier()
+    return
+        string.match("f(a(b)c) d", "%b()") == "(a(b)c)" and
+        string.match("if [x] then", "%b[]") == "[x]" and
+        string.match("(unbalanced", "%b()") == nil and
+        string.match("THE (quick) fox", "%f[%a]%a+") == "THE" and
+        string.match("THE (quick) fox", "%f[%l]%a+") == "quick" and
+        string.gsub("the cat sat", "%f[%w]%w+", "X") == "X X X"
+end
+
+function test_back_references()
+    return
+        string.match([[say "hi" there]], "([\"'])(.-)%1") == '"' and
+        returns({"'", "hi"}, string.match([[say 'hi' there]], "([\"'])(.-)%1")) and
+        string.match("abab", "(ab)%1") == "ab" and
+        string.match("abac", "(ab)%1") == nil
+end
+
+function test_gmatch()
+    local words = {}
+    for w in string.gmatch("one two  three", "%a+") do
+        words[#words + 1] = w
+    end
+
+    local pairs = {}
+    for k, v in string.gmatch("a=1, b=2, c=3", "(%w+)=(%w+)") do
+        pairs[#pairs + 1] = k .. v
+    end
+
+    local empty = {}
+    for m in string.gmatch("abc", "x*") do
+        empty[#empty + 1] = m
+    end
+
+    local positions = {}
+    for p in string.gmatch("hello", "()l") do
+        positions[#positions + 1] = p
+    end
+
+    return
+        equal(words, {"one", "two", "three"}) and
+        equal(pairs, {"a1", "b2", "c3"}) and
+        equal(empty, {"", "", "", ""}) and
+        equal(positions, {3, 4})
+end
+
+function test_gsub_string()
+    return
+        returns({"hell0 w0rld", 2}, string.gsub("hello world", "o", "0")) and
+        returns({"hell0 world", 1}, string.gsub("hello world", "o", "0", 1)) and
+        returns({"<hello> <world>", 2}, string.gsub("hello world", "(%w+)", "<%1>")) and
+        string.gsub("hello world", "%w+", "%0 %0") == "hello hello world world" and
+        string.gsub("hello world", "(%w+) (%w+)", "%2 %1") == "world hello" and
+        string.gsub("abc", "%w", "%%") == "%%%" and
+        string.gsub("abc", "", "-") == "-a-b-c-" and
+        string.gsub("hello", "^h", "H") == "Hello" and
+        returns({"Hhh", 1}, string.gsub("hhh", "^h", "H")) and
+        string.gsub("abc", "b*", "-") == "-a-c-" and
+        string.gsub("hello", "l", 1) == "he11o" and
+        string.gsub("hello", "()l", "%1") == "he34o"
+end
+
+function test_gsub_table()
+    local vars = {name = "lua", version = 5.3, missing = false}
+    return
+        string.gsub("$name $version", "%$(%w+)", vars) == "lua 5.3" and
+        string.gsub("$name $other $missing", "%$(%w+)", vars) == "lua $other $missing" and
+        string.gsub("a b", "%w", {a = "x"}) == "x b"
+end
+
+function test_gsub_function()
+    local calls = 0
+    local upper = string.gsub("hello world", "%w+", function(w)
+        calls = calls + 1
+        return string.upper(w)
+    end)
+
+    local kept = string.gsub("a b c", "%w", function(c)
+        if c == "b" then
+            return nil
+        end
+        return c .. c
+    end)
+
+    local digits = ""
+    string.gsub("1 2 3", "%d", function(d)
+        digits = digits .. d
+    end)
+
+    local swapped = string.gsub("k1=v1 k2=v2", "(%w+)=(%w+)", function(k, v)
+        return v .. "=" .. k
+    end)
+
+    local co = coroutine.create(function()
+        return string.gsub("abc", "%w", function(c)
+            return coroutine.yield(c)
+        end)
+    end)
+    local _, a = coroutine.resume(co)
+    local _, b = coroutine.resume(co, "1")
+    local _, c = coroutine.resume(co, "2")
+    local _, result, count = coroutine.resume(co, "3")
+    local yielded = a .. b .. c
+
+    return
+        upper == "HELLO WORLD" and calls == 2 and
+        kept == "aa b cc" and
+        digits == "123" and
+        swapped == "v1=k1 v2=k2" and
+        string.gsub("hello", "l", function() return 1.5 end) == "he1.51.5o" and
+        string.gsub("abc", "", function() return "-" end) == "-a-b-c-" and
+        yielded == "abc" and result == "123" and count == 3
+end
+
+function test_errors()
+    return
+        not pcall(string.find, "a", "%") and
+        not pcall(string.find, "a", "[a") and
+        not pcall(string.find, "a", "(a") and
+        not pcall(string.match, "a", "a)") and
+        not pcall(string.find, "a", "%1") and
+        not pcall(string.find, "a", "%b") and
+        not pcall(string.find, "a", "%fa") and
+        not pcall(string.match, string.rep("a", 300), string.rep("a?", 300)) and
+        not pcall(string.gsub, "abc", "%w", "%2") and
+        not pcall(string.gsub, "abc", "%w", "%x") and
+        not pcall(string.gsub, "abc", "%w", true) and
+        not pcall(string.gsub, "abc", "%w", function() return {} end) and
+        not pcall(string.gsub, "abc", "%w", function() error("error") end) and
+        not pcall(string.gmatch("abc", "[a"))
+end
+
+return
+    test_find() and
+    test_match() and
+    test_classes() and
+    test_sets() and
+    test_balance_frontier() and
+    test_back_references() and
+    test_gmatch() and
+    test_gsub_string() and
+    test_gsub_table() and
+    test_gsub_function() and
+    test_errors()
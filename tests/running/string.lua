@@ -5,4 +5,199 @@ function test_concat()
         1 .. 2 .. 3 == "123"
 end
 
-return test_concat()
+function test_len()
+    return
+        string.len("") == 0 and
+        string.len("abc") == 3 and
+        string.len("a\0b") == 3 and
+        string.len(123) == 3
+end
+
+function test_sub()
+    local s = "hello world"
+    return
+        string.sub(s, 1, 5) == "hello" and
+        string.sub(s, 7) == "world" and
+        string.sub(s, -5) == "world" and
+        string.sub(s, -5, -2) == "worl" and
+        string.sub(s, 0) == s and
+        string.sub(s, -100, 2) == "he" and
+        string.sub(s, 5, 100) == "o world" and
+        string.sub(s, 6, 5) == "" and
+        string.sub(s, 100) == "" and
+        string.sub(s, 2, -100) == "" and
+        string.sub(s, "2", 3.0) == "el"
+end
+
+function test_case()
+    return
+        string.upper("Hello, World 1") == "HELLO, WORLD 1" and
+        string.lower("Hello, World 1") == "hello, world 1" and
+        string.upper("\xe4") == "\xe4"
+end
+
+function test_rep()
+    return
+        string.rep("ab", 3) == "ababab" and
+        string.rep("ab", 3, ",") == "ab,ab,ab" and
+        string.rep("ab", 1, ",") == "ab" and
+        string.rep("ab", 0) == "" and
+        string.rep("ab", -1) == "" and
+        string.rep("", 5) == "" and
+        select(2, pcall(string.rep, "x", 1 << 62)) == "resulting string too large" and
+        select(2, pcall(string.rep, "x", 1 << 40, ",")) == "resulting string too large"
+end
+
+function test_reverse()
+    return
+        string.reverse("") == "" and
+        string.reverse("abc") == "cba"
+end
+
+function test_byte()
+    local a, b, c = string.byte("abc", 1, -1)
+    local none = #{string.byte("abc", 4)}
+    return
+        string.byte("abc") == 97 and
+        string.byte("abc", 2) == 98 and
+        string.byte("abc", -1) == 99 and
+        a == 97 and b == 98 and c == 99 and
+        none == 0
+end
+
+function test_char()
+    return
+        string.char() == "" and
+        string.char(104, 105) == "hi" and
+        string.char(0, 255) == "\0\xff" and
+        string.byte(string.char(200)) == 200 and
+        not pcall(string.char, 256) and
+        not pcall(string.char, -1)
+end
+
+function test_format()
+    return
+        string.format("%d %s %%", 42, "abc") == "42 abc %" and
+        string.format("%5d|%-5d|%05d", 42, 42, -42) == "   42|42   |-0042" and
+        string.format("%+d % d %.3d %.0d", 5, 5, 7, 0) == "+5  5 007 " and
+        string.format("%i", 3.0) == "3" and
+        string.format("%u", 7) == "7" and
+        string.format("%x %X %#x %#X %o %#o", 255, 255, 255, 255, 8, 8) == "ff FF 0xff 0XFF 10 010" and
+        string.format("%x", -1) == "ffffffffffffffff" and
+        string.format("%c%c%c", 76, 117, 97) == "Lua" and
+        string.format("%5.1f|%-8.3f|%08.3f", 3.14159, 3.14159, -3.14159) == "  3.1|3.142   |-003.142" and
+        string.format("%.0f %.0f", 2.5, 3.5) == "2 4" and
+        string.format("%e %.2E", 12345.678, 0.000123) == "1.234568e+04 1.23E-04" and
+        string.format("%g %g %g %g", 100000, 1000000, 0.0001, 0.00001) == "100000 1e+06 0.0001 1e-05" and
+        string.format("%.3g %#g %g %G", 3.14159, 1.0, 0.0, 1e-10) == "3.14 1.00000 0 1E-10" and
+        string.format("%a %a %A %.1a %.0a", 1.0, 0.5, 255.5, 1.03125, 1.5) ==
+            "0x1p+0 0x1p-1 0X1.FFP+7 0x1.0p+0 0x2p+0" and
+        string.format("%f %F %e", 1/0, -1/0, 1/0) == "inf -INF inf" and
+        string.format("%10.3s|%-6s|%s", "abcdef", "ab", 12) == "       abc|ab    |12" and
+        string.format("%s %s %s", nil, true, false) == "nil true false" and
+        string.format("%q", 'a\n"b\\\0c\1' .. "2\r") == '"a\\\n\\"b\\\\\\0c\\0012\\r"' and
+        string.format("%q %q %q", 10, math.mininteger, 0.5) == "10 0x8000000000000000 0x1p-1" and
+        string.format("%q %q %q", 1/0, -1/0, nil) == "1e9999 -1e9999 nil"
+end
+
+function test_format_tostring()
+    local point = setmetatable({x = 1, y = 2}, {
+        __tostring = function(p)
+            return "(" .. p.x .. ", " .. p.y .. ")"
+        end
+    })
+    local calls = 0
+    local counted = setmetatable({}, {
+        __tostring = function()
+            calls = calls + 1
+            return "counted"
+        end
+    })
+
+    local co = coroutine.create(function()
+        return string.format("%s!", setmetatable({}, {
+            __tostring = function()
+                return coroutine.yield("yielded")
+            end
+        }))
+    end)
+    local _, yielded = coroutine.resume(co)
+    local _, resumed = coroutine.resume(co, "resumed")
+
+    return
+        string.format("%s", point) == "(1, 2)" and
+        string.format("%d %s %-8s|%.2s", 3, point, point, point) == "3 (1, 2) (1, 2)  |(1" and
+        string.format("%s %s", counted, counted) == "counted counted" and calls == 2 and
+        yielded == "yielded" and resumed == "resumed!" and
+        not pcall(string.format, "%s", setmetatable({}, {__tostring = function() return 1 end})) and
+        not pcall(string.format, "%s", setmetatable({}, {__tostring = function() error("x") end}))
+end
+
+function test_format_errors()
+    return
+        not pcall(string.format, "%d") and
+        not pcall(string.format, "%d", 1.5) and
+        not pcall(string.format, "%d", "x") and
+        not pcall(string.format, "%z", 1) and
+        not pcall(string.format, "%", 1) and
+        not pcall(string.format, "%123d", 1) and
+        not pcall(string.format, "%.123f", 1) and
+        not pcall(string.format, "%------d", 1) and
+        not pcall(string.format, "%10q", "a") and
+        not pcall(string.format, "%q", {})
+end
+
+function test_methods()
+    local s = "hello"
+    local co = coroutine.create(function(s)
+        return s:upper()
+    end)
+    local _, upper = coroutine.resume(co, "abc")
+
+    string.shout = function(s)
+        return s:upper() .. "!"
+    end
+    local shout = s:shout()
+    string.shout = nil
+
+    return
+        ("x"):rep(3) == "xxx" and
+        s:upper() == "HELLO" and
+        s:len() == 5 and
+        s:sub(2, 3) == "el" and
+        s:byte() == 104 and
+        s:find("l+") == 3 and
+        s:gsub("l", "L") == "heLLo" and
+        ("%d-%s"):format(1, "a") == "1-a" and
+        s.len == string.len and
+        s.missing == nil and
+        getmetatable("").__index == string and
+        getmetatable(s) == getmetatable("other") and
+        upper == "ABC" and
+        shout == "HELLO!" and
+        not pcall(function() return s:shout() end) and
+        not pcall(function() local n = 1; return n:upper() end)
+end
+
+function test_errors()
+    return
+        not pcall(string.len) and
+        not pcall(string.sub, {}) and
+        not pcall(string.sub, "abc", 1.5) and
+        not pcall(string.rep, "abc")
+end
+
+return
+    test_concat() and
+    test_len() and
+    test_sub() and
+    test_case() and
+    test_rep() and
+    test_reverse() and
+    test_byte() and
+    test_char() and
+    test_format() and
+    test_format_tostring() and
+    test_format_errors() and
+    test_methods() and
+    test_errors()
@@ -20,6 +20,55 @@ local function test2()
         varargs(0, 1, 1, 2, 3, 5) == 4
 end
 
+local function test3()
+    local function pass(...)
+        return ...
+    end
+    local function last(a, b, c, ...)
+        return c, ...
+    end
+
+    local a, b, c, d = pass(1, nil, 3)
+    local e, f = last(1, pass(2, 3, 4), 5)
+    local g, h, i = last(1, pass(2, 3, 4, 5))
+    return
+        a == 1 and b == nil and c == 3 and d == nil and
+        e == 5 and f == nil and
+        g == 3 and h == 4 and i == 5 and
+        pass() == nil
+end
+
+local function test4()
+    local callable = setmetatable({}, {
+        __call = function(self, a, ...)
+            return ..., a
+        end
+    })
+    local function forward(...)
+        return callable(...)
+    end
+
+    local a, b, c = forward(4, 5)
+    return a == 5 and b == 4 and c == nil
+end
+
+local function test5()
+    local deep
+    deep = function(n, ...)
+        if n == 0 then
+            return ...
+        end
+        local a, b = deep(n - 1, n, ...)
+        return b, a
+    end
+
+    local a, b = deep(1000)
+    return a == 1 and b == 2
+end
+
 return
     test1() and
-    test2()
+    test2() and
+    test3() and
+    test4() and
+    test5()
@@ -0,0 +1,140 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use luster::{Callback, CallbackResult, Lua, StaticError, StdLib, String, Table, Value};
+
+use common::run;
+
+#[test]
+fn select_libs() -> Result<(), StaticError> {
+    let mut lua = Lua::builder()
+        .set_libs(&[StdLib::Base, StdLib::Math])
+        .build();
+    assert_eq!(
+        run(
+            &mut lua,
+            "return coroutine == nil and math.floor ~= nil and pcall ~= nil"
+        )?,
+        Value::Boolean(true)
+    );
+    // Without the string library, strings have no methods
+    assert_eq!(
+        run(
+            &mut lua,
+            "return string == nil and not pcall(function() return ('x'):upper() end)"
+        )?,
+        Value::Boolean(true)
+    );
+
+    let mut lua = Lua::builder().set_libs(&[]).build();
+    assert_eq!(
+        run(&mut lua, "return print == nil and math == nil")?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn remove_globals() -> Result<(), StaticError> {
+    let mut lua = Lua::builder()
+        .remove_global("print")
+        .remove_global("math.random")
+        .remove_global("missing.field")
+        .build();
+    assert_eq!(
+        run(
+            &mut lua,
+            "return print == nil and math.random == nil and math.floor ~= nil and missing == nil"
+        )?,
+        Value::Boolean(true)
+    );
+
+    // Removing the string library removes string methods along with it
+    let mut lua = Lua::builder().remove_global("string").build();
+    assert_eq!(
+        run(
+            &mut lua,
+            "return string == nil and not pcall(function() return ('x'):rep(2) end)"
+        )?,
+        Value::Boolean(true)
+    );
+
+    let mut lua = Lua::builder().remove_global("string.rep").build();
+    assert_eq!(
+        run(
+            &mut lua,
+            "return not pcall(function() return ('x'):rep(2) end) and ('x'):upper() == 'X'"
+        )?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn replace_globals() -> Result<(), StaticError> {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let print_log = log.clone();
+    let mut lua = Lua::builder()
+        .set_global("print", move |mc, _| {
+            let log = print_log.clone();
+            Callback::new_immediate(mc, move |args| {
+                for arg in args.iter() {
+                    if let Value::String(s) = arg {
+                        log.borrow_mut().push(s.as_bytes().to_vec());
+                    }
+                }
+                Ok(CallbackResult::Return(args.with_values(&[])))
+            })
+            .into()
+        })
+        .set_global("plugin.version", |_, _| Value::Integer(3))
+        .build();
+
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+            print("hello", "world")
+            return plugin.version == 3
+        "#
+        )?,
+        Value::Boolean(true)
+    );
+    assert_eq!(*log.borrow(), vec![b"hello".to_vec(), b"world".to_vec()]);
+
+    Ok(())
+}
+
+#[test]
+fn custom_globals() -> Result<(), StaticError> {
+    let mut lua = Lua::builder()
+        .set_libs(&[StdLib::Base])
+        .set_globals(|mc| {
+            let globals = Table::new(mc);
+            globals
+                .set(
+                    mc,
+                    String::new_static(b"name"),
+                    String::new_static(b"plugin"),
+                )
+                .unwrap();
+            globals
+        })
+        .setup(|mc, root| {
+            root.globals
+                .set(mc, String::new_static(b"globals"), root.globals)
+                .unwrap();
+        })
+        .build();
+
+    assert_eq!(run(
+        &mut lua,
+        "return name == 'plugin' and globals.name == 'plugin' and type(setmetatable) == 'function'"
+    )?, Value::Boolean(true));
+
+    Ok(())
+}
@@ -0,0 +1,263 @@
+use gc_sequence::{self as sequence, SequenceExt, SequenceResultExt};
+use luster::{
+    compile, Callback, CallbackResult, Closure, Error, Function, Lua, MetaMethod, StaticError,
+    String, Table, ThreadSequence, TypeError, UserData, UserDataMethods, UserDataType, Value,
+    ValueBuffer,
+};
+
+struct Counter {
+    count: i64,
+}
+
+#[test]
+fn userdata() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let counter = UserData::new(mc, Counter { count: 0 });
+            assert!(counter.is::<Counter>());
+            assert!(!counter.is::<i64>());
+            assert!(counter.read::<i64>().is_none());
+
+            let increment = Callback::new_immediate_with(mc, counter, |counter, args| {
+                match args.first().cloned().unwrap_or(Value::Nil) {
+                    Value::UserData(u) if u == *counter => {
+                        let mut counter = u.read::<Counter>().unwrap().count;
+                        counter += 1;
+                        Ok(CallbackResult::Return(
+                            args.with_values(&[Value::Integer(counter)]),
+                        ))
+                    }
+                    value => Err(TypeError {
+                        expected: "counter",
+                        found: value.type_name(),
+                    }
+                    .into()),
+                }
+            });
+
+            let metatable = Table::new(mc);
+            let methods = Table::new(mc);
+            methods.set(mc, String::new_static(b"increment"), increment)?;
+            metatable.set(mc, String::new_static(b"__index"), methods)?;
+            counter.set_metatable(mc, Some(metatable));
+
+            root.globals
+                .set(mc, String::new_static(b"counter"), counter)?;
+            root.globals.set(
+                mc,
+                String::new_static(b"other"),
+                UserData::new(mc, Counter { count: 0 }),
+            )?;
+            Ok(())
+        })
+        .and_then_with(root, |mc, root, _| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    &br#"
+                        return type(counter) == "userdata" and counter == counter and
+                            counter ~= other and getmetatable(other) == nil and
+                            rawequal(counter, counter) and counter:increment() == 1 and
+                            not pcall(counter.increment, other)
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|b| assert_eq!(b, vec![Value::Boolean(true)]))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    lua.mutate(|mc, root| {
+        if let Value::UserData(counter) = root.globals.get(String::new_static(b"counter")) {
+            counter.write::<Counter>(mc).unwrap().count = 5;
+            assert_eq!(counter.read::<Counter>().unwrap().count, 5);
+        } else {
+            panic!("counter is not userdata");
+        }
+    });
+
+    Ok(())
+}
+
+#[test]
+fn userdata_finalize() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.mutate(|mc, root| {
+        let metatable = Table::new(mc);
+        metatable
+            .set(
+                mc,
+                String::new_static(b"__gc"),
+                Callback::new_immediate(mc, |args| match args.first() {
+                    Some(Value::UserData(u)) if u.is::<Counter>() => {
+                        Ok(CallbackResult::Return(ValueBuffer::new()))
+                    }
+                    _ => Err(TypeError {
+                        expected: "counter",
+                        found: "other",
+                    }
+                    .into()),
+                }),
+            )
+            .unwrap();
+
+        let counter = UserData::new(mc, Counter { count: 0 });
+        counter.set_metatable(mc, Some(metatable));
+        root.finalizers.register_user_data(mc, counter);
+    });
+
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 1);
+    lua.finalize()?;
+    assert_eq!(lua.pending_finalization(), 0);
+
+    Ok(())
+}
+
+struct Vector {
+    x: f64,
+    y: f64,
+}
+
+impl UserDataType for Vector {
+    const NAME: &'static str = "vector";
+
+    fn add_methods<'gc>(methods: &mut UserDataMethods<'gc, '_, Self>) {
+        methods.add_method("length", |_, this, args| {
+            Ok(args.with_values(&[Value::Number((this.x * this.x + this.y * this.y).sqrt())]))
+        });
+        methods.add_method_mut("scale", |_, this, args| {
+            let factor = args.first().and_then(|v| v.to_number()).unwrap_or(1.0);
+            this.x *= factor;
+            this.y *= factor;
+            Ok(args.with_values(&[]))
+        });
+        methods.add_method("dot", |_, this, args| {
+            let dot = match args.first() {
+                Some(Value::UserData(u)) => u
+                    .read::<Vector>()
+                    .map(|other| this.x * other.x + this.y * other.y),
+                _ => None,
+            };
+            let dot = dot.ok_or(TypeError {
+                expected: "vector",
+                found: "other",
+            })?;
+            Ok(args.with_values(&[Value::Number(dot)]))
+        });
+        methods.add_method_mut("add", |_, this, args| {
+            let other = match args.first() {
+                Some(Value::UserData(u)) => u.read::<Vector>().map(|other| (other.x, other.y)),
+                _ => None,
+            };
+            let (x, y) = other.ok_or(TypeError {
+                expected: "vector",
+                found: "other",
+            })?;
+            this.x += x;
+            this.y += y;
+            Ok(args.with_values(&[]))
+        });
+        methods.add_field_getter("x", |_, this| Ok(Value::Number(this.x)));
+        methods.add_field_getter("y", |_, this| Ok(Value::Number(this.y)));
+        methods.add_field_setter("x", |_, this, value| {
+            this.x = value.to_number().ok_or(TypeError {
+                expected: "number",
+                found: value.type_name(),
+            })?;
+            Ok(())
+        });
+        methods.add_meta_method(MetaMethod::Len, |_, _, args| {
+            Ok(args.with_values(&[Value::Integer(2)]))
+        });
+        methods.add_meta_method(MetaMethod::Gc, |_, this, args| {
+            assert_eq!(this.x, 6.0);
+            Ok(args.with_values(&[]))
+        });
+    }
+}
+
+#[test]
+fn userdata_type() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    lua.sequence(|root| {
+        sequence::from_fn_with(root, |mc, root| {
+            let vector = root.user_data.create(mc, Vector { x: 3.0, y: 4.0 });
+            root.globals
+                .set(mc, String::new_static(b"vector"), vector)?;
+            root.globals.set(
+                mc,
+                String::new_static(b"counter"),
+                UserData::new(mc, Counter { count: 0 }),
+            )?;
+            Ok(())
+        })
+        .and_then_with(root, |mc, root, _| {
+            Ok(Closure::new(
+                mc,
+                compile(
+                    mc,
+                    root.interned_strings,
+                    &br#"
+                        local ok = vector:length() == 5 and vector.x == 3 and vector.y == 4 and
+                            #vector == 2 and vector.z == nil
+                        vector:scale(2)
+                        ok = ok and vector.x == 6 and vector.y == 8 and vector:length() == 10
+                        vector.x = 1
+                        ok = ok and vector.x == 1
+                        vector.x = 6
+                        ok = ok and vector:dot(vector) == 100
+                        -- A mutable method may not also see its own userdata as an argument
+                        ok = ok and not pcall(vector.add, vector, vector) and vector:length() == 10
+                        local mt = getmetatable(vector)
+                        ok = ok and not pcall(mt.__index) and not pcall(mt.__newindex) and
+                            not pcall(mt.__index, counter, "x") and
+                            not pcall(mt.__newindex, counter, "x", 1) and
+                            mt.__index(vector, "x") == 6
+                        return ok and
+                            not pcall(vector.length, counter) and
+                            not pcall(function() vector.y = 1 end) and
+                            not pcall(function() vector.x = "a" end) and
+                            getmetatable(vector) == getmetatable(vector)
+                    "#[..],
+                )?,
+                Some(root.globals),
+            )?)
+        })
+        .and_chain_with(root, |mc, root, closure| {
+            Ok(ThreadSequence::call_function(
+                mc,
+                root.main_thread,
+                Function::Closure(closure),
+                &[],
+            )?)
+        })
+        .map_ok(|b| assert_eq!(b, vec![Value::Boolean(true)]))
+        .map_err(Error::to_static)
+        .boxed()
+    })?;
+
+    lua.mutate(|mc, root| {
+        root.globals
+            .set(mc, String::new_static(b"vector"), Value::Nil)
+            .unwrap();
+    });
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 1);
+    lua.finalize()?;
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use luster::{Lua, StaticError, Value};
+
+use common::run;
+
+#[test]
+fn weak_values() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            kept = {}
+            weak = setmetatable({}, {__mode = "v"})
+            weak[1] = kept
+            weak[2] = {}
+            weak.kept = kept
+            weak.collected = {}
+            weak.string = "string"
+            weak.number = 4
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+                return weak[1] == kept and weak[2] == nil and weak.kept == kept and
+                    weak.collected == nil and weak.string == "string" and weak.number == 4
+            "#,
+        )?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn weak_keys() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            local mt = {__gc = function(t) finalized = t.name end}
+            kept = setmetatable({name = "kept"}, mt)
+            weak = setmetatable({}, {__mode = "k"})
+            weak[kept] = 1
+            weak[setmetatable({name = "collected"}, mt)] = 2
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 1);
+    lua.finalize()?;
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"return finalized == "collected" and weak[kept] == 1"#
+        )?,
+        Value::Boolean(true)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn ephemerons() -> Result<(), Box<StaticError>> {
+    let mut lua = Lua::new();
+    run(
+        &mut lua,
+        r#"
+            finalized = 0
+            local mt = {__gc = function() finalized = finalized + 1 end}
+            weak = setmetatable({}, {__mode = "k"})
+
+            -- Values which refer to their own keys do not keep them alive
+            key = {}
+            weak[key] = setmetatable({key = key}, mt)
+            local other = {}
+            weak[other] = setmetatable({key = other}, mt)
+
+            -- Values may be the keys of other entries
+            chain = {}
+            local last = chain
+            for i = 1, 5 do
+                local next = {}
+                weak[last] = next
+                last = next
+            end
+            weak[last] = setmetatable({}, mt)
+        "#,
+    )?;
+
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 1);
+    lua.finalize()?;
+    assert_eq!(
+        run(
+            &mut lua,
+            r#"
+                local last = chain
+                for i = 1, 5 do
+                    last = weak[last]
+                end
+                return finalized == 1 and weak[key].key == key and getmetatable(weak[last]) ~= nil
+            "#,
+        )?,
+        Value::Boolean(true)
+    );
+
+    run(&mut lua, "key = nil; chain = nil")?;
+    lua.collect_garbage();
+    assert_eq!(lua.pending_finalization(), 2);
+
+    Ok(())
+}